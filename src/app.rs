@@ -3,9 +3,20 @@
 //! The `App` model lives in `app::model` and holds the current library,
 //! selection and playback state.
 
+mod browser;
+mod cover;
+mod dupes;
+mod filter;
+mod folders;
 mod model;
+mod tag_editor;
 
+pub use browser::{BrowseGroup, BrowseLevel};
+pub use cover::{CoverArt, CoverImage};
+pub use dupes::DupesView;
+pub use folders::{FolderRow, FolderRowKind};
 pub use model::*;
+pub use tag_editor::TagEditor;
 
 #[cfg(test)]
 mod tests;
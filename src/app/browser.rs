@@ -0,0 +1,306 @@
+//! Artist → album → track browser.
+//!
+//! While browsing, the library is sorted by album artist, album, disc and
+//! track, so every artist and album is a contiguous run of the display order.
+//! The columns are those runs around the selected track: the selection is
+//! always a track, and the artist and album cursors are the groups holding it.
+//! Runs are cut on the values the sort compares (sort tags, case and leading
+//! articles ignored), so `The Beatles` and `Beatles` form one artist.
+//!
+//! A track is also listed under each of its other artists (`A; B` shows under
+//! both), so the artist column holds entries rather than tracks; the entries
+//! are re-sorted by artist when any track has more than one.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{SortField, SortKey};
+use crate::library::{SortValue, Track, name_key, sort_value};
+
+/// Which browser column the cursor keys act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BrowseLevel {
+    #[default]
+    Artist,
+    Album,
+    Track,
+}
+
+impl BrowseLevel {
+    /// One column to the right (`l`), stopping at tracks.
+    pub fn deeper(self) -> Self {
+        match self {
+            Self::Artist => Self::Album,
+            Self::Album | Self::Track => Self::Track,
+        }
+    }
+
+    /// One column to the left (`h`), stopping at artists.
+    pub fn shallower(self) -> Self {
+        match self {
+            Self::Artist | Self::Album => Self::Artist,
+            Self::Track => Self::Album,
+        }
+    }
+}
+
+/// Sort keys that make artists and albums contiguous.
+pub(crate) fn browse_sort_keys() -> Vec<SortKey> {
+    [
+        SortField::AlbumArtist,
+        SortField::Album,
+        SortField::Disc,
+        SortField::Track,
+        SortField::Display,
+    ]
+    .into_iter()
+    .map(SortKey::ascending)
+    .collect()
+}
+
+/// Label of the artist a track is first listed under: album artist, else
+/// the first artist.
+pub(crate) fn artist_label(track: &Track) -> &str {
+    [track.album_artist.as_deref(), track.artist()]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .find(|s| !s.is_empty())
+        .unwrap_or("Unknown artist")
+}
+
+/// Album column label.
+pub(crate) fn album_label(track: &Track) -> &str {
+    track
+        .album
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("Unknown album")
+}
+
+/// Artists a track is listed under, as (group key, label): first where the
+/// album artist sort puts it, then each of its other artists.
+fn artist_entries<'a>(track: &'a Track, articles: &[String]) -> Vec<(SortValue, &'a str)> {
+    let label = artist_label(track);
+    let mut entries = vec![(sort_value(track, SortField::AlbumArtist, articles), label)];
+    let listed = name_key(label, articles);
+    for artist in track.artists.iter().map(|a| a.trim()) {
+        let key = name_key(artist, articles);
+        if !artist.is_empty() && key != listed {
+            let key = SortValue::Text(key);
+            if entries.iter().all(|(k, _)| *k != key) {
+                entries.push((key, artist));
+            }
+        }
+    }
+    entries
+}
+
+/// Album group key: what the album sort compares.
+fn album_key(track: &Track, articles: &[String]) -> SortValue {
+    sort_value(track, SortField::Album, articles)
+}
+
+/// A run of entries sharing a group key: `indices[start..end]`. The label
+/// is that of the run's first entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowseGroup {
+    pub label: String,
+    pub(crate) key: SortValue,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Split `entries` into runs sharing a key.
+fn groups<'a>(entries: impl IntoIterator<Item = (SortValue, &'a str)>) -> Vec<BrowseGroup> {
+    let mut out: Vec<BrowseGroup> = Vec::new();
+    for (pos, (key, label)) in entries.into_iter().enumerate() {
+        match out.last_mut() {
+            Some(group) if group.key == key => group.end = pos + 1,
+            _ => out.push(BrowseGroup {
+                label: label.to_string(),
+                key,
+                start: pos,
+                end: pos + 1,
+            }),
+        }
+    }
+    out
+}
+
+/// The three browser columns around the selected track.
+#[derive(Debug, Clone, Default)]
+pub struct BrowseView {
+    /// Track index of each artist entry, in browse order; a track with
+    /// several artists is listed once per artist.
+    pub indices: Vec<usize>,
+    pub artists: Vec<BrowseGroup>,
+    pub artist: usize,
+    /// Albums of the selected artist, as offsets into `indices`.
+    pub albums: Vec<BrowseGroup>,
+    pub album: usize,
+    /// Position of the selected track within the selected album.
+    pub track: usize,
+}
+
+impl BrowseView {
+    /// Build the columns from browse-ordered `indices`. The cursor is on
+    /// `selected` as listed under `artist` (any listing when that is not
+    /// one of its artists); a `selected` track that is not listed falls
+    /// back to the first one. `articles` as from `article_keys`.
+    pub(crate) fn new(
+        tracks: &[Track],
+        indices: Vec<usize>,
+        selected: usize,
+        artist: Option<&SortValue>,
+        articles: &[String],
+    ) -> Self {
+        let mut entries: Vec<(SortValue, &str, usize)> = indices
+            .iter()
+            .flat_map(|&i| {
+                artist_entries(&tracks[i], articles)
+                    .into_iter()
+                    .map(move |(key, label)| (key, label, i))
+            })
+            .collect();
+        if entries.len() > indices.len() {
+            // Further artists' entries sit where their album artist sorts;
+            // move them under their own artist, in album/disc/track order.
+            let rest = |i: usize| {
+                [
+                    SortField::Album,
+                    SortField::Disc,
+                    SortField::Track,
+                    SortField::Display,
+                ]
+                .map(|field| sort_value(&tracks[i], field, articles))
+            };
+            entries.sort_by(|a, b| {
+                a.0.cmp(&b.0).then_with(|| {
+                    let (ra, rb) = (rest(a.2), rest(b.2));
+                    ra.iter()
+                        .zip(&rb)
+                        .map(|(x, y)| x.cmp(y))
+                        .find(|o| o.is_ne())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+            });
+        }
+        let pos = entries
+            .iter()
+            .position(|(key, _, i)| *i == selected && artist.is_none_or(|a| a == key))
+            .or_else(|| entries.iter().position(|&(_, _, i)| i == selected))
+            .unwrap_or(0);
+        let indices: Vec<usize> = entries.iter().map(|&(_, _, i)| i).collect();
+        let artists = groups(entries.into_iter().map(|(key, label, _)| (key, label)));
+        let artist = artists.iter().position(|g| pos < g.end).unwrap_or(0);
+        let albums = match artists.get(artist) {
+            Some(g) => {
+                let mut albums = groups(indices[g.start..g.end].iter().map(|&i| {
+                    let track = &tracks[i];
+                    (album_key(track, articles), album_label(track))
+                }));
+                for album in &mut albums {
+                    album.start += g.start;
+                    album.end += g.start;
+                }
+                albums
+            }
+            None => Vec::new(),
+        };
+        let album = albums.iter().position(|g| pos < g.end).unwrap_or(0);
+        let track = albums.get(album).map_or(0, |g| pos.saturating_sub(g.start));
+        Self {
+            indices,
+            artists,
+            artist,
+            albums,
+            album,
+            track,
+        }
+    }
+
+    /// Tracks of the selected album, in disc/track order.
+    pub fn tracks(&self) -> &[usize] {
+        match self.albums.get(self.album) {
+            Some(g) => &self.indices[g.start..g.end],
+            None => &[],
+        }
+    }
+
+    /// Tracks of the selected artist.
+    pub(crate) fn artist_tracks(&self) -> &[usize] {
+        match self.artists.get(self.artist) {
+            Some(g) => &self.indices[g.start..g.end],
+            None => &[],
+        }
+    }
+
+    /// Track to select after moving one entry in `level`'s column, wrapping,
+    /// with the artist it is listed under there: the first track of the next
+    /// artist or album, or the next track.
+    pub(crate) fn step(&self, level: BrowseLevel, forward: bool) -> Option<(usize, SortValue)> {
+        let wrap = |at: usize, len: usize| {
+            if forward {
+                (at + 1) % len
+            } else {
+                (at + len - 1) % len
+            }
+        };
+        let artist = |pos: usize| {
+            let group = self.artists.iter().find(|g| pos < g.end)?;
+            Some((self.indices[pos], group.key.clone()))
+        };
+        let first_of = |groups: &[BrowseGroup], at: usize| {
+            if groups.is_empty() {
+                return None;
+            }
+            artist(groups[wrap(at, groups.len())].start)
+        };
+        match level {
+            BrowseLevel::Artist => first_of(&self.artists, self.artist),
+            BrowseLevel::Album => first_of(&self.albums, self.album),
+            BrowseLevel::Track => {
+                let album = self.albums.get(self.album)?;
+                artist(album.start + wrap(self.track, album.end - album.start))
+            }
+        }
+    }
+}
+
+/// The group queued from the browser or folder view; the queue keeps only its tracks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BrowseScope {
+    /// An artist's group key; `album: None` queues every album of theirs.
+    Group {
+        artist: SortValue,
+        album: Option<SortValue>,
+        /// Articles the keys were built with.
+        articles: Vec<String>,
+    },
+    /// Every track below a directory.
+    Folder(PathBuf),
+}
+
+impl BrowseScope {
+    pub(crate) fn contains(&self, track: &Track) -> bool {
+        match self {
+            Self::Group {
+                artist,
+                album,
+                articles,
+            } => {
+                artist_entries(track, articles)
+                    .iter()
+                    .any(|(key, _)| key == artist)
+                    && album
+                        .as_ref()
+                        .is_none_or(|album| album_key(track, articles) == *album)
+            }
+            Self::Folder(dir) => track.path.starts_with(dir),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Cover art prepared for the metadata pane.
+//!
+//! Images are decoded and scaled off the UI thread (see `runtime::cover`);
+//! the app only keeps the result for the selected track, already sized to the
+//! cell area it was requested for.
+
+use std::path::PathBuf;
+
+/// How a prepared cover is drawn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoverImage {
+    /// The track has no cover art.
+    Missing,
+    /// Half-block cells, row by row: `(top, bottom)` RGB pixel per cell.
+    Cells(Vec<Vec<([u8; 3], [u8; 3])>>),
+    /// A kitty graphics or sixel escape sequence, written by the runtime at
+    /// the top-left cell of the area after each frame that needs it.
+    Graphics(String),
+}
+
+/// Cover art for one track at one cell size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverArt {
+    pub track: PathBuf,
+    pub cols: u16,
+    pub rows: u16,
+    pub image: CoverImage,
+}
+
+impl CoverArt {
+    /// True when this was prepared for `track` in a `cols` x `rows` area.
+    pub fn fits(&self, track: &std::path::Path, cols: u16, rows: u16) -> bool {
+        self.track == track && self.cols == cols && self.rows == rows
+    }
+}
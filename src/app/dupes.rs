@@ -0,0 +1,85 @@
+//! Duplicate finder view: groups of copies and the one to keep in each.
+//!
+//! Groups are a snapshot taken when the view opened, so trashing copies or a
+//! library refresh never shifts what the cursor points at.
+
+use crate::library::{DupeGroup, Track};
+
+/// The open duplicate finder.
+#[derive(Debug, Clone)]
+pub struct DupesView {
+    pub groups: Vec<DupeGroup>,
+    /// Copy kept per group (index into `copies`); starts at the best one.
+    pub keep: Vec<usize>,
+    /// Cursor over all copies of all groups, in order.
+    pub cursor: usize,
+    /// Groups were confirmed by decoded-audio hashes.
+    pub hashed: bool,
+    /// Waiting for `y` before trashing the extra copies of every group.
+    pub confirm_all: bool,
+}
+
+impl DupesView {
+    pub(crate) fn new(groups: Vec<DupeGroup>, hashed: bool) -> Self {
+        Self {
+            keep: vec![0; groups.len()],
+            groups,
+            cursor: 0,
+            hashed,
+            confirm_all: false,
+        }
+    }
+
+    fn copy_count(&self) -> usize {
+        self.groups.iter().map(|g| g.copies.len()).sum()
+    }
+
+    /// Group and copy under the cursor.
+    pub fn position(&self) -> (usize, usize) {
+        let mut rest = self.cursor;
+        for (g, group) in self.groups.iter().enumerate() {
+            if rest < group.copies.len() {
+                return (g, rest);
+            }
+            rest -= group.copies.len();
+        }
+        (self.groups.len().saturating_sub(1), 0)
+    }
+
+    /// Move the cursor by `delta` copies, clamped.
+    pub fn step(&mut self, delta: isize) {
+        let last = self.copy_count().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+    }
+
+    /// Keep the copy under the cursor instead of the current choice.
+    pub fn keep_selected(&mut self) {
+        let (g, c) = self.position();
+        if let Some(keep) = self.keep.get_mut(g) {
+            *keep = c;
+        }
+    }
+
+    /// Copies of group `g` that are not kept.
+    pub fn extras(&self, g: usize) -> Vec<&Track> {
+        self.groups[g]
+            .copies
+            .iter()
+            .enumerate()
+            .filter(|(c, _)| *c != self.keep[g])
+            .map(|(_, t)| t)
+            .collect()
+    }
+
+    /// Drop group `g` (after its extras were trashed), keeping the cursor
+    /// on the group that takes its place.
+    pub fn remove_group(&mut self, g: usize) {
+        self.groups.remove(g);
+        self.keep.remove(g);
+        self.cursor = self.groups[..g.min(self.groups.len())]
+            .iter()
+            .map(|group| group.copies.len())
+            .sum();
+        self.step(0);
+    }
+}
@@ -0,0 +1,176 @@
+//! Filter query parsing: fuzzy text plus audio property predicates.
+//!
+//! Words such as `codec:flac`, `bits>=24` or `rate>=96k` test a track's audio
+//! properties (and `rating>=4` its stars), and `artist:davis` or `genre:jazz` test each of its artists or
+//! genres on their own; every other word is fuzzy-matched against the display
+//! string.
+//! A word only becomes a predicate when both its key and value parse, so
+//! ordinary titles containing `:` or `>` still filter as text.
+
+use crate::library::Track;
+
+/// A parsed filter query.
+#[derive(Debug, Default)]
+pub(crate) struct FilterQuery {
+    /// The words left for fuzzy matching, joined by single spaces.
+    pub(crate) text: String,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Property {
+    Bitrate,
+    SampleRate,
+    BitDepth,
+    Channels,
+    /// File size, compared in bytes.
+    Size,
+    /// Star rating.
+    Rating,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cmp {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Predicate {
+    /// Case-insensitive substring of the codec name.
+    Codec(String),
+    /// Case-insensitive substring of any one artist.
+    Artist(String),
+    /// Case-insensitive substring of any one genre.
+    Genre(String),
+    Number(Property, Cmp, u64),
+}
+
+impl FilterQuery {
+    pub(crate) fn parse(query: &str) -> Self {
+        let mut text: Vec<&str> = Vec::new();
+        let mut predicates = Vec::new();
+        for word in query.split_whitespace() {
+            match Predicate::parse(word) {
+                Some(p) => predicates.push(p),
+                None => text.push(word),
+            }
+        }
+        Self {
+            text: text.join(" "),
+            predicates,
+        }
+    }
+
+    /// Return true when `track` satisfies every property predicate.
+    pub(crate) fn matches_properties(&self, track: &Track) -> bool {
+        self.predicates.iter().all(|p| p.matches(track))
+    }
+}
+
+impl Predicate {
+    fn parse(word: &str) -> Option<Self> {
+        let split = word.find([':', '=', '<', '>'])?;
+        let (key, rest) = word.split_at(split);
+        let (cmp, value) = if let Some(v) = rest.strip_prefix(">=") {
+            (Cmp::Ge, v)
+        } else if let Some(v) = rest.strip_prefix("<=") {
+            (Cmp::Le, v)
+        } else if let Some(v) = rest.strip_prefix('>') {
+            (Cmp::Gt, v)
+        } else if let Some(v) = rest.strip_prefix('<') {
+            (Cmp::Lt, v)
+        } else {
+            (Cmp::Eq, &rest[1..])
+        };
+        if value.is_empty() {
+            return None;
+        }
+
+        let property = match key.to_ascii_lowercase().as_str() {
+            "codec" | "format" if cmp == Cmp::Eq => {
+                return Some(Self::Codec(value.to_ascii_lowercase()));
+            }
+            "artist" if cmp == Cmp::Eq => return Some(Self::Artist(value.to_lowercase())),
+            "genre" if cmp == Cmp::Eq => return Some(Self::Genre(value.to_lowercase())),
+            "bitrate" | "kbps" => Property::Bitrate,
+            "rate" | "samplerate" => Property::SampleRate,
+            "bits" | "depth" => Property::BitDepth,
+            "channels" | "ch" => Property::Channels,
+            "size" => Property::Size,
+            "rating" | "stars" => Property::Rating,
+            _ => return None,
+        };
+        let value = match property {
+            // `44.1k` / `96k` as well as plain Hz.
+            Property::SampleRate => scaled(value, &[("k", 1_000.0)])?,
+            // Megabytes by default; `k`/`m`/`g` suffixes are decimal.
+            Property::Size => scaled(
+                value,
+                &[
+                    ("k", 1e3),
+                    ("kb", 1e3),
+                    ("m", 1e6),
+                    ("mb", 1e6),
+                    ("g", 1e9),
+                    ("gb", 1e9),
+                    ("", 1e6),
+                ],
+            )?,
+            _ => value.parse().ok()?,
+        };
+        Some(Self::Number(property, cmp, value))
+    }
+
+    fn matches(&self, track: &Track) -> bool {
+        let audio = &track.audio;
+        let any =
+            |values: &[String], part: &str| values.iter().any(|v| v.to_lowercase().contains(part));
+        match self {
+            Self::Codec(name) => audio
+                .codec
+                .as_deref()
+                .is_some_and(|c| c.to_ascii_lowercase().contains(name.as_str())),
+            Self::Artist(part) => any(&track.artists, part),
+            Self::Genre(part) => any(&track.genres, part),
+            Self::Number(property, cmp, want) => {
+                let have = match property {
+                    Property::Bitrate => audio.bitrate.map(u64::from),
+                    Property::SampleRate => audio.sample_rate.map(u64::from),
+                    Property::BitDepth => audio.bit_depth.map(u64::from),
+                    Property::Channels => audio.channels.map(u64::from),
+                    Property::Size => audio.file_size,
+                    Property::Rating => track.rating.map(u64::from),
+                };
+                // Tracks without the property never match a predicate on it.
+                have.is_some_and(|have| match cmp {
+                    Cmp::Eq => have == *want,
+                    Cmp::Lt => have < *want,
+                    Cmp::Le => have <= *want,
+                    Cmp::Gt => have > *want,
+                    Cmp::Ge => have >= *want,
+                })
+            }
+        }
+    }
+}
+
+/// Parse a decimal number with an optional unit suffix from `units`
+/// (checked in order; a `""` entry sets the unit for bare numbers).
+fn scaled(value: &str, units: &[(&str, f64)]) -> Option<u64> {
+    let lower = value.to_ascii_lowercase();
+    let split = lower
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(lower.len());
+    let (number, suffix) = lower.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let factor = match units.iter().find(|(unit, _)| *unit == suffix) {
+        Some((_, factor)) => *factor,
+        None if suffix.is_empty() => 1.0,
+        None => return None,
+    };
+    Some((number * factor).round() as u64)
+}
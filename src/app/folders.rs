@@ -0,0 +1,202 @@
+//! Folder tree built from track paths.
+//!
+//! The tree starts at the deepest directory shared by every listed track, so
+//! a single library root shows its own contents and several roots show the
+//! directories that separate them. Entries keep the order they first appear
+//! in (the library is path-sorted while the folder view is open), and chains
+//! of folders holding nothing but one subfolder collapse into a single
+//! `Artist/Album` row.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::library::Track;
+
+/// One visible row of the folder tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderRow {
+    /// Indentation level (0 for top-level entries).
+    pub depth: usize,
+    pub kind: FolderRowKind,
+}
+
+impl FolderRow {
+    /// The directory of a folder row.
+    pub fn folder_path(&self) -> Option<&Path> {
+        match &self.kind {
+            FolderRowKind::Folder { path, .. } => Some(path),
+            FolderRowKind::Track(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FolderRowKind {
+    Folder {
+        path: PathBuf,
+        /// Name relative to the parent row (`a/b` for compacted chains).
+        name: String,
+        /// Tracks anywhere below the folder.
+        tracks: usize,
+        /// Sum of the known track durations below the folder.
+        duration: Duration,
+        expanded: bool,
+    },
+    Track(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    Folder(usize),
+    Track(usize),
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    path: PathBuf,
+    name: String,
+    entries: Vec<Entry>,
+    children: HashMap<OsString, usize>,
+    tracks: usize,
+    duration: Duration,
+}
+
+/// Deepest directory containing every track in `indices`.
+fn common_dir(tracks: &[Track], indices: &[usize]) -> PathBuf {
+    let mut base: Option<PathBuf> = None;
+    for &i in indices {
+        let dir = tracks[i].path.parent().unwrap_or(Path::new(""));
+        base = Some(match base {
+            None => dir.to_path_buf(),
+            Some(base) => base
+                .components()
+                .zip(dir.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    base.unwrap_or_default()
+}
+
+/// Build the visible rows for path-ordered `indices`, opening the folders in `expanded`.
+pub(crate) fn folder_rows(
+    tracks: &[Track],
+    indices: &[usize],
+    expanded: &HashSet<PathBuf>,
+) -> Vec<FolderRow> {
+    let base = common_dir(tracks, indices);
+    let mut nodes = vec![Node {
+        path: base.clone(),
+        ..Node::default()
+    }];
+
+    for &i in indices {
+        let track = &tracks[i];
+        let duration = track.duration.unwrap_or_default();
+        let rel = track
+            .path
+            .parent()
+            .and_then(|dir| dir.strip_prefix(&base).ok())
+            .unwrap_or(Path::new(""));
+        let mut node = 0;
+        nodes[0].tracks += 1;
+        nodes[0].duration += duration;
+        for part in rel.iter() {
+            let next = match nodes[node].children.get(part) {
+                Some(&next) => next,
+                None => {
+                    let next = nodes.len();
+                    nodes.push(Node {
+                        path: nodes[node].path.join(part),
+                        name: part.to_string_lossy().into_owned(),
+                        ..Node::default()
+                    });
+                    nodes[node].children.insert(part.to_os_string(), next);
+                    nodes[node].entries.push(Entry::Folder(next));
+                    next
+                }
+            };
+            node = next;
+            nodes[node].tracks += 1;
+            nodes[node].duration += duration;
+        }
+        nodes[node].entries.push(Entry::Track(i));
+    }
+
+    let mut rows = Vec::new();
+    push_entries(&nodes, 0, 0, expanded, &mut rows);
+    rows
+}
+
+fn push_entries(
+    nodes: &[Node],
+    node: usize,
+    depth: usize,
+    expanded: &HashSet<PathBuf>,
+    rows: &mut Vec<FolderRow>,
+) {
+    for entry in &nodes[node].entries {
+        match *entry {
+            Entry::Track(i) => rows.push(FolderRow {
+                depth,
+                kind: FolderRowKind::Track(i),
+            }),
+            Entry::Folder(mut folder) => {
+                let mut name = nodes[folder].name.clone();
+                while let [Entry::Folder(only)] = nodes[folder].entries[..] {
+                    folder = only;
+                    name = format!("{}/{}", name, nodes[folder].name);
+                }
+                let open = expanded.contains(&nodes[folder].path);
+                rows.push(FolderRow {
+                    depth,
+                    kind: FolderRowKind::Folder {
+                        path: nodes[folder].path.clone(),
+                        name,
+                        tracks: nodes[folder].tracks,
+                        duration: nodes[folder].duration,
+                        expanded: open,
+                    },
+                });
+                if open {
+                    push_entries(nodes, folder, depth + 1, expanded, rows);
+                }
+            }
+        }
+    }
+}
+
+/// Row the folder cursor sits on: the `cursor` folder when set, else the
+/// `selected` track, else the closest visible folder holding that track.
+pub(crate) fn cursor_row(
+    rows: &[FolderRow],
+    tracks: &[Track],
+    cursor: Option<&Path>,
+    selected: usize,
+) -> usize {
+    if let Some(cursor) = cursor
+        && let Some(pos) = rows.iter().position(|r| r.folder_path() == Some(cursor))
+    {
+        return pos;
+    }
+    if let Some(pos) = rows
+        .iter()
+        .position(|r| r.kind == FolderRowKind::Track(selected))
+    {
+        return pos;
+    }
+    let Some(track) = tracks.get(selected) else {
+        return 0;
+    };
+    rows.iter()
+        .enumerate()
+        .filter(|(_, r)| {
+            r.folder_path()
+                .is_some_and(|dir| track.path.starts_with(dir))
+        })
+        .max_by_key(|(_, r)| r.depth)
+        .map_or(0, |(pos, _)| pos)
+}
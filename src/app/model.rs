@@ -3,11 +3,23 @@
 //! The `App` struct holds the current library, selected track and playback
 //! related flags used by the UI and runtime.
 
-use std::collections::HashMap;
-use std::path::PathBuf;
-
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::browser::{
+    BrowseLevel, BrowseScope, BrowseView, album_label, artist_label, browse_sort_keys,
+};
+use super::cover::CoverArt;
+use super::dupes::DupesView;
+use super::filter::FilterQuery;
+use super::folders::{FolderRow, FolderRowKind, cursor_row, folder_rows};
+use super::tag_editor::TagEditor;
 use crate::audio::{LoopMode, PlaybackHandle};
-use crate::library::{Lyrics, Track};
+use crate::config::{LibrarySettings, SortField, SortKey, SortPreset};
+use crate::library::{
+    BatchPlan, Diagnostic, DisplayOrder, LibraryChange, Lyrics, SortSpec, SortValue, Track,
+    apply_changes, article_keys,
+};
 
 /// The playback state of the application.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -23,16 +35,74 @@ impl Default for PlaybackState {
     }
 }
 
+/// How the library is laid out in the main pane.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LibraryView {
+    /// The flat track list.
+    #[default]
+    List,
+    /// Artist → album → track columns.
+    Browser,
+    /// Directory tree.
+    Folders,
+}
+
+/// A `:rename` / `:retag` dry run waiting for confirmation.
+#[derive(Debug, Clone)]
+pub struct BatchPreview {
+    /// Command name, used in the popup title.
+    pub title: String,
+    pub plan: BatchPlan,
+    /// First preview line shown.
+    pub scroll: usize,
+}
+
+/// The `:report` list of scan problems, as of when it was opened.
+#[derive(Debug, Clone)]
+pub struct ScanReport {
+    pub diagnostics: Vec<Diagnostic>,
+    pub cursor: usize,
+}
+
 /// The main application model.
 pub struct App {
     pub tracks: Vec<Track>,
     pub selected: usize,
     pub playback: PlaybackState,
     pub playback_handle: Option<PlaybackHandle>,
+    /// `ApplyLibraryChanges` batches sent to the audio thread; compared with
+    /// `PlaybackInfo::library_changes` by `playing_index`.
+    library_changes: u64,
+    /// The playing track in the new numbering, reported until the audio
+    /// thread has applied the latest library changes.
+    playing_until_synced: Option<usize>,
     pub volume: f32,
     pub initial_volume: f32,
 
     lower_titles: Option<Vec<String>>,
+    /// Unshuffled display order; tracks appended by a background scan are
+    /// merged in rather than re-sorting `tracks` (indices must stay stable).
+    library_order: DisplayOrder,
+    /// True while a background library scan is still delivering tracks.
+    pub scanning: bool,
+    /// Entries the last scan could not list (they have no track).
+    pub walk_problems: Vec<Diagnostic>,
+    /// Active sort preset; `custom_sort` holds the keys of the `custom` one.
+    pub sort_preset: SortPreset,
+    custom_sort: Vec<SortKey>,
+    /// Leading articles ignored by every preset.
+    sort_articles: Vec<String>,
+
+    pub view: LibraryView,
+    pub browse_level: BrowseLevel,
+    /// Group or folder queued with Enter in the browser/folder view, if any.
+    browse_scope: Option<BrowseScope>,
+    /// Artist group the browser cursor is in, for tracks listed under several.
+    browse_artist: Option<SortValue>,
+    /// Open folders in the folder view.
+    folders_expanded: HashSet<PathBuf>,
+    /// Folder row under the cursor; `None` when the cursor is on `selected`.
+    folder_cursor: Option<PathBuf>,
 
     pub follow_playback: bool,
     pub pending_follow_index: Option<usize>,
@@ -41,8 +111,27 @@ pub struct App {
     pub queue_dirty: bool,
 
     pub shuffle: bool,
+    /// Lowest rating listed while the favourites view is on.
+    pub favourites: Option<u8>,
+    /// Ratings presto keeps itself (`[library] write_ratings = false`) by
+    /// absolute path, 0 for cleared; they win over the tags of every track
+    /// that arrives.
+    stored_ratings: HashMap<PathBuf, u8>,
     pub filter_mode: bool,
     pub filter_query: String,
+    /// True while the `:` command line is being edited.
+    pub command_mode: bool,
+    pub command_line: String,
+    /// Open tag editor form, if any.
+    pub tag_editor: Option<TagEditor>,
+    /// Open batch preview, if any.
+    pub batch_preview: Option<BatchPreview>,
+    /// Cover art last prepared for the metadata pane.
+    pub cover: Option<CoverArt>,
+    /// Open duplicate finder, if any.
+    pub dupes: Option<DupesView>,
+    /// Open scan report, if any.
+    pub scan_report: Option<ScanReport>,
     pub order_handle: Option<crate::audio::OrderHandle>,
     pub current_dir: Option<String>,
     pub metadata_window: bool,
@@ -50,6 +139,7 @@ pub struct App {
     pub pending_count: Option<u32>,
     pub controls_popup: bool,
     pub lyrics_popup: bool,
+    pub lyrics_fullscreen: bool,
 
     current_track_lyrics_index: Option<usize>,
     current_track_lyrics: Option<Lyrics>,
@@ -65,34 +155,54 @@ impl App {
     pub fn toggle_controls_popup(&mut self) {
         self.controls_popup = !self.controls_popup;
     }
+    /// True while a centered popup (controls, tag editor, batch preview,
+    /// duplicate finder) is open.
+    pub fn popup_open(&self) -> bool {
+        self.controls_popup
+            || self.tag_editor.is_some()
+            || self.batch_preview.is_some()
+            || self.dupes.is_some()
+            || self.scan_report.is_some()
+    }
     /// Toggle the lyrics popup visibility.
     pub fn toggle_lyrics_popup(&mut self) {
         self.lyrics_popup = !self.lyrics_popup;
     }
+    /// Toggle the full-screen (karaoke) lyrics view.
+    pub fn toggle_lyrics_fullscreen(&mut self) {
+        self.lyrics_fullscreen = !self.lyrics_fullscreen;
+    }
     /// Create a new `App` with the provided list of `tracks`.
     pub fn new(tracks: Vec<Track>) -> Self {
         // Optimization: for larger libraries, precompute lowercase titles to speed up fuzzy
         // filtering (avoid per-char lowercase conversions on every redraw/keystroke).
-        let lower_titles = if tracks.len() > 100 {
-            Some(
-                tracks
-                    .iter()
-                    .map(|t| t.display.to_ascii_lowercase())
-                    .collect(),
-            )
-        } else {
-            None
-        };
+        let lower_titles = Self::lower_titles_for(&tracks);
+        let library_order = DisplayOrder::from_sorted(&tracks);
 
         Self {
             tracks,
             selected: 0,
             playback: PlaybackState::Stopped,
             playback_handle: None,
+            library_changes: 0,
+            playing_until_synced: None,
             volume: 1.0,
             initial_volume: 1.0,
 
             lower_titles,
+            library_order,
+            scanning: false,
+            walk_problems: Vec::new(),
+            sort_preset: SortPreset::Custom,
+            custom_sort: vec![SortKey::ascending(SortField::Display)],
+            sort_articles: Vec::new(),
+
+            view: LibraryView::List,
+            browse_level: BrowseLevel::Artist,
+            browse_scope: None,
+            browse_artist: None,
+            folders_expanded: HashSet::new(),
+            folder_cursor: None,
 
             follow_playback: true,
             pending_follow_index: None,
@@ -100,8 +210,17 @@ impl App {
             loop_mode: LoopMode::LoopAll,
             queue_dirty: true,
             shuffle: false,
+            favourites: None,
+            stored_ratings: HashMap::new(),
             filter_mode: false,
             filter_query: String::new(),
+            command_mode: false,
+            command_line: String::new(),
+            tag_editor: None,
+            batch_preview: None,
+            cover: None,
+            dupes: None,
+            scan_report: None,
             order_handle: None,
             current_dir: None,
             metadata_window: false,
@@ -109,12 +228,182 @@ impl App {
             pending_count: None,
             controls_popup: false,
             lyrics_popup: false,
+            lyrics_fullscreen: false,
             current_track_lyrics_index: None,
             current_track_lyrics: None,
             lyrics_cache: HashMap::new(),
         }
     }
 
+    fn lower_titles_for(tracks: &[Track]) -> Option<Vec<String>> {
+        if tracks.len() > 100 {
+            Some(
+                tracks
+                    .iter()
+                    .map(|t| t.display.to_ascii_lowercase())
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Append tracks delivered by a background scan.
+    ///
+    /// Existing indices (selection, playing track, queue) stay valid; the new
+    /// tracks are merged into the display order and the queue is marked dirty.
+    pub fn append_tracks(&mut self, new_tracks: Vec<Track>) {
+        if new_tracks.is_empty() {
+            return;
+        }
+        let start = self.tracks.len();
+        self.tracks.extend(new_tracks);
+        for track in &mut self.tracks[start..] {
+            apply_stored_rating(&self.stored_ratings, track);
+        }
+        match self.lower_titles.as_mut() {
+            Some(lower) => lower.extend(
+                self.tracks[start..]
+                    .iter()
+                    .map(|t| t.display.to_ascii_lowercase()),
+            ),
+            None => self.lower_titles = Self::lower_titles_for(&self.tracks),
+        }
+        self.library_order.extend(&self.tracks);
+        self.mark_queue_dirty();
+    }
+
+    /// Swap in a different library (after `:cd`).
+    ///
+    /// Everything tied to the old track indices is dropped; the sort, view and
+    /// playback modes are kept. The playing track is shown as detached until
+    /// the audio thread, which replaces its library too, lets it finish.
+    pub fn replace_library(&mut self, tracks: Vec<Track>) {
+        let playing = self.playing_index();
+        if let Some(ref handle) = self.playback_handle
+            && let Ok(mut info) = handle.lock()
+            && let Some(track) = info
+                .index
+                .take()
+                .and(playing)
+                .and_then(|i| self.tracks.get(i))
+        {
+            info.detached = Some(track.clone());
+        }
+        if let Some(ref handle) = self.order_handle
+            && let Ok(mut order) = handle.lock()
+        {
+            order.clear();
+        }
+        self.lower_titles = Self::lower_titles_for(&tracks);
+        self.tracks = tracks;
+        self.library_order.rebuild(&self.tracks);
+        self.selected = 0;
+        self.pending_follow_index = None;
+        self.browse_scope = None;
+        self.folders_expanded.clear();
+        self.folder_cursor = None;
+        self.filter_mode = false;
+        self.filter_query.clear();
+        self.current_track_lyrics_index = None;
+        self.current_track_lyrics = None;
+        self.lyrics_cache.clear();
+        self.walk_problems.clear();
+        self.mark_queue_dirty();
+    }
+
+    /// Apply filesystem-watcher changes to the library.
+    ///
+    /// Selection, the follow target and the now-playing index stay attached
+    /// to their files (also across renames); a removed selection falls back
+    /// to whatever now occupies its place in the visible list.
+    pub fn apply_library_changes(&mut self, mut changes: Vec<LibraryChange>) {
+        for change in &mut changes {
+            if let LibraryChange::Upsert(track) | LibraryChange::Rename { to: track, .. } = change {
+                // Lyrics sit next to or inside the file; re-read them on next use.
+                self.lyrics_cache.remove(&track.path);
+                apply_stored_rating(&self.stored_ratings, track);
+            }
+        }
+        let view_pos = self
+            .display_indices()
+            .iter()
+            .position(|&i| i == self.selected);
+
+        let remap = apply_changes(&mut self.tracks, changes);
+        let moved = |i: usize| remap.get(i).copied().flatten();
+
+        self.lower_titles = Self::lower_titles_for(&self.tracks);
+        self.library_order.rebuild(&self.tracks);
+        self.pending_follow_index = self.pending_follow_index.and_then(moved);
+        // Force a lyrics reload: the index may have moved or the file changed.
+        self.current_track_lyrics_index = None;
+        // The audio thread remaps the shared index itself; until it has,
+        // `playing_index` reports the playing track from here.
+        self.playing_until_synced = self.playing_index().and_then(moved);
+        self.library_changes += 1;
+        if let Some(ref handle) = self.order_handle
+            && let Ok(mut order) = handle.lock()
+        {
+            *order = order.iter().filter_map(|&i| moved(i)).collect();
+        }
+
+        self.selected = match moved(self.selected) {
+            Some(i) => i,
+            None => {
+                let view = self.display_indices();
+                view_pos
+                    .and_then(|p| view.get(p.min(view.len().saturating_sub(1))))
+                    .or(view.first())
+                    .copied()
+                    .unwrap_or(0)
+            }
+        };
+        self.ensure_selected_visible();
+        self.mark_queue_dirty();
+    }
+
+    /// Use `ratings` (see `stored_ratings`) for the current and future tracks.
+    pub fn set_stored_ratings(&mut self, ratings: HashMap<PathBuf, u8>) {
+        self.stored_ratings = ratings;
+        for track in &mut self.tracks {
+            apply_stored_rating(&self.stored_ratings, track);
+        }
+        self.mark_queue_dirty();
+    }
+
+    pub fn stored_ratings(&self) -> &HashMap<PathBuf, u8> {
+        &self.stored_ratings
+    }
+
+    /// Keep `stars` for the file at `path`; the track itself is updated
+    /// when it next arrives (see `apply_library_changes`).
+    pub fn store_rating(&mut self, path: &Path, stars: u8) {
+        self.stored_ratings.insert(rating_key(path), stars);
+    }
+
+    /// Carry stored ratings over to files that moved; returns whether any did.
+    pub fn move_stored_ratings(&mut self, moves: &[(PathBuf, PathBuf)]) -> bool {
+        let mut moved = false;
+        for (from, to) in moves {
+            if let Some(stars) = self.stored_ratings.remove(&rating_key(from)) {
+                self.stored_ratings.insert(rating_key(to), stars);
+                moved = true;
+            }
+        }
+        moved
+    }
+
+    /// Show only tracks rated at least `min_stars`, or everything again.
+    pub fn toggle_favourites(&mut self, min_stars: u8) {
+        self.favourites = match self.favourites {
+            Some(_) => None,
+            None => Some(min_stars),
+        };
+        self.mark_queue_dirty();
+        self.ensure_selected_visible();
+    }
+
     /// Set a user-facing notice message.
     pub fn set_notice(&mut self, message: String) {
         self.notice = Some(message);
@@ -133,6 +422,300 @@ impl App {
     pub fn clear_queue_dirty(&mut self) {
         self.queue_dirty = false;
     }
+    /// Configure the sort: the active preset, the `custom` preset's keys and
+    /// the leading articles to ignore.
+    pub fn set_sort(&mut self, preset: SortPreset, custom: Vec<SortKey>, articles: Vec<String>) {
+        self.custom_sort = custom;
+        self.sort_articles = articles;
+        self.set_sort_preset(preset);
+    }
+    /// Switch to `preset` and re-sort the library; the selection stays on its track.
+    pub fn set_sort_preset(&mut self, preset: SortPreset) {
+        self.sort_preset = preset;
+        self.library_order.set_sort(&self.tracks, &self.sort_spec());
+        self.mark_queue_dirty();
+    }
+    /// Advance to the next sort preset. The browser and folder views keep
+    /// their own order, so there this does nothing and returns false.
+    pub fn cycle_sort_preset(&mut self) -> bool {
+        if self.view != LibraryView::List {
+            return false;
+        }
+        self.set_sort_preset(self.sort_preset.next());
+        true
+    }
+    /// The active sort, as sent to the audio thread. The browser and folder
+    /// views override the preset so their groups play in order.
+    pub fn sort_spec(&self) -> SortSpec {
+        let keys = match self.view {
+            LibraryView::List => self.sort_preset.keys(&self.custom_sort),
+            LibraryView::Browser => browse_sort_keys(),
+            LibraryView::Folders => vec![SortKey::ascending(SortField::Path)],
+        };
+        SortSpec {
+            keys,
+            articles: self.sort_articles.clone(),
+        }
+    }
+    /// Switch between the flat track list and `view`.
+    ///
+    /// Re-sorts the library (send `sort_spec()` to the audio thread afterwards)
+    /// and drops any group queued from the previous view.
+    pub fn toggle_view(&mut self, view: LibraryView) {
+        self.set_view(if self.view == view {
+            LibraryView::List
+        } else {
+            view
+        });
+    }
+    /// Show `view`, with the same re-sort as `toggle_view`.
+    pub fn set_view(&mut self, view: LibraryView) {
+        self.view = view;
+        self.browse_scope = None;
+        self.folder_cursor = None;
+        self.library_order.set_sort(&self.tracks, &self.sort_spec());
+        self.mark_queue_dirty();
+        self.ensure_selected_visible();
+    }
+    /// Columns of the browser around the selected track.
+    pub fn browse_view(&self) -> BrowseView {
+        BrowseView::new(
+            &self.tracks,
+            self.browse_indices(),
+            self.selected,
+            self.browse_artist.as_ref(),
+            &article_keys(&self.sort_articles),
+        )
+    }
+    /// Move the browser cursor one entry down (or up) in the focused column.
+    pub fn browse_step(&mut self, forward: bool) {
+        if let Some((idx, artist)) = self.browse_view().step(self.browse_level, forward) {
+            self.selected = idx;
+            self.browse_artist = Some(artist);
+        }
+    }
+    /// Focus the column to the right.
+    pub fn browse_deeper(&mut self) {
+        self.browse_level = self.browse_level.deeper();
+    }
+    /// Focus the column to the left.
+    pub fn browse_shallower(&mut self) {
+        self.browse_level = self.browse_level.shallower();
+    }
+    /// Activate the focused browser entry and return the track to play.
+    ///
+    /// On an artist or album this queues the whole group; on a track the
+    /// queued group is kept when it holds the track and dropped otherwise.
+    pub fn browse_activate(&mut self) -> Option<usize> {
+        let view = self.browse_view();
+        let scope = |album: Option<SortValue>| {
+            view.artists
+                .get(view.artist)
+                .map(|artist| BrowseScope::Group {
+                    artist: artist.key.clone(),
+                    album,
+                    articles: article_keys(&self.sort_articles),
+                })
+        };
+        let (scope, play) = match self.browse_level {
+            BrowseLevel::Artist => (scope(None), view.artist_tracks().first().copied()),
+            BrowseLevel::Album => (
+                scope(view.albums.get(view.album).map(|a| a.key.clone())),
+                view.tracks().first().copied(),
+            ),
+            BrowseLevel::Track => {
+                let play = view.tracks().get(view.track).copied();
+                let keep = play.is_some_and(|i| {
+                    self.browse_scope
+                        .as_ref()
+                        .is_some_and(|s| s.contains(&self.tracks[i]))
+                });
+                (self.browse_scope.take().filter(|_| keep), play)
+            }
+        };
+        let play = play?;
+        self.browse_scope = scope;
+        self.selected = play;
+        self.mark_queue_dirty();
+        Some(play)
+    }
+    /// Labels of the artist and album holding the selected track (its own
+    /// when the browser does not list it), for persisting the browse path.
+    pub fn browse_path(&self) -> Option<(String, String)> {
+        let track = self.tracks.get(self.selected)?;
+        let view = self.browse_view();
+        match (view.artists.get(view.artist), view.albums.get(view.album)) {
+            (Some(artist), Some(album)) if view.indices.contains(&self.selected) => {
+                Some((artist.label.clone(), album.label.clone()))
+            }
+            _ => Some((
+                artist_label(track).to_string(),
+                album_label(track).to_string(),
+            )),
+        }
+    }
+    /// Select the first track of `artist`/`album` (or of `artist` when the
+    /// album is gone). Returns false when the artist is not in the library.
+    pub fn select_browse_path(&mut self, artist: &str, album: Option<&str>) -> bool {
+        let view = self.browse_view();
+        let Some(a) = view
+            .artists
+            .iter()
+            .find(|g| g.label.to_lowercase() == artist.to_lowercase())
+        else {
+            return false;
+        };
+        let mut target = view.indices[a.start];
+        if let Some(album) = album
+            && let Some(&i) = view.indices[a.start..a.end]
+                .iter()
+                .find(|&&i| album_label(&self.tracks[i]).to_lowercase() == album.to_lowercase())
+        {
+            target = i;
+        }
+        self.selected = target;
+        self.browse_artist = Some(a.key.clone());
+        true
+    }
+    /// Visible rows of the folder view.
+    pub fn folder_rows(&self) -> Vec<FolderRow> {
+        folder_rows(&self.tracks, &self.browse_indices(), &self.folders_expanded)
+    }
+    /// Position of the folder view cursor in `rows`.
+    pub fn folder_cursor_row(&self, rows: &[FolderRow]) -> usize {
+        cursor_row(
+            rows,
+            &self.tracks,
+            self.folder_cursor.as_deref(),
+            self.selected,
+        )
+    }
+    /// Put the folder view cursor on `row`; a track row also selects the track.
+    fn set_folder_cursor(&mut self, row: &FolderRow) {
+        match &row.kind {
+            FolderRowKind::Folder { path, .. } => self.folder_cursor = Some(path.clone()),
+            FolderRowKind::Track(i) => {
+                self.folder_cursor = None;
+                self.selected = *i;
+            }
+        }
+    }
+    /// Move the folder view cursor one row down (or up), wrapping.
+    pub fn folder_step(&mut self, forward: bool) {
+        let rows = self.folder_rows();
+        if rows.is_empty() {
+            return;
+        }
+        let at = self.folder_cursor_row(&rows);
+        let next = if forward {
+            (at + 1) % rows.len()
+        } else {
+            (at + rows.len() - 1) % rows.len()
+        };
+        self.set_folder_cursor(&rows[next]);
+    }
+    /// Open the folder under the cursor.
+    pub fn folder_expand(&mut self) {
+        let rows = self.folder_rows();
+        if let Some(FolderRowKind::Folder { path, .. }) =
+            rows.get(self.folder_cursor_row(&rows)).map(|r| &r.kind)
+        {
+            self.folders_expanded.insert(path.clone());
+            self.folder_cursor = Some(path.clone());
+        }
+    }
+    /// Close the open folder under the cursor, or move up to the parent folder.
+    pub fn folder_collapse(&mut self) {
+        let rows = self.folder_rows();
+        let at = self.folder_cursor_row(&rows);
+        let Some(row) = rows.get(at) else {
+            return;
+        };
+        if let FolderRowKind::Folder {
+            path,
+            expanded: true,
+            ..
+        } = &row.kind
+        {
+            self.folders_expanded.remove(path);
+            self.folder_cursor = Some(path.clone());
+            return;
+        }
+        if let Some(parent) = rows[..at].iter().rev().find(|r| r.depth < row.depth) {
+            self.set_folder_cursor(parent);
+        }
+    }
+    /// Activate the folder view row under the cursor and return the track to play.
+    ///
+    /// A folder queues every track below it in path order; a track keeps the
+    /// queued folder when it lies inside it and drops it otherwise.
+    pub fn folder_activate(&mut self) -> Option<usize> {
+        let rows = self.folder_rows();
+        let row = rows.get(self.folder_cursor_row(&rows))?;
+        let (scope, play) = match &row.kind {
+            FolderRowKind::Folder { path, .. } => {
+                let scope = BrowseScope::Folder(path.clone());
+                let play = self
+                    .browse_indices()
+                    .into_iter()
+                    .find(|&i| scope.contains(&self.tracks[i]))?;
+                (Some(scope), play)
+            }
+            FolderRowKind::Track(i) => {
+                let keep = self
+                    .browse_scope
+                    .as_ref()
+                    .is_some_and(|s| s.contains(&self.tracks[*i]));
+                (self.browse_scope.take().filter(|_| keep), *i)
+            }
+        };
+        self.browse_scope = scope;
+        self.selected = play;
+        self.mark_queue_dirty();
+        Some(play)
+    }
+    /// Tracks the tag editor acts on: the focused album or artist in the
+    /// browser, the folder under the cursor in the folder view, else the
+    /// selected track.
+    pub fn edit_targets(&self) -> Vec<usize> {
+        let group = match self.view {
+            LibraryView::List => None,
+            LibraryView::Browser => {
+                let view = self.browse_view();
+                match self.browse_level {
+                    BrowseLevel::Artist => Some(view.artist_tracks().to_vec()),
+                    BrowseLevel::Album => Some(view.tracks().to_vec()),
+                    BrowseLevel::Track => None,
+                }
+            }
+            LibraryView::Folders => {
+                let rows = self.folder_rows();
+                rows.get(self.folder_cursor_row(&rows))
+                    .and_then(FolderRow::folder_path)
+                    .map(|dir| {
+                        let scope = BrowseScope::Folder(dir.to_path_buf());
+                        self.browse_indices()
+                            .into_iter()
+                            .filter(|&i| scope.contains(&self.tracks[i]))
+                            .collect()
+                    })
+            }
+        };
+        match group {
+            Some(group) => group,
+            None if self.selected < self.tracks.len() => vec![self.selected],
+            None => Vec::new(),
+        }
+    }
+    /// Open the tag editor on `edit_targets()`; false when there is nothing to edit.
+    pub fn open_tag_editor(&mut self, settings: &LibrarySettings) -> bool {
+        let targets = self.edit_targets();
+        if targets.is_empty() {
+            return false;
+        }
+        self.tag_editor = Some(TagEditor::new(&self.tracks, targets, settings));
+        true
+    }
     /// Cycle `loop_mode` through `NoLoop -> LoopAll -> LoopOne`.
     pub fn cycle_loop_mode(&mut self) {
         self.loop_mode = match self.loop_mode {
@@ -176,6 +759,18 @@ impl App {
         clamped
     }
 
+    /// Library index of the playing track. While the audio thread has not
+    /// yet applied the library changes sent to it, its shared index still
+    /// counts in the old numbering, so the UI's own remapped copy is used.
+    pub fn playing_index(&self) -> Option<usize> {
+        let info = self.playback_handle.as_ref()?.lock().ok()?;
+        if info.library_changes == self.library_changes {
+            info.index
+        } else {
+            self.playing_until_synced
+        }
+    }
+
     /// Reset current volume back to the stored initial volume.
     pub fn reset_volume_to_initial(&mut self) -> f32 {
         let v = self.initial_volume;
@@ -228,6 +823,7 @@ impl App {
         self.current_track_lyrics_index = None;
         self.current_track_lyrics = None;
         self.lyrics_popup = false;
+        self.lyrics_fullscreen = false;
     }
 
     /// Return the current playing index for which lyrics are stored.
@@ -241,45 +837,77 @@ impl App {
     }
 
     /// Return the display order of track indices, taking into account shuffle
-    /// `order_handle` and active filtering.
+    /// `order_handle`, active filtering and a group queued from the browser.
     pub fn display_indices(&self) -> Vec<usize> {
+        let mut view = self.filtered(self.shuffled_order());
+        if self.view != LibraryView::List
+            && let Some(ref scope) = self.browse_scope
+        {
+            view.retain(|&i| scope.contains(&self.tracks[i]));
+        }
+        view
+    }
+
+    /// The unshuffled, filtered library the browser groups into columns.
+    pub fn browse_indices(&self) -> Vec<usize> {
+        self.filtered(self.library_order.as_slice().to_vec())
+    }
+
+    /// The play order: the shuffle order if enabled, else the library order.
+    fn shuffled_order(&self) -> Vec<usize> {
+        let unshuffled = || self.library_order.as_slice().to_vec();
         let base: Vec<usize> = if self.shuffle {
             if let Some(ref oh) = self.order_handle {
                 if let Ok(v) = oh.lock() {
-                    v.clone()
+                    // The audio thread may not have seen the latest appended
+                    // batch yet; only keep indices the UI knows about.
+                    v.iter()
+                        .copied()
+                        .filter(|&i| i < self.tracks.len())
+                        .collect()
                 } else {
-                    (0..self.tracks.len()).collect()
+                    unshuffled()
                 }
             } else {
-                (0..self.tracks.len()).collect()
+                unshuffled()
             }
         } else {
-            (0..self.tracks.len()).collect()
+            unshuffled()
         };
+        base
+    }
 
-        // Apply filtering (retain only indices that match filter)
+    /// Keep only the indices in `base` that match the filter query.
+    fn filtered(&self, mut base: Vec<usize>) -> Vec<usize> {
+        if let Some(min_stars) = self.favourites {
+            base.retain(|&i| self.tracks[i].rating.is_some_and(|r| r >= min_stars));
+        }
         let query = self.filter_query.trim();
         if query.is_empty() {
-            base
-        } else {
-            match self.lower_titles.as_deref() {
-                Some(lower_titles) => {
-                    let query_lower = query.to_ascii_lowercase();
-                    base.into_iter()
-                        .filter(|&i| {
+            return base;
+        }
+        let filter = FilterQuery::parse(query);
+        let query_lower = filter.text.to_ascii_lowercase();
+        base.into_iter()
+            .filter(|&i| filter.matches_properties(&self.tracks[i]))
+            .filter(|&i| {
+                filter.text.is_empty()
+                    || match self.lower_titles.as_deref() {
+                        Some(lower_titles) => {
                             Self::fuzzy_match_positions_lower(&lower_titles[i], &query_lower)
                                 .is_some()
-                        })
-                        .collect()
-                }
-                None => base
-                    .into_iter()
-                    .filter(|&i| {
-                        Self::fuzzy_match_positions(&self.tracks[i].display, query).is_some()
-                    })
-                    .collect(),
-            }
-        }
+                        }
+                        None => Self::fuzzy_match_positions(&self.tracks[i].display, &filter.text)
+                            .is_some(),
+                    }
+            })
+            .collect()
+    }
+
+    /// The part of the filter query matched against track text, without
+    /// property predicates such as `codec:flac`.
+    pub fn filter_text(&self) -> String {
+        FilterQuery::parse(&self.filter_query).text
     }
 
     /// Return true if this `App` uses precomputed lowercase titles.
@@ -503,10 +1131,26 @@ impl App {
         self.ensure_selected_visible();
     }
 
+    /// Open the `:` command line.
+    pub fn enter_command_mode(&mut self) {
+        self.command_mode = true;
+        self.command_line.clear();
+    }
+    /// Close the command line, returning what was typed.
+    pub fn take_command_line(&mut self) -> String {
+        self.command_mode = false;
+        std::mem::take(&mut self.command_line)
+    }
+
     /// Ensure that `selected` is part of the current filtered/shuffled view,
     /// otherwise move selection to the first visible track.
     fn ensure_selected_visible(&mut self) {
-        let display = self.display_indices();
+        // The browser and folder view may move outside the queued group.
+        let display = if self.view == LibraryView::List {
+            self.display_indices()
+        } else {
+            self.browse_indices()
+        };
         if display.is_empty() {
             self.selected = 0;
             return;
@@ -530,3 +1174,18 @@ impl App {
         }
     }
 }
+
+/// Key of `path` in `App::stored_ratings`.
+fn rating_key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Replace `track`'s tagged rating with the stored one, if any.
+fn apply_stored_rating(ratings: &HashMap<PathBuf, u8>, track: &mut Track) {
+    if ratings.is_empty() {
+        return;
+    }
+    if let Some(&stars) = ratings.get(&rating_key(&track.path)) {
+        track.rating = (stars > 0).then_some(stars);
+    }
+}
@@ -0,0 +1,130 @@
+//! Tag editor form for one or more tracks.
+//!
+//! Each field starts at the value the targets share; fields where they
+//! differ start empty and are only written once typed into, so editing the
+//! album of a whole album leaves every track's title alone.
+
+use crate::config::LibrarySettings;
+use crate::library::{TagField, Track};
+
+/// One row of the form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditorField {
+    pub field: TagField,
+    /// The value every target shares, or `None` when they differ.
+    pub original: Option<String>,
+    pub value: String,
+    /// How many different values the targets hold.
+    pub variants: usize,
+    /// Typed into since the form opened.
+    pub touched: bool,
+}
+
+impl EditorField {
+    /// True when saving would write this field.
+    pub fn changed(&self) -> bool {
+        self.touched && self.original.as_deref() != Some(self.value.as_str())
+    }
+}
+
+/// The open tag editor.
+#[derive(Debug, Clone)]
+pub struct TagEditor {
+    /// Track indices being edited.
+    pub targets: Vec<usize>,
+    pub fields: Vec<EditorField>,
+    pub cursor: usize,
+    /// Showing the diff preview, waiting for confirmation.
+    pub confirming: bool,
+}
+
+impl TagEditor {
+    /// Open on `targets`; artists and genres are joined with the separators
+    /// the scan splits them at (see `TagField::value`).
+    pub(crate) fn new(tracks: &[Track], targets: Vec<usize>, settings: &LibrarySettings) -> Self {
+        let fields = TagField::ALL
+            .into_iter()
+            .map(|field| {
+                let mut values: Vec<String> = targets
+                    .iter()
+                    .map(|&i| field.value(&tracks[i], settings))
+                    .collect();
+                let first = values.first().cloned().unwrap_or_default();
+                let original = values.iter().all(|v| *v == first).then_some(first);
+                values.sort();
+                values.dedup();
+                EditorField {
+                    field,
+                    value: original.clone().unwrap_or_default(),
+                    original,
+                    variants: values.len(),
+                    touched: false,
+                }
+            })
+            .collect();
+        Self {
+            targets,
+            fields,
+            cursor: 0,
+            confirming: false,
+        }
+    }
+
+    /// Move the cursor to the next (or previous) field, wrapping.
+    pub fn step(&mut self, forward: bool) {
+        let len = self.fields.len();
+        self.cursor = if forward {
+            (self.cursor + 1) % len
+        } else {
+            (self.cursor + len - 1) % len
+        };
+    }
+
+    pub fn push(&mut self, c: char) {
+        let field = &mut self.fields[self.cursor];
+        field.value.push(c);
+        field.touched = true;
+    }
+
+    pub fn pop(&mut self) {
+        let field = &mut self.fields[self.cursor];
+        field.value.pop();
+        field.touched = true;
+    }
+
+    /// Empty the field under the cursor (saving then removes the tag).
+    pub fn clear(&mut self) {
+        let field = &mut self.fields[self.cursor];
+        field.value.clear();
+        field.touched = true;
+    }
+
+    /// Fields to write, with their new text.
+    pub fn changes(&self) -> Vec<(TagField, String)> {
+        self.fields
+            .iter()
+            .filter(|f| f.changed())
+            .map(|f| (f.field, f.value.trim().to_string()))
+            .collect()
+    }
+
+    /// Preview lines: `Field: old -> new` per changed field.
+    pub fn diff(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|f| f.changed())
+            .map(|f| {
+                let old = match &f.original {
+                    Some(v) if v.is_empty() => "(empty)".to_string(),
+                    Some(v) => v.clone(),
+                    None => format!("({} different values)", f.variants),
+                };
+                let new = match f.value.trim() {
+                    "" => "(removed)",
+                    v => v,
+                };
+                format!("{}: {} -> {}", f.field.label(), old, new)
+            })
+            .collect()
+    }
+}
@@ -7,10 +7,8 @@ fn t(title: &str) -> Track {
     Track {
         path: std::path::PathBuf::new(),
         title: title.into(),
-        artist: None,
-        album: None,
-        duration: None,
         display: title.into(),
+        ..Track::default()
     }
 }
 
@@ -87,6 +85,103 @@ fn trimming_filter_query_affects_matching() {
     assert_eq!(app.display_indices(), vec![0]);
 }
 
+#[test]
+fn append_tracks_keeps_indices_and_sorted_view() {
+    let mut app = App::new(vec![t("Beta"), t("Delta")]);
+    app.clear_queue_dirty();
+
+    app.append_tracks(vec![t("gamma"), t("Alpha")]);
+    assert!(app.queue_dirty);
+    assert_eq!(app.tracks[0].display, "Beta");
+    assert_eq!(app.display_indices(), vec![3, 0, 1, 2]);
+
+    app.filter_query = "ta".into();
+    assert_eq!(app.display_indices(), vec![0, 1]);
+
+    // Crossing the large-library threshold switches to precomputed titles.
+    app.append_tracks((0..100).map(|i| t(&format!("Zeta {i}"))).collect());
+    assert!(app.uses_lower_titles());
+    app.filter_query = "zeta 99".into();
+    assert_eq!(app.display_indices(), vec![103]);
+}
+
+#[test]
+fn replaced_library_drops_old_indices_and_detaches_now_playing() {
+    use crate::audio::PlaybackInfo;
+
+    let mut app = App::new(vec![t("Old A"), t("Old B")]);
+    let info = Arc::new(Mutex::new(PlaybackInfo {
+        index: Some(1),
+        ..PlaybackInfo::default()
+    }));
+    app.set_playback_handle(info.clone());
+    app.set_selected(1);
+    app.push_filter_char('b');
+    app.clear_queue_dirty();
+
+    app.replace_library(vec![t("New A"), t("New B"), t("New C")]);
+    assert!(app.queue_dirty);
+    assert_eq!(app.selected, 0);
+    assert!(app.filter_query.is_empty());
+    assert_eq!(app.display_indices(), vec![0, 1, 2]);
+    let info = info.lock().unwrap();
+    assert_eq!(info.index, None);
+    assert_eq!(
+        info.detached.as_ref().map(|t| t.title.as_str()),
+        Some("Old B")
+    );
+}
+
+#[test]
+fn library_changes_keep_selection_and_now_playing_by_path() {
+    use crate::audio::PlaybackInfo;
+    use crate::library::LibraryChange;
+
+    let at = |path: &str, title: &str| Track {
+        path: path.into(),
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        at("/a", "A"),
+        at("/b", "B"),
+        at("/c", "C"),
+        at("/d", "D"),
+    ]);
+    let info = Arc::new(Mutex::new(PlaybackInfo {
+        index: Some(3),
+        ..PlaybackInfo::default()
+    }));
+    app.set_playback_handle(info.clone());
+    app.set_selected(2);
+    app.clear_queue_dirty();
+
+    app.apply_library_changes(vec![
+        LibraryChange::Remove("/a".into()),
+        LibraryChange::Rename {
+            from: "/c".into(),
+            to: at("/c2", "C"),
+        },
+    ]);
+    assert!(app.queue_dirty);
+    assert_eq!(app.tracks[app.selected].path, std::path::Path::new("/c2"));
+    // The shared index is the audio thread's to remap; until it has applied
+    // the batch, even an index it writes in the old numbering is not used.
+    assert_eq!(info.lock().unwrap().index, Some(3));
+    assert_eq!(app.playing_index(), Some(2));
+    info.lock().unwrap().index = Some(0);
+    assert_eq!(app.playing_index(), Some(2));
+    {
+        let mut info = info.lock().unwrap();
+        info.index = Some(1);
+        info.library_changes = 1;
+    }
+    assert_eq!(app.playing_index(), Some(1));
+
+    // A removed selection falls back to the track now in its place.
+    app.apply_library_changes(vec![LibraryChange::Remove("/c2".into())]);
+    assert_eq!(app.tracks[app.selected].display, "D");
+}
+
 #[test]
 fn next_prev_in_view_helpers_work() {
     let tracks = vec![t("Alpha"), t("Beta"), t("Gamma")];
@@ -197,3 +292,421 @@ fn clear_current_track_lyrics_hides_popup() {
     assert_eq!(app.current_track_lyrics_index(), None);
     assert_eq!(app.current_track_lyrics(), None);
 }
+
+#[test]
+fn filter_combines_property_predicates_with_fuzzy_text() {
+    use crate::library::AudioProperties;
+
+    let with = |title: &str, codec: &str, rate: u32, bits: u8| Track {
+        audio: AudioProperties {
+            codec: Some(codec.to_string()),
+            sample_rate: Some(rate),
+            bit_depth: Some(bits),
+            ..AudioProperties::default()
+        },
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        with("Blue Train", "FLAC", 96_000, 24),
+        with("Blue Monk", "FLAC", 44_100, 16),
+        with("Blue in Green", "MP3", 44_100, 16),
+        t("Blues Untagged"),
+    ]);
+
+    app.filter_query = "codec:flac".into();
+    assert_eq!(app.display_indices(), vec![0, 1]);
+    app.filter_query = "blue bits>=24".into();
+    assert_eq!(app.display_indices(), vec![0]);
+    app.filter_query = "rate<48k monk".into();
+    assert_eq!(app.display_indices(), vec![1]);
+    assert_eq!(app.filter_text(), "monk");
+
+    // Unknown keys and unparsable values stay part of the text query.
+    app.filter_query = "blue:train".into();
+    assert_eq!(app.filter_text(), "blue:train");
+    app.filter_query = "bits>=lots".into();
+    assert!(app.display_indices().is_empty());
+}
+
+#[test]
+fn filter_matches_any_of_several_artists_and_genres() {
+    let with = |title: &str, artists: &[&str], genres: &[&str]| Track {
+        artists: artists.iter().map(|a| a.to_string()).collect(),
+        genres: genres.iter().map(|g| g.to_string()).collect(),
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        with("Blue Train", &["John Coltrane"], &["Jazz"]),
+        with(
+            "Kind of Blue",
+            &["Miles Davis", "John Coltrane"],
+            &["Jazz", "Modal"],
+        ),
+        with("Untagged", &[], &[]),
+    ]);
+
+    app.filter_query = "artist:coltrane".into();
+    assert_eq!(app.display_indices(), vec![0, 1]);
+    app.filter_query = "artist:Davis genre:jazz".into();
+    assert_eq!(app.display_indices(), vec![1]);
+    app.filter_query = "genre:modal blue".into();
+    assert_eq!(app.display_indices(), vec![1]);
+    assert_eq!(app.filter_text(), "blue");
+}
+
+#[test]
+fn favourites_view_uses_stored_ratings_over_tagged_ones() {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    let rated = |title: &str, rating: Option<u8>| Track {
+        path: PathBuf::from(format!("/m/{}.flac", title)),
+        rating,
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        rated("a", Some(5)),
+        rated("b", Some(3)),
+        rated("c", None),
+    ]);
+    app.toggle_favourites(4);
+    assert_eq!(app.display_indices(), vec![0]);
+    app.filter_query = "rating<=3".into();
+    assert!(app.display_indices().is_empty());
+    app.filter_query.clear();
+
+    // A stored 0 clears a tagged rating; tracks arriving later pick theirs up.
+    app.set_stored_ratings(HashMap::from([
+        (PathBuf::from("/m/a.flac"), 0),
+        (PathBuf::from("/m/b.flac"), 4),
+        (PathBuf::from("/m/d.flac"), 5),
+    ]));
+    app.append_tracks(vec![rated("d", None)]);
+    assert_eq!(app.display_indices(), vec![1, 3]);
+    assert_eq!(app.tracks[0].rating, None);
+
+    assert!(app.move_stored_ratings(&[(PathBuf::from("/m/b.flac"), PathBuf::from("/m/e.flac"))]));
+    assert_eq!(
+        app.stored_ratings().get(&PathBuf::from("/m/e.flac")),
+        Some(&4)
+    );
+
+    app.toggle_favourites(4);
+    assert_eq!(app.favourites, None);
+    assert_eq!(app.display_indices().len(), 4);
+}
+
+#[test]
+fn sort_presets_reorder_the_view_and_keep_the_selection() {
+    use crate::config::{SortField, SortKey, SortPreset};
+
+    let song = |title: &str, secs: u64| Track {
+        duration: Some(std::time::Duration::from_secs(secs)),
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        song("Alpha", 300),
+        song("Beta", 100),
+        song("Gamma", 200),
+    ]);
+    app.set_sort(
+        SortPreset::Custom,
+        vec![SortKey::descending(SortField::Title)],
+        Vec::new(),
+    );
+    assert_eq!(app.display_indices(), vec![2, 1, 0]);
+
+    app.set_selected(1);
+    app.clear_queue_dirty();
+    app.set_sort_preset(SortPreset::Duration);
+    assert!(app.queue_dirty);
+    assert_eq!(app.display_indices(), vec![1, 2, 0]);
+    assert_eq!(app.selected, 1);
+
+    assert!(app.cycle_sort_preset());
+    assert_eq!(app.sort_preset, SortPreset::Added);
+    // Cycling wraps back to the configured keys.
+    assert!(app.cycle_sort_preset());
+    assert_eq!(app.sort_preset, SortPreset::Custom);
+    assert_eq!(
+        app.sort_spec().keys,
+        vec![SortKey::descending(SortField::Title)]
+    );
+
+    // The browser's order is fixed; the preset is left alone.
+    app.toggle_view(LibraryView::Browser);
+    assert!(!app.cycle_sort_preset());
+    assert_eq!(app.sort_preset, SortPreset::Custom);
+}
+
+#[test]
+fn browser_groups_artists_and_albums_and_queues_a_group() {
+    let song = |title: &str, artist: &str, album: &str, number: u32| Track {
+        artists: vec![artist.into()],
+        album: Some(album.into()),
+        track_number: Some(number),
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        song("Blue Train", "Coltrane", "Blue Train", 1),
+        song("So What", "Davis", "Kind of Blue", 1),
+        song("Locomotion", "Coltrane", "Blue Train", 3),
+        song("Giant Steps", "Coltrane", "Giant Steps", 1),
+        song("Moment's Notice", "Coltrane", "Blue Train", 2),
+    ]);
+    app.toggle_view(LibraryView::Browser);
+    assert_eq!(app.browse_indices(), vec![0, 4, 2, 3, 1]);
+
+    app.set_selected(2);
+    let view = app.browse_view();
+    let labels = |groups: &[BrowseGroup]| -> Vec<String> {
+        groups.iter().map(|g| g.label.clone()).collect()
+    };
+    assert_eq!(labels(&view.artists), vec!["Coltrane", "Davis"]);
+    assert_eq!(labels(&view.albums), vec!["Blue Train", "Giant Steps"]);
+    assert_eq!(view.tracks(), &[0, 4, 2]);
+    assert_eq!(view.track, 2);
+
+    // Stepping an album column jumps to the next album's first track.
+    app.browse_deeper();
+    app.browse_step(true);
+    assert_eq!(app.selected, 3);
+    app.browse_shallower();
+    app.browse_step(true);
+    assert_eq!(app.selected, 1);
+    app.browse_step(true);
+    assert_eq!(app.selected, 0);
+
+    // Enter on an artist queues all of their albums, starting at the first track.
+    assert_eq!(app.browse_activate(), Some(0));
+    assert_eq!(app.display_indices(), vec![0, 4, 2, 3]);
+    assert_eq!(
+        app.browse_path(),
+        Some(("Coltrane".into(), "Blue Train".into()))
+    );
+
+    // Playing a track outside the queued group drops the group.
+    app.browse_step(true);
+    app.browse_deeper();
+    app.browse_deeper();
+    assert_eq!(app.browse_activate(), Some(1));
+    assert_eq!(app.display_indices().len(), 5);
+
+    assert!(app.select_browse_path("coltrane", Some("giant steps")));
+    assert_eq!(app.selected, 3);
+    app.toggle_view(LibraryView::Browser);
+    assert_eq!(app.display_indices(), vec![0, 3, 2, 4, 1]);
+}
+
+#[test]
+fn browser_groups_artists_by_their_sort_key() {
+    let song = |title: &str, artist: &str, album: &str, number: u32| Track {
+        artists: vec![artist.into()],
+        album: Some(album.into()),
+        track_number: Some(number),
+        ..t(title)
+    };
+    let mut tagged = song("Help!", "Fab Four", "Help!", 1);
+    tagged.sort_tags.artist = Some("Beatles".into());
+    let mut app = App::new(vec![
+        song("Something", "The Beatles", "Abbey Road", 2),
+        song("Low", "Bowie", "Low", 1),
+        song("Come Together", "Beatles", "abbey road", 1),
+        tagged,
+    ]);
+    app.set_sort(
+        crate::config::SortPreset::Custom,
+        Vec::new(),
+        vec!["The".into()],
+    );
+    app.toggle_view(LibraryView::Browser);
+    assert_eq!(app.browse_indices(), vec![2, 0, 3, 1]);
+
+    app.set_selected(0);
+    let view = app.browse_view();
+    let labels = |groups: &[BrowseGroup]| -> Vec<String> {
+        groups.iter().map(|g| g.label.clone()).collect()
+    };
+    // One artist and one album despite the differing spellings.
+    assert_eq!(labels(&view.artists), vec!["Beatles", "Bowie"]);
+    assert_eq!(labels(&view.albums), vec!["abbey road", "Help!"]);
+    assert_eq!(view.tracks(), &[2, 0]);
+
+    // Queueing the artist takes every spelling.
+    assert_eq!(app.browse_activate(), Some(2));
+    assert_eq!(app.display_indices(), vec![2, 0, 3]);
+}
+
+#[test]
+fn browser_lists_a_track_under_each_of_its_artists() {
+    let song = |title: &str, artists: &[&str], album: &str| Track {
+        artists: artists.iter().map(|&a| a.into()).collect(),
+        album: Some(album.into()),
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        song("Duet", &["Alpha", "Beta"], "Both"),
+        song("Solo", &["Beta"], "Own"),
+    ]);
+    app.toggle_view(LibraryView::Browser);
+    app.set_selected(0);
+    let labels = |groups: &[BrowseGroup]| -> Vec<String> {
+        groups.iter().map(|g| g.label.clone()).collect()
+    };
+    let view = app.browse_view();
+    assert_eq!(labels(&view.artists), vec!["Alpha", "Beta"]);
+    assert_eq!(view.artist, 0);
+
+    // The duet is Beta's too, and the cursor stays under Beta on it.
+    app.browse_step(true);
+    assert_eq!(app.selected, 0);
+    let view = app.browse_view();
+    assert_eq!(view.artist, 1);
+    assert_eq!(labels(&view.albums), vec!["Both", "Own"]);
+
+    // Queueing Beta takes the duet as well.
+    assert_eq!(app.browse_activate(), Some(0));
+    assert_eq!(app.display_indices(), vec![0, 1]);
+}
+
+#[test]
+fn folder_view_expands_folders_and_queues_a_folder_in_path_order() {
+    use crate::config::{SortField, SortKey};
+
+    let file = |path: &str, secs: u64| Track {
+        path: std::path::PathBuf::from(path),
+        duration: Some(std::time::Duration::from_secs(secs)),
+        ..t(path)
+    };
+    let mut app = App::new(vec![
+        file("/m/B/Single/1.mp3", 60),
+        file("/m/A/X/2.mp3", 100),
+        file("/m/loose.mp3", 30),
+        file("/m/A/Y/1.mp3", 200),
+        file("/m/A/X/1.mp3", 50),
+    ]);
+    app.toggle_view(LibraryView::Folders);
+    assert_eq!(
+        app.sort_spec().keys,
+        vec![SortKey::ascending(SortField::Path)]
+    );
+
+    let names = |app: &App| -> Vec<String> {
+        app.folder_rows()
+            .iter()
+            .map(|row| match &row.kind {
+                FolderRowKind::Folder {
+                    name,
+                    tracks,
+                    duration,
+                    ..
+                } => format!(
+                    "{}{}/ {} {}s",
+                    "-".repeat(row.depth),
+                    name,
+                    tracks,
+                    duration.as_secs()
+                ),
+                FolderRowKind::Track(i) => format!("{}{}", "-".repeat(row.depth), i),
+            })
+            .collect()
+    };
+    // Folders holding a single subfolder collapse into one row.
+    assert_eq!(names(&app), vec!["A/ 3 350s", "B/Single/ 1 60s", "2"]);
+
+    app.set_selected(4);
+    // The selected track is hidden, so the cursor sits on its folder.
+    assert_eq!(app.folder_cursor_row(&app.folder_rows()), 0);
+    app.folder_expand();
+    app.folder_step(true);
+    app.folder_expand();
+    assert_eq!(
+        names(&app),
+        vec![
+            "A/ 3 350s",
+            "-X/ 2 150s",
+            "--4",
+            "--1",
+            "-Y/ 1 200s",
+            "B/Single/ 1 60s",
+            "2"
+        ]
+    );
+
+    // Enter on a folder queues everything below it in path order.
+    app.folder_collapse();
+    app.folder_collapse();
+    assert_eq!(app.folder_cursor_row(&app.folder_rows()), 0);
+    assert_eq!(app.folder_activate(), Some(4));
+    assert_eq!(app.display_indices(), vec![4, 1, 3]);
+
+    app.toggle_view(LibraryView::Folders);
+    assert_eq!(app.view, LibraryView::List);
+    assert_eq!(app.display_indices().len(), 5);
+}
+
+#[test]
+fn tag_editor_edits_the_focused_album_and_only_writes_touched_fields() {
+    use crate::library::TagField;
+
+    let song = |title: &str, album: &str, number: u32| Track {
+        artists: vec!["Coltrane".into()],
+        album: Some(album.into()),
+        track_number: Some(number),
+        ..t(title)
+    };
+    let mut app = App::new(vec![
+        song("Blue Train", "Blue Trian", 1),
+        song("Locomotion", "Blue Trian", 2),
+        song("Giant Steps", "Giant Steps", 1),
+    ]);
+    app.toggle_view(LibraryView::Browser);
+    app.browse_deeper();
+    assert!(app.open_tag_editor(&crate::config::LibrarySettings::default()));
+    let editor = app.tag_editor.as_mut().unwrap();
+    assert_eq!(editor.targets, vec![0, 1]);
+
+    let title = &editor.fields[0];
+    assert_eq!(
+        (title.field, title.original.as_deref()),
+        (TagField::Title, None)
+    );
+    // Retyping a shared value unchanged is not a change.
+    editor.cursor = 1;
+    editor.pop();
+    editor.push('e');
+    editor.step(true);
+    editor.clear();
+    "Blue Train".chars().for_each(|c| editor.push(c));
+    assert_eq!(
+        editor.changes(),
+        vec![(TagField::Album, "Blue Train".to_string())]
+    );
+    assert_eq!(
+        editor.diff(),
+        vec!["Album: Blue Trian -> Blue Train".to_string()]
+    );
+}
+
+#[test]
+fn dupes_view_keeps_the_chosen_copy_and_drops_settled_groups() {
+    use crate::library::DupeGroup;
+    let group = |names: &[&str]| DupeGroup {
+        copies: names.iter().map(|n| t(n)).collect(),
+    };
+    let mut view = DupesView::new(
+        vec![group(&["a1", "a2"]), group(&["b1", "b2", "b3"])],
+        false,
+    );
+    view.step(3);
+    assert_eq!(view.position(), (1, 1));
+    view.keep_selected();
+    let extras: Vec<&str> = view.extras(1).iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(extras, ["b1", "b3"]);
+
+    view.step(10);
+    assert_eq!(view.position(), (1, 2));
+    view.remove_group(0);
+    assert_eq!(view.position(), (0, 0));
+    assert_eq!(view.keep, vec![1]);
+}
@@ -3,7 +3,11 @@
 //! This module re-exports the `AudioPlayer` and audio-related types used
 //! by the rest of the application.
 
+mod backend;
+mod engine;
+mod envelope;
 mod player;
+mod preload;
 mod queue;
 mod sink;
 mod thread;
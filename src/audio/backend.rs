@@ -0,0 +1,111 @@
+//! Output abstraction used by the audio engine.
+//!
+//! `SinkBackend` hides how tracks are opened and played so the engine's queue,
+//! loop, shuffle, crossfade and seek logic can run against a fake in tests.
+
+use std::time::Duration;
+
+use rodio::{OutputStream, Sink};
+
+use crate::library::Track;
+
+use super::preload::Preloader;
+use super::sink::{DspControls, create_sink_at, sink_from_source};
+
+/// Playback controls the engine needs from a single opened track.
+pub(super) trait PlaybackSink {
+    fn play(&self);
+    fn pause(&self);
+    fn stop(&self);
+    fn set_volume(&self, volume: f32);
+    /// Return true once all queued audio has been played.
+    fn empty(&self) -> bool;
+}
+
+/// Factory for paused sinks, plus an optional hint about the next track.
+pub(super) trait SinkBackend {
+    type Sink: PlaybackSink;
+
+    /// Open library track `index` as a paused sink positioned at `start_at`;
+    /// fails with `path: reason` when the file cannot be read or decoded.
+    fn open(
+        &mut self,
+        index: usize,
+        track: &Track,
+        start_at: Duration,
+    ) -> Result<Self::Sink, String>;
+
+    /// Hint which track is expected to play next so it can be warmed up.
+    fn prepare(&mut self, _next: Option<usize>, _tracks: &[Track]) {}
+
+    /// Switch the night-mode compressor on every current and future sink.
+    fn set_night_mode(&mut self, _on: bool) {}
+
+    /// Switch karaoke vocal removal and set its amount (`0.0..=1.0`).
+    fn set_karaoke(&mut self, _on: bool, _amount: f32) {}
+}
+
+impl PlaybackSink for Sink {
+    fn play(&self) {
+        Sink::play(self);
+    }
+
+    fn pause(&self) {
+        Sink::pause(self);
+    }
+
+    fn stop(&self) {
+        Sink::stop(self);
+    }
+
+    fn set_volume(&self, volume: f32) {
+        Sink::set_volume(self, volume);
+    }
+
+    fn empty(&self) -> bool {
+        Sink::empty(self)
+    }
+}
+
+/// Real output through the default `rodio` device with decode-ahead buffering.
+pub(super) struct RodioBackend {
+    stream: OutputStream,
+    preloader: Preloader,
+    dsp: DspControls,
+}
+
+impl RodioBackend {
+    pub(super) fn new(stream: OutputStream, preload_buffer_kb: u64, dsp: DspControls) -> Self {
+        Self {
+            stream,
+            preloader: Preloader::new(preload_buffer_kb),
+            dsp,
+        }
+    }
+}
+
+impl SinkBackend for RodioBackend {
+    type Sink = Sink;
+
+    fn open(&mut self, index: usize, track: &Track, start_at: Duration) -> Result<Sink, String> {
+        // Prefer the already-warm source when the prediction was right.
+        if start_at.is_zero()
+            && let Some(source) = self.preloader.take(index)
+        {
+            return Ok(sink_from_source(&self.stream, source, start_at, &self.dsp));
+        }
+        create_sink_at(&self.stream, track, start_at, &self.dsp)
+    }
+
+    fn prepare(&mut self, next: Option<usize>, tracks: &[Track]) {
+        self.preloader.schedule(next, tracks);
+    }
+
+    fn set_night_mode(&mut self, on: bool) {
+        self.dsp.set_night_mode(on);
+    }
+
+    fn set_karaoke(&mut self, on: bool, amount: f32) {
+        self.dsp.set_karaoke(on, amount);
+    }
+}
@@ -0,0 +1,790 @@
+//! Audio engine: the playback state machine behind the audio thread.
+//!
+//! `AudioEngine` owns the queue, shuffle order, loop mode and the active sink.
+//! The audio thread feeds it commands through `handle` and the wall clock
+//! through `tick`; everything time-based (elapsed, fades, auto-advance) is
+//! derived from the last tick so the engine can be driven deterministically.
+
+use std::time::{Duration, Instant};
+
+use rand::RngExt;
+use rand::prelude::ThreadRng;
+use rand::seq::SliceRandom;
+
+use crate::config::AudioSettings;
+use crate::library::{DisplayOrder, LibraryChange, SortSpec, Track, apply_changes};
+
+use super::backend::{PlaybackSink, SinkBackend};
+use super::envelope::Envelope;
+use super::queue::{album_ends_between, auto_advance_target, reorder_queue_in_place};
+use super::types::{AudioCmd, LoopMode, OrderHandle, PlaybackHandle};
+
+/// How often the thread polls for auto-advance when no fade is running.
+const IDLE_POLL: Duration = Duration::from_millis(200);
+/// Number of volume updates used for the quit fade-out.
+const QUIT_FADE_STEPS: u64 = 20;
+
+#[inline]
+fn clamp_volume(v: f32) -> f32 {
+    v.clamp(0.0, 1.0)
+}
+
+/// A sink on its way out, faded with absolute volumes until it is stopped.
+struct FadingSink<S> {
+    sink: S,
+    envelope: Envelope,
+}
+
+/// Playback state machine, generic over the output backend.
+pub(super) struct AudioEngine<B: SinkBackend> {
+    backend: B,
+    tracks: Vec<Track>,
+    /// Unshuffled play order, kept sorted as tracks are appended.
+    library_order: DisplayOrder,
+    settings: AudioSettings,
+    playback_info: PlaybackHandle,
+    order_handle: OrderHandle,
+
+    /// Engine clock, advanced by `tick`.
+    now: Instant,
+
+    sink: Option<B::Sink>,
+    index: Option<usize>,
+    /// The track `sink` plays when it is not part of the current library.
+    detached: Option<Track>,
+    paused: bool,
+    // Track start time and accumulated elapsed when paused.
+    started_at: Option<Instant>,
+    accumulated: Duration,
+
+    shuffle: bool,
+    order: Vec<usize>,
+    // Current playback queue (usually the visible list: filtered/unfiltered + shuffle order).
+    queue: Vec<usize>,
+    queue_pos: usize,
+
+    loop_mode: LoopMode,
+    volume: f32,
+    stop_after_current: bool,
+    stop_after_album: bool,
+    night_mode: bool,
+    karaoke: bool,
+    karaoke_amount_percent: u8,
+
+    /// Settled gain multiplier applied on top of `volume` for the active sink.
+    gain: f32,
+    /// Ramp of that multiplier (fade-in, crossfade-in or soft pause).
+    ramp: Option<Envelope>,
+    /// The active sink pauses for real once `ramp` has faded it out.
+    pause_pending: bool,
+    /// Outgoing sinks (crossfade or quit) that are stopped once silent.
+    fading: Vec<FadingSink<B::Sink>>,
+    quitting: bool,
+}
+
+impl<B: SinkBackend> AudioEngine<B> {
+    /// Create an engine for `tracks` whose clock starts at `now`.
+    pub(super) fn new(
+        backend: B,
+        tracks: Vec<Track>,
+        playback_info: PlaybackHandle,
+        order_handle: OrderHandle,
+        settings: AudioSettings,
+        now: Instant,
+    ) -> Self {
+        let volume = clamp_volume(settings.initial_volume_percent as f32 / 100.0);
+        let night_mode = settings.night_mode;
+        let karaoke = settings.karaoke;
+        let karaoke_amount_percent = settings.karaoke_amount_percent.min(100);
+        if let Ok(mut info) = playback_info.lock() {
+            info.night_mode = night_mode;
+            info.karaoke = karaoke;
+            info.karaoke_amount_percent = karaoke_amount_percent;
+        }
+        let library_order = DisplayOrder::from_sorted(&tracks);
+        Self {
+            backend,
+            order: library_order.as_slice().to_vec(),
+            queue: library_order.as_slice().to_vec(),
+            library_order,
+            tracks,
+            settings,
+            playback_info,
+            order_handle,
+            now,
+            sink: None,
+            index: None,
+            detached: None,
+            paused: true,
+            started_at: None,
+            accumulated: Duration::ZERO,
+            shuffle: false,
+            queue_pos: 0,
+            loop_mode: LoopMode::default(),
+            volume,
+            stop_after_current: false,
+            stop_after_album: false,
+            night_mode,
+            karaoke,
+            karaoke_amount_percent,
+            gain: 1.0,
+            ramp: None,
+            pause_pending: false,
+            fading: Vec::new(),
+            quitting: false,
+        }
+    }
+
+    /// Return true once a quit was requested and its fade-out has finished.
+    pub(super) fn is_finished(&self) -> bool {
+        self.quitting && self.fading.is_empty()
+    }
+
+    /// Return how long the thread may wait for a command before ticking again.
+    pub(super) fn poll_interval(&self) -> Duration {
+        self.fading
+            .iter()
+            .map(|f| f.envelope.step())
+            .chain(self.ramp.map(|e| e.step()))
+            .min()
+            .unwrap_or(IDLE_POLL)
+            .min(IDLE_POLL)
+    }
+
+    /// Advance the engine clock: apply fades, auto-advance and publish elapsed time.
+    pub(super) fn tick(&mut self, now: Instant) {
+        self.now = now;
+        self.apply_fades();
+        if self.quitting {
+            return;
+        }
+
+        let ended = !self.paused && self.sink.as_ref().is_some_and(|s| s.empty());
+        if ended {
+            self.on_track_end();
+        }
+
+        if !self.paused && (self.index.is_some() || self.detached.is_some()) {
+            let elapsed = self.elapsed();
+            if let Ok(mut info) = self.playback_info.lock() {
+                info.elapsed = elapsed;
+            }
+        }
+        self.sync_preload();
+    }
+
+    /// Apply a single command at the current engine time.
+    pub(super) fn handle(&mut self, cmd: AudioCmd) {
+        if self.quitting {
+            return;
+        }
+
+        match cmd {
+            AudioCmd::SeekBy(secs) => self.seek_by(secs),
+            AudioCmd::Play(i) => {
+                // Ensure queue_pos points at the played index if present.
+                if let Some(pos) = self.queue.iter().position(|&x| x == i) {
+                    self.queue_pos = pos;
+                } else {
+                    self.queue = vec![i];
+                    self.queue_pos = 0;
+                }
+                self.play_index(i);
+            }
+            AudioCmd::Stop => self.stop(),
+            AudioCmd::TogglePause => self.toggle_pause(),
+            AudioCmd::SetVolume(v) => {
+                self.volume = clamp_volume(v);
+                self.apply_sink_volume();
+            }
+            AudioCmd::ToggleShuffle => self.toggle_shuffle(),
+            AudioCmd::SetQueue(queue) => self.set_queue(queue),
+            AudioCmd::AppendTracks(tracks) => self.append_tracks(tracks),
+            AudioCmd::ApplyLibraryChanges(changes) => self.apply_library_changes(changes),
+            AudioCmd::SetSortOrder(sort) => self.set_sort_order(&sort),
+            AudioCmd::ReplaceLibrary(tracks) => self.replace_library(tracks),
+            AudioCmd::SetLoopMode(m) => self.loop_mode = m,
+            AudioCmd::Next => self.step_manual(true),
+            AudioCmd::Prev => self.step_manual(false),
+            AudioCmd::ToggleStopAfterCurrent => {
+                self.stop_after_current = !self.stop_after_current;
+                self.publish_stop_flags();
+            }
+            AudioCmd::ToggleStopAfterAlbum => {
+                self.stop_after_album = !self.stop_after_album;
+                self.publish_stop_flags();
+            }
+            AudioCmd::ToggleNightMode => {
+                self.night_mode = !self.night_mode;
+                self.backend.set_night_mode(self.night_mode);
+                if let Ok(mut info) = self.playback_info.lock() {
+                    info.night_mode = self.night_mode;
+                }
+            }
+            AudioCmd::ToggleKaraoke => {
+                self.karaoke = !self.karaoke;
+                self.apply_karaoke();
+            }
+            AudioCmd::StepKaraokeAmount(delta) => {
+                let amount = (i32::from(self.karaoke_amount_percent) + delta).clamp(0, 100);
+                self.karaoke_amount_percent = amount as u8;
+                self.apply_karaoke();
+            }
+            AudioCmd::Quit { fade_out_ms } => self.quit(fade_out_ms),
+        }
+
+        self.sync_preload();
+    }
+
+    /// Elapsed playback time of the current track at the engine clock.
+    fn elapsed(&self) -> Duration {
+        self.accumulated
+            + self
+                .started_at
+                .map_or(Duration::ZERO, |st| self.now.saturating_duration_since(st))
+    }
+
+    /// Start playback of a specific index and update queue tracking.
+    ///
+    /// A track that cannot be opened is reported and skipped for the next
+    /// one in the queue, at most once around it.
+    fn play_index(&mut self, mut i: usize) {
+        let mut attempts = self.queue.len().max(1);
+        let new_sink = loop {
+            let Some(track) = self.tracks.get(i) else {
+                return;
+            };
+            match self.backend.open(i, track, Duration::ZERO) {
+                Ok(sink) => break sink,
+                Err(e) => {
+                    self.report_error(e);
+                    attempts -= 1;
+                    match self.skip_target(i) {
+                        Some(next) if attempts > 0 => i = next,
+                        _ => {
+                            self.stop();
+                            return;
+                        }
+                    }
+                }
+            }
+        };
+
+        // The outgoing sink may itself still be fading in from a previous switch.
+        let old_gain = self.volume * self.current_gain();
+        let audible = !self.paused && self.sink.is_some();
+        if let Some(old_sink) = self.sink.take() {
+            let crossfade = Duration::from_millis(self.settings.crossfade_ms);
+            if audible && !crossfade.is_zero() {
+                // Crossfade: ramp the old sink down while the new one ramps up.
+                // Audio keeps flowing in rodio's mixer thread.
+                let steps = self.settings.crossfade_steps;
+                self.fading.push(FadingSink {
+                    sink: old_sink,
+                    envelope: Envelope::new(self.now, crossfade, old_gain, 0.0, steps),
+                });
+                self.gain = 0.0;
+                self.ramp = Some(Envelope::new(self.now, crossfade, 0.0, 1.0, steps));
+            } else {
+                old_sink.stop();
+                self.gain = 1.0;
+                self.ramp = None;
+            }
+        } else {
+            self.gain = 1.0;
+            self.ramp = None;
+        }
+        if !audible {
+            // Starting from silence: optional fade-in.
+            self.gain = 0.0;
+            self.start_ramp(1.0, self.settings.resume_fade_ms);
+        }
+        self.pause_pending = false;
+
+        new_sink.play();
+        self.sink = Some(new_sink);
+        self.apply_sink_volume();
+        self.index = Some(i);
+        self.detached = None;
+        self.paused = false;
+        self.started_at = Some(self.now);
+        self.accumulated = Duration::ZERO;
+
+        if let Some(pos) = self.queue.iter().position(|&x| x == i) {
+            self.queue_pos = pos;
+        }
+
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.index = Some(i);
+            info.elapsed = Duration::ZERO;
+            info.playing = true;
+            info.detached = None;
+        }
+    }
+
+    /// Stop playback and reset shared playback state.
+    fn stop(&mut self) {
+        let fade = Duration::from_millis(self.settings.pause_fade_ms);
+        let from = self.volume * self.current_gain();
+        if let Some(s) = self.sink.take() {
+            if !self.paused && !fade.is_zero() {
+                // Soft stop: the sink fades out on its own; outgoing crossfades finish too.
+                self.fading.push(FadingSink {
+                    sink: s,
+                    envelope: Envelope::new(
+                        self.now,
+                        fade,
+                        from,
+                        0.0,
+                        self.settings.crossfade_steps,
+                    ),
+                });
+            } else {
+                s.stop();
+                self.finish_crossfade_out();
+            }
+        } else {
+            self.finish_crossfade_out();
+        }
+        self.gain = 1.0;
+        self.ramp = None;
+        self.pause_pending = false;
+        self.index = None;
+        self.detached = None;
+        self.paused = true;
+        self.started_at = None;
+        self.accumulated = Duration::ZERO;
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.index = None;
+            info.elapsed = Duration::ZERO;
+            info.playing = false;
+            info.detached = None;
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        if self.sink.is_none() {
+            return;
+        }
+
+        if self.paused {
+            if let Some(s) = self.sink.as_ref() {
+                s.play();
+            }
+            // A pending soft pause never stopped the clock.
+            if self.started_at.is_none() {
+                self.started_at = Some(self.now);
+            }
+            self.pause_pending = false;
+            self.start_ramp(1.0, self.settings.resume_fade_ms);
+            if let Ok(mut info) = self.playback_info.lock() {
+                info.playing = true;
+            }
+        } else {
+            self.finish_crossfade_out();
+            // Report the pause right away; the sink itself may keep fading out.
+            if let Ok(mut info) = self.playback_info.lock() {
+                info.playing = false;
+            }
+            if self.settings.pause_fade_ms == 0 {
+                self.pause_now();
+            } else {
+                self.pause_pending = true;
+                self.start_ramp(0.0, self.settings.pause_fade_ms);
+            }
+        }
+        self.paused = !self.paused;
+        self.apply_sink_volume();
+    }
+
+    /// Actually pause the active sink and freeze the elapsed clock.
+    fn pause_now(&mut self) {
+        if let Some(s) = self.sink.as_ref() {
+            s.pause();
+        }
+        self.accumulated = self.elapsed();
+        self.started_at = None;
+        self.pause_pending = false;
+        self.ramp = None;
+        // Resuming starts from silence so `resume_fade_ms` can ramp back up.
+        self.gain = 0.0;
+    }
+
+    /// Scrubbing: rebuild the current sink and skip into the file.
+    fn seek_by(&mut self, secs: i32) {
+        let Some(i) = self.index else {
+            return;
+        };
+        if self.sink.is_none() {
+            return;
+        }
+        self.finish_crossfade_out();
+        if self.pause_pending {
+            self.pause_now();
+        }
+
+        let cur = self.elapsed().as_secs() as i64;
+        let new = (cur + secs as i64).max(0) as u64;
+        let new_elapsed = Duration::from_secs(new);
+
+        // Stop old sink and replace with a fresh one.
+        if let Some(s) = self.sink.take() {
+            s.stop();
+        }
+
+        let new_sink = match self.backend.open(i, &self.tracks[i], new_elapsed) {
+            Ok(sink) => sink,
+            Err(e) => {
+                self.report_error(e);
+                self.stop();
+                return;
+            }
+        };
+        new_sink.set_volume(self.volume * self.current_gain());
+        if self.paused {
+            new_sink.pause();
+            self.started_at = None;
+        } else {
+            new_sink.play();
+            self.started_at = Some(self.now);
+        }
+
+        self.sink = Some(new_sink);
+        self.accumulated = new_elapsed;
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.elapsed = new_elapsed;
+        }
+    }
+
+    fn toggle_shuffle(&mut self) {
+        self.shuffle = !self.shuffle;
+        if self.shuffle {
+            self.order.shuffle(&mut ThreadRng::default());
+        } else {
+            self.order = self.library_order.as_slice().to_vec();
+        }
+        self.publish_order();
+        self.reorder_queue();
+    }
+
+    /// Re-sort the unshuffled order; a shuffled order is left alone.
+    fn set_sort_order(&mut self, sort: &SortSpec) {
+        self.library_order.set_sort(&self.tracks, sort);
+        if !self.shuffle {
+            self.order = self.library_order.as_slice().to_vec();
+            self.publish_order();
+            self.reorder_queue();
+        }
+    }
+
+    /// Keep the actual playback queue in sync with the play order.
+    ///
+    /// Queue membership is NOT changed here (that is controlled via SetQueue);
+    /// the existing queue is only reordered to match the current order.
+    fn reorder_queue(&mut self) {
+        if !self.queue.is_empty() {
+            reorder_queue_in_place(&mut self.queue, self.tracks.len(), &self.order);
+            self.queue_pos = self
+                .index
+                .and_then(|i| self.queue.iter().position(|&x| x == i))
+                .unwrap_or(0);
+        }
+    }
+
+    /// Update the shared order handle so the UI can read the current order.
+    fn publish_order(&self) {
+        if let Ok(mut oh) = self.order_handle.lock() {
+            *oh = self.order.clone();
+        }
+    }
+
+    /// Add tracks found by a background scan. Existing indices stay valid;
+    /// queue membership is still controlled by the UI through `SetQueue`.
+    fn append_tracks(&mut self, new_tracks: Vec<Track>) {
+        let start = self.tracks.len();
+        self.tracks.extend(new_tracks);
+        self.library_order.extend(&self.tracks);
+        self.extend_order(start);
+    }
+
+    /// Apply filesystem-watcher changes. The playing track is followed by
+    /// path across renames and compaction; if its file is gone, playback stops.
+    fn apply_library_changes(&mut self, changes: Vec<LibraryChange>) {
+        let remap = apply_changes(&mut self.tracks, changes);
+        let moved = |i: usize| remap.get(i).copied().flatten();
+        let survivors = remap.iter().flatten().count();
+
+        self.library_order.rebuild(&self.tracks);
+        self.order = self.order.iter().filter_map(|&i| moved(i)).collect();
+        self.queue = self.queue.iter().filter_map(|&i| moved(i)).collect();
+        // Any warm preload was keyed by the old numbering.
+        self.backend.prepare(None, &self.tracks);
+
+        if let Some(i) = self.index {
+            match moved(i) {
+                Some(j) => self.index = Some(j),
+                None => self.stop(),
+            }
+        }
+        // Only this thread remaps the shared index; the count tells the UI
+        // when it is in the new numbering.
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.index = self.index;
+            info.library_changes += 1;
+        }
+        self.queue_pos = self
+            .index
+            .and_then(|i| self.queue.iter().position(|&x| x == i))
+            .unwrap_or(0);
+        self.extend_order(survivors);
+    }
+
+    /// Swap in a new library. Queue and order start empty (the UI sends
+    /// them as the new scan streams in); the playing sink is kept, detached
+    /// from the list, so the current song is not interrupted.
+    fn replace_library(&mut self, tracks: Vec<Track>) {
+        if let Some(i) = self.index.take() {
+            self.detached = self.tracks.get(i).cloned();
+        }
+        self.tracks = tracks;
+        self.library_order.rebuild(&self.tracks);
+        self.order.clear();
+        self.queue.clear();
+        self.queue_pos = 0;
+        self.backend.prepare(None, &self.tracks);
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.index = None;
+            info.detached = self.detached.clone();
+        }
+        self.extend_order(0);
+    }
+
+    /// Bring `order` up to date after tracks from `start` on were added.
+    fn extend_order(&mut self, start: usize) {
+        if self.shuffle {
+            // Scatter the newcomers through the existing shuffle order.
+            let mut rng = ThreadRng::default();
+            for i in start..self.tracks.len() {
+                let at = rng.random_range(0..=self.order.len());
+                self.order.insert(at, i);
+            }
+        } else {
+            self.order = self.library_order.as_slice().to_vec();
+        }
+        self.publish_order();
+    }
+
+    fn set_queue(&mut self, mut new_queue: Vec<usize>) {
+        // If the caller sends an empty queue (e.g. filter has no matches),
+        // just store it; auto-advance/next/prev will become no-ops.
+        // Keep it sane by removing out-of-range indices.
+        new_queue.retain(|&i| i < self.tracks.len());
+
+        // IMPORTANT: always order the queue according to the audio thread's
+        // current shuffle order. This prevents a race where the UI computes
+        // display_indices() using a stale order_handle immediately after
+        // toggling shuffle and then overwrites the correct shuffled queue.
+        reorder_queue_in_place(&mut new_queue, self.tracks.len(), &self.order);
+
+        self.queue = new_queue;
+        self.queue_pos = self
+            .index
+            .and_then(|i| self.queue.iter().position(|&x| x == i))
+            .unwrap_or(0);
+    }
+
+    /// Manual next/prev: respects `LoopAll` wrap, but does not repeat-one.
+    fn step_manual(&mut self, forward: bool) {
+        if self.tracks.is_empty() || self.queue.is_empty() {
+            return;
+        }
+
+        let len = self.queue.len();
+        let cur_pos = if self.index.is_some() {
+            self.queue_pos.min(len - 1)
+        } else {
+            0
+        };
+        let at_edge = if forward {
+            cur_pos + 1 >= len
+        } else {
+            cur_pos == 0
+        };
+
+        let target_pos = match (at_edge, forward) {
+            (false, true) => cur_pos + 1,
+            (false, false) => cur_pos - 1,
+            (true, _) if self.loop_mode == LoopMode::LoopAll => {
+                if forward {
+                    0
+                } else {
+                    len - 1
+                }
+            }
+            // NoLoop/LoopOne at the edge: do nothing.
+            (true, _) => return,
+        };
+
+        self.queue_pos = target_pos;
+        self.play_index(self.queue[target_pos]);
+    }
+
+    /// Auto-advance when the current sink runs dry.
+    fn on_track_end(&mut self) {
+        let Some(i) = self.index else {
+            // A detached track has nothing to advance to.
+            if self.detached.is_some() {
+                self.stop();
+            }
+            return;
+        };
+        let next = auto_advance_target(&self.queue, self.queue_pos, self.loop_mode, i);
+
+        // Honor one-shot stop requests before picking the next track.
+        let album_done = self.stop_after_album && album_ends_between(&self.tracks, i, next);
+        if self.stop_after_current || album_done {
+            self.stop_after_current = false;
+            if album_done {
+                self.stop_after_album = false;
+            }
+            self.stop();
+            self.publish_stop_flags();
+            return;
+        }
+
+        match next {
+            Some(n) => self.play_index(n),
+            None => self.stop(),
+        }
+    }
+
+    /// Track after unplayable `i` in the queue, wrapping unless `NoLoop`.
+    fn skip_target(&self, i: usize) -> Option<usize> {
+        let pos = self.queue.iter().position(|&x| x == i)?;
+        match self.queue.get(pos + 1) {
+            Some(&next) => Some(next),
+            None if self.loop_mode != LoopMode::NoLoop => self.queue.first().copied(),
+            None => None,
+        }
+    }
+
+    /// Hand an open failure to the UI.
+    fn report_error(&self, error: String) {
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.error = Some(format!("Cannot play {}", error));
+        }
+    }
+
+    fn quit(&mut self, fade_out_ms: u64) {
+        self.quitting = true;
+        self.finish_crossfade_out();
+        let from = self.volume * self.current_gain();
+        self.ramp = None;
+
+        if let Some(s) = self.sink.take() {
+            if self.paused || fade_out_ms == 0 {
+                s.stop();
+            } else {
+                // Fade out gently before stopping.
+                self.fading.push(FadingSink {
+                    sink: s,
+                    envelope: Envelope::new(
+                        self.now,
+                        Duration::from_millis(fade_out_ms),
+                        from,
+                        0.0,
+                        QUIT_FADE_STEPS,
+                    ),
+                });
+            }
+        }
+
+        // Update shared state so UI/MPRIS don't keep showing Playing.
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.playing = false;
+        }
+    }
+
+    /// Re-evaluate every active envelope and retire the finished ones.
+    fn apply_fades(&mut self) {
+        let now = self.now;
+        self.fading.retain(|f| {
+            if f.envelope.is_done(now) {
+                f.sink.set_volume(0.0);
+                f.sink.stop();
+                false
+            } else {
+                f.sink.set_volume(f.envelope.gain(now));
+                true
+            }
+        });
+
+        if let Some(env) = self.ramp {
+            if env.is_done(now) {
+                self.ramp = None;
+                self.gain = env.target();
+                if self.pause_pending {
+                    self.pause_now();
+                }
+            }
+            self.apply_sink_volume();
+        }
+    }
+
+    /// Stop outgoing sinks immediately instead of letting them fade.
+    fn finish_crossfade_out(&mut self) {
+        for f in self.fading.drain(..) {
+            f.sink.stop();
+        }
+    }
+
+    /// Ramp the active sink's gain multiplier to `to` over `ms` (0 jumps there).
+    fn start_ramp(&mut self, to: f32, ms: u64) {
+        let from = self.current_gain();
+        let duration = Duration::from_millis(ms);
+        if duration.is_zero() {
+            self.gain = to;
+            self.ramp = None;
+        } else {
+            let steps = self.settings.crossfade_steps;
+            self.ramp = Some(Envelope::new(self.now, duration, from, to, steps));
+        }
+    }
+
+    /// Gain multiplier of the active sink at the engine clock.
+    fn current_gain(&self) -> f32 {
+        self.ramp.map_or(self.gain, |e| e.gain(self.now))
+    }
+
+    /// Set the active sink volume, including any ramp in progress.
+    fn apply_sink_volume(&self) {
+        if let Some(s) = self.sink.as_ref() {
+            s.set_volume(self.volume * self.current_gain());
+        }
+    }
+
+    fn publish_stop_flags(&self) {
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.stop_after_current = self.stop_after_current;
+            info.stop_after_album = self.stop_after_album;
+        }
+    }
+
+    /// Push the karaoke switch and amount to the backend and the UI.
+    fn apply_karaoke(&mut self) {
+        let amount = f32::from(self.karaoke_amount_percent) / 100.0;
+        self.backend.set_karaoke(self.karaoke, amount);
+        if let Ok(mut info) = self.playback_info.lock() {
+            info.karaoke = self.karaoke;
+            info.karaoke_amount_percent = self.karaoke_amount_percent;
+        }
+    }
+
+    /// Keep the backend's warm-up hint in line with the current prediction.
+    fn sync_preload(&mut self) {
+        let predicted = self
+            .index
+            .and_then(|i| auto_advance_target(&self.queue, self.queue_pos, self.loop_mode, i));
+        self.backend.prepare(predicted, &self.tracks);
+    }
+}
@@ -0,0 +1,56 @@
+//! Linear gain envelopes evaluated against the engine clock.
+//!
+//! Fades never block the audio thread: the engine records an envelope and
+//! re-applies its gain on every `tick`, so commands stay responsive mid-fade.
+
+use std::time::{Duration, Instant};
+
+/// A linear ramp from `from` to `to` over `duration`, starting at `start`.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Envelope {
+    start: Instant,
+    duration: Duration,
+    from: f32,
+    to: f32,
+    step: Duration,
+}
+
+impl Envelope {
+    /// Create a ramp that the engine should re-evaluate every `duration / steps`.
+    pub(super) fn new(start: Instant, duration: Duration, from: f32, to: f32, steps: u64) -> Self {
+        let steps = steps.max(1) as u32;
+        Self {
+            start,
+            duration,
+            from,
+            to,
+            step: (duration / steps).max(Duration::from_millis(1)),
+        }
+    }
+
+    /// Return the gain at `now`, clamped to the ramp end points.
+    pub(super) fn gain(&self, now: Instant) -> f32 {
+        if self.duration.is_zero() {
+            return self.to;
+        }
+        let t =
+            now.saturating_duration_since(self.start).as_secs_f32() / self.duration.as_secs_f32();
+        let t = t.clamp(0.0, 1.0);
+        self.from + (self.to - self.from) * t
+    }
+
+    /// Return true once the ramp has reached its end value.
+    pub(super) fn is_done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= self.duration
+    }
+
+    /// Return the gain the ramp settles at.
+    pub(super) fn target(&self) -> f32 {
+        self.to
+    }
+
+    /// Return how often the engine should tick while this ramp is active.
+    pub(super) fn step(&self) -> Duration {
+        self.step
+    }
+}
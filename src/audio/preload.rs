@@ -0,0 +1,99 @@
+//! Decode-ahead buffering for the predicted next track.
+//!
+//! The audio thread asks the `Preloader` to warm the track it expects to play
+//! next. Opening and probing happen on one background worker so a slow disk
+//! or network share does not stall the switch itself; a switch that comes
+//! before the worker is done opens the file as usual instead of waiting.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::library::Track;
+
+use super::sink::{TrackSource, open_track_source};
+
+/// Channels to the worker thread, which runs until they are dropped.
+struct Worker {
+    requests: Sender<(usize, Track)>,
+    results: Receiver<(usize, TrackSource)>,
+}
+
+impl Worker {
+    fn spawn(read_ahead_bytes: usize) -> Self {
+        let (requests, inbox) = mpsc::channel::<(usize, Track)>();
+        let (outbox, results) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(mut request) = inbox.recv() {
+                // Only the newest prediction is worth opening.
+                while let Ok(newer) = inbox.try_recv() {
+                    request = newer;
+                }
+                let (index, track) = request;
+                if let Ok(source) = open_track_source(&track, read_ahead_bytes)
+                    && outbox.send((index, source)).is_err()
+                {
+                    break;
+                }
+            }
+        });
+        Self { requests, results }
+    }
+}
+
+/// Owns the worker and the one prediction still wanted.
+pub(super) struct Preloader {
+    read_ahead_bytes: usize,
+    /// Started on the first prediction.
+    worker: Option<Worker>,
+    /// Library index of the current prediction.
+    pending: Option<usize>,
+}
+
+impl Preloader {
+    /// Create a preloader that buffers `read_ahead_kb` KiB per track (0 disables it).
+    pub(super) fn new(read_ahead_kb: u64) -> Self {
+        Self {
+            read_ahead_bytes: (read_ahead_kb.saturating_mul(1024)).min(usize::MAX as u64) as usize,
+            worker: None,
+            pending: None,
+        }
+    }
+
+    /// Start preloading `index`, discarding any preload for a different track.
+    pub(super) fn schedule(&mut self, index: Option<usize>, tracks: &[Track]) {
+        if self.read_ahead_bytes == 0 || self.pending == index {
+            return;
+        }
+        self.pending = None;
+        let read_ahead_bytes = self.read_ahead_bytes;
+        let worker = self
+            .worker
+            .get_or_insert_with(|| Worker::spawn(read_ahead_bytes));
+        // Sources for earlier predictions are dropped unused.
+        while worker.results.try_recv().is_ok() {}
+
+        let Some((index, track)) = index.and_then(|i| tracks.get(i).map(|track| (i, track))) else {
+            return;
+        };
+        if worker.requests.send((index, track.clone())).is_ok() {
+            self.pending = Some(index);
+        }
+    }
+
+    /// Take the warm source for `index` if the worker has finished it;
+    /// `None` when the prediction missed or is still opening.
+    pub(super) fn take(&mut self, index: usize) -> Option<TrackSource> {
+        if self.pending != Some(index) {
+            return None;
+        }
+        let worker = self.worker.as_ref()?;
+        let source = worker
+            .results
+            .try_iter()
+            .filter(|&(i, _)| i == index)
+            .last()
+            .map(|(_, source)| source)?;
+        self.pending = None;
+        Some(source)
+    }
+}
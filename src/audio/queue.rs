@@ -4,23 +4,18 @@
 //! kept consistent with the current shuffle `order`. These helpers
 //! reorder and sanitize the queue in place.
 
-/// Sanitize and reorder a queue according to current shuffle order.
+use crate::library::Track;
+
+use super::types::LoopMode;
+
+/// Sanitize and reorder a queue according to the current play order.
 ///
-/// Removes out-of-range indices and sorts by shuffle order when enabled,
-/// otherwise sorts ascending to keep a stable deterministic queue.
-pub(crate) fn reorder_queue_in_place(
-    queue: &mut Vec<usize>,
-    tracks_len: usize,
-    shuffle: bool,
-    order: &[usize],
-) {
+/// Removes out-of-range indices and sorts by position in `order`: the shuffle
+/// order when enabled, otherwise the library display order (which is not
+/// ascending once tracks have been appended during a background scan).
+pub(crate) fn reorder_queue_in_place(queue: &mut Vec<usize>, tracks_len: usize, order: &[usize]) {
     // Remove out-of-range indices first.
     queue.retain(|&i| i < tracks_len);
-    if !shuffle {
-        // Non-shuffle mode: keep a stable ascending order.
-        queue.sort_unstable();
-        return;
-    }
 
     // Build a position map for quick ordering lookups and sort accordingly.
     let mut pos_map = vec![usize::MAX; tracks_len];
@@ -31,3 +26,49 @@ pub(crate) fn reorder_queue_in_place(
     }
     queue.sort_by_key(|&ti| pos_map.get(ti).copied().unwrap_or(usize::MAX));
 }
+
+/// Return the track auto-advance would play after `current` finishes.
+///
+/// Mirrors the timeout-branch rules: `LoopOne` repeats, `LoopAll` wraps and
+/// `NoLoop` yields `None` at the end of the queue.
+pub(crate) fn auto_advance_target(
+    queue: &[usize],
+    queue_pos: usize,
+    loop_mode: LoopMode,
+    current: usize,
+) -> Option<usize> {
+    match loop_mode {
+        LoopMode::LoopOne => Some(current),
+        LoopMode::LoopAll => {
+            if queue.is_empty() {
+                None
+            } else {
+                Some(queue[(queue_pos + 1) % queue.len()])
+            }
+        }
+        LoopMode::NoLoop => queue.get(queue_pos + 1).copied(),
+    }
+}
+
+/// Return true when moving from `current` to `next` leaves the current album.
+///
+/// Tracks without album metadata are treated as single-track albums.
+pub(crate) fn album_ends_between(tracks: &[Track], current: usize, next: Option<usize>) -> bool {
+    let album_of = |i: usize| {
+        tracks
+            .get(i)
+            .and_then(|t| t.album.as_deref())
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+    };
+
+    let Some(current_album) = album_of(current) else {
+        return true;
+    };
+
+    match next {
+        Some(n) if n != current => album_of(n) != Some(current_album),
+        Some(_) => false,
+        None => true,
+    }
+}
@@ -1,28 +1,513 @@
 //! Utilities for creating `rodio` sinks from `Track` values.
 //!
-//! The helper here encapsulates opening/decoding a file and preparing a
-//! paused `Sink` at the requested start position.
+//! The helpers here encapsulate opening/decoding a file and preparing a
+//! paused `Sink` at the requested start position, wrapped in the DSP stages
+//! that can be switched live while a track plays.
 
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::time::Duration;
 
-use rodio::{Decoder, OutputStream, Sink, Source};
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Decoder, OutputStream, SampleRate, Sink, Source};
 
+use crate::config::AudioSettings;
 use crate::library::Track;
 
-/// Create a paused `Sink` for `track` that starts playback at `start_at`.
-pub(super) fn create_sink_at(handle: &OutputStream, track: &Track, start_at: Duration) -> Sink {
-    let file =
-        File::open(&track.path).unwrap_or_else(|_| panic!("failed to open {:?}", track.path));
+/// How long a DSP stage takes to blend in or out after being toggled.
+const DSP_TOGGLE_RAMP: Duration = Duration::from_millis(50);
+
+/// Decoded track source shared by the direct and preloaded playback paths.
+pub(super) type TrackSource = Decoder<PrefetchReader>;
+
+/// File reader that serves an in-memory head before falling back to disk.
+///
+/// Reading the first bytes up front warms slow or network storage so the
+/// decoder does not stall right at a track switch.
+pub(super) struct PrefetchReader {
+    head: Vec<u8>,
+    file: BufReader<File>,
+    pos: u64,
+    file_pos: u64,
+}
+
+impl PrefetchReader {
+    /// Open `path`, eagerly reading up to `read_ahead_bytes` into memory.
+    pub(super) fn open(path: &Path, read_ahead_bytes: usize) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut head = Vec::new();
+        (&mut file)
+            .take(read_ahead_bytes as u64)
+            .read_to_end(&mut head)?;
+        let file_pos = head.len() as u64;
+
+        Ok(Self {
+            head,
+            file: BufReader::new(file),
+            pos: 0,
+            file_pos,
+        })
+    }
+}
+
+impl Read for PrefetchReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(rest) = self.head.get(self.pos as usize..)
+            && !rest.is_empty()
+        {
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            self.pos += n as u64;
+            return Ok(n);
+        }
+
+        if self.file_pos != self.pos {
+            self.file.seek(SeekFrom::Start(self.pos))?;
+            self.file_pos = self.pos;
+        }
+        let n = self.file.read(buf)?;
+        self.pos += n as u64;
+        self.file_pos = self.pos;
+        Ok(n)
+    }
+}
+
+impl Seek for PrefetchReader {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        let new_pos = match target {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(_) => {
+                let p = self.file.seek(target)?;
+                self.file_pos = p;
+                Some(p)
+            }
+        };
+
+        let Some(new_pos) = new_pos else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before start of file",
+            ));
+        };
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
+}
+
+/// Night-mode compressor parameters, taken from `[audio]` settings.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct CompressorParams {
+    pub(super) threshold_db: f32,
+    pub(super) ratio: f32,
+    pub(super) attack: Duration,
+    pub(super) release: Duration,
+}
+
+impl CompressorParams {
+    pub(super) fn from_settings(settings: &AudioSettings) -> Self {
+        Self {
+            threshold_db: settings.night_mode_threshold_db,
+            ratio: settings.night_mode_ratio.max(1.0),
+            attack: Duration::from_millis(settings.night_mode_attack_ms),
+            release: Duration::from_millis(settings.night_mode_release_ms),
+        }
+    }
+
+    /// Gain (dB) to apply for a detector level of `level` (linear peak).
+    ///
+    /// Half of the reduction a full-scale peak would get is added back as
+    /// make-up gain, so quiet passages come up while loud ones go down.
+    fn gain_db(&self, level: f32) -> f32 {
+        let slope = 1.0 - 1.0 / self.ratio;
+        let makeup = (-self.threshold_db).max(0.0) * slope / 2.0;
+        let over = 20.0 * level.max(1e-5).log10() - self.threshold_db;
+        makeup - over.max(0.0) * slope
+    }
+}
+
+/// Live switches shared by the backend and every source it builds.
+///
+/// Flipping a flag takes effect on the playing sink at the next sample; the
+/// stage blends in over `DSP_TOGGLE_RAMP` instead of rebuilding anything.
+#[derive(Debug, Clone)]
+pub(super) struct DspControls {
+    night_mode: Arc<AtomicBool>,
+    compressor: CompressorParams,
+    karaoke: Arc<AtomicBool>,
+    /// Vocal-removal amount (0.0..=1.0) stored as `f32` bits.
+    karaoke_amount: Arc<AtomicU32>,
+    karaoke_band: KaraokeBand,
+}
+
+impl DspControls {
+    pub(super) fn new(settings: &AudioSettings) -> Self {
+        let amount = f32::from(settings.karaoke_amount_percent.min(100)) / 100.0;
+        Self {
+            night_mode: Arc::new(AtomicBool::new(settings.night_mode)),
+            compressor: CompressorParams::from_settings(settings),
+            karaoke: Arc::new(AtomicBool::new(settings.karaoke)),
+            karaoke_amount: Arc::new(AtomicU32::new(amount.to_bits())),
+            karaoke_band: KaraokeBand {
+                low_cut_hz: settings.karaoke_low_cut_hz,
+                high_cut_hz: settings.karaoke_high_cut_hz,
+            },
+        }
+    }
+
+    pub(super) fn set_night_mode(&self, on: bool) {
+        self.night_mode.store(on, Ordering::Relaxed);
+    }
+
+    /// Switch vocal removal and set its strength (`amount` in `0.0..=1.0`).
+    pub(super) fn set_karaoke(&self, on: bool, amount: f32) {
+        self.karaoke_amount
+            .store(amount.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+        self.karaoke.store(on, Ordering::Relaxed);
+    }
+
+    /// Wrap `source` in every DSP stage controlled by these switches.
+    pub(super) fn wrap<S: Source>(&self, source: S) -> Compressor<VocalRemover<S>> {
+        let source = VocalRemover::new(
+            source,
+            self.karaoke_band,
+            self.karaoke.clone(),
+            self.karaoke_amount.clone(),
+        );
+        Compressor::new(source, self.compressor, self.night_mode.clone())
+    }
+}
+
+/// Frequency band of the centre channel that karaoke mode removes.
+///
+/// A cut of 0 Hz leaves that side of the band open.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct KaraokeBand {
+    pub(super) low_cut_hz: u32,
+    pub(super) high_cut_hz: u32,
+}
+
+/// One-pole low-pass coefficient for `cutoff_hz` at `sample_rate`.
+fn one_pole(cutoff_hz: u32, sample_rate: SampleRate) -> Option<f32> {
+    (cutoff_hz > 0).then(|| {
+        let w = std::f32::consts::TAU * cutoff_hz as f32 / sample_rate.max(1) as f32;
+        1.0 - (-w).exp()
+    })
+}
+
+/// Centre-channel canceller for stereo sources.
+///
+/// Each frame is split into mid (L+R) and side (L−R); the mid signal inside
+/// the karaoke band is subtracted by the current amount, which is where
+/// lead vocals usually sit. Bass below the band survives. Mono and
+/// multichannel sources pass through untouched.
+pub(super) struct VocalRemover<S> {
+    inner: S,
+    band: KaraokeBand,
+    enabled: Arc<AtomicBool>,
+    amount: Arc<AtomicU32>,
+    /// Cached `(sample_rate, low coeff, high coeff, ramp step per frame)`.
+    coeffs: Option<(SampleRate, Option<f32>, Option<f32>, f32)>,
+    low_state: f32,
+    high_state: f32,
+    current: f32,
+    pending_right: Option<f32>,
+}
+
+impl<S: Source> VocalRemover<S> {
+    pub(super) fn new(
+        inner: S,
+        band: KaraokeBand,
+        enabled: Arc<AtomicBool>,
+        amount: Arc<AtomicU32>,
+    ) -> Self {
+        let current = if enabled.load(Ordering::Relaxed) {
+            f32::from_bits(amount.load(Ordering::Relaxed))
+        } else {
+            0.0
+        };
+        Self {
+            inner,
+            band,
+            enabled,
+            amount,
+            coeffs: None,
+            low_state: 0.0,
+            high_state: 0.0,
+            current,
+            pending_right: None,
+        }
+    }
+
+    fn coeffs(&mut self) -> (SampleRate, Option<f32>, Option<f32>, f32) {
+        let rate = self.inner.sample_rate();
+        match self.coeffs {
+            Some(c) if c.0 == rate => c,
+            _ => {
+                let step = 1.0 / (DSP_TOGGLE_RAMP.as_secs_f32() * rate.max(1) as f32);
+                let c = (
+                    rate,
+                    one_pole(self.band.low_cut_hz, rate),
+                    one_pole(self.band.high_cut_hz, rate),
+                    step,
+                );
+                self.coeffs = Some(c);
+                c
+            }
+        }
+    }
+}
+
+impl<S: Source> Iterator for VocalRemover<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+        if self.inner.channels() != 2 {
+            return self.inner.next();
+        }
+        let left = self.inner.next()?;
+        let Some(right) = self.inner.next() else {
+            return Some(left);
+        };
+        let (_, low, high, step) = self.coeffs();
+
+        // Filters keep running while off so switching on does not click.
+        let mid = (left + right) / 2.0;
+        let upper = match high {
+            Some(a) => {
+                self.high_state += a * (mid - self.high_state);
+                self.high_state
+            }
+            None => mid,
+        };
+        let lower = match low {
+            Some(a) => {
+                self.low_state += a * (mid - self.low_state);
+                self.low_state
+            }
+            None => 0.0,
+        };
 
-    let source = Decoder::new(BufReader::new(file))
-        .unwrap_or_else(|_| panic!("failed to decode {:?}", track.path))
-        // `skip_duration` is our seeking primitive; even Duration::ZERO is fine.
-        .skip_duration(start_at);
+        let target = if self.enabled.load(Ordering::Relaxed) {
+            f32::from_bits(self.amount.load(Ordering::Relaxed))
+        } else {
+            0.0
+        };
+        if self.current < target {
+            self.current = (self.current + step).min(target);
+        } else if self.current > target {
+            self.current = (self.current - step).max(target);
+        }
+        if self.current == 0.0 {
+            self.pending_right = Some(right);
+            return Some(left);
+        }
 
+        let cut = self.current * (upper - lower);
+        self.pending_right = Some(right - cut);
+        Some(left - cut)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let extra = usize::from(self.pending_right.is_some());
+        let (lower, upper) = self.inner.size_hint();
+        (lower + extra, upper.map(|u| u + extra))
+    }
+}
+
+impl<S: Source> Source for VocalRemover<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.pending_right = None;
+        self.inner.try_seek(pos)
+    }
+}
+
+/// Per-format smoothing coefficients, recomputed when the stream changes.
+#[derive(Debug, Clone, Copy)]
+struct CompressorCoeffs {
+    format: (ChannelCount, SampleRate),
+    attack: f32,
+    release: f32,
+    blend_step: f32,
+}
+
+impl CompressorCoeffs {
+    fn new(channels: ChannelCount, sample_rate: SampleRate, params: &CompressorParams) -> Self {
+        // Samples are interleaved, so time constants count every channel.
+        let per_sec = (sample_rate as f32 * channels.max(1) as f32).max(1.0);
+        let smoothing = |t: Duration| {
+            let n = t.as_secs_f32() * per_sec;
+            if n <= 0.0 { 0.0 } else { (-1.0 / n).exp() }
+        };
+        Self {
+            format: (channels, sample_rate),
+            attack: smoothing(params.attack),
+            release: smoothing(params.release),
+            blend_step: 1.0 / (DSP_TOGGLE_RAMP.as_secs_f32() * per_sec),
+        }
+    }
+}
+
+/// Feed-forward peak compressor with a hard ceiling, switched by a shared flag.
+///
+/// The detector is linked across channels (one envelope for the interleaved
+/// stream) so the stereo image does not wander. It keeps tracking while
+/// disabled so switching it on mid-track does not pump.
+pub(super) struct Compressor<S> {
+    inner: S,
+    params: CompressorParams,
+    enabled: Arc<AtomicBool>,
+    coeffs: Option<CompressorCoeffs>,
+    envelope: f32,
+    blend: f32,
+}
+
+impl<S: Source> Compressor<S> {
+    pub(super) fn new(inner: S, params: CompressorParams, enabled: Arc<AtomicBool>) -> Self {
+        let blend = if enabled.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            0.0
+        };
+        Self {
+            inner,
+            params,
+            enabled,
+            coeffs: None,
+            envelope: 0.0,
+            blend,
+        }
+    }
+
+    fn coeffs(&mut self) -> CompressorCoeffs {
+        let format = (self.inner.channels(), self.inner.sample_rate());
+        match self.coeffs {
+            Some(c) if c.format == format => c,
+            _ => {
+                let c = CompressorCoeffs::new(format.0, format.1, &self.params);
+                self.coeffs = Some(c);
+                c
+            }
+        }
+    }
+}
+
+impl<S: Source> Iterator for Compressor<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.inner.next()?;
+        let c = self.coeffs();
+
+        let level = x.abs();
+        let k = if level > self.envelope {
+            c.attack
+        } else {
+            c.release
+        };
+        self.envelope = k * self.envelope + (1.0 - k) * level;
+
+        let target = if self.enabled.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            0.0
+        };
+        if self.blend < target {
+            self.blend = (self.blend + c.blend_step).min(target);
+        } else if self.blend > target {
+            self.blend = (self.blend - c.blend_step).max(target);
+        }
+        if self.blend == 0.0 {
+            return Some(x);
+        }
+
+        let gain = 10f32.powf(self.params.gain_db(self.envelope) / 20.0);
+        let y = x * (1.0 + self.blend * (gain - 1.0));
+        Some(y.clamp(-1.0, 1.0))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S: Source> Source for Compressor<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.envelope = 0.0;
+        self.inner.try_seek(pos)
+    }
+}
+
+/// Open and decode `track`, reading up to `read_ahead_bytes` eagerly.
+/// Errors read `path: reason`.
+pub(super) fn open_track_source(
+    track: &Track,
+    read_ahead_bytes: usize,
+) -> Result<TrackSource, String> {
+    let failed = |e: &dyn std::fmt::Display| format!("{}: {}", track.path.display(), e);
+    let reader = PrefetchReader::open(&track.path, read_ahead_bytes).map_err(|e| failed(&e))?;
+    Decoder::new(reader).map_err(|e| failed(&e))
+}
+
+/// Wrap an already-decoded `source` in a paused `Sink` starting at `start_at`.
+pub(super) fn sink_from_source(
+    handle: &OutputStream,
+    source: TrackSource,
+    start_at: Duration,
+    dsp: &DspControls,
+) -> Sink {
     let sink = Sink::connect_new(handle.mixer());
-    sink.append(source);
+    // `skip_duration` is our seeking primitive; even Duration::ZERO is fine.
+    sink.append(dsp.wrap(source.skip_duration(start_at)));
     sink.pause();
     sink
 }
+
+/// Create a paused `Sink` for `track` that starts playback at `start_at`.
+pub(super) fn create_sink_at(
+    handle: &OutputStream,
+    track: &Track,
+    start_at: Duration,
+    dsp: &DspControls,
+) -> Result<Sink, String> {
+    let source = open_track_source(track, 0)?;
+    Ok(sink_from_source(handle, source, start_at, dsp))
+}
@@ -1,9 +1,44 @@
-use super::queue::reorder_queue_in_place;
+use super::preload::Preloader;
+use super::queue::{album_ends_between, auto_advance_target, reorder_queue_in_place};
+use super::sink::{Compressor, CompressorParams, KaraokeBand, PrefetchReader, VocalRemover};
+use super::types::LoopMode;
+use crate::library::Track;
+use rodio::buffer::SamplesBuffer;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+fn album_track(album: Option<&str>) -> Track {
+    Track {
+        path: PathBuf::new(),
+        title: String::new(),
+        album: album.map(str::to_string),
+        display: String::new(),
+        ..Track::default()
+    }
+}
+
+/// Night-mode defaults over a 1 kHz mono constant signal.
+fn compressor(level: f32, len: usize, on: &Arc<AtomicBool>) -> Compressor<SamplesBuffer> {
+    let params = CompressorParams {
+        threshold_db: -24.0,
+        ratio: 4.0,
+        attack: Duration::from_millis(10),
+        release: Duration::from_millis(250),
+    };
+    Compressor::new(
+        SamplesBuffer::new(1, 1000, vec![level; len]),
+        params,
+        on.clone(),
+    )
+}
 
 #[test]
 fn reorder_queue_unshuffled_sorts_and_filters() {
     let mut q = vec![5, 2, 999, 2, 0];
-    reorder_queue_in_place(&mut q, 6, false, &[]);
+    reorder_queue_in_place(&mut q, 6, &[0, 1, 2, 3, 4, 5]);
     assert_eq!(q, vec![0, 2, 2, 5]);
 }
 
@@ -12,6 +47,799 @@ fn reorder_queue_shuffled_follows_order_positions() {
     // order position: 3->0, 1->1, 0->2, 2->3
     let order = vec![3, 1, 0, 2];
     let mut q = vec![0, 3, 2];
-    reorder_queue_in_place(&mut q, 4, true, &order);
+    reorder_queue_in_place(&mut q, 4, &order);
     assert_eq!(q, vec![3, 0, 2]);
 }
+
+#[test]
+fn auto_advance_target_follows_loop_mode() {
+    let q = vec![4, 7, 9];
+    assert_eq!(auto_advance_target(&q, 1, LoopMode::NoLoop, 7), Some(9));
+    assert_eq!(auto_advance_target(&q, 2, LoopMode::NoLoop, 9), None);
+    assert_eq!(auto_advance_target(&q, 2, LoopMode::LoopAll, 9), Some(4));
+    assert_eq!(auto_advance_target(&q, 2, LoopMode::LoopOne, 9), Some(9));
+}
+
+#[test]
+fn album_ends_between_detects_album_changes() {
+    let tracks = vec![
+        album_track(Some("A")),
+        album_track(Some("A")),
+        album_track(Some("B")),
+        album_track(None),
+    ];
+    assert!(!album_ends_between(&tracks, 0, Some(1)));
+    assert!(album_ends_between(&tracks, 1, Some(2)));
+    assert!(album_ends_between(&tracks, 1, None));
+    assert!(!album_ends_between(&tracks, 1, Some(1)));
+    // Untagged tracks count as their own album.
+    assert!(album_ends_between(&tracks, 3, Some(3)));
+}
+
+#[test]
+fn prefetch_reader_serves_head_then_file_across_seeks() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    let data: Vec<u8> = (0u8..=99).collect();
+    std::fs::write(&path, &data).unwrap();
+
+    let mut reader = PrefetchReader::open(&path, 10).unwrap();
+    let mut all = Vec::new();
+    reader.read_to_end(&mut all).unwrap();
+    assert_eq!(all, data);
+
+    reader.seek(SeekFrom::Start(5)).unwrap();
+    let mut buf = [0u8; 10];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf.to_vec(), (5u8..15).collect::<Vec<u8>>());
+
+    assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 97);
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, vec![97, 98, 99]);
+
+    assert!(reader.seek(SeekFrom::Current(-1000)).is_err());
+}
+
+#[test]
+fn preloader_hands_out_only_the_current_prediction() {
+    let dir = tempfile::tempdir().unwrap();
+    let tracks: Vec<Track> = ["a.wav", "b.wav"]
+        .iter()
+        .map(|name| {
+            let path = dir.path().join(name);
+            crate::library::tests::write_wav(&path, 8000);
+            Track {
+                path,
+                ..Track::default()
+            }
+        })
+        .collect();
+    let mut preloader = Preloader::new(64);
+    preloader.schedule(Some(0), &tracks);
+    // The prediction changes (new queue, shuffle) before `a` is played.
+    preloader.schedule(Some(1), &tracks);
+    assert!(preloader.take(0).is_none());
+
+    // `take` never waits; the worker delivers `b` shortly.
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while preloader.take(1).is_none() {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "preload never arrived"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(preloader.take(1).is_none(), "a preload is taken once");
+}
+
+#[test]
+fn compressor_is_transparent_when_off() {
+    let off = Arc::new(AtomicBool::new(false));
+    let out: Vec<f32> = compressor(0.9, 200, &off).collect();
+    assert!(out.iter().all(|&x| x == 0.9));
+}
+
+#[test]
+fn compressor_tames_loud_and_lifts_quiet_passages() {
+    let on = Arc::new(AtomicBool::new(true));
+    let loud = compressor(0.9, 500, &on).last().unwrap();
+    assert!(loud < 0.45, "loud settles well below input: {loud}");
+
+    let quiet = compressor(0.01, 500, &on).last().unwrap();
+    assert!(quiet > 0.02, "quiet gets make-up gain: {quiet}");
+}
+
+#[test]
+fn compressor_toggles_live_with_a_short_blend() {
+    let on = Arc::new(AtomicBool::new(false));
+    let mut c = compressor(0.9, 1000, &on);
+    assert_eq!(c.nth(99), Some(0.9));
+
+    on.store(true, Ordering::Relaxed);
+    let first = c.next().unwrap();
+    assert!(first < 0.9 && first > 0.85, "no hard jump: {first}");
+    let settled = c.nth(99).unwrap();
+    assert!(settled < 0.45);
+
+    on.store(false, Ordering::Relaxed);
+    assert_eq!(c.nth(99), Some(0.9));
+}
+
+/// Karaoke at full strength over interleaved 1 kHz stereo `frames`.
+fn vocal_remover(frames: &[(f32, f32)], band: KaraokeBand) -> VocalRemover<SamplesBuffer> {
+    let data: Vec<f32> = frames.iter().flat_map(|&(l, r)| [l, r]).collect();
+    VocalRemover::new(
+        SamplesBuffer::new(2, 1000, data),
+        band,
+        Arc::new(AtomicBool::new(true)),
+        Arc::new(AtomicU32::new(1.0f32.to_bits())),
+    )
+}
+
+#[test]
+fn vocal_remover_cancels_centre_and_keeps_sides() {
+    let open = KaraokeBand {
+        low_cut_hz: 0,
+        high_cut_hz: 0,
+    };
+    let out: Vec<f32> = vocal_remover(&[(0.5, 0.5), (0.5, -0.5), (0.3, 0.1)], open).collect();
+    let expected = [0.0, 0.0, 0.5, -0.5, 0.1, -0.1];
+    assert_eq!(out.len(), expected.len());
+    for (got, want) in out.iter().zip(expected) {
+        assert!((got - want).abs() < 1e-6, "{out:?}");
+    }
+}
+
+#[test]
+fn vocal_remover_band_limit_keeps_bass() {
+    let band = KaraokeBand {
+        low_cut_hz: 150,
+        high_cut_hz: 400,
+    };
+    // A steady centred offset is as low as bass gets; it must survive.
+    let last = vocal_remover(&[(0.5, 0.5); 500], band).last().unwrap();
+    assert!((last - 0.5).abs() < 1e-3, "{last}");
+}
+
+#[test]
+fn vocal_remover_leaves_mono_untouched() {
+    let mono = SamplesBuffer::new(1, 1000, vec![0.25; 10]);
+    let out: Vec<f32> = VocalRemover::new(
+        mono,
+        KaraokeBand {
+            low_cut_hz: 0,
+            high_cut_hz: 0,
+        },
+        Arc::new(AtomicBool::new(true)),
+        Arc::new(AtomicU32::new(1.0f32.to_bits())),
+    )
+    .collect();
+    assert_eq!(out, vec![0.25; 10]);
+}
+
+mod engine {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::album_track;
+    use crate::audio::backend::{PlaybackSink, SinkBackend};
+    use crate::audio::engine::AudioEngine;
+    use crate::audio::sink::open_track_source;
+    use crate::audio::types::{AudioCmd, LoopMode, OrderHandle, PlaybackHandle, PlaybackInfo};
+    use crate::config::AudioSettings;
+    use crate::library::Track;
+
+    #[derive(Debug, Default)]
+    struct FakeSinkState {
+        playing: bool,
+        stopped: bool,
+        volume: f32,
+        empty: bool,
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeSink(Rc<RefCell<FakeSinkState>>);
+
+    impl FakeSink {
+        fn finish(&self) {
+            self.0.borrow_mut().empty = true;
+        }
+        fn volume(&self) -> f32 {
+            self.0.borrow().volume
+        }
+        fn is_playing(&self) -> bool {
+            let s = self.0.borrow();
+            s.playing && !s.stopped
+        }
+        fn is_stopped(&self) -> bool {
+            self.0.borrow().stopped
+        }
+    }
+
+    impl PlaybackSink for FakeSink {
+        fn play(&self) {
+            self.0.borrow_mut().playing = true;
+        }
+        fn pause(&self) {
+            self.0.borrow_mut().playing = false;
+        }
+        fn stop(&self) {
+            self.0.borrow_mut().stopped = true;
+        }
+        fn set_volume(&self, volume: f32) {
+            self.0.borrow_mut().volume = volume;
+        }
+        fn empty(&self) -> bool {
+            let s = self.0.borrow();
+            s.empty || s.stopped
+        }
+    }
+
+    /// Records every sink it opens as `(track index, start offset, sink)`.
+    /// Files that exist are decoded for real first, so corrupt ones fail.
+    #[derive(Clone, Default)]
+    struct FakeBackend {
+        opened: Rc<RefCell<Vec<(usize, Duration, FakeSink)>>>,
+        /// Every `prepare` hint, in order.
+        prepared: Rc<RefCell<Vec<Option<usize>>>>,
+    }
+
+    impl SinkBackend for FakeBackend {
+        type Sink = FakeSink;
+
+        fn open(
+            &mut self,
+            index: usize,
+            track: &Track,
+            start_at: Duration,
+        ) -> Result<FakeSink, String> {
+            if track.path.is_file() {
+                open_track_source(track, 0)?;
+            }
+            let sink = FakeSink::default();
+            self.opened
+                .borrow_mut()
+                .push((index, start_at, sink.clone()));
+            Ok(sink)
+        }
+
+        fn prepare(&mut self, next: Option<usize>, _tracks: &[Track]) {
+            self.prepared.borrow_mut().push(next);
+        }
+    }
+
+    struct Harness {
+        engine: AudioEngine<FakeBackend>,
+        backend: FakeBackend,
+        info: PlaybackHandle,
+        order: OrderHandle,
+        now: Instant,
+    }
+
+    impl Harness {
+        fn new(tracks: Vec<Track>, crossfade_ms: u64) -> Self {
+            Self::with_settings(
+                tracks,
+                AudioSettings {
+                    crossfade_ms,
+                    ..Self::settings()
+                },
+            )
+        }
+
+        /// Test defaults: full volume, coarse fade steps and no fades.
+        fn settings() -> AudioSettings {
+            AudioSettings {
+                crossfade_ms: 0,
+                crossfade_steps: 4,
+                initial_volume_percent: 100,
+                ..AudioSettings::default()
+            }
+        }
+
+        fn with_settings(tracks: Vec<Track>, settings: AudioSettings) -> Self {
+            let backend = FakeBackend::default();
+            let info: PlaybackHandle = Arc::new(Mutex::new(PlaybackInfo::default()));
+            let order: OrderHandle = Arc::new(Mutex::new((0..tracks.len()).collect()));
+            let now = Instant::now();
+            let engine = AudioEngine::new(
+                backend.clone(),
+                tracks,
+                info.clone(),
+                order.clone(),
+                settings,
+                now,
+            );
+            Self {
+                engine,
+                backend,
+                info,
+                order,
+                now,
+            }
+        }
+
+        fn with_len(n: usize) -> Self {
+            Self::new((0..n).map(|_| album_track(None)).collect(), 0)
+        }
+
+        fn advance(&mut self, by: Duration) {
+            self.now += by;
+            self.engine.tick(self.now);
+        }
+
+        fn send(&mut self, cmd: AudioCmd) {
+            self.engine.handle(cmd);
+        }
+
+        fn last_sink(&self) -> FakeSink {
+            self.backend.opened.borrow().last().unwrap().2.clone()
+        }
+
+        fn opened_indices(&self) -> Vec<usize> {
+            self.backend.opened.borrow().iter().map(|o| o.0).collect()
+        }
+
+        fn playing_index(&self) -> Option<usize> {
+            self.info.lock().unwrap().index
+        }
+
+        /// Let the current sink run dry and tick once so auto-advance runs.
+        fn finish_current(&mut self) {
+            self.last_sink().finish();
+            self.advance(Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn manual_next_prev_wrap_only_with_loop_all() {
+        let mut h = Harness::with_len(3);
+        h.send(AudioCmd::Play(2));
+        h.send(AudioCmd::Next);
+        assert_eq!(h.playing_index(), Some(0));
+        h.send(AudioCmd::Prev);
+        assert_eq!(h.playing_index(), Some(2));
+
+        h.send(AudioCmd::SetLoopMode(LoopMode::NoLoop));
+        h.send(AudioCmd::Next);
+        assert_eq!(h.playing_index(), Some(2));
+        assert_eq!(h.opened_indices(), vec![2, 0, 2]);
+    }
+
+    #[test]
+    fn auto_advance_follows_each_loop_mode() {
+        let mut h = Harness::with_len(2);
+        h.send(AudioCmd::Play(0));
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(1));
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(0), "LoopAll wraps");
+
+        h.send(AudioCmd::SetLoopMode(LoopMode::LoopOne));
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(0), "LoopOne repeats");
+
+        h.send(AudioCmd::SetLoopMode(LoopMode::NoLoop));
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(1));
+        h.finish_current();
+        assert_eq!(h.playing_index(), None, "NoLoop stops at the end");
+        assert!(!h.info.lock().unwrap().playing);
+    }
+
+    #[test]
+    fn unplayable_tracks_are_reported_and_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let corrupt = dir.path().join("corrupt.flac");
+        std::fs::write(&corrupt, b"fLaC but not really").unwrap();
+        let broken = Track {
+            path: corrupt,
+            ..album_track(None)
+        };
+        let mut h = Harness::new(vec![broken.clone(), album_track(None), broken], 0);
+        h.send(AudioCmd::Play(0));
+        assert_eq!(h.playing_index(), Some(1));
+        let error = h.info.lock().unwrap().error.take().unwrap();
+        assert!(error.contains("corrupt.flac"), "{error}");
+
+        // Nothing playable left after it: playback stops.
+        h.send(AudioCmd::SetLoopMode(LoopMode::NoLoop));
+        h.finish_current();
+        assert_eq!(h.playing_index(), None);
+        assert!(h.info.lock().unwrap().error.is_some());
+        assert_eq!(h.opened_indices(), vec![1]);
+    }
+
+    #[test]
+    fn preload_prediction_follows_queue_and_shuffle_changes() {
+        let mut h = Harness::with_len(4);
+        let predicted = |h: &Harness| *h.backend.prepared.borrow().last().unwrap();
+        h.send(AudioCmd::Play(0));
+        assert_eq!(predicted(&h), Some(1));
+
+        // A new queue replaces the stale prediction.
+        h.send(AudioCmd::SetQueue(vec![0, 2, 3]));
+        assert_eq!(predicted(&h), Some(2));
+
+        // So does shuffling, which predicts from the shuffled queue.
+        h.send(AudioCmd::ToggleShuffle);
+        let order = h.order.lock().unwrap().clone();
+        let queue: Vec<usize> = order
+            .into_iter()
+            .filter(|i| [0, 2, 3].contains(i))
+            .collect();
+        let pos = queue.iter().position(|&i| i == 0).unwrap();
+        assert_eq!(predicted(&h), Some(queue[(pos + 1) % queue.len()]));
+    }
+
+    #[test]
+    fn auto_advance_walks_the_filtered_queue() {
+        let mut h = Harness::with_len(5);
+        h.send(AudioCmd::SetQueue(vec![4, 1, 3]));
+        h.send(AudioCmd::Play(1));
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(3));
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(4));
+    }
+
+    #[test]
+    fn stop_after_current_fires_once_and_clears() {
+        let mut h = Harness::with_len(3);
+        h.send(AudioCmd::Play(0));
+        h.send(AudioCmd::ToggleStopAfterCurrent);
+        assert!(h.info.lock().unwrap().stop_after_current);
+
+        h.finish_current();
+        assert_eq!(h.playing_index(), None);
+        assert!(!h.info.lock().unwrap().stop_after_current);
+
+        h.send(AudioCmd::Play(0));
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(1));
+    }
+
+    #[test]
+    fn stop_after_album_waits_for_album_boundary() {
+        let tracks = vec![
+            album_track(Some("A")),
+            album_track(Some("A")),
+            album_track(Some("B")),
+        ];
+        let mut h = Harness::new(tracks, 0);
+        h.send(AudioCmd::Play(0));
+        h.send(AudioCmd::ToggleStopAfterAlbum);
+
+        h.finish_current();
+        assert_eq!(h.playing_index(), Some(1));
+        h.finish_current();
+        assert_eq!(h.playing_index(), None);
+        assert!(!h.info.lock().unwrap().stop_after_album);
+    }
+
+    #[test]
+    fn shuffle_reorders_queue_to_match_shared_order() {
+        let mut h = Harness::with_len(6);
+        h.send(AudioCmd::ToggleShuffle);
+        let order = h.order.lock().unwrap().clone();
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..6).collect::<Vec<_>>());
+
+        // Next from a stopped engine walks the shuffled order.
+        h.send(AudioCmd::Play(order[0]));
+        h.send(AudioCmd::Next);
+        assert_eq!(h.playing_index(), Some(order[1]));
+
+        h.send(AudioCmd::ToggleShuffle);
+        assert_eq!(*h.order.lock().unwrap(), (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn appended_tracks_merge_into_unshuffled_order_and_queue() {
+        let titled = |display: &str| Track {
+            display: display.to_string(),
+            ..album_track(None)
+        };
+        let mut h = Harness::new(vec![titled("b"), titled("d")], 0);
+        h.send(AudioCmd::Play(1));
+        h.send(AudioCmd::AppendTracks(vec![titled("c"), titled("a")]));
+        assert_eq!(*h.order.lock().unwrap(), vec![3, 0, 2, 1]);
+
+        // The queue follows the library order, not index order, and the
+        // playing track keeps its index.
+        h.send(AudioCmd::SetQueue(vec![0, 1, 2, 3]));
+        h.send(AudioCmd::Prev);
+        assert_eq!(h.playing_index(), Some(2));
+
+        // Shuffled: newcomers join the shuffle order without losing anyone.
+        h.send(AudioCmd::ToggleShuffle);
+        h.send(AudioCmd::AppendTracks(vec![titled("e")]));
+        let mut order = h.order.lock().unwrap().clone();
+        order.sort_unstable();
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn sort_order_reorders_queue_unless_shuffled() {
+        use crate::config::{SortField, SortKey};
+        use crate::library::SortSpec;
+
+        let titled = |display: &str| Track {
+            display: display.to_string(),
+            ..album_track(None)
+        };
+        let mut h = Harness::new(vec![titled("a"), titled("b"), titled("c")], 0);
+        h.send(AudioCmd::SetQueue(vec![0, 1, 2]));
+        h.send(AudioCmd::Play(1));
+        h.send(AudioCmd::SetSortOrder(SortSpec {
+            keys: vec![SortKey::descending(SortField::Display)],
+            articles: Vec::new(),
+        }));
+        assert_eq!(*h.order.lock().unwrap(), vec![2, 1, 0]);
+        h.send(AudioCmd::Next);
+        assert_eq!(h.playing_index(), Some(0));
+
+        // A shuffled order is kept; unshuffling picks up the new sort.
+        h.send(AudioCmd::ToggleShuffle);
+        let shuffled = h.order.lock().unwrap().clone();
+        h.send(AudioCmd::SetSortOrder(SortSpec::default()));
+        assert_eq!(*h.order.lock().unwrap(), shuffled);
+        h.send(AudioCmd::ToggleShuffle);
+        assert_eq!(*h.order.lock().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn library_changes_follow_the_playing_track_and_stop_when_it_is_removed() {
+        use crate::library::LibraryChange;
+
+        let at = |path: &str| Track {
+            path: path.into(),
+            display: path.to_string(),
+            ..album_track(None)
+        };
+        let mut h = Harness::new(vec![at("/a"), at("/b"), at("/c")], 0);
+        h.send(AudioCmd::Play(2));
+        h.send(AudioCmd::ApplyLibraryChanges(vec![
+            LibraryChange::Remove("/a".into()),
+            LibraryChange::Upsert(at("/0")),
+        ]));
+        assert_eq!(h.playing_index(), Some(1));
+        assert_eq!(*h.order.lock().unwrap(), vec![2, 0, 1]);
+        // The UI waits for this count before trusting the shared index.
+        assert_eq!(h.info.lock().unwrap().library_changes, 1);
+
+        h.send(AudioCmd::Prev);
+        assert_eq!(h.playing_index(), Some(0));
+        h.send(AudioCmd::ApplyLibraryChanges(vec![LibraryChange::Remove(
+            "/b".into(),
+        )]));
+        assert_eq!(h.playing_index(), None);
+        assert_eq!(h.info.lock().unwrap().library_changes, 2);
+    }
+
+    #[test]
+    fn replaced_library_keeps_the_playing_track_until_it_ends() {
+        let at = |path: &str| Track {
+            path: path.into(),
+            display: path.to_string(),
+            ..album_track(None)
+        };
+        let mut h = Harness::new(vec![at("/old/a"), at("/old/b")], 0);
+        h.send(AudioCmd::Play(1));
+        let sink = h.last_sink();
+        h.send(AudioCmd::ReplaceLibrary(vec![
+            at("/new/a"),
+            at("/new/b"),
+            at("/new/c"),
+        ]));
+        assert!(sink.is_playing());
+        assert_eq!(h.playing_index(), None);
+        assert_eq!(
+            h.info
+                .lock()
+                .unwrap()
+                .detached
+                .as_ref()
+                .map(|t| t.path.clone()),
+            Some("/old/b".into())
+        );
+        assert_eq!(*h.order.lock().unwrap(), vec![0, 1, 2]);
+
+        h.send(AudioCmd::SetQueue(vec![0, 1, 2]));
+        h.finish_current();
+        assert_eq!(h.opened_indices(), vec![1]);
+        assert!(h.info.lock().unwrap().detached.is_none());
+
+        h.send(AudioCmd::Play(2));
+        assert_eq!(h.playing_index(), Some(2));
+    }
+
+    #[test]
+    fn crossfade_ramps_without_blocking_and_stops_old_sink() {
+        let mut h = Harness::new((0..2).map(|_| album_track(None)).collect(), 400);
+        h.send(AudioCmd::Play(0));
+        let old = h.last_sink();
+        h.send(AudioCmd::Play(1));
+        let new = h.last_sink();
+        assert_eq!(h.engine.poll_interval(), Duration::from_millis(100));
+
+        h.advance(Duration::from_millis(200));
+        assert!((old.volume() - 0.5).abs() < 1e-3);
+        assert!((new.volume() - 0.5).abs() < 1e-3);
+        assert!(old.is_playing());
+
+        h.advance(Duration::from_millis(250));
+        assert!(old.is_stopped());
+        assert!((new.volume() - 1.0).abs() < 1e-6);
+        assert_eq!(h.engine.poll_interval(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn seek_reopens_current_track_at_new_offset() {
+        let mut h = Harness::with_len(1);
+        h.send(AudioCmd::Play(0));
+        h.advance(Duration::from_secs(2));
+        h.send(AudioCmd::SeekBy(5));
+        h.send(AudioCmd::SeekBy(-30));
+
+        let opened: Vec<(usize, Duration)> = h
+            .backend
+            .opened
+            .borrow()
+            .iter()
+            .map(|o| (o.0, o.1))
+            .collect();
+        assert_eq!(
+            opened,
+            vec![
+                (0, Duration::ZERO),
+                (0, Duration::from_secs(7)),
+                (0, Duration::ZERO),
+            ]
+        );
+        assert_eq!(h.info.lock().unwrap().elapsed, Duration::ZERO);
+    }
+
+    #[test]
+    fn pause_freezes_elapsed_time() {
+        let mut h = Harness::with_len(1);
+        h.send(AudioCmd::Play(0));
+        h.advance(Duration::from_secs(3));
+        h.send(AudioCmd::TogglePause);
+        h.advance(Duration::from_secs(10));
+        assert_eq!(h.info.lock().unwrap().elapsed, Duration::from_secs(3));
+        assert!(!h.last_sink().is_playing());
+
+        h.send(AudioCmd::TogglePause);
+        h.advance(Duration::from_secs(1));
+        assert_eq!(h.info.lock().unwrap().elapsed, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn quit_fades_out_before_finishing() {
+        let mut h = Harness::with_len(1);
+        h.send(AudioCmd::Play(0));
+        h.send(AudioCmd::Quit { fade_out_ms: 100 });
+        assert!(!h.engine.is_finished());
+        assert!(!h.info.lock().unwrap().playing);
+
+        h.advance(Duration::from_millis(50));
+        assert!(h.last_sink().volume() < 1.0);
+        h.advance(Duration::from_millis(60));
+        assert!(h.engine.is_finished());
+        assert!(h.last_sink().is_stopped());
+    }
+
+    fn with_soft_fades(n: usize) -> Harness {
+        let settings = AudioSettings {
+            pause_fade_ms: 100,
+            resume_fade_ms: 200,
+            ..Harness::settings()
+        };
+        Harness::with_settings((0..n).map(|_| album_track(None)).collect(), settings)
+    }
+
+    #[test]
+    fn first_play_fades_in_from_silence() {
+        let mut h = with_soft_fades(2);
+        h.send(AudioCmd::Play(0));
+        let sink = h.last_sink();
+        assert_eq!(sink.volume(), 0.0);
+        assert_eq!(h.engine.poll_interval(), Duration::from_millis(50));
+
+        h.advance(Duration::from_millis(100));
+        assert!((sink.volume() - 0.5).abs() < 1e-3);
+        h.advance(Duration::from_millis(100));
+        assert!((sink.volume() - 1.0).abs() < 1e-6);
+
+        // Switching while audible is a plain swap when crossfade is off.
+        h.send(AudioCmd::Play(1));
+        assert!((h.last_sink().volume() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn soft_pause_reports_immediately_and_pauses_after_fade() {
+        let mut h = with_soft_fades(1);
+        h.send(AudioCmd::Play(0));
+        h.advance(Duration::from_secs(1));
+        let sink = h.last_sink();
+
+        h.send(AudioCmd::TogglePause);
+        assert!(!h.info.lock().unwrap().playing);
+        assert!(sink.is_playing(), "sink keeps playing while fading out");
+
+        h.advance(Duration::from_millis(50));
+        assert!((sink.volume() - 0.5).abs() < 1e-3);
+        assert!(sink.is_playing());
+
+        h.advance(Duration::from_millis(60));
+        assert!(!sink.is_playing());
+
+        h.send(AudioCmd::TogglePause);
+        assert!(h.info.lock().unwrap().playing);
+        assert!(sink.is_playing());
+        assert_eq!(sink.volume(), 0.0);
+        h.advance(Duration::from_millis(200));
+        assert!((sink.volume() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn resume_during_soft_pause_ramps_back_up() {
+        let mut h = with_soft_fades(1);
+        h.send(AudioCmd::Play(0));
+        h.advance(Duration::from_millis(200));
+        let sink = h.last_sink();
+
+        h.send(AudioCmd::TogglePause);
+        h.advance(Duration::from_millis(50));
+        h.send(AudioCmd::TogglePause);
+        h.advance(Duration::from_millis(100));
+        assert!(sink.is_playing());
+        assert!((sink.volume() - 0.75).abs() < 1e-3);
+
+        h.advance(Duration::from_millis(200));
+        assert!(sink.is_playing());
+        assert!((sink.volume() - 1.0).abs() < 1e-6);
+        assert_eq!(h.info.lock().unwrap().elapsed, Duration::from_millis(550));
+    }
+
+    #[test]
+    fn soft_stop_clears_state_and_fades_sink() {
+        let mut h = with_soft_fades(1);
+        h.send(AudioCmd::Play(0));
+        h.advance(Duration::from_millis(200));
+        let sink = h.last_sink();
+
+        h.send(AudioCmd::Stop);
+        assert_eq!(h.playing_index(), None);
+        assert!(!h.info.lock().unwrap().playing);
+        assert!(!sink.is_stopped());
+
+        h.advance(Duration::from_millis(50));
+        assert!((sink.volume() - 0.5).abs() < 1e-3);
+        h.advance(Duration::from_millis(60));
+        assert!(sink.is_stopped());
+    }
+
+    #[test]
+    fn karaoke_toggle_and_amount_are_published() {
+        let mut h = Harness::with_len(1);
+        h.send(AudioCmd::ToggleKaraoke);
+        h.send(AudioCmd::StepKaraokeAmount(-30));
+        {
+            let info = h.info.lock().unwrap();
+            assert!(info.karaoke);
+            assert_eq!(info.karaoke_amount_percent, 70);
+        }
+
+        h.send(AudioCmd::StepKaraokeAmount(500));
+        h.send(AudioCmd::ToggleKaraoke);
+        let info = h.info.lock().unwrap();
+        assert!(!info.karaoke);
+        assert_eq!(info.karaoke_amount_percent, 100);
+    }
+}
@@ -6,6 +6,8 @@
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use crate::library::{LibraryChange, SortSpec, Track};
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum LoopMode {
     /// Do not wrap at the end of the current queue.
@@ -46,6 +48,25 @@ pub enum AudioCmd {
     Quit { fade_out_ms: u64 },
     /// Seek by the specified number of seconds (positive or negative).
     SeekBy(i32), // seconds, positive or negative
+    /// Toggle stopping once the current track finishes.
+    ToggleStopAfterCurrent,
+    /// Toggle stopping once the current album finishes.
+    ToggleStopAfterAlbum,
+    /// Toggle the night-mode dynamic range compressor.
+    ToggleNightMode,
+    /// Toggle karaoke (centre-channel vocal removal).
+    ToggleKaraoke,
+    /// Change the karaoke vocal-removal amount by the given percentage points.
+    StepKaraokeAmount(i32),
+    /// Append tracks found by a background library scan.
+    AppendTracks(Vec<Track>),
+    /// Apply added, changed, removed or renamed files from the library watcher.
+    ApplyLibraryChanges(Vec<LibraryChange>),
+    /// Re-sort the unshuffled play order.
+    SetSortOrder(SortSpec),
+    /// Swap in a different library (e.g. after `:cd`); a playing track keeps
+    /// playing until it ends or another track is played.
+    ReplaceLibrary(Vec<Track>),
 }
 
 #[derive(Debug, Clone)]
@@ -57,6 +78,23 @@ pub struct PlaybackInfo {
     pub elapsed: Duration,
     /// Whether playback is currently active.
     pub playing: bool,
+    /// Stop when the current track ends (cleared once it fires).
+    pub stop_after_current: bool,
+    /// Stop when the current album ends (cleared once it fires).
+    pub stop_after_album: bool,
+    /// Whether the night-mode compressor is active.
+    pub night_mode: bool,
+    /// Whether karaoke vocal removal is active.
+    pub karaoke: bool,
+    /// Karaoke vocal-removal amount (0-100).
+    pub karaoke_amount_percent: u8,
+    /// Track still playing from a replaced library; `index` is `None` meanwhile.
+    pub detached: Option<Track>,
+    /// Why the last track could not be played; the UI takes it as a notice.
+    pub error: Option<String>,
+    /// `ApplyLibraryChanges` batches applied so far. `index` counts in the
+    /// UI's numbering only once this matches the batches the UI sent.
+    pub library_changes: u64,
 }
 
 impl Default for PlaybackInfo {
@@ -65,6 +103,14 @@ impl Default for PlaybackInfo {
             index: None,
             elapsed: Duration::ZERO,
             playing: false,
+            stop_after_current: false,
+            stop_after_album: false,
+            night_mode: false,
+            karaoke: false,
+            karaoke_amount_percent: 100,
+            detached: None,
+            error: None,
+            library_changes: 0,
         }
     }
 }
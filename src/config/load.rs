@@ -38,6 +38,18 @@ impl Settings {
         if self.audio.initial_volume_percent > 100 {
             errors.push("audio.initial_volume_percent must be between 0 and 100".to_string());
         }
+        if self.audio.night_mode_threshold_db > 0.0 {
+            errors.push("audio.night_mode_threshold_db must be <= 0".to_string());
+        }
+        if self.audio.night_mode_ratio < 1.0 {
+            errors.push("audio.night_mode_ratio must be >= 1".to_string());
+        }
+        if self.audio.karaoke_amount_percent > 100 {
+            errors.push("audio.karaoke_amount_percent must be between 0 and 100".to_string());
+        }
+        if !(1..=5).contains(&self.ui.favourite_stars) {
+            errors.push("ui.favourite_stars must be between 1 and 5".to_string());
+        }
         if self.controls.scrub_seconds == 0 {
             errors.push("controls.scrub_seconds must be >= 1".to_string());
         }
@@ -47,11 +59,31 @@ impl Settings {
         if self.controls.volume_step_percent > 100 {
             errors.push("controls.volume_step_percent must be <= 100".to_string());
         }
+        if self.controls.karaoke_step_percent == 0 || self.controls.karaoke_step_percent > 100 {
+            errors.push("controls.karaoke_step_percent must be between 1 and 100".to_string());
+        }
         if let Some(depth) = self.library.max_depth {
             if depth == 0 {
                 errors.push("library.max_depth must be >= 1".to_string());
             }
         }
+        if self.library.roots.iter().any(|r| r.trim().is_empty()) {
+            errors.push("library.roots must not contain empty paths".to_string());
+        }
+        for (name, separators) in [
+            ("artist_separators", &self.library.artist_separators),
+            ("genre_separators", &self.library.genre_separators),
+        ] {
+            if separators.iter().any(|s| s.is_empty()) {
+                errors.push(format!("library.{} must not contain empty strings", name));
+            }
+        }
+        if let Err(e) = crate::library::validate_patterns(&self.library.exclude) {
+            errors.push(format!("library.exclude has an invalid pattern {}", e));
+        }
+        if self.library.sort.is_empty() {
+            errors.push("library.sort must list at least one key".to_string());
+        }
 
         let trimmed_exts: Vec<&str> = self
             .library
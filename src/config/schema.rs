@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Top-level application settings loaded from `config.toml`.
 ///
@@ -44,8 +44,35 @@ pub struct AudioSettings {
     /// Fade-out duration when quitting (milliseconds).
     /// Set to 0 to stop immediately.
     pub quit_fade_out_ms: u64,
+    /// Fade-out duration when pausing or stopping (milliseconds).
+    /// Set to 0 to pause immediately.
+    pub pause_fade_ms: u64,
+    /// Fade-in duration when resuming or starting playback (milliseconds).
+    /// Set to 0 to start at full volume.
+    pub resume_fade_ms: u64,
     /// Initial playback volume as a percentage (0-100).
     pub initial_volume_percent: u8,
+    /// Read-ahead memory used to pre-buffer the predicted next track (KiB).
+    /// Set to 0 to disable decode-ahead buffering.
+    pub preload_buffer_kb: u64,
+    /// Start with the night-mode compressor enabled.
+    pub night_mode: bool,
+    /// Night-mode compressor threshold (dBFS, <= 0).
+    pub night_mode_threshold_db: f32,
+    /// Night-mode compression ratio (>= 1; e.g. 4 means 4:1 above threshold).
+    pub night_mode_ratio: f32,
+    /// Night-mode attack time (milliseconds).
+    pub night_mode_attack_ms: u64,
+    /// Night-mode release time (milliseconds).
+    pub night_mode_release_ms: u64,
+    /// Start with karaoke (centre-channel vocal removal) enabled.
+    pub karaoke: bool,
+    /// How much of the centre channel karaoke mode removes (0-100).
+    pub karaoke_amount_percent: u8,
+    /// Centre content below this frequency is kept, so bass survives (Hz, 0 = no limit).
+    pub karaoke_low_cut_hz: u32,
+    /// Centre content above this frequency is kept (Hz, 0 = no limit).
+    pub karaoke_high_cut_hz: u32,
 }
 
 impl Default for AudioSettings {
@@ -54,7 +81,19 @@ impl Default for AudioSettings {
             crossfade_ms: 250,
             crossfade_steps: 10,
             quit_fade_out_ms: 500,
+            pause_fade_ms: 0,
+            resume_fade_ms: 0,
             initial_volume_percent: 50,
+            preload_buffer_kb: 4096,
+            night_mode: false,
+            night_mode_threshold_db: -24.0,
+            night_mode_ratio: 4.0,
+            night_mode_attack_ms: 10,
+            night_mode_release_ms: 250,
+            karaoke: false,
+            karaoke_amount_percent: 100,
+            karaoke_low_cut_hz: 150,
+            karaoke_high_cut_hz: 8000,
         }
     }
 }
@@ -77,6 +116,12 @@ pub struct UiSettings {
     /// Show the current line number in the track list.
     pub show_current_line_number: bool,
 
+    /// Show star ratings as a column in the track list (once any track is rated).
+    pub show_ratings: bool,
+
+    /// Lowest rating the favourites view (`F`) lists.
+    pub favourite_stars: u8,
+
     /// The text rendered inside the top "presto" header box.
     pub header_text: String,
 
@@ -95,6 +140,9 @@ pub struct UiSettings {
 
     /// Separator used to join `now_playing_time_fields`.
     pub now_playing_time_separator: String,
+
+    /// How the metadata pane draws cover art.
+    pub cover_art: CoverArtSetting,
 }
 
 impl Default for UiSettings {
@@ -105,6 +153,8 @@ impl Default for UiSettings {
             show_pending_count: true,
             show_relative_numbers: false,
             show_current_line_number: false,
+            show_ratings: true,
+            favourite_stars: 4,
             header_text: " ~ And presto! It's music ~ ".to_string(),
             now_playing_track_fields: vec![TrackDisplayField::Display],
             now_playing_track_separator: " - ".to_string(),
@@ -114,6 +164,7 @@ impl Default for UiSettings {
                 TimeField::Remaining,
             ],
             now_playing_time_separator: " / ".to_string(),
+            cover_art: CoverArtSetting::Auto,
         }
     }
 }
@@ -125,6 +176,8 @@ pub struct ControlsSettings {
     pub scrub_seconds: u64,
     /// Percentage step to change volume when pressing `-` / `=`.
     pub volume_step_percent: u8,
+    /// Karaoke amount step per keypress (percentage points).
+    pub karaoke_step_percent: u8,
 }
 
 impl Default for ControlsSettings {
@@ -132,6 +185,7 @@ impl Default for ControlsSettings {
         Self {
             scrub_seconds: 5,
             volume_step_percent: 5,
+            karaoke_step_percent: 10,
         }
     }
 }
@@ -175,6 +229,18 @@ pub enum LoopModeSetting {
     LoopOne,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoverArtSetting {
+    /// Pick kitty graphics or sixel from the terminal, else half-blocks.
+    Auto,
+    Kitty,
+    Sixel,
+    #[serde(alias = "half_blocks", alias = "blocks")]
+    HalfBlocks,
+    Off,
+}
+
 #[derive(Debug, Copy, Clone, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum TimeField {
@@ -191,10 +257,168 @@ pub enum TrackDisplayField {
     Title,
     Artist,
     Album,
+    AlbumArtist,
+    Composer,
+    /// All genres, comma-separated.
+    Genre,
+    /// Zero-padded track number (`03`).
+    TrackNumber,
+    DiscNumber,
+    Year,
+    Comment,
     Filename,
     Path,
 }
 
+/// A track property the library can be sorted by.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SortField {
+    /// The track list label (`Track.display`).
+    Display,
+    Title,
+    Artist,
+    Album,
+    /// Album artist, falling back to the track artist.
+    AlbumArtist,
+    Composer,
+    Genre,
+    Year,
+    Disc,
+    Track,
+    Path,
+    Filename,
+    Duration,
+    /// When the file was first seen in the library.
+    Added,
+}
+
+/// One sort key; written as the field name, prefixed with `-` to sort descending.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub const fn ascending(field: SortField) -> Self {
+        Self {
+            field,
+            descending: false,
+        }
+    }
+
+    pub const fn descending(field: SortField) -> Self {
+        Self {
+            field,
+            descending: true,
+        }
+    }
+}
+
+impl TryFrom<String> for SortKey {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let trimmed = value.trim();
+        let (descending, name) = match trimmed.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, trimmed),
+        };
+        let field = match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "display" => SortField::Display,
+            "title" => SortField::Title,
+            "artist" => SortField::Artist,
+            "album" => SortField::Album,
+            "album-artist" | "albumartist" => SortField::AlbumArtist,
+            "composer" => SortField::Composer,
+            "genre" => SortField::Genre,
+            "year" | "date" => SortField::Year,
+            "disc" | "disc-number" => SortField::Disc,
+            "track" | "track-number" => SortField::Track,
+            "path" => SortField::Path,
+            "filename" => SortField::Filename,
+            "duration" => SortField::Duration,
+            "added" | "date-added" => SortField::Added,
+            _ => return Err(format!("unknown sort key `{}`", value)),
+        };
+        Ok(Self { field, descending })
+    }
+}
+
+/// Named sort orders that can be cycled at runtime.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortPreset {
+    /// `[library] sort`.
+    #[default]
+    Custom,
+    /// Albums in track order.
+    Album,
+    /// Artists, then their albums by year, in track order.
+    Artist,
+    Path,
+    /// Shortest first.
+    Duration,
+    /// Most recently added first.
+    #[serde(alias = "date-added", alias = "date_added")]
+    Added,
+}
+
+impl SortPreset {
+    /// Preset after `self` when cycling.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Custom => Self::Album,
+            Self::Album => Self::Artist,
+            Self::Artist => Self::Path,
+            Self::Path => Self::Duration,
+            Self::Duration => Self::Added,
+            Self::Added => Self::Custom,
+        }
+    }
+
+    /// Short name shown in the UI.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Custom => "custom",
+            Self::Album => "album",
+            Self::Artist => "artist",
+            Self::Path => "path",
+            Self::Duration => "duration",
+            Self::Added => "date added",
+        }
+    }
+
+    /// Sort keys for this preset; `custom` is the configured `[library] sort`.
+    pub fn keys(self, custom: &[SortKey]) -> Vec<SortKey> {
+        use SortField::*;
+
+        let asc = SortKey::ascending;
+        match self {
+            Self::Custom => custom.to_vec(),
+            Self::Album => vec![
+                asc(Album),
+                asc(AlbumArtist),
+                asc(Disc),
+                asc(Track),
+                asc(Path),
+            ],
+            Self::Artist => vec![
+                asc(AlbumArtist),
+                asc(Year),
+                asc(Album),
+                asc(Disc),
+                asc(Track),
+                asc(Path),
+            ],
+            Self::Path => vec![asc(Path)],
+            Self::Duration => vec![asc(Duration), asc(Display)],
+            Self::Added => vec![SortKey::descending(Added), asc(Display)],
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct LibrarySettings {
@@ -215,6 +439,34 @@ pub struct LibrarySettings {
     pub display_fields: Vec<TrackDisplayField>,
     /// Separator used to join `display_fields`.
     pub display_separator: String,
+    /// Keep an on-disk index of tags so unchanged files are not re-read at startup.
+    pub index_cache: bool,
+    /// Threads used to read tags while scanning (0 = one per CPU, 1 = sequential).
+    pub scan_workers: usize,
+    /// Watch the library for added, changed, removed and renamed files.
+    pub watch: bool,
+    /// Library directories combined into one library when none is given on the command line.
+    pub roots: Vec<String>,
+    /// Gitignore-style patterns (relative to each root) for files and directories to skip.
+    pub exclude: Vec<String>,
+    /// Sort keys for the `custom` preset, most significant first.
+    ///
+    /// Example: ["album_artist", "year", "album", "disc", "track"]
+    pub sort: Vec<SortKey>,
+    /// Sort preset used at startup (cycled at runtime with `o`).
+    pub sort_preset: SortPreset,
+    /// Leading articles ignored when sorting names, e.g. ["The", "A", "Los", "Die"].
+    pub sort_articles: Vec<String>,
+    /// Where the duplicate finder moves unwanted copies (empty = the XDG data
+    /// directory's `presto/trash`).
+    pub trash_dir: String,
+    /// Separators that split one artist value into several (case-insensitive).
+    pub artist_separators: Vec<String>,
+    /// Separators that split one genre value into several (case-insensitive).
+    pub genre_separators: Vec<String>,
+    /// Write ratings into the files' tags; when off they are kept in
+    /// `ratings.json` beside the config file instead.
+    pub write_ratings: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -240,6 +492,18 @@ impl Default for LibrarySettings {
             max_depth: None,
             display_fields: vec![TrackDisplayField::Artist, TrackDisplayField::Title],
             display_separator: " - ".to_string(),
+            index_cache: true,
+            scan_workers: 0,
+            watch: true,
+            roots: Vec::new(),
+            exclude: Vec::new(),
+            sort: vec![SortKey::ascending(SortField::Display)],
+            sort_preset: SortPreset::Custom,
+            sort_articles: Vec::new(),
+            trash_dir: String::new(),
+            artist_separators: vec![";".into(), " / ".into(), " feat. ".into(), " ft. ".into()],
+            genre_separators: vec![";".into(), "/".into()],
+            write_ratings: true,
         }
     }
 }
@@ -117,6 +117,7 @@ now_playing_track_fields = ["artist", "title"]
 now_playing_track_separator = " • "
 now_playing_time_fields = ["elapsed", "remaining"]
 now_playing_time_separator = " | "
+cover_art = "blocks"
 
 [library]
 extensions = ["mp3"]
@@ -125,6 +126,8 @@ include_hidden = false
 follow_links = false
 display_fields = ["filename"]
 display_separator = "::"
+sort = ["album_artist", "-year", "Disc", "track-number"]
+sort_preset = "date-added"
 "#,
     )
     .unwrap();
@@ -165,6 +168,7 @@ display_separator = "::"
         TimeField::Remaining
     ));
     assert_eq!(s.ui.now_playing_time_separator, " | ");
+    assert_eq!(s.ui.cover_art, CoverArtSetting::HalfBlocks);
     assert_eq!(s.library.extensions, vec!["mp3".to_string()]);
     assert!(!s.library.recursive);
     assert!(!s.library.include_hidden);
@@ -174,6 +178,24 @@ display_separator = "::"
         s.library.display_fields[0],
         TrackDisplayField::Filename
     ));
+    assert_eq!(
+        s.library.sort,
+        vec![
+            SortKey::ascending(SortField::AlbumArtist),
+            SortKey::descending(SortField::Year),
+            SortKey::ascending(SortField::Disc),
+            SortKey::ascending(SortField::Track),
+        ]
+    );
+    assert_eq!(s.library.sort_preset, SortPreset::Added);
+}
+
+#[test]
+fn sort_keys_reject_unknown_fields() {
+    assert_eq!(
+        SortKey::try_from("-bpm".to_string()),
+        Err("unknown sort key `-bpm`".to_string())
+    );
 }
 
 #[test]
@@ -203,16 +225,32 @@ fn settings_validate_reports_invalid_values() {
     let mut s = Settings::default();
     s.audio.crossfade_steps = 0;
     s.audio.initial_volume_percent = 101;
+    s.audio.night_mode_threshold_db = 3.0;
+    s.audio.night_mode_ratio = 0.5;
+    s.ui.favourite_stars = 0;
     s.controls.scrub_seconds = 0;
     s.controls.volume_step_percent = 0;
+    s.controls.karaoke_step_percent = 0;
     s.library.extensions = vec!["".to_string()];
     s.library.max_depth = Some(0);
+    s.library.roots = vec![" ".to_string()];
+    s.library.exclude = vec!["stems/".to_string(), "[abc".to_string()];
+    s.library.sort = Vec::new();
+    s.library.genre_separators = vec!["".to_string()];
 
     let err = s.validate().unwrap_err();
     assert!(err.contains("audio.crossfade_steps"));
     assert!(err.contains("audio.initial_volume_percent"));
+    assert!(err.contains("audio.night_mode_threshold_db"));
+    assert!(err.contains("audio.night_mode_ratio"));
+    assert!(err.contains("ui.favourite_stars"));
     assert!(err.contains("controls.scrub_seconds"));
     assert!(err.contains("controls.volume_step_percent"));
+    assert!(err.contains("controls.karaoke_step_percent"));
     assert!(err.contains("library.extensions"));
     assert!(err.contains("library.max_depth"));
+    assert!(err.contains("library.roots"));
+    assert!(err.contains("library.exclude has an invalid pattern `[abc`"));
+    assert!(err.contains("library.sort"));
+    assert!(err.contains("library.genre_separators"));
 }
@@ -1,16 +1,51 @@
 //! Library crate: scanning and track model for the music library.
 //!
-//! This module provides the `Track` model and a `scan` helper used to
-//! discover audio files on disk.
+//! This module provides the `Track` model, `scan_streaming` to discover
+//! audio files on disk, the on-disk `LibraryIndex` that lets
+//! repeat scans skip unchanged files, and the `LibraryWatcher` that turns
+//! filesystem events into incremental `LibraryChange`s. Edited tags are
+//! written back through `write_tags`, and `batch` renames files from their
+//! tags (or retags them from their names) with undo. `load_cover` finds a
+//! track's embedded or folder cover art, `find_duplicates` groups copies
+//! of the same song, `diagnose` reports files the scan could not read, and
+//! `write_rating` stores a track's star rating in its tags.
 
+mod batch;
+mod cache;
+mod changes;
+mod cover;
+mod diagnostics;
 mod display;
+mod dupes;
+mod ignore;
 mod lyrics;
 mod model;
+mod order;
+mod rating;
 mod scan;
+mod tags;
+mod template;
+mod watch;
 
+pub use batch::{BatchOp, BatchPlan, FileMove, plan_renames, plan_retags};
+pub use cache::LibraryIndex;
+pub use changes::{LibraryChange, apply_changes};
+pub use cover::load_cover;
+pub use diagnostics::{Diagnostic, diagnose, issue_counts};
+pub use display::field_text;
+pub use dupes::{
+    DupeGroup, confirm_by_hash, content_hash, copy_summary, find_duplicates, trash_target,
+};
+pub(crate) use ignore::validate_patterns;
 pub use lyrics::{Lyrics, TimedLyricLine, load_lyrics_from_path};
-pub use model::Track;
-pub use scan::scan;
+pub use model::{AudioProperties, Track};
+pub use order::{DisplayOrder, SortSpec};
+pub(crate) use order::{SortValue, article_keys, name_key, sort_value};
+pub use rating::{MAX_STARS, stars_text, write_rating};
+pub use scan::scan_streaming;
+pub use tags::{TagField, reread_track, write_tags};
+pub use template::{NamePattern, PathTemplate};
+pub use watch::{LibraryWatcher, WatchStop};
 
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
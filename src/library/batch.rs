@@ -0,0 +1,332 @@
+//! Batch renames and batch retagging with dry-run plans and undo.
+//!
+//! Planning never touches the disk beyond checking for existing files: it
+//! returns the operations that can run plus preview lines that flag
+//! conflicts. An applied `BatchOp` records what actually happened, and its
+//! `inverse` undoes it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::LibrarySettings;
+
+use super::model::Track;
+use super::tags::{RawField, TagField, read_raw_fields, restore_raw_fields, write_tags};
+use super::template::{NamePattern, PathTemplate};
+
+/// One file moved by a rename.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// One file retagged, with the values it had before.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRetag {
+    pub path: PathBuf,
+    pub edits: Vec<(TagField, String)>,
+    /// The edited fields as the file held them, recorded when `edits` are
+    /// applied.
+    pub previous: Vec<RawField>,
+    /// Undo: write `previous` back instead of `edits`.
+    #[serde(default)]
+    pub restore: bool,
+}
+
+/// A batch of file operations.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum BatchOp {
+    Rename { moves: Vec<FileMove> },
+    Retag { files: Vec<FileRetag> },
+}
+
+/// A dry run: what would be done, and one preview line per file considered.
+#[derive(Debug, Clone)]
+pub struct BatchPlan {
+    pub op: BatchOp,
+    pub preview: Vec<String>,
+    /// Files left out because of a conflict or a non-matching name.
+    pub skipped: usize,
+}
+
+/// `path` relative to the first of `roots` containing it, for previews.
+fn short(path: &Path, roots: &[PathBuf]) -> String {
+    roots
+        .iter()
+        .find_map(|r| path.strip_prefix(r).ok())
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Plan renaming the `indices` tracks to `template`, relative to the library
+/// root holding each (the deepest one, for nested roots).
+pub fn plan_renames(
+    tracks: &[Track],
+    indices: &[usize],
+    template: &PathTemplate,
+    roots: &[PathBuf],
+) -> BatchPlan {
+    let mut targets: HashMap<PathBuf, usize> = HashMap::new();
+    let mut planned: Vec<(usize, Option<PathBuf>)> = Vec::new();
+    for &i in indices {
+        let path = &tracks[i].path;
+        let root = roots
+            .iter()
+            .filter(|r| path.starts_with(r))
+            .max_by_key(|r| r.components().count());
+        let to = root.map(|root| root.join(template.render(&tracks[i])));
+        if let Some(ref to) = to {
+            *targets.entry(to.clone()).or_default() += 1;
+        }
+        planned.push((i, to));
+    }
+
+    let mut moves = Vec::new();
+    let mut preview = Vec::new();
+    let mut skipped = 0;
+    let mut unchanged = 0;
+    for (i, to) in planned {
+        let from = &tracks[i].path;
+        let Some(to) = to else {
+            preview.push(format!("! {}: outside the library roots", from.display()));
+            skipped += 1;
+            continue;
+        };
+        if &to == from {
+            unchanged += 1;
+            continue;
+        }
+        let (old, new) = (short(from, roots), short(&to, roots));
+        let conflict = if targets[&to] > 1 {
+            Some("several files would get this name")
+        } else if to.exists() {
+            Some("target exists")
+        } else {
+            None
+        };
+        match conflict {
+            Some(why) => {
+                preview.push(format!("! {} -> {} ({})", old, new, why));
+                skipped += 1;
+            }
+            None => {
+                preview.push(format!("{} -> {}", old, new));
+                moves.push(FileMove {
+                    from: from.clone(),
+                    to,
+                });
+            }
+        }
+    }
+    if unchanged > 0 {
+        preview.push(format!("({} file(s) already named this way)", unchanged));
+    }
+    BatchPlan {
+        op: BatchOp::Rename { moves },
+        preview,
+        skipped,
+    }
+}
+
+/// Plan filling tags of the `indices` tracks from their names via `pattern`.
+pub fn plan_retags(
+    tracks: &[Track],
+    indices: &[usize],
+    pattern: &NamePattern,
+    roots: &[PathBuf],
+    settings: &LibrarySettings,
+) -> BatchPlan {
+    let mut files = Vec::new();
+    let mut preview = Vec::new();
+    let mut skipped = 0;
+    let mut unchanged = 0;
+    for &i in indices {
+        let track = &tracks[i];
+        let name = short(&track.path, roots);
+        let Some(captures) = pattern.captures(&track.path) else {
+            preview.push(format!("! {}: does not match", name));
+            skipped += 1;
+            continue;
+        };
+        let edits: Vec<(TagField, String)> = captures
+            .into_iter()
+            .filter(|(field, value)| *value != field.value(track, settings))
+            .collect();
+        if edits.is_empty() {
+            unchanged += 1;
+            continue;
+        }
+        let diff: Vec<String> = edits
+            .iter()
+            .map(|(field, new)| {
+                let old = field.value(track, settings);
+                format!("{}: {} -> {}", field.label(), old, new)
+            })
+            .collect();
+        preview.push(format!("{}: {}", name, diff.join(", ")));
+        files.push(FileRetag {
+            path: track.path.clone(),
+            edits,
+            previous: Vec::new(),
+            restore: false,
+        });
+    }
+    if unchanged > 0 {
+        preview.push(format!("({} file(s) already tagged this way)", unchanged));
+    }
+    BatchPlan {
+        op: BatchOp::Retag { files },
+        preview,
+        skipped,
+    }
+}
+
+impl BatchOp {
+    /// Number of files the batch touches.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Rename { moves } => moves.len(),
+            Self::Retag { files } => files.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The batch that reverts this one (moves run back in reverse order).
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Rename { moves } => Self::Rename {
+                moves: moves
+                    .iter()
+                    .rev()
+                    .map(|m| FileMove {
+                        from: m.to.clone(),
+                        to: m.from.clone(),
+                    })
+                    .collect(),
+            },
+            Self::Retag { files } => Self::Retag {
+                files: files
+                    .iter()
+                    .map(|f| FileRetag {
+                        restore: !f.restore,
+                        ..f.clone()
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// What is still to be reverted once `reverted`, the part of this
+    /// batch's inverse that `apply` managed, has run.
+    pub fn unreverted(&self, reverted: &Self) -> Self {
+        match (self, reverted) {
+            (Self::Rename { moves }, Self::Rename { moves: back }) => Self::Rename {
+                moves: moves
+                    .iter()
+                    .filter(|m| !back.iter().any(|b| b.from == m.to && b.to == m.from))
+                    .cloned()
+                    .collect(),
+            },
+            (Self::Retag { files }, Self::Retag { files: back }) => Self::Retag {
+                files: files
+                    .iter()
+                    .filter(|f| !back.iter().any(|b| b.path == f.path))
+                    .cloned()
+                    .collect(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Run the batch. Directories a move leaves empty are removed up to, but
+    /// not including, the one of `roots` holding them; retags split artists
+    /// and genres like the scan `settings` do. Returns the part that
+    /// succeeded and one message per failure.
+    pub fn apply(&self, roots: &[PathBuf], settings: &LibrarySettings) -> (Self, Vec<String>) {
+        let mut failures = Vec::new();
+        let done = match self {
+            Self::Rename { moves } => Self::Rename {
+                moves: moves
+                    .iter()
+                    .filter(|m| match move_file(&m.from, &m.to, roots) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            failures.push(format!("{}: {}", m.from.display(), e));
+                            false
+                        }
+                    })
+                    .cloned()
+                    .collect(),
+            },
+            Self::Retag { files } => Self::Retag {
+                files: files
+                    .iter()
+                    .filter_map(|f| match retag_file(f, settings) {
+                        Ok(done) => Some(done),
+                        Err(e) => {
+                            failures.push(format!("{}: {}", f.path.display(), e));
+                            None
+                        }
+                    })
+                    .collect(),
+            },
+        };
+        (done, failures)
+    }
+}
+
+/// Apply one file's retag, returning it with the prior values recorded.
+fn retag_file(file: &FileRetag, settings: &LibrarySettings) -> Result<FileRetag, String> {
+    if file.restore {
+        restore_raw_fields(&file.path, &file.previous)?;
+        return Ok(file.clone());
+    }
+    let fields: Vec<TagField> = file.edits.iter().map(|(field, _)| *field).collect();
+    let previous = read_raw_fields(&file.path, &fields)?;
+    write_tags(&file.path, &file.edits, settings)?;
+    Ok(FileRetag {
+        previous,
+        ..file.clone()
+    })
+}
+
+/// Move `from` to `to`, creating directories and removing the ones left
+/// empty below the root of `roots` that holds `from`.
+fn move_file(from: &Path, to: &Path, roots: &[PathBuf]) -> Result<(), String> {
+    // Checked again: the plan may be stale, and rename would silently replace.
+    if to.exists() {
+        return Err(format!("{} already exists", to.display()));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    match fs::rename(from, to) {
+        // The trash folder may live on another filesystem.
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)
+                .and_then(|_| fs::remove_file(from))
+                .map_err(|e| e.to_string())?;
+        }
+        result => result.map_err(|e| e.to_string())?,
+    }
+    let mut dir = from.parent();
+    let inside_root = |d: &Path| roots.iter().any(|r| d != r && d.starts_with(r));
+    // `remove_dir` only succeeds on empty directories.
+    while let Some(d) = dir
+        && inside_root(d)
+        && fs::remove_dir(d).is_ok()
+    {
+        dir = d.parent();
+    }
+    Ok(())
+}
@@ -0,0 +1,372 @@
+//! On-disk library index so startup does not re-read every tag.
+//!
+//! The index lives under the XDG cache directory, one file per library root.
+//! Each entry records a file's size and modification time next to the tag
+//! fields extracted from it; a scan only re-reads files whose size or mtime
+//! changed and drops entries for files that no longer exist.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::diagnostics::Issue;
+use super::model::{AudioProperties, SortTags};
+
+/// Bump whenever `IndexEntry` changes shape or meaning; older files are discarded.
+pub(crate) const INDEX_VERSION: u32 = 8;
+
+/// Tag fields extracted from one audio file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TrackTags {
+    pub(crate) title: String,
+    /// Every artist value as tagged; split per settings when building the `Track`.
+    pub(crate) artists: Vec<String>,
+    pub(crate) album: Option<String>,
+    pub(crate) album_artist: Option<String>,
+    pub(crate) composer: Option<String>,
+    /// Every genre value as tagged; split like `artists`.
+    pub(crate) genres: Vec<String>,
+    pub(crate) track_number: Option<u32>,
+    pub(crate) track_total: Option<u32>,
+    pub(crate) disc_number: Option<u32>,
+    pub(crate) disc_total: Option<u32>,
+    pub(crate) year: Option<u32>,
+    pub(crate) date: Option<String>,
+    pub(crate) comment: Option<String>,
+    /// Stars from 1 to 5.
+    pub(crate) rating: Option<u8>,
+    pub(crate) duration: Option<Duration>,
+    pub(crate) audio: AudioProperties,
+    /// First time the file was seen (Unix seconds); kept across re-reads.
+    pub(crate) added: Option<u64>,
+    /// Sort tags as read from the file; their `display` stays empty because it
+    /// depends on settings and is rebuilt per scan.
+    pub(crate) sort_tags: SortTags,
+    /// Problems found while reading the file.
+    pub(crate) issues: Vec<Issue>,
+}
+
+/// File identity used to decide whether cached tags are still valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FileStamp {
+    pub(crate) size: u64,
+    /// Modification time as `(seconds, nanoseconds)` since the Unix epoch.
+    pub(crate) mtime: (u64, u32),
+}
+
+impl FileStamp {
+    /// Read the stamp for `path`, or `None` when size/mtime are unavailable.
+    pub(crate) fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let since_epoch = meta
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO);
+        Some(Self {
+            size: meta.len(),
+            mtime: (since_epoch.as_secs(), since_epoch.subsec_nanos()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    path: PathBuf,
+    stamp: FileStamp,
+    tags: TrackTags,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+    root: PathBuf,
+    entries: Vec<IndexEntry>,
+}
+
+/// Cached tags for one library root, keyed by path relative to that root.
+#[derive(Debug, Default)]
+pub struct LibraryIndex {
+    file: Option<PathBuf>,
+    root: PathBuf,
+    entries: HashMap<PathBuf, (FileStamp, TrackTags)>,
+    /// Paths looked up or inserted during the current scan.
+    seen: HashSet<PathBuf>,
+}
+
+impl LibraryIndex {
+    /// An empty index that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Load the index for `root` from the default cache location.
+    ///
+    /// Missing, unreadable, corrupt and out-of-date files all yield an empty
+    /// index: the cache is only ever an accelerator.
+    pub fn load_default(root: &Path) -> Self {
+        Self::load(index_file_path(root), root)
+    }
+
+    /// Start an empty index for `root` that replaces the cached one on save.
+    pub fn fresh_default(root: &Path) -> Self {
+        Self {
+            file: index_file_path(root),
+            root: root.to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn load(file: Option<PathBuf>, root: &Path) -> Self {
+        let entries = file
+            .as_deref()
+            .and_then(|f| fs::read(f).ok())
+            .and_then(|data| serde_json::from_slice::<IndexFile>(&data).ok())
+            .filter(|idx| idx.version == INDEX_VERSION && idx.root == root)
+            .map(|idx| {
+                idx.entries
+                    .into_iter()
+                    .map(|e| (e.path, (e.stamp, e.tags)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            file,
+            root: root.to_path_buf(),
+            entries,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Return cached tags for `path` if its stamp still matches.
+    pub(crate) fn lookup(&mut self, path: &Path, stamp: Option<FileStamp>) -> Option<TrackTags> {
+        let (cached_stamp, tags) = self.entries.get(path)?;
+        if Some(*cached_stamp) != stamp {
+            return None;
+        }
+        self.seen.insert(path.to_path_buf());
+        Some(tags.clone())
+    }
+
+    /// Record freshly read tags for `path` and return them, with the
+    /// original `added` time carried over from any previous entry.
+    pub(crate) fn insert(
+        &mut self,
+        path: &Path,
+        stamp: Option<FileStamp>,
+        mut tags: TrackTags,
+    ) -> TrackTags {
+        self.seen.insert(path.to_path_buf());
+        if let Some((_, old)) = self.entries.get(path) {
+            tags.added = old.added.or(tags.added);
+        }
+        match stamp {
+            Some(stamp) => {
+                self.entries
+                    .insert(path.to_path_buf(), (stamp, tags.clone()));
+            }
+            // Without a stamp we could never validate the entry, so don't keep one.
+            None => {
+                self.entries.remove(path);
+            }
+        }
+        tags
+    }
+
+    /// Forget `path` and everything below it.
+    pub(crate) fn remove(&mut self, path: &Path) {
+        self.entries.retain(|p, _| !p.starts_with(path));
+    }
+
+    /// Move the entries for `from` and everything below it to `to`.
+    pub(crate) fn rename(&mut self, from: &Path, to: &Path) {
+        let moved: Vec<PathBuf> = self
+            .entries
+            .keys()
+            .filter(|p| p.starts_with(from))
+            .cloned()
+            .collect();
+        for old in moved {
+            let entry = self.entries.remove(&old).expect("key was just listed");
+            let rest = old.strip_prefix(from).unwrap_or(&old);
+            let new = if rest.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(rest)
+            };
+            self.entries.insert(new, entry);
+        }
+    }
+
+    /// Drop entries for files the last scan did not see (deleted or now excluded).
+    pub(crate) fn retain_seen(&mut self) {
+        let seen = std::mem::take(&mut self.seen);
+        self.entries.retain(|path, _| seen.contains(path));
+    }
+
+    /// Write the index to its cache file, if it has one.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(file) = self.file.as_deref() else {
+            return Ok(());
+        };
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut entries: Vec<IndexEntry> = self
+            .entries
+            .iter()
+            .map(|(path, (stamp, tags))| IndexEntry {
+                path: path.clone(),
+                stamp: *stamp,
+                tags: tags.clone(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let data = serde_json::to_vec(&IndexFile {
+            version: INDEX_VERSION,
+            root: self.root.clone(),
+            entries,
+        })
+        .map_err(io::Error::other)?;
+
+        // Write to a sibling file first so a crash never leaves a torn index.
+        let tmp = file.with_extension(format!("json.tmp-{}", std::process::id()));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, file)
+    }
+}
+
+/// Resolve the cache directory (XDG_CACHE_HOME, falling back to ~/.cache).
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    let cache_home = if let Some(xdg) = env::var_os("XDG_CACHE_HOME") {
+        Some(PathBuf::from(xdg))
+    } else {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache"))
+    };
+    cache_home.map(|d| d.join("presto"))
+}
+
+/// Compute the index file for `root`: one file per library root, named by a path hash.
+pub(crate) fn index_file_path(root: &Path) -> Option<PathBuf> {
+    let hash = fnv1a(root.as_os_str().as_encoded_bytes());
+    cache_dir().map(|d| d.join(format!("index-{hash:016x}.json")))
+}
+
+/// FNV-1a offset basis: the hash of no bytes.
+pub(super) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Stable 64-bit FNV-1a, so cache file names survive toolchain upgrades.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_extend(FNV_OFFSET, bytes)
+}
+
+/// Continue an FNV-1a `hash` over more `bytes`.
+pub(super) fn fnv1a_extend(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LibrarySettings, TrackDisplayField};
+    use crate::library::tests::scan_with_index;
+    use tempfile::tempdir;
+
+    fn settings() -> LibrarySettings {
+        LibrarySettings {
+            display_fields: vec![TrackDisplayField::Title],
+            ..LibrarySettings::default()
+        }
+    }
+
+    #[test]
+    fn scan_reuses_unchanged_entries_and_drops_deleted_files() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.mp3"), b"not real").unwrap();
+        fs::write(dir.path().join("b.mp3"), b"not real").unwrap();
+
+        let mut index = LibraryIndex::in_memory();
+        scan_with_index(dir.path(), &settings(), &mut index);
+        assert_eq!(index.entries.len(), 2);
+
+        // Unchanged files come from the index, not from the tags on disk.
+        let key = Path::new("a.mp3");
+        index.entries.get_mut(key).unwrap().1.title = "Cached".to_string();
+        let tracks = scan_with_index(dir.path(), &settings(), &mut index);
+        assert_eq!(tracks[0].title, "b");
+        assert_eq!(tracks[1].title, "Cached");
+
+        // A size change invalidates the entry; a deleted file loses its entry.
+        fs::write(dir.path().join("a.mp3"), b"changed and longer").unwrap();
+        fs::remove_file(dir.path().join("b.mp3")).unwrap();
+        let tracks = scan_with_index(dir.path(), &settings(), &mut index);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].title, "a");
+        assert_eq!(index.entries.len(), 1);
+    }
+
+    #[test]
+    fn index_round_trips_and_discards_other_versions_or_roots() {
+        let music = tempdir().unwrap();
+        fs::write(music.path().join("a.mp3"), b"not real").unwrap();
+        let cache = tempdir().unwrap();
+        let file = cache.path().join("nested").join("index.json");
+
+        let mut index = LibraryIndex::load(Some(file.clone()), music.path());
+        scan_with_index(music.path(), &settings(), &mut index);
+        index.save().unwrap();
+
+        let reloaded = LibraryIndex::load(Some(file.clone()), music.path());
+        assert_eq!(reloaded.entries, index.entries);
+        assert!(
+            LibraryIndex::load(Some(file.clone()), Path::new("/elsewhere"))
+                .entries
+                .is_empty()
+        );
+
+        let stale = fs::read_to_string(&file).unwrap().replacen(
+            &format!("\"version\":{}", INDEX_VERSION),
+            "\"version\":0",
+            1,
+        );
+        fs::write(&file, stale).unwrap();
+        assert!(
+            LibraryIndex::load(Some(file), music.path())
+                .entries
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn reread_files_keep_their_added_time() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.mp3"), b"not real").unwrap();
+
+        let mut index = LibraryIndex::in_memory();
+        scan_with_index(dir.path(), &settings(), &mut index);
+        let key = Path::new("a.mp3");
+        index.entries.get_mut(key).unwrap().1.added = Some(42);
+
+        fs::write(dir.path().join("a.mp3"), b"retagged, so re-read").unwrap();
+        let tracks = scan_with_index(dir.path(), &settings(), &mut index);
+        assert_eq!(tracks[0].added, Some(42));
+        assert_eq!(index.entries[key].1.added, Some(42));
+    }
+
+    #[test]
+    fn root_hash_is_stable_fnv1a() {
+        // Reference values for 64-bit FNV-1a; cache file names must not drift.
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
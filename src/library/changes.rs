@@ -0,0 +1,80 @@
+//! Incremental library updates produced by the filesystem watcher.
+//!
+//! Changes are keyed by path. `apply_changes` is deterministic, so the UI and
+//! the audio thread can each apply the same batch to their own copy of the
+//! track list and end up with identical indices.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::model::Track;
+
+/// One coalesced change to the library.
+#[derive(Debug, Clone)]
+pub enum LibraryChange {
+    /// A file appeared or its tags changed.
+    Upsert(Track),
+    /// A file, or every file under a directory, is gone.
+    Remove(PathBuf),
+    /// A file moved; it keeps its identity (selection, now playing).
+    Rename { from: PathBuf, to: Track },
+}
+
+/// Apply `changes` to `tracks` in place.
+///
+/// Returns the old-to-new index map: `None` for removed tracks. Renamed and
+/// updated tracks keep their slot (modulo compaction), new ones are appended.
+pub fn apply_changes(tracks: &mut Vec<Track>, changes: Vec<LibraryChange>) -> Vec<Option<usize>> {
+    let mut by_path: HashMap<PathBuf, usize> = tracks
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.path.clone(), i))
+        .collect();
+
+    let mut removed: Vec<PathBuf> = Vec::new();
+    let mut upserts: Vec<Track> = Vec::new();
+    for change in changes {
+        match change {
+            LibraryChange::Rename { from, mut to } => match by_path.remove(&from) {
+                Some(i) => {
+                    by_path.insert(to.path.clone(), i);
+                    to.added = tracks[i].added.or(to.added);
+                    tracks[i] = to;
+                }
+                None => upserts.push(to),
+            },
+            LibraryChange::Remove(path) => removed.push(path),
+            LibraryChange::Upsert(track) => upserts.push(track),
+        }
+    }
+
+    let mut remap = Vec::with_capacity(tracks.len());
+    let mut kept = 0;
+    tracks.retain(|t| {
+        // `starts_with` matches whole components, so this covers files and directories.
+        let gone = removed.iter().any(|r| t.path.starts_with(r));
+        remap.push((!gone).then_some(kept));
+        kept += usize::from(!gone);
+        !gone
+    });
+
+    by_path = tracks
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.path.clone(), i))
+        .collect();
+    for mut track in upserts {
+        match by_path.get(&track.path) {
+            Some(&i) => {
+                // A retagged file keeps its place in "date added" order.
+                track.added = tracks[i].added.or(track.added);
+                tracks[i] = track;
+            }
+            None => {
+                by_path.insert(track.path.clone(), tracks.len());
+                tracks.push(track);
+            }
+        }
+    }
+    remap
+}
@@ -0,0 +1,64 @@
+//! Cover art lookup: embedded pictures first, then an image beside the file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lofty::picture::PictureType;
+use lofty::prelude::*;
+
+/// Image files recognised as folder art, best first (compared case-insensitively).
+const FOLDER_ART: [&str; 3] = ["cover", "folder", "front"];
+const FOLDER_ART_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Encoded cover image bytes for a track.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverData {
+    /// Where the bytes came from: the track itself for embedded art, otherwise
+    /// the image file, so every track of a folder shares one cache entry.
+    pub source: PathBuf,
+    pub bytes: Vec<u8>,
+}
+
+/// Find the cover for the track at `path`: the embedded front cover (or the
+/// first embedded picture), else `cover`/`folder`/`front` `.jpg`/`.png` in
+/// the track's directory.
+pub fn load_cover(path: &Path) -> Option<CoverData> {
+    embedded_cover(path).or_else(|| folder_cover(path.parent()?))
+}
+
+fn embedded_cover(path: &Path) -> Option<CoverData> {
+    let tagged = lofty::read_from_path(path).ok()?;
+    let pictures: Vec<_> = tagged.tags().iter().flat_map(|t| t.pictures()).collect();
+    let picture = pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())?;
+    Some(CoverData {
+        source: path.to_path_buf(),
+        bytes: picture.data().to_vec(),
+    })
+}
+
+/// The best-ranked folder art image in `dir`.
+fn folder_cover_path(dir: &Path) -> Option<PathBuf> {
+    let rank = |path: &Path| {
+        let stem = path.file_stem()?.to_str()?.to_lowercase();
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        if !FOLDER_ART_EXTENSIONS.contains(&ext.as_str()) {
+            return None;
+        }
+        FOLDER_ART.iter().position(|name| *name == stem)
+    };
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| Some((rank(&path)?, path)))
+        .min()
+        .map(|(_, path)| path)
+}
+
+fn folder_cover(dir: &Path) -> Option<CoverData> {
+    let source = folder_cover_path(dir)?;
+    let bytes = fs::read(&source).ok()?;
+    Some(CoverData { source, bytes })
+}
@@ -0,0 +1,153 @@
+//! Scan diagnostics: files the scanner could not read or read only in part.
+//!
+//! Problems with a file's contents are found while reading its tags and kept
+//! on the `Track` (and in the index, so cached files still report them).
+//! Entries the directory walk could not list have no track; the scan returns
+//! them separately and `diagnose` merges both into one sorted report.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::model::Track;
+
+/// Something wrong with one library file or directory.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Issue {
+    /// The file or directory may not be read.
+    PermissionDenied,
+    /// The walk could not list an entry (broken link, loop, vanished).
+    WalkFailed(String),
+    /// The file could not be parsed as audio.
+    Unreadable(String),
+    /// The content is another format than the extension says.
+    ExtensionMismatch {
+        extension: String,
+        detected: String,
+    },
+    /// The file parses as audio but reports no length.
+    ZeroDuration,
+    /// The file has no tags at all.
+    Untagged,
+    /// Tags are present but carry no title; the file name is shown instead.
+    NoTitle,
+    MissingArtist,
+    MissingAlbum,
+}
+
+impl Issue {
+    /// Stable short name, used as the first column of `presto scan --report`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied => "permission-denied",
+            Self::WalkFailed(_) => "walk-failed",
+            Self::Unreadable(_) => "unreadable",
+            Self::ExtensionMismatch { .. } => "extension-mismatch",
+            Self::ZeroDuration => "zero-duration",
+            Self::Untagged => "untagged",
+            Self::NoTitle => "no-title",
+            Self::MissingArtist => "missing-artist",
+            Self::MissingAlbum => "missing-album",
+        }
+    }
+
+    /// Whether the file could not be played or listed at all.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            Self::PermissionDenied | Self::WalkFailed(_) | Self::Unreadable(_)
+        )
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PermissionDenied => write!(f, "permission denied"),
+            Self::WalkFailed(err) => write!(f, "could not list: {}", err),
+            Self::Unreadable(err) => write!(f, "unreadable: {}", err),
+            Self::ExtensionMismatch {
+                extension,
+                detected,
+            } => write!(f, ".{} file contains {}", extension, detected),
+            Self::ZeroDuration => write!(f, "zero duration"),
+            Self::Untagged => write!(f, "no tags"),
+            Self::NoTitle => write!(f, "no title tag"),
+            Self::MissingArtist => write!(f, "no artist tag"),
+            Self::MissingAlbum => write!(f, "no album tag"),
+        }
+    }
+}
+
+/// One problem at one path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub issue: Issue,
+}
+
+impl Diagnostic {
+    /// Diagnostic for an entry the directory walk failed on, if it has a path.
+    pub(super) fn from_walk_error(err: &walkdir::Error) -> Option<Self> {
+        let path = err.path()?.to_path_buf();
+        let issue = match err.io_error().map(|e| e.kind()) {
+            Some(std::io::ErrorKind::PermissionDenied) => Issue::PermissionDenied,
+            _ => Issue::WalkFailed(err.to_string()),
+        };
+        Some(Self { path, issue })
+    }
+}
+
+/// Problems of `tracks` plus `walk` failures, errors first, then by path.
+pub fn diagnose(tracks: &[Track], walk: &[Diagnostic]) -> Vec<Diagnostic> {
+    let mut report: Vec<Diagnostic> = tracks
+        .iter()
+        .flat_map(|track| {
+            track.issues.iter().map(|issue| Diagnostic {
+                path: track.path.clone(),
+                issue: issue.clone(),
+            })
+        })
+        .chain(walk.iter().cloned())
+        .collect();
+    report.sort_by(|a, b| {
+        (!a.issue.is_error(), &a.path, &a.issue).cmp(&(!b.issue.is_error(), &b.path, &b.issue))
+    });
+    report
+}
+
+/// Count of each issue kind in `report`, in first-seen order.
+pub fn issue_counts(report: &[Diagnostic]) -> Vec<(&'static str, usize)> {
+    let mut counts: Vec<(&'static str, usize)> = Vec::new();
+    for diagnostic in report {
+        let kind = diagnostic.issue.kind();
+        match counts.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((kind, 1)),
+        }
+    }
+    counts
+}
+
+/// `ExtensionMismatch` when `path`'s extension names another format than
+/// `detected` (the format sniffed from the content). Ogg extensions hold
+/// Vorbis, Opus or Speex alike.
+pub(super) fn extension_mismatch(
+    path: &Path,
+    detected: lofty::file::FileType,
+    detected_name: &str,
+) -> Option<Issue> {
+    use lofty::file::FileType;
+
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let expected = FileType::from_ext(&extension)?;
+    let ogg = |t: &FileType| matches!(t, FileType::Vorbis | FileType::Opus | FileType::Speex);
+    if expected == detected || (ogg(&expected) && ogg(&detected)) {
+        return None;
+    }
+    Some(Issue::ExtensionMismatch {
+        extension,
+        detected: detected_name.to_string(),
+    })
+}
@@ -1,63 +1,64 @@
-use std::path::Path;
-
 use crate::config::TrackDisplayField;
 
+use super::model::Track;
+
 /// Build a display string for a track according to the provided `fields` and separator.
 ///
-/// This composes metadata fields (artist, title, album, filename, path) in the
-/// configured order and falls back to `title` when no parts were produced.
-pub fn display_from_fields(
-    path: &Path,
-    title: &str,
-    artist: Option<&str>,
-    album: Option<&str>,
-    fields: &[TrackDisplayField],
-    sep: &str,
-) -> String {
+/// This composes metadata fields (artist, title, album, filename, path, ...) in
+/// the configured order and falls back to `title` when no parts were produced.
+/// `track.display` is not read, so this can fill it in.
+pub fn display_from_fields(track: &Track, fields: &[TrackDisplayField], sep: &str) -> String {
     let mut parts: Vec<String> = Vec::new();
 
     for f in fields {
         match f {
             TrackDisplayField::Display => {
                 // If someone includes "display" here, treat it as "artist - title" by default.
-                if let Some(a) = artist.map(str::trim).filter(|s| !s.is_empty()) {
-                    parts.push(a.to_string());
-                }
-                if !title.trim().is_empty() {
-                    parts.push(title.trim().to_string());
-                }
-            }
-            TrackDisplayField::Title => {
-                if !title.trim().is_empty() {
-                    parts.push(title.trim().to_string());
-                }
-            }
-            TrackDisplayField::Artist => {
-                if let Some(a) = artist.map(str::trim).filter(|s| !s.is_empty()) {
-                    parts.push(a.to_string());
-                }
-            }
-            TrackDisplayField::Album => {
-                if let Some(a) = album.map(str::trim).filter(|s| !s.is_empty()) {
-                    parts.push(a.to_string());
-                }
-            }
-            TrackDisplayField::Filename => {
-                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    if !stem.trim().is_empty() {
-                        parts.push(stem.to_string());
-                    }
-                }
-            }
-            TrackDisplayField::Path => {
-                parts.push(path.display().to_string());
+                parts.extend(field_text(track, TrackDisplayField::Artist));
+                parts.extend(field_text(track, TrackDisplayField::Title));
             }
+            _ => parts.extend(field_text(track, *f)),
         }
     }
 
     if parts.is_empty() {
-        title.to_string()
+        track.title.clone()
     } else {
         parts.join(sep)
     }
 }
+
+/// Text for a single `field` of `track`, or `None` when the field is empty.
+///
+/// `Display` yields the precomputed `track.display`.
+pub fn field_text(track: &Track, field: TrackDisplayField) -> Option<String> {
+    let trimmed = |s: Option<&str>| {
+        s.map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    match field {
+        TrackDisplayField::Display => trimmed(Some(&track.display)),
+        TrackDisplayField::Title => trimmed(Some(&track.title)),
+        TrackDisplayField::Artist => trimmed(Some(&track.artists.join(", "))),
+        TrackDisplayField::Album => trimmed(track.album.as_deref()),
+        TrackDisplayField::AlbumArtist => trimmed(track.album_artist.as_deref()),
+        TrackDisplayField::Composer => trimmed(track.composer.as_deref()),
+        TrackDisplayField::Genre => {
+            Some(track.genres.join(", ")).filter(|genres| !genres.is_empty())
+        }
+        // Zero-padded so "02" sorts before "10" in the track list.
+        TrackDisplayField::TrackNumber => track.track_number.map(|n| format!("{n:02}")),
+        TrackDisplayField::DiscNumber => track.disc_number.map(|n| n.to_string()),
+        TrackDisplayField::Year => track.year.map(|y| y.to_string()),
+        TrackDisplayField::Comment => trimmed(track.comment.as_deref()),
+        TrackDisplayField::Filename => track
+            .path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|stem| !stem.trim().is_empty())
+            .map(str::to_string),
+        TrackDisplayField::Path => Some(track.path.display().to_string()),
+    }
+}
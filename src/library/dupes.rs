@@ -0,0 +1,209 @@
+//! Duplicate track detection.
+//!
+//! Tracks are grouped by normalised artist and title, then split into runs
+//! lasting at most `DURATION_TOLERANCE` longer than the run's shortest copy,
+//! so a live take and the studio cut stay separate. `confirm_by_hash`
+//! optionally narrows groups to copies whose decoded audio is identical;
+//! with `content_hash` that means bit-identical copies in the same format,
+//! not the same recording re-encoded. Copies are ordered best first:
+//! lossless over lossy, then by bitrate, bit depth and sample rate.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use rodio::{Decoder, Source};
+
+use super::cache::{FNV_OFFSET, fnv1a_extend};
+use super::model::Track;
+
+/// Largest duration difference between copies of one song.
+pub const DURATION_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Codecs that never lose information (MP4 counts when it carries ALAC).
+const LOSSLESS: [&str; 5] = ["FLAC", "WAV", "AIFF", "Monkey's Audio", "WavPack"];
+
+/// Copies of one song, best first.
+#[derive(Debug, Clone)]
+pub struct DupeGroup {
+    pub copies: Vec<Track>,
+}
+
+impl DupeGroup {
+    /// `Artist - Title` of the best copy.
+    pub fn label(&self) -> String {
+        let best = &self.copies[0];
+        match best.artist().or(best.album_artist.as_deref()) {
+            Some(artist) => format!("{} - {}", artist, best.title),
+            None => best.title.clone(),
+        }
+    }
+}
+
+/// Lowercase letters and digits, words separated by single spaces, so
+/// `AC/DC` matches `ac dc` and `Song!` matches `song`.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether `track` is stored without loss.
+pub fn is_lossless(track: &Track) -> bool {
+    match track.audio.codec.as_deref() {
+        Some("MP4") => track.audio.bit_depth.is_some(),
+        Some(codec) => LOSSLESS.contains(&codec),
+        None => false,
+    }
+}
+
+/// Sort key for picking the copy to keep; larger is better.
+fn quality(track: &Track) -> (bool, u32, u8, u32) {
+    (
+        is_lossless(track),
+        track.audio.bitrate.unwrap_or(0),
+        track.audio.bit_depth.unwrap_or(0),
+        track.audio.sample_rate.unwrap_or(0),
+    )
+}
+
+/// Short description of a copy, e.g. `FLAC 24-bit 96 kHz 2304 kbps 4:01`.
+pub fn copy_summary(track: &Track) -> String {
+    let audio = &track.audio;
+    let parts = [
+        Some(audio.codec.clone().unwrap_or_else(|| "?".to_string())),
+        audio.bit_depth.map(|b| format!("{}-bit", b)),
+        audio
+            .sample_rate
+            .map(|r| format!("{} kHz", f64::from(r) / 1000.0)),
+        audio.bitrate.map(|b| format!("{} kbps", b)),
+        track.duration.map(|d| {
+            let secs = d.as_secs();
+            format!("{}:{:02}", secs / 60, secs % 60)
+        }),
+    ];
+    parts.into_iter().flatten().collect::<Vec<_>>().join(" ")
+}
+
+/// Group `tracks` into duplicates. Tracks without a title or duration are
+/// never grouped. Groups come sorted by label, copies best first.
+pub fn find_duplicates(tracks: &[Track]) -> Vec<DupeGroup> {
+    let mut by_song: BTreeMap<(String, String), Vec<&Track>> = BTreeMap::new();
+    for track in tracks.iter().filter(|t| t.duration.is_some()) {
+        let title = normalize(&track.title);
+        if title.is_empty() {
+            continue;
+        }
+        let artist = normalize(
+            track
+                .artist()
+                .or(track.album_artist.as_deref())
+                .unwrap_or_default(),
+        );
+        by_song.entry((artist, title)).or_default().push(track);
+    }
+
+    let mut groups = Vec::new();
+    for (_, mut copies) in by_song {
+        copies.sort_by_key(|t| t.duration);
+        // Start a new run once a copy is too long to match the run's first,
+        // so small steps never chain into one group.
+        let mut start = 0;
+        for i in 1..=copies.len() {
+            let split = i == copies.len()
+                || copies[i].duration.unwrap_or_default()
+                    - copies[start].duration.unwrap_or_default()
+                    > DURATION_TOLERANCE;
+            if split {
+                if i - start > 1 {
+                    groups.push(best_first(copies[start..i].iter().map(|t| (*t).clone())));
+                }
+                start = i;
+            }
+        }
+    }
+    groups
+}
+
+fn best_first(copies: impl IntoIterator<Item = Track>) -> DupeGroup {
+    let mut copies: Vec<Track> = copies.into_iter().collect();
+    copies.sort_by(|a, b| {
+        quality(b)
+            .cmp(&quality(a))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    DupeGroup { copies }
+}
+
+/// Keep only copies whose audio `hash` matches another copy in their group,
+/// splitting groups that hold several different recordings. Files `hash`
+/// cannot read are dropped.
+pub fn confirm_by_hash(
+    groups: Vec<DupeGroup>,
+    mut hash: impl FnMut(&Path) -> Option<u64>,
+) -> Vec<DupeGroup> {
+    let mut confirmed = Vec::new();
+    for group in groups {
+        let mut by_hash: BTreeMap<u64, Vec<Track>> = BTreeMap::new();
+        for track in group.copies {
+            if let Some(h) = hash(&track.path) {
+                by_hash.entry(h).or_default().push(track);
+            }
+        }
+        confirmed.extend(
+            by_hash
+                .into_values()
+                .filter(|copies| copies.len() > 1)
+                .map(best_first),
+        );
+    }
+    confirmed
+}
+
+/// Hash of the decoded audio at `path` (samples rounded to 16 bits, plus
+/// channel count and sample rate), so copies differing only in tags match.
+///
+/// This is no fingerprint: the same song encoded twice, or converted to
+/// another format or bitrate, decodes to different samples and never
+/// matches. Only identical copies of one file do.
+pub fn content_hash(path: &Path) -> Option<u64> {
+    let file = File::open(path).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let mut hash = fnv1a_extend(FNV_OFFSET, &decoder.channels().to_le_bytes());
+    hash = fnv1a_extend(hash, &decoder.sample_rate().to_le_bytes());
+    for sample in decoder {
+        let quantized = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)).round() as i16;
+        hash = fnv1a_extend(hash, &quantized.to_le_bytes());
+    }
+    Some(hash)
+}
+
+/// Where `path` goes inside `trash`: its full path below the trash folder,
+/// so the original location stays visible. A name already taken gets a
+/// `~2`, `~3`, ... suffix.
+pub fn trash_target(trash: &Path, path: &Path) -> PathBuf {
+    let relative: PathBuf = path
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    let target = trash.join(relative);
+    if !target.exists() {
+        return target;
+    }
+    let stem = target
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = target
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| target.with_file_name(format!("{}~{}{}", stem, n, ext)))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or(target)
+}
@@ -0,0 +1,315 @@
+//! Gitignore-style exclusion rules for library scans.
+//!
+//! Rules come from `[library] exclude` (relative to each root) and from
+//! `.prestoignore` files, whose rules apply to the directory holding them and
+//! everything below it. Deeper files win over shallower ones and over the
+//! config, and within a source the last matching rule wins, as in git.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::LibrarySettings;
+
+/// Name of the per-directory ignore file.
+pub(crate) const IGNORE_FILE: &str = ".prestoignore";
+
+/// One parsed pattern line.
+#[derive(Debug, Clone)]
+struct Rule {
+    /// `!pattern`: re-include what an earlier rule excluded.
+    negate: bool,
+    /// `pattern/`: only matches directories.
+    dir_only: bool,
+    /// Contains a `/` before the end: matched from the rule's base directory.
+    anchored: bool,
+    segments: Vec<String>,
+}
+
+impl Rule {
+    /// Parse one line, returning `Ok(None)` for blanks and comments.
+    fn parse(line: &str) -> Result<Option<Self>, String> {
+        let mut pattern = line.trim_end();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return Ok(None);
+        }
+        let negate = pattern.starts_with('!');
+        // Drop the `!`, or the `\` escaping a literal leading `!` or `#`.
+        if negate || pattern.starts_with("\\!") || pattern.starts_with("\\#") {
+            pattern = &pattern[1..];
+        }
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return Err("empty pattern".to_string());
+        }
+
+        let segments: Vec<String> = pattern.split('/').map(str::to_string).collect();
+        for segment in &segments {
+            check_segment(segment)?;
+        }
+        Ok(Some(Self {
+            negate,
+            dir_only,
+            anchored,
+            segments,
+        }))
+    }
+
+    /// Match `parts` (path components below the rule's base directory).
+    fn matches(&self, parts: &[String], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            match_segments(&self.segments, parts)
+        } else {
+            parts
+                .last()
+                .is_some_and(|name| glob_match(&self.segments[0], name))
+        }
+    }
+}
+
+/// Reject unterminated character classes and dangling escapes.
+fn check_segment(segment: &str) -> Result<(), String> {
+    let chars: Vec<char> = segment.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 == chars.len() => return Err("trailing backslash".to_string()),
+            '\\' => i += 1,
+            '[' => match class_end(&chars, i) {
+                Some(end) => i = end,
+                None => return Err("unclosed character class".to_string()),
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Index of the `]` closing the class opened at `start`.
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if matches!(chars.get(i), Some('!' | '^')) {
+        i += 1;
+    }
+    // A leading `]` is literal.
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    while i < chars.len() {
+        if chars[i] == ']' {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Match path components against pattern segments, where `**` spans any number of them.
+fn match_segments(pattern: &[String], parts: &[String]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=parts.len()).any(|skip| match_segments(rest, &parts[skip..]))
+        }
+        Some((first, rest)) => parts
+            .split_first()
+            .is_some_and(|(part, tail)| glob_match(first, part) && match_segments(rest, tail)),
+    }
+}
+
+/// Match one component against a glob with `*`, `?`, `[...]` and `\` escapes.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Where to resume after the most recent `*` if the rest fails to match.
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        let step = match p.get(pi) {
+            Some('*') => {
+                star = Some((pi + 1, ti));
+                pi += 1;
+                continue;
+            }
+            Some('?') => Some(pi + 1),
+            Some('[') => class_end(&p, pi)
+                .and_then(|end| class_matches(&p[pi + 1..end], t[ti]).then_some(end + 1)),
+            Some('\\') => p.get(pi + 1).filter(|&&c| c == t[ti]).map(|_| pi + 2),
+            Some(&c) => (c == t[ti]).then_some(pi + 1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(next), _) => {
+                pi = next;
+                ti += 1;
+            }
+            (None, Some((resume, from))) => {
+                pi = resume;
+                ti = from + 1;
+                star = Some((resume, from + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Test `c` against the body of a `[...]` class (without the brackets).
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, body) = match class.first() {
+        Some('!' | '^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut i = 0;
+    let mut found = false;
+    while i < body.len() {
+        if i + 2 < body.len() && body[i + 1] == '-' {
+            found |= (body[i]..=body[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= body[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+/// Parse a list of pattern lines, dropping invalid ones.
+fn parse_rules<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<Rule> {
+    lines
+        .filter_map(|l| Rule::parse(l).ok().flatten())
+        .collect()
+}
+
+/// Check `[library] exclude` patterns, returning a message for the first bad one.
+pub(crate) fn validate_patterns(patterns: &[String]) -> Result<(), String> {
+    for pattern in patterns {
+        Rule::parse(pattern).map_err(|e| format!("`{}`: {}", pattern, e))?;
+    }
+    Ok(())
+}
+
+/// Exclusion rules for one library root, loading ignore files on demand.
+pub(crate) struct Exclusions {
+    root: PathBuf,
+    config: Vec<Rule>,
+    /// Rules from each directory's ignore file (empty when it has none).
+    files: HashMap<PathBuf, Vec<Rule>>,
+}
+
+impl Exclusions {
+    pub(crate) fn new(root: &Path, settings: &LibrarySettings) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            config: parse_rules(settings.exclude.iter().map(String::as_str)),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Return true when `path` (below the root) is excluded.
+    pub(crate) fn is_excluded(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let parts: Vec<String> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        if parts.is_empty() {
+            return false;
+        }
+
+        let mut excluded = verdict(&self.config, &parts, is_dir).unwrap_or(false);
+        // Ignore files from the root down to the path's parent directory.
+        let mut dir = self.root.clone();
+        for depth in 0..parts.len() {
+            let rules = self.files.entry(dir.clone()).or_insert_with(|| {
+                fs::read_to_string(dir.join(IGNORE_FILE))
+                    .map(|text| parse_rules(text.lines()))
+                    .unwrap_or_default()
+            });
+            if let Some(v) = verdict(rules, &parts[depth..], is_dir) {
+                excluded = v;
+            }
+            dir.push(&parts[depth]);
+        }
+        excluded
+    }
+}
+
+/// Outcome of the last rule in `rules` that matches, if any.
+fn verdict(rules: &[Rule], parts: &[String], is_dir: bool) -> Option<bool> {
+    rules
+        .iter()
+        .rev()
+        .find(|r| r.matches(parts, is_dir))
+        .map(|r| !r.negate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn parts(path: &str) -> Vec<String> {
+        path.split('/').map(str::to_string).collect()
+    }
+
+    fn rule(pattern: &str) -> Rule {
+        Rule::parse(pattern).unwrap().unwrap()
+    }
+
+    #[test]
+    fn globs_follow_gitignore_semantics() {
+        assert!(glob_match("*.wav", "kick.wav"));
+        assert!(!glob_match("*.wav", "kick.wav.bak"));
+        assert!(glob_match("stem?", "stem1"));
+        assert!(glob_match("[a-c]*", "beats"));
+        assert!(!glob_match("[!a-c]*", "beats"));
+        assert!(glob_match("\\*", "*"));
+
+        // Unanchored patterns match the name at any depth.
+        assert!(rule("Samples").matches(&parts("a/b/Samples"), true));
+        // Anchored patterns match from the base directory only.
+        assert!(rule("/Samples").matches(&parts("Samples"), true));
+        assert!(!rule("/Samples").matches(&parts("a/Samples"), true));
+        assert!(rule("a/**/stems").matches(&parts("a/x/y/stems"), true));
+        assert!(rule("a/**/stems").matches(&parts("a/stems"), true));
+        // Directory-only patterns skip files.
+        assert!(!rule("stems/").matches(&parts("stems"), false));
+
+        assert!(Rule::parse("# comment").unwrap().is_none());
+        assert!(Rule::parse("[abc").is_err());
+    }
+
+    #[test]
+    fn ignore_files_apply_below_their_directory_and_can_negate() {
+        let root = tempdir().unwrap();
+        let album = root.path().join("album");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(album.join(IGNORE_FILE), "!keep.wav\n# stems\nstems/\n").unwrap();
+
+        let settings = LibrarySettings {
+            exclude: vec!["*.wav".to_string()],
+            ..LibrarySettings::default()
+        };
+        let mut ex = Exclusions::new(root.path(), &settings);
+        assert!(ex.is_excluded(&root.path().join("loop.wav"), false));
+        assert!(ex.is_excluded(&album.join("loop.wav"), false));
+        assert!(!ex.is_excluded(&album.join("keep.wav"), false));
+        assert!(ex.is_excluded(&album.join("stems"), true));
+        assert!(!ex.is_excluded(&root.path().join("stems"), true));
+        assert!(!ex.is_excluded(&album.join("song.mp3"), false));
+    }
+}
@@ -1,19 +1,89 @@
 use std::path::PathBuf;
 use std::time::Duration;
 
+use serde::{Deserialize, Serialize};
+
+use super::diagnostics::Issue;
+
 /// Representation of a single audio track discovered in the library.
-#[derive(Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Track {
     /// Filesystem path to the audio file.
     pub path: PathBuf,
     /// Track title (from tags or filename fallback).
     pub title: String,
-    /// Optional artist metadata.
-    pub artist: Option<String>,
+    /// Track artists in tag order, split on `[library] artist_separators`.
+    pub artists: Vec<String>,
     /// Optional album metadata.
     pub album: Option<String>,
+    /// Album-level artist, for compilations whose track artists differ.
+    pub album_artist: Option<String>,
+    /// Optional composer metadata.
+    pub composer: Option<String>,
+    /// Genres in tag order (a file may carry several).
+    pub genres: Vec<String>,
+    /// Position of the track on its disc.
+    pub track_number: Option<u32>,
+    /// Number of tracks on the disc.
+    pub track_total: Option<u32>,
+    /// Disc position for multi-disc albums.
+    pub disc_number: Option<u32>,
+    /// Number of discs in the album.
+    pub disc_total: Option<u32>,
+    /// Release year, from the recording date or year tag.
+    pub year: Option<u32>,
+    /// Full date string as tagged (e.g. `1997-05-21`), when more precise than the year.
+    pub date: Option<String>,
+    /// Optional free-form comment.
+    pub comment: Option<String>,
     /// Optional duration if it could be read from file properties.
     pub duration: Option<Duration>,
+    /// Codec and stream properties read from the file.
+    pub audio: AudioProperties,
+    /// When the file was first seen in the library (Unix seconds).
+    pub added: Option<u64>,
+    /// Sort-order tags (`ARTISTSORT`, ...), used in place of the plain fields when sorting.
+    pub sort_tags: SortTags,
+    /// Star rating from 1 to 5; `None` when unrated.
+    pub rating: Option<u8>,
     /// Precomputed display string used for sorting and UI.
     pub display: String,
+    /// Problems found while reading the file (see `diagnose`).
+    pub issues: Vec<Issue>,
+}
+
+impl Track {
+    /// The first listed artist, which the track sorts and browses under.
+    pub fn artist(&self) -> Option<&str> {
+        self.artists.first().map(String::as_str)
+    }
+}
+
+/// Technical properties of a track's audio stream.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioProperties {
+    /// Codec/container name (`FLAC`, `MP3`, `Vorbis`, ...).
+    pub codec: Option<String>,
+    /// Audio bitrate in kbps.
+    pub bitrate: Option<u32>,
+    /// Sample rate in Hz.
+    pub sample_rate: Option<u32>,
+    /// Bits per sample, for formats that have one.
+    pub bit_depth: Option<u8>,
+    /// Channel count.
+    pub channels: Option<u8>,
+    /// File size in bytes.
+    pub file_size: Option<u64>,
+}
+
+/// Alternative spellings used only for sorting, e.g. `Beatles, The`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SortTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    /// `display` rebuilt from the sort tags; set only when any sort tag exists.
+    pub display: Option<String>,
 }
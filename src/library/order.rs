@@ -0,0 +1,282 @@
+//! Library display order that stays sorted while tracks are appended.
+//!
+//! Track indices are stable once assigned (the audio thread, queue and
+//! selection all refer to them), so a growing library cannot be re-sorted in
+//! place. Instead both the UI and the audio thread keep a `DisplayOrder`: a
+//! permutation of indices sorted by the active sort keys, merged with each
+//! appended batch.
+
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use crate::config::{SortField, SortKey};
+
+use super::model::Track;
+
+/// How the library is sorted: keys plus text normalisation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortSpec {
+    pub keys: Vec<SortKey>,
+    /// Leading words ignored when sorting names, e.g. `The` or `Die`.
+    pub articles: Vec<String>,
+}
+
+impl Default for SortSpec {
+    /// Display label only.
+    fn default() -> Self {
+        Self {
+            keys: vec![SortKey::ascending(SortField::Display)],
+            articles: Vec::new(),
+        }
+    }
+}
+
+/// One precomputed sort value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SortValue {
+    Number(u64),
+    /// Lowercased (and article-stripped where it applies); compared naturally.
+    Text(String),
+    /// Missing values sort after present ones in either direction.
+    Missing,
+}
+
+impl SortValue {
+    pub(crate) fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a.cmp(b),
+            (Self::Text(a), Self::Text(b)) => natural_cmp(a, b),
+            (Self::Missing, Self::Missing) => Ordering::Equal,
+            (Self::Missing, _) => Ordering::Greater,
+            (_, Self::Missing) => Ordering::Less,
+            // A key never mixes numbers and text.
+            (Self::Number(_), Self::Text(_)) => Ordering::Less,
+            (Self::Text(_), Self::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+/// Compare strings so that digit runs order by value: `track 2` < `track 10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let run = |s: &[u8], start: usize| {
+                let end = start + s[start..].iter().take_while(|c| c.is_ascii_digit()).count();
+                (
+                    end,
+                    start + s[start..end].iter().take_while(|&&c| c == b'0').count(),
+                )
+            };
+            let ((end_a, sig_a), (end_b, sig_b)) = (run(a, i), run(b, j));
+            // Without leading zeros, a longer run is a bigger number.
+            let ord = (end_a - sig_a)
+                .cmp(&(end_b - sig_b))
+                .then_with(|| a[sig_a..end_a].cmp(&b[sig_b..end_b]));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            (i, j) = (end_a, end_b);
+        } else {
+            // Byte order matches code point order for UTF-8.
+            let ord = a[i].cmp(&b[j]);
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j))
+}
+
+/// Lowercase `s` and drop a leading article (`the beatles` -> `beatles`).
+///
+/// An article only counts as a separate word, or directly before the next one
+/// when it ends in an apostrophe (`L'`).
+pub(crate) fn name_key(s: &str, articles: &[String]) -> String {
+    let lower = s.trim().to_lowercase();
+    for article in articles {
+        let Some(rest) = lower.strip_prefix(article.as_str()) else {
+            continue;
+        };
+        let rest = if article.ends_with('\'') {
+            rest
+        } else if let Some(rest) = rest.strip_prefix(' ') {
+            rest
+        } else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        if !rest.is_empty() {
+            return rest.to_string();
+        }
+    }
+    lower
+}
+
+fn number(n: Option<u64>) -> SortValue {
+    n.map_or(SortValue::Missing, SortValue::Number)
+}
+
+/// Articles as `sort_value` expects them: trimmed and lowercased.
+pub(crate) fn article_keys(articles: &[String]) -> Vec<String> {
+    articles
+        .iter()
+        .map(|a| a.trim().to_lowercase())
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// What `field` compares for `track`; `articles` as from `article_keys`.
+pub(crate) fn sort_value(track: &Track, field: SortField, articles: &[String]) -> SortValue {
+    let sort = &track.sort_tags;
+    // Sort tags win over the plain field; articles are only stripped from names.
+    let name = |tagged: &Option<String>, plain: Option<&str>| match tagged
+        .as_deref()
+        .or(plain)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        Some(s) => SortValue::Text(name_key(s, articles)),
+        None => SortValue::Missing,
+    };
+    let text = |s: Option<&str>| match s.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(s) => SortValue::Text(s.to_lowercase()),
+        None => SortValue::Missing,
+    };
+
+    match field {
+        // Unlike the other text fields, the display label is never "missing".
+        SortField::Display => SortValue::Text(name_key(
+            sort.display.as_deref().unwrap_or(&track.display),
+            articles,
+        )),
+        SortField::Title => name(&sort.title, Some(&track.title)),
+        SortField::Artist => name(&sort.artist, track.artist()),
+        SortField::Album => name(&sort.album, track.album.as_deref()),
+        SortField::AlbumArtist => match sort
+            .album_artist
+            .as_deref()
+            .or(track.album_artist.as_deref())
+        {
+            Some(album_artist) => name(&None, Some(album_artist)),
+            None => name(&sort.artist, track.artist()),
+        },
+        SortField::Composer => name(&sort.composer, track.composer.as_deref()),
+        SortField::Genre => text(track.genres.first().map(String::as_str)),
+        SortField::Year => number(track.year.map(u64::from)),
+        SortField::Disc => number(track.disc_number.map(u64::from)),
+        SortField::Track => number(track.track_number.map(u64::from)),
+        SortField::Path => SortValue::Text(track.path.to_string_lossy().to_lowercase()),
+        SortField::Filename => text(track.path.file_name().and_then(|s| s.to_str())),
+        SortField::Duration => number(track.duration.map(|d| d.as_millis() as u64)),
+        SortField::Added => number(track.added),
+    }
+}
+
+/// Indices of a track list in display order.
+#[derive(Debug, Clone, Default)]
+pub struct DisplayOrder {
+    /// Active sort, with articles lowercased.
+    spec: SortSpec,
+    /// Per-track sort values, one per sort key.
+    keys: Vec<Vec<SortValue>>,
+    /// Per-track paths, the tie-break when every key is equal.
+    paths: Vec<PathBuf>,
+    order: Vec<usize>,
+}
+
+impl DisplayOrder {
+    /// Adopt `tracks` as already sorted by display label.
+    pub fn from_sorted(tracks: &[Track]) -> Self {
+        let mut order = Self::default();
+        order.keys = tracks.iter().map(|t| order.values(t)).collect();
+        order.paths = tracks.iter().map(|t| t.path.clone()).collect();
+        order.order = (0..tracks.len()).collect();
+        order
+    }
+
+    /// Re-sort from scratch with the current keys (after removals or in-place updates).
+    pub fn rebuild(&mut self, tracks: &[Track]) {
+        self.keys = tracks.iter().map(|t| self.values(t)).collect();
+        self.paths = tracks.iter().map(|t| t.path.clone()).collect();
+        let mut order: Vec<usize> = (0..tracks.len()).collect();
+        order.sort_by(|&a, &b| self.compare(a, b));
+        self.order = order;
+    }
+
+    /// Switch to a different sort and re-sort `tracks`.
+    pub fn set_sort(&mut self, tracks: &[Track], spec: &SortSpec) {
+        self.spec = SortSpec {
+            keys: spec.keys.clone(),
+            articles: article_keys(&spec.articles),
+        };
+        self.rebuild(tracks);
+    }
+
+    /// Merge every track past the last one seen into the order.
+    ///
+    /// `tracks` is the full, grown list. Equal keys order by path, so the
+    /// result does not depend on how the tracks were split into batches or
+    /// in which order the batches arrived.
+    pub fn extend(&mut self, tracks: &[Track]) {
+        let start = self.keys.len();
+        if tracks.len() <= start {
+            return;
+        }
+        let added_keys: Vec<Vec<SortValue>> =
+            tracks[start..].iter().map(|t| self.values(t)).collect();
+        self.keys.extend(added_keys);
+        self.paths
+            .extend(tracks[start..].iter().map(|t| t.path.clone()));
+
+        let mut added: Vec<usize> = (start..tracks.len()).collect();
+        added.sort_by(|&a, &b| self.compare(a, b));
+
+        let mut merged = Vec::with_capacity(self.order.len() + added.len());
+        let (mut old, mut new) = (self.order.iter().peekable(), added.into_iter().peekable());
+        while let (Some(&&o), Some(&n)) = (old.peek(), new.peek()) {
+            if self.compare(o, n) != Ordering::Greater {
+                merged.push(o);
+                old.next();
+            } else {
+                merged.push(n);
+                new.next();
+            }
+        }
+        merged.extend(old.copied());
+        merged.extend(new);
+        self.order = merged;
+    }
+
+    /// Track indices in display order.
+    pub fn as_slice(&self) -> &[usize] {
+        &self.order
+    }
+
+    fn values(&self, track: &Track) -> Vec<SortValue> {
+        self.spec
+            .keys
+            .iter()
+            .map(|key| sort_value(track, key.field, &self.spec.articles))
+            .collect()
+    }
+
+    /// Compare two tracks by index: key by key, then by path, then by index.
+    fn compare(&self, a: usize, b: usize) -> Ordering {
+        for (i, key) in self.spec.keys.iter().enumerate() {
+            let (va, vb) = (&self.keys[a][i], &self.keys[b][i]);
+            let ord = match (va, vb) {
+                (SortValue::Missing, _) | (_, SortValue::Missing) => va.cmp(vb),
+                _ if key.descending => vb.cmp(va),
+                _ => va.cmp(vb),
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        self.paths[a].cmp(&self.paths[b]).then(a.cmp(&b))
+    }
+}
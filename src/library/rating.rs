@@ -0,0 +1,140 @@
+//! Star ratings (one to five) read from and written to tags.
+//!
+//! Formats disagree on how a rating is stored. lofty hands ID3v2 `POPM`
+//! frames over as `email|stars|play count`, while Vorbis `RATING`, the MP4
+//! `rate` atom and RIFF `IRTD` arrive as plain numbers on a 0-100 (sometimes
+//! 0-5 or 0-1) scale. `FMPS_RATING` (0.0-1.0) is not mapped by lofty at all
+//! and gets lost in its generic tag, so the scan reads Vorbis-comment files
+//! as their own type and picks it up on the way.
+
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, FileType, TaggedFile};
+use lofty::flac::FlacFile;
+use lofty::ogg::{OpusFile, SpeexFile, VorbisComments, VorbisFile};
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use lofty::tag::items::popularimeter::{Popularimeter, StarRating};
+use lofty::tag::{Tag, TagType};
+
+use super::tags::edit_tag;
+
+/// Highest rating.
+pub const MAX_STARS: u8 = 5;
+
+/// `stars` as five filled or empty stars, e.g. `★★★☆☆`.
+pub fn stars_text(stars: u8) -> String {
+    let stars = usize::from(stars.min(MAX_STARS));
+    format!(
+        "{}{}",
+        "★".repeat(stars),
+        "☆".repeat(usize::from(MAX_STARS) - stars)
+    )
+}
+
+/// The first rating in `tag`, in stars.
+pub(super) fn tag_rating(tag: &Tag) -> Option<u8> {
+    tag.get_strings(ItemKey::Popularimeter)
+        .find_map(parse_rating)
+}
+
+/// Stars from one rating value: `email|stars|count`, or a bare number read
+/// as a fraction (`0.8`), as stars (`4`) or as a percentage (`80`).
+fn parse_rating(value: &str) -> Option<u8> {
+    let value = value.trim();
+    if let Some(stars) = value.split('|').nth(1) {
+        return stars.parse().ok().filter(|s| (1..=MAX_STARS).contains(s));
+    }
+    let number: f64 = value.parse().ok()?;
+    let fraction = if value.contains('.') && number <= 1.0 {
+        number
+    } else if number <= f64::from(MAX_STARS) {
+        number / f64::from(MAX_STARS)
+    } else {
+        number.min(100.0) / 100.0
+    };
+    let stars = (fraction * f64::from(MAX_STARS)).round() as u8;
+    (stars > 0).then_some(stars)
+}
+
+/// Read a probed file along with its `FMPS_RATING`. Vorbis-comment formats
+/// are read as their own type, whose comments still hold it, and only then
+/// turned into a `TaggedFile`; other formats carry no such rating.
+pub(super) fn read_with_fmps_rating<R: Read + Seek>(
+    probe: Probe<R>,
+) -> lofty::error::Result<(TaggedFile, Option<u8>)> {
+    let options = ParseOptions::new();
+    Ok(match probe.file_type() {
+        Some(FileType::Flac) => {
+            let file = FlacFile::read_from(&mut probe.into_inner(), options)?;
+            let rating = file.vorbis_comments().and_then(fmps_rating);
+            (file.into(), rating)
+        }
+        Some(FileType::Vorbis) => {
+            let file = VorbisFile::read_from(&mut probe.into_inner(), options)?;
+            let rating = fmps_rating(file.vorbis_comments());
+            (file.into(), rating)
+        }
+        Some(FileType::Opus) => {
+            let file = OpusFile::read_from(&mut probe.into_inner(), options)?;
+            let rating = fmps_rating(file.vorbis_comments());
+            (file.into(), rating)
+        }
+        Some(FileType::Speex) => {
+            let file = SpeexFile::read_from(&mut probe.into_inner(), options)?;
+            let rating = fmps_rating(file.vorbis_comments());
+            (file.into(), rating)
+        }
+        _ => (probe.read()?, None),
+    })
+}
+
+/// `FMPS_RATING` (0.0-1.0) of `comments`, in stars.
+fn fmps_rating(comments: &VorbisComments) -> Option<u8> {
+    let fraction: f64 = comments.get("FMPS_RATING")?.trim().parse().ok()?;
+    let stars = (fraction.clamp(0.0, 1.0) * f64::from(MAX_STARS)).round() as u8;
+    (stars > 0).then_some(stars)
+}
+
+/// Write `stars` (`None` or `Some(0)` clears) to the tag the scan reads
+/// ratings from.
+pub fn write_rating(path: &Path, stars: Option<u8>) -> Result<(), String> {
+    let stars = stars.filter(|&s| s > 0);
+    if stars.is_some_and(|s| s > MAX_STARS) {
+        return Err(format!("Ratings go up to {} stars", MAX_STARS));
+    }
+    edit_tag(path, |tag| {
+        let tag_type = tag.tag_type();
+        if ItemKey::Popularimeter.map_key(tag_type).is_none() {
+            return Err(format!("{:?} tags cannot hold a rating", tag_type));
+        }
+
+        // Keep the play count other players stored next to the rating.
+        let play_counter = tag.ratings().next().map_or(0, |p| p.play_counter);
+        tag.remove_key(ItemKey::Popularimeter);
+        if let Some(stars) = stars {
+            let value = match tag_type {
+                // lofty converts these to the format's own scale on save.
+                TagType::Id3v2 | TagType::RiffInfo => {
+                    Popularimeter::custom("", star_rating(stars), play_counter).to_string()
+                }
+                // The rest are saved verbatim, so use the common 0-100 scale.
+                _ => (u32::from(stars) * 20).to_string(),
+            };
+            tag.insert_text(ItemKey::Popularimeter, value);
+        }
+        Ok(())
+    })
+}
+
+fn star_rating(stars: u8) -> StarRating {
+    match stars {
+        1 => StarRating::One,
+        2 => StarRating::Two,
+        3 => StarRating::Three,
+        4 => StarRating::Four,
+        _ => StarRating::Five,
+    }
+}
@@ -1,17 +1,26 @@
-use std::path::Path;
-use std::time::Duration;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, mpsc};
+use std::thread;
 
 // use lofty::{AudioFile, ItemKey, TaggedFileExt};
+use lofty::error::{ErrorKind, LoftyError};
+use lofty::file::TaggedFile;
 use lofty::prelude::*;
+use lofty::probe::Probe;
 use walkdir::WalkDir;
 
 use crate::config::LibrarySettings;
 
+use super::cache::{FileStamp, LibraryIndex, TrackTags};
+use super::diagnostics::{Diagnostic, Issue, extension_mismatch};
 use super::display::display_from_fields;
-use super::model::Track;
+use super::ignore::Exclusions;
+use super::model::{AudioProperties, SortTags, Track};
+use super::rating::{read_with_fmps_rating, tag_rating};
 
 /// Return true when `path`'s extension matches configured audio extensions.
-fn is_audio_file(path: &Path, settings: &LibrarySettings) -> bool {
+pub(super) fn is_audio_file(path: &Path, settings: &LibrarySettings) -> bool {
     let exts: Vec<String> = settings
         .extensions
         .iter()
@@ -29,18 +38,207 @@ fn is_audio_file(path: &Path, settings: &LibrarySettings) -> bool {
 }
 
 /// Return true if the path's final component is a hidden (dot) file.
-fn is_hidden(path: &Path) -> bool {
+pub(super) fn is_hidden(path: &Path) -> bool {
     path.file_name()
         .and_then(|s| s.to_str())
         .map(|name| name.starts_with('.'))
         .unwrap_or(false)
 }
 
-/// Scan `dir` for audio files according to `settings` and return a sorted list
-/// of `Track` entries with metadata extracted when available.
-pub fn scan(dir: &Path, settings: &LibrarySettings) -> Vec<Track> {
-    let mut tracks: Vec<Track> = Vec::new();
+/// Read tag fields and duration from `path`, falling back to the file stem as title.
+pub(crate) fn read_tags(path: &Path) -> TrackTags {
+    let default_title = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("UNKNOWN")
+        .to_string();
+
+    let mut tags = TrackTags {
+        title: default_title,
+        ..TrackTags::default()
+    };
+
+    // The modification time stands in for "date added" until the index
+    // remembers when the file was first seen.
+    tags.added = FileStamp::of(path).map(|stamp| stamp.mtime.0);
+    tags.audio.file_size = std::fs::metadata(path).ok().map(|m| m.len());
+
+    let (tagged, fmps_rating) = match read_tagged(path) {
+        Ok((tagged, fmps_rating, mismatch)) => {
+            tags.issues.extend(mismatch);
+            (tagged, fmps_rating)
+        }
+        Err(err) => {
+            tags.issues.push(read_issue(&err));
+            return tags;
+        }
+    };
+    let props = tagged.properties();
+    tags.duration = Some(props.duration());
+    if props.duration().is_zero() {
+        tags.issues.push(Issue::ZeroDuration);
+    }
+    tags.audio = AudioProperties {
+        codec: Some(codec_name(tagged.file_type())),
+        bitrate: props.audio_bitrate().or(props.overall_bitrate()),
+        sample_rate: props.sample_rate(),
+        bit_depth: props.bit_depth(),
+        channels: props.channels(),
+        file_size: tags.audio.file_size,
+    };
+
+    let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) else {
+        tags.issues.push(Issue::Untagged);
+        return tags;
+    };
+    match text(tag, ItemKey::TrackTitle) {
+        Some(v) => tags.title = v,
+        None => tags.issues.push(Issue::NoTitle),
+    }
+    // A multi-artist list (`ARTISTS`) beats the display string in `ARTIST`.
+    tags.artists = texts(tag, ItemKey::TrackArtists);
+    if tags.artists.is_empty() {
+        tags.artists = texts(tag, ItemKey::TrackArtist);
+    }
+    tags.album = text(tag, ItemKey::AlbumTitle);
+    tags.album_artist = text(tag, ItemKey::AlbumArtist);
+    tags.composer = text(tag, ItemKey::Composer);
+    tags.comment = text(tag, ItemKey::Comment);
+    tags.sort_tags = SortTags {
+        title: text(tag, ItemKey::TrackTitleSortOrder),
+        artist: text(tag, ItemKey::TrackArtistSortOrder),
+        album: text(tag, ItemKey::AlbumTitleSortOrder),
+        album_artist: text(tag, ItemKey::AlbumArtistSortOrder),
+        composer: text(tag, ItemKey::ComposerSortOrder),
+        display: None,
+    };
+    tags.genres = texts(tag, ItemKey::Genre);
+    tags.rating = tag_rating(tag).or(fmps_rating);
+
+    tags.track_number = tag.track();
+    tags.track_total = tag.track_total();
+    tags.disc_number = tag.disk();
+    tags.disc_total = tag.disk_total();
+    if let Some(date) = tag.date() {
+        tags.year = Some(u32::from(date.year));
+        // Only keep the full date when it says more than the year.
+        if date.month.is_some() {
+            tags.date = Some(date.to_string());
+        }
+    }
+    if tags.artists.is_empty() && tags.album_artist.is_none() {
+        tags.issues.push(Issue::MissingArtist);
+    }
+    if tags.album.is_none() {
+        tags.issues.push(Issue::MissingAlbum);
+    }
+
+    tags
+}
+
+/// Read `path` as the format its content shows, which may differ from its
+/// extension, with its `FMPS_RATING`; the mismatch is returned as an issue.
+fn read_tagged(path: &Path) -> lofty::error::Result<(TaggedFile, Option<u8>, Option<Issue>)> {
+    let (tagged, fmps_rating) = read_with_fmps_rating(Probe::open(path)?.guess_file_type()?)?;
+    let detected = tagged.file_type();
+    let mismatch = extension_mismatch(path, detected, &codec_name(detected));
+    Ok((tagged, fmps_rating, mismatch))
+}
+
+/// Issue for a file lofty could not read.
+fn read_issue(err: &LoftyError) -> Issue {
+    match err.kind() {
+        ErrorKind::Io(e) if e.kind() == io::ErrorKind::PermissionDenied => Issue::PermissionDenied,
+        _ => Issue::Unreadable(err.to_string()),
+    }
+}
+
+/// Human-readable name for a detected file type.
+fn codec_name(file_type: lofty::file::FileType) -> String {
+    use lofty::file::FileType;
+
+    match file_type {
+        FileType::Aac => "AAC",
+        FileType::Aiff => "AIFF",
+        FileType::Ape => "Monkey's Audio",
+        FileType::Flac => "FLAC",
+        FileType::Mpeg => "MP3",
+        FileType::Mp4 => "MP4",
+        FileType::Mpc => "Musepack",
+        FileType::Opus => "Opus",
+        FileType::Vorbis => "Vorbis",
+        FileType::Speex => "Speex",
+        FileType::Wav => "WAV",
+        FileType::WavPack => "WavPack",
+        FileType::Custom(name) => name,
+        other => return format!("{other:?}"),
+    }
+    .to_string()
+}
+
+/// Trimmed, non-empty string value for `key`.
+fn text(tag: &lofty::tag::Tag, key: ItemKey) -> Option<String> {
+    tag.get_string(key)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Every trimmed, non-empty string value for `key`, without repeats.
+fn texts(tag: &lofty::tag::Tag, key: ItemKey) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+    for value in tag.get_strings(key).map(str::trim) {
+        if !value.is_empty() && !values.iter().any(|v| v == value) {
+            values.push(value.to_string());
+        }
+    }
+    values
+}
 
+/// Split each of `values` at NULs and at `separators` (ASCII case-insensitive),
+/// dropping empty and repeated (case-insensitively) entries.
+pub(super) fn split_values(values: &[String], separators: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for value in values {
+        let mut parts = vec![value.as_str()];
+        for separator in std::iter::once("\0").chain(separators.iter().map(String::as_str)) {
+            parts = parts
+                .into_iter()
+                .flat_map(|part| split_ignore_case(part, separator))
+                .collect();
+        }
+        for part in parts.into_iter().map(str::trim).filter(|p| !p.is_empty()) {
+            if !out.iter().any(|o| o.to_lowercase() == part.to_lowercase()) {
+                out.push(part.to_string());
+            }
+        }
+    }
+    out
+}
+
+/// Split `text` at every ASCII case-insensitive occurrence of `separator`.
+fn split_ignore_case<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    // ASCII lowercasing keeps byte offsets, so they index `text` too.
+    let lower = text.to_ascii_lowercase();
+    let separator = separator.to_ascii_lowercase();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (at, _) in lower.match_indices(&separator) {
+        parts.push(&text[start..at]);
+        start = at + separator.len();
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Configure a walk of `dir` honouring depth, hidden, symlink and exclusion
+/// settings. Entries that cannot be listed go to `on_error`.
+fn library_walk<'a>(
+    dir: &Path,
+    settings: &'a LibrarySettings,
+    mut exclusions: Exclusions,
+    mut on_error: impl FnMut(walkdir::Error) + 'a,
+) -> impl Iterator<Item = walkdir::DirEntry> + 'a {
     let mut walker = WalkDir::new(dir).follow_links(settings.follow_links);
 
     // Non-recursive = only the root directory.
@@ -53,80 +251,260 @@ pub fn scan(dir: &Path, settings: &LibrarySettings) -> Vec<Track> {
         walker = walker.max_depth(d);
     }
 
-    for entry in walker
+    walker
         .into_iter()
-        .filter_entry(|e| settings.include_hidden || e.depth() == 0 || !is_hidden(e.path()))
-        .filter_map(Result::ok)
-    {
-        let path = entry.path();
-        if path.is_file()
-            && (settings.include_hidden || !is_hidden(path))
-            && is_audio_file(path, settings)
-        {
-            let default_title = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("UNKNOWN")
-                .to_string();
-
-            let mut title = default_title;
-            let mut artist: Option<String> = None;
-            let mut album: Option<String> = None;
-            let mut duration: Option<Duration> = None;
-
-            if let Ok(tagged) = lofty::read_from_path(path) {
-                duration = Some(tagged.properties().duration());
-
-                if let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) {
-                    if let Some(v) = tag.get_string(ItemKey::TrackTitle) {
-                        if !v.trim().is_empty() {
-                            title = v.to_string();
-                        }
-                    }
-                    if let Some(v) = tag.get_string(ItemKey::TrackArtist) {
-                        let v = v.trim();
-                        if !v.is_empty() {
-                            artist = Some(v.to_string());
+        .filter_entry(move |e| {
+            e.depth() == 0
+                || ((settings.include_hidden || !is_hidden(e.path()))
+                    && !exclusions.is_excluded(e.path(), e.file_type().is_dir()))
+        })
+        .filter_map(move |entry| entry.map_err(&mut on_error).ok())
+}
+
+/// Walk `dir` according to `settings`, yielding audio files in walk order.
+///
+/// `exclusions` belong to the library root, which may be above `dir`.
+pub(super) fn audio_files<'a>(
+    dir: &Path,
+    settings: &'a LibrarySettings,
+    exclusions: Exclusions,
+    on_error: impl FnMut(walkdir::Error) + 'a,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    library_walk(dir, settings, exclusions, on_error)
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            path.is_file()
+                && (settings.include_hidden || !is_hidden(path))
+                && is_audio_file(path, settings)
+        })
+}
+
+/// Walk `dir` according to `settings`, yielding every directory that may hold
+/// library files (`dir` itself included).
+pub(super) fn library_dirs<'a>(
+    dir: &Path,
+    settings: &'a LibrarySettings,
+    exclusions: Exclusions,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    let file_depth_cap = if settings.recursive {
+        settings.max_depth
+    } else {
+        Some(1)
+    };
+    library_walk(dir, settings, exclusions, |_| {})
+        .filter(move |e| e.file_type().is_dir() && file_depth_cap.is_none_or(|cap| e.depth() < cap))
+        .map(|entry| entry.into_path())
+}
+
+/// Number of tag-reading workers to use (`0` in settings means one per CPU).
+fn worker_count(settings: &LibrarySettings) -> usize {
+    match settings.scan_workers {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// A discovered file and what is needed to record its tags in the index.
+struct Candidate {
+    path: PathBuf,
+    stamp: Option<FileStamp>,
+    fresh: bool,
+    emitted: bool,
+}
+
+/// Record `path` as a candidate, returning its slot and any still-valid cached tags.
+fn discover(
+    dir: &Path,
+    path: PathBuf,
+    index: &mut LibraryIndex,
+    candidates: &mut Vec<Candidate>,
+) -> (usize, Option<TrackTags>) {
+    // Key the index relative to the scan root so it survives cwd changes.
+    let key = path.strip_prefix(dir).unwrap_or(&path);
+    let stamp = FileStamp::of(&path);
+    let tags = index.lookup(key, stamp);
+    candidates.push(Candidate {
+        path,
+        stamp,
+        fresh: tags.is_none(),
+        emitted: false,
+    });
+    (candidates.len() - 1, tags)
+}
+
+/// Scan `dir` for audio files according to `settings`, handing each track to
+/// `on_track` as soon as it is ready.
+///
+/// Tags are reused from `index` for files whose size and mtime are
+/// unchanged; new and changed files are read by a pool of
+/// `settings.scan_workers` threads fed by the directory walk and recorded,
+/// and entries for files that were not found are dropped from the index.
+/// Cached tracks arrive during the walk and freshly read ones as workers
+/// finish them, so the order is unspecified; callers keep their own sort
+/// (see `DisplayOrder`). Returns the entries the walk could not list.
+pub fn scan_streaming(
+    dir: &Path,
+    settings: &LibrarySettings,
+    index: &mut LibraryIndex,
+    mut on_track: impl FnMut(Track),
+) -> Vec<Diagnostic> {
+    let workers = worker_count(settings);
+    let mut walk_errors: Vec<Diagnostic> = Vec::new();
+    let on_error = |err: walkdir::Error| walk_errors.extend(Diagnostic::from_walk_error(&err));
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut finish = |candidates: &mut [Candidate],
+                      index: &mut LibraryIndex,
+                      slot: usize,
+                      mut tags: TrackTags| {
+        let candidate = &mut candidates[slot];
+        if candidate.fresh {
+            let key = candidate.path.strip_prefix(dir).unwrap_or(&candidate.path);
+            tags = index.insert(key, candidate.stamp, tags);
+        }
+        candidate.emitted = true;
+        on_track(build_track(candidate.path.clone(), tags, settings));
+    };
+
+    if workers <= 1 {
+        for path in audio_files(dir, settings, Exclusions::new(dir, settings), on_error) {
+            let (slot, cached) = discover(dir, path, index, &mut candidates);
+            let tags = cached.unwrap_or_else(|| read_tags(&candidates[slot].path));
+            finish(&mut candidates, index, slot, tags);
+        }
+    } else {
+        let (job_tx, job_rx) = mpsc::channel::<(usize, PathBuf)>();
+        let (done_tx, done_rx) = mpsc::channel::<(usize, TrackTags)>();
+        let job_rx = Mutex::new(job_rx);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let job_rx = &job_rx;
+                let done_tx = done_tx.clone();
+                scope.spawn(move || {
+                    loop {
+                        // Hold the lock only while waiting for the next job.
+                        let job = match job_rx.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok((slot, path)) = job else {
+                            return;
+                        };
+                        if done_tx.send((slot, read_tags(&path))).is_err() {
+                            return;
                         }
                     }
-                    if let Some(v) = tag.get_string(ItemKey::AlbumTitle) {
-                        let v = v.trim();
-                        if !v.is_empty() {
-                            album = Some(v.to_string());
-                        }
+                });
+            }
+            drop(done_tx);
+
+            for path in audio_files(dir, settings, Exclusions::new(dir, settings), on_error) {
+                match discover(dir, path, index, &mut candidates) {
+                    (slot, Some(tags)) => finish(&mut candidates, index, slot, tags),
+                    (slot, None) => {
+                        let _ = job_tx.send((slot, candidates[slot].path.clone()));
                     }
                 }
+                // Pass on whatever the workers finished while we were walking.
+                while let Ok((slot, tags)) = done_rx.try_recv() {
+                    finish(&mut candidates, index, slot, tags);
+                }
             }
+            drop(job_tx);
 
-            let display = display_from_fields(
-                path,
-                &title,
-                artist.as_deref(),
-                album.as_deref(),
-                &settings.display_fields,
-                &settings.display_separator,
-            );
+            for (slot, tags) in done_rx {
+                finish(&mut candidates, index, slot, tags);
+            }
+        });
+    }
 
-            tracks.push(Track {
-                path: path.to_path_buf(),
-                title,
-                artist,
-                album,
-                duration,
-                display,
-            });
+    // A worker that died mid-file leaves no result; fall back to reading here.
+    for slot in 0..candidates.len() {
+        if !candidates[slot].emitted {
+            let tags = read_tags(&candidates[slot].path);
+            finish(&mut candidates, index, slot, tags);
         }
     }
+    index.retain_seen();
+    walk_errors
+}
 
-    tracks.sort_by(|a, b| a.display.to_lowercase().cmp(&b.display.to_lowercase()));
-    tracks
+/// Build a `Track` from its path and tag fields.
+pub(super) fn build_track(path: PathBuf, tags: TrackTags, settings: &LibrarySettings) -> Track {
+    let mut track = Track {
+        path,
+        title: tags.title,
+        artists: split_values(&tags.artists, &settings.artist_separators),
+        album: tags.album,
+        album_artist: tags.album_artist,
+        composer: tags.composer,
+        genres: split_values(&tags.genres, &settings.genre_separators),
+        track_number: tags.track_number,
+        track_total: tags.track_total,
+        disc_number: tags.disc_number,
+        disc_total: tags.disc_total,
+        year: tags.year,
+        date: tags.date,
+        comment: tags.comment,
+        rating: tags.rating,
+        duration: tags.duration,
+        audio: tags.audio,
+        added: tags.added,
+        sort_tags: tags.sort_tags,
+        issues: tags.issues,
+        display: String::new(),
+    };
+    track.display = display_from_fields(
+        &track,
+        &settings.display_fields,
+        &settings.display_separator,
+    );
+    track.sort_tags.display = sort_display(&track, settings);
+    track
+}
+
+/// Build the display string from sort tags, if the track has any.
+fn sort_display(track: &Track, settings: &LibrarySettings) -> Option<String> {
+    let sort = &track.sort_tags;
+    if [
+        &sort.title,
+        &sort.artist,
+        &sort.album,
+        &sort.album_artist,
+        &sort.composer,
+    ]
+    .iter()
+    .all(|tag| tag.is_none())
+    {
+        return None;
+    }
+    let sortable = Track {
+        title: sort.title.clone().unwrap_or_else(|| track.title.clone()),
+        artists: match &sort.artist {
+            Some(artist) => vec![artist.clone()],
+            None => track.artists.clone(),
+        },
+        album: sort.album.clone().or_else(|| track.album.clone()),
+        album_artist: sort
+            .album_artist
+            .clone()
+            .or_else(|| track.album_artist.clone()),
+        composer: sort.composer.clone().or_else(|| track.composer.clone()),
+        ..track.clone()
+    };
+    Some(display_from_fields(
+        &sortable,
+        &settings.display_fields,
+        &settings.display_separator,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::TrackDisplayField;
+    use crate::library::tests::{scan, write_flac, write_wav};
     use std::fs;
+    use std::time::Duration;
     use tempfile::tempdir;
 
     #[test]
@@ -221,4 +599,277 @@ mod tests {
         assert!(names.contains(&"one".to_string()));
         assert!(!names.contains(&"two".to_string()));
     }
+
+    #[test]
+    fn scan_skips_excluded_patterns_and_prestoignore_entries() {
+        let dir = tempdir().unwrap();
+        let samples = dir.path().join("Samples");
+        let album = dir.path().join("album");
+        fs::create_dir_all(&samples).unwrap();
+        fs::create_dir_all(album.join("stems")).unwrap();
+        fs::write(samples.join("kick.mp3"), b"not real").unwrap();
+        fs::write(album.join("song.mp3"), b"not real").unwrap();
+        fs::write(album.join("song.wav"), b"not real").unwrap();
+        fs::write(album.join("stems").join("vox.mp3"), b"not real").unwrap();
+        fs::write(album.join(".prestoignore"), "stems/\n").unwrap();
+
+        let settings = LibrarySettings {
+            exclude: vec!["/Samples/".to_string(), "*.wav".to_string()],
+            display_fields: vec![TrackDisplayField::Filename],
+            ..LibrarySettings::default()
+        };
+        let names: Vec<String> = scan(dir.path(), &settings)
+            .into_iter()
+            .map(|t| t.display)
+            .collect();
+        assert_eq!(names, vec!["song"]);
+    }
+
+    #[test]
+    fn read_tags_extracts_numbers_dates_genres_and_credits() {
+        use lofty::config::WriteOptions;
+        use lofty::tag::{ItemValue, Tag, TagItem, TagType};
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("song.wav");
+        write_wav(&path, 800);
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_title("Song".to_string());
+        tag.insert_text(ItemKey::AlbumArtist, "Various Artists".to_string());
+        tag.insert_text(ItemKey::Composer, "Someone".to_string());
+        tag.insert_text(ItemKey::Comment, "  ".to_string());
+        for artist in ["Miles Davis feat. John Coltrane", "Bill Evans"] {
+            tag.push(TagItem::new(
+                ItemKey::TrackArtist,
+                ItemValue::Text(artist.to_string()),
+            ));
+        }
+        tag.push(TagItem::new(
+            ItemKey::Genre,
+            ItemValue::Text("Jazz".to_string()),
+        ));
+        tag.push(TagItem::new(
+            ItemKey::Genre,
+            ItemValue::Text("Soul".to_string()),
+        ));
+        tag.set_track(3);
+        tag.set_track_total(12);
+        tag.set_disk(2);
+        tag.insert_text(ItemKey::RecordingDate, "1997-05-21".to_string());
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+        let tags = read_tags(&path);
+        assert_eq!(tags.title, "Song");
+        assert_eq!(tags.album_artist.as_deref(), Some("Various Artists"));
+        assert_eq!(tags.composer.as_deref(), Some("Someone"));
+        assert_eq!(tags.comment, None);
+        assert_eq!(tags.genres, vec!["Jazz", "Soul"]);
+        assert_eq!(
+            build_track(path.clone(), tags.clone(), &LibrarySettings::default()).artists,
+            ["Miles Davis", "John Coltrane", "Bill Evans"]
+        );
+        assert_eq!((tags.track_number, tags.track_total), (Some(3), Some(12)));
+        assert_eq!((tags.disc_number, tags.disc_total), (Some(2), None));
+        assert_eq!(tags.year, Some(1997));
+        assert_eq!(tags.date.as_deref(), Some("1997-05-21"));
+
+        // Stream properties come from the WAV header written above.
+        assert_eq!(tags.audio.codec.as_deref(), Some("WAV"));
+        assert_eq!(tags.audio.sample_rate, Some(8000));
+        assert_eq!(tags.audio.bit_depth, Some(16));
+        assert_eq!(tags.audio.channels, Some(1));
+        assert_eq!(
+            tags.audio.file_size,
+            Some(fs::metadata(&path).unwrap().len())
+        );
+    }
+
+    #[test]
+    fn ratings_are_read_from_each_format_and_written_back() {
+        use crate::library::write_rating;
+
+        let dir = tempdir().unwrap();
+        let flac = |name: &str, comments: &[&str]| {
+            let path = dir.path().join(name);
+            write_flac(&path, comments);
+            read_tags(&path).rating
+        };
+        assert_eq!(flac("percent.flac", &["TITLE=A", "RATING=80"]), Some(4));
+        assert_eq!(flac("fmps.flac", &["TITLE=B", "FMPS_RATING=0.6"]), Some(3));
+        assert_eq!(flac("none.flac", &["TITLE=C"]), None);
+
+        let path = dir.path().join("song.wav");
+        write_wav(&path, 800);
+        write_rating(&path, Some(4)).unwrap();
+        assert_eq!(read_tags(&path).rating, Some(4));
+        write_rating(&path, Some(0)).unwrap();
+        assert_eq!(read_tags(&path).rating, None);
+
+        let path = dir.path().join("percent.flac");
+        write_rating(&path, Some(2)).unwrap();
+        assert_eq!(read_tags(&path).rating, Some(2));
+        // Vorbis comments get the common 0-100 scale, not lofty's internal form.
+        let written = fs::read(&path).unwrap();
+        assert!(written.windows(9).any(|w| w == b"RATING=40"));
+
+        // Rating a file that only had `FMPS_RATING` keeps it and other comments.
+        let path = dir.path().join("fmps.flac");
+        write_flac(&path, &["TITLE=B", "FMPS_RATING=0.6", "CUSTOMFIELD=x"]);
+        write_rating(&path, Some(5)).unwrap();
+        assert_eq!(read_tags(&path).rating, Some(5));
+        let written = fs::read(&path).unwrap();
+        for kept in ["RATING=100", "FMPS_RATING=0.6", "CUSTOMFIELD=x"] {
+            assert!(written.windows(kept.len()).any(|w| w == kept.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn split_values_splits_on_separators_and_drops_repeats() {
+        let separators: Vec<String> = [";", " / ", " FEAT. "].map(String::from).to_vec();
+        let values = [
+            "Alpha feat. Beta; Gamma".to_string(),
+            "AC/DC / alpha\0Delta ;".to_string(),
+        ];
+        assert_eq!(
+            split_values(&values, &separators),
+            ["Alpha", "Beta", "Gamma", "AC/DC", "Delta"]
+        );
+        assert!(split_values(&["  ".to_string()], &separators).is_empty());
+    }
+
+    #[test]
+    fn parallel_scan_matches_sequential_scan() {
+        let dir = tempdir().unwrap();
+        for i in 0..40 {
+            let sub = dir.path().join(format!("d{}", i % 3));
+            fs::create_dir_all(&sub).unwrap();
+            // Case-only collisions tie on the display sort.
+            let name = if i % 2 == 0 { "Song" } else { "song" };
+            if i % 4 == 0 {
+                write_wav(&sub.join(format!("{name}{}.wav", i / 4)), 800 * (i + 1));
+            } else {
+                fs::write(sub.join(format!("{name}{}.mp3", i / 4)), b"not real").unwrap();
+            }
+        }
+
+        let summary = |workers: usize| {
+            let settings = LibrarySettings {
+                scan_workers: workers,
+                ..LibrarySettings::default()
+            };
+            scan(dir.path(), &settings)
+                .into_iter()
+                .map(|t| (t.path, t.display, t.duration))
+                .collect::<Vec<_>>()
+        };
+
+        let sequential = summary(1);
+        assert_eq!(sequential.len(), 40);
+        assert!(
+            sequential
+                .iter()
+                .any(|t| t.2 == Some(Duration::from_millis(100)))
+        );
+        assert_eq!(summary(4), sequential);
+        assert_eq!(summary(0), sequential);
+    }
+
+    #[test]
+    fn streamed_batches_sort_the_same_whatever_their_arrival_order() {
+        let dir = tempdir().unwrap();
+        let mut expected = Vec::new();
+        for d in 0..4 {
+            let sub = dir.path().join(format!("d{d}"));
+            fs::create_dir_all(&sub).unwrap();
+            // Every folder repeats the same labels, two of them case-only variants.
+            for (n, name) in ["Song", "song", "Intro"].iter().enumerate() {
+                let path = if (d + n) % 2 == 0 {
+                    sub.join(format!("{name}.wav"))
+                } else {
+                    sub.join(format!("{name}.mp3"))
+                };
+                if path.extension().unwrap() == "wav" {
+                    write_wav(&path, 800);
+                } else {
+                    fs::write(&path, b"not real").unwrap();
+                }
+                expected.push(path);
+            }
+        }
+        // Label first, then path.
+        expected.sort_by_key(|p| (p.file_stem().unwrap().to_ascii_lowercase(), p.clone()));
+
+        let ordered = |tracks: &[Track], batch: usize| -> Vec<PathBuf> {
+            let mut order = crate::library::DisplayOrder::default();
+            for end in (batch..tracks.len()).step_by(batch) {
+                order.extend(&tracks[..end]);
+            }
+            order.extend(tracks);
+            order
+                .as_slice()
+                .iter()
+                .map(|&i| tracks[i].path.clone())
+                .collect()
+        };
+        for workers in [1, 4] {
+            let settings = LibrarySettings {
+                scan_workers: workers,
+                ..LibrarySettings::default()
+            };
+            let mut index = LibraryIndex::in_memory();
+            // The second pass serves every track from the index.
+            for _ in 0..2 {
+                let mut streamed: Vec<Track> = Vec::new();
+                scan_streaming(dir.path(), &settings, &mut index, |t| streamed.push(t));
+                assert_eq!(ordered(&streamed, 1), expected);
+                assert_eq!(ordered(&streamed, 5), expected);
+                streamed.reverse();
+                assert_eq!(ordered(&streamed, 2), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn scan_diagnoses_broken_untagged_and_mislabelled_files() {
+        use crate::library::diagnose;
+
+        let dir = tempdir().unwrap();
+        write_wav(&dir.path().join("plain.wav"), 800);
+        write_wav(&dir.path().join("wav inside.flac"), 800);
+        fs::write(dir.path().join("broken.mp3"), b"not real").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("gone"), dir.path().join("dangling.mp3"))
+            .unwrap();
+        let settings = LibrarySettings::default();
+
+        let mut index = LibraryIndex::in_memory();
+        for _ in 0..2 {
+            // The second pass comes from the index and must report the same.
+            let mut tracks = Vec::new();
+            let walk = scan_streaming(dir.path(), &settings, &mut index, |t| tracks.push(t));
+            let report: Vec<(String, Issue)> = diagnose(&tracks, &walk)
+                .into_iter()
+                .map(|d| {
+                    (
+                        d.path.file_name().unwrap().to_string_lossy().into(),
+                        d.issue,
+                    )
+                })
+                .collect();
+            let names: Vec<(&str, &str)> = report
+                .iter()
+                .map(|(name, issue)| (name.as_str(), issue.kind()))
+                .collect();
+            assert_eq!(
+                names,
+                [
+                    ("broken.mp3", "unreadable"),
+                    ("dangling.mp3", "walk-failed"),
+                    ("plain.wav", "untagged"),
+                    ("wav inside.flac", "extension-mismatch"),
+                    ("wav inside.flac", "untagged"),
+                ]
+            );
+            assert_eq!(report[3].1.to_string(), ".flac file contains WAV");
+        }
+    }
 }
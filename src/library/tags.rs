@@ -0,0 +1,484 @@
+//! Writing edited tag fields back to audio files.
+//!
+//! Edits go to the tag the scan reads from (the primary tag, else the first
+//! one; a file without tags gets a new primary tag), leaving items the edit
+//! does not touch as they were. Afterwards the track is re-read, so the
+//! library shows what the file actually holds. Batch retags also record each
+//! field's items as they were (`RawField`), so an undo restores them exactly.
+
+use std::path::Path;
+
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::{AudioFile, FileType};
+use lofty::flac::FlacFile;
+use lofty::ogg::{OpusFile, SpeexFile, VorbisComments, VorbisFile};
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use lofty::tag::{ItemValue, Tag, TagItem, TagType};
+use serde::{Deserialize, Serialize};
+
+use crate::config::LibrarySettings;
+
+use super::model::Track;
+use super::scan::{build_track, read_tags, split_values};
+
+/// A tag field the editor can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    TrackNumber,
+    DiscNumber,
+    Year,
+    Genre,
+}
+
+impl TagField {
+    /// Every editable field, in form order.
+    pub const ALL: [TagField; 8] = [
+        Self::Title,
+        Self::Artist,
+        Self::Album,
+        Self::AlbumArtist,
+        Self::TrackNumber,
+        Self::DiscNumber,
+        Self::Year,
+        Self::Genre,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Title => "Title",
+            Self::Artist => "Artist",
+            Self::Album => "Album",
+            Self::AlbumArtist => "Album artist",
+            Self::TrackNumber => "Track",
+            Self::DiscNumber => "Disc",
+            Self::Year => "Year",
+            Self::Genre => "Genre",
+        }
+    }
+
+    /// The field's current value as editable text; artists and genres are
+    /// joined with the first of their `separators`.
+    pub fn value(self, track: &Track, settings: &LibrarySettings) -> String {
+        let list = |items: &[String]| {
+            let separators = self.separators(settings);
+            // A bare `;` or `,` reads better with a space after it.
+            let joiner = match separators[0].as_str() {
+                sep @ (";" | ",") => format!("{} ", sep),
+                sep => sep.to_string(),
+            };
+            items.join(&joiner)
+        };
+        match self {
+            Self::Artist => list(&track.artists),
+            Self::Genre => list(&track.genres),
+            _ => self.single_value(track).unwrap_or_default(),
+        }
+    }
+
+    /// The text of a single-valued field; `None` for artists and genres.
+    pub(super) fn single_value(self, track: &Track) -> Option<String> {
+        let number = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+        Some(match self {
+            Self::Title => track.title.clone(),
+            Self::Album => track.album.clone().unwrap_or_default(),
+            Self::AlbumArtist => track.album_artist.clone().unwrap_or_default(),
+            Self::TrackNumber => number(track.track_number),
+            Self::DiscNumber => number(track.disc_number),
+            Self::Year => number(track.year),
+            Self::Artist | Self::Genre => return None,
+        })
+    }
+
+    /// Separators a list field's text is split at, in both directions: the
+    /// scan's `[library] artist_separators` or `genre_separators`, else `;`.
+    fn separators(self, settings: &LibrarySettings) -> Vec<String> {
+        let configured = match self {
+            Self::Artist => &settings.artist_separators,
+            Self::Genre => &settings.genre_separators,
+            _ => return Vec::new(),
+        };
+        if configured.is_empty() {
+            vec![";".to_string()]
+        } else {
+            configured.clone()
+        }
+    }
+
+    /// Tag items the field is stored in; writing it replaces all of them.
+    fn item_keys(self) -> &'static [ItemKey] {
+        match self {
+            Self::Title => &[ItemKey::TrackTitle],
+            Self::Artist => &[ItemKey::TrackArtist, ItemKey::TrackArtists],
+            Self::Album => &[ItemKey::AlbumTitle],
+            Self::AlbumArtist => &[ItemKey::AlbumArtist],
+            Self::TrackNumber => &[ItemKey::TrackNumber],
+            Self::DiscNumber => &[ItemKey::DiscNumber],
+            Self::Year => &[ItemKey::RecordingDate, ItemKey::Year],
+            Self::Genre => &[ItemKey::Genre],
+        }
+    }
+
+    /// Check that `value` can be written; numbers must parse (empty clears).
+    pub fn validate(self, value: &str) -> Result<(), String> {
+        match self {
+            Self::TrackNumber | Self::DiscNumber | Self::Year => match number(value) {
+                Ok(Some(year)) if self == Self::Year && year > 9999 => {
+                    Err(format!("Year {} is out of range", year))
+                }
+                Ok(_) => Ok(()),
+                Err(_) => Err(format!(
+                    "{} must be a number, not \"{}\"",
+                    self.label(),
+                    value.trim()
+                )),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+fn number(value: &str) -> Result<Option<u32>, std::num::ParseIntError> {
+    let value = value.trim();
+    if value.is_empty() {
+        Ok(None)
+    } else {
+        value.parse().map(Some)
+    }
+}
+
+/// A field's text items exactly as a file held them: several artists, a
+/// full date or a `03/12` track number survive where the editable text
+/// would not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawField {
+    pub field: TagField,
+    /// Values of each of the field's item keys, in `item_keys` order.
+    pub items: Vec<Vec<String>>,
+}
+
+/// Write `edits` (field, new text) to the file at `path`. An empty value
+/// removes the field; artists and genres are split at the separators the
+/// scan uses. Nothing is written if any value is invalid.
+pub fn write_tags(
+    path: &Path,
+    edits: &[(TagField, String)],
+    settings: &LibrarySettings,
+) -> Result<(), String> {
+    for (field, value) in edits {
+        field.validate(value)?;
+    }
+    edit_tag(path, |tag| {
+        for (field, value) in edits {
+            let separators = field.separators(settings);
+            apply(tag, *field, value.trim(), &separators);
+        }
+        Ok(())
+    })
+}
+
+/// The `fields` of the file at `path` as stored in the tag `write_tags` edits.
+pub fn read_raw_fields(path: &Path, fields: &[TagField]) -> Result<Vec<RawField>, String> {
+    let tagged = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+    let tag = tagged.primary_tag().or(tagged.first_tag());
+    Ok(fields
+        .iter()
+        .map(|&field| RawField {
+            field,
+            items: field
+                .item_keys()
+                .iter()
+                .map(|&key| {
+                    tag.map(|tag| tag.get_strings(key).map(str::to_string).collect())
+                        .unwrap_or_default()
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Put `fields` back into the file at `path` exactly as they were read.
+pub fn restore_raw_fields(path: &Path, fields: &[RawField]) -> Result<(), String> {
+    edit_tag(path, |tag| {
+        for raw in fields {
+            for (&key, values) in raw.field.item_keys().iter().zip(&raw.items) {
+                tag.remove_key(key);
+                for value in values {
+                    tag.push(TagItem::new(key, ItemValue::Text(value.clone())));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Change the tag the scan reads from (creating one if the file has none)
+/// with `edit`, then save the file.
+///
+/// lofty's generic `Tag` has no room for custom Vorbis comments such as
+/// `FMPS_RATING`, and saving it would drop them. Vorbis-comment formats are
+/// therefore edited as their own type, rewriting only the items `edit`
+/// changed.
+pub(super) fn edit_tag(
+    path: &Path,
+    edit: impl FnOnce(&mut Tag) -> Result<(), String>,
+) -> Result<(), String> {
+    let probe = Probe::open(path)
+        .map_err(|e| e.to_string())?
+        .guess_file_type()
+        .map_err(|e| e.to_string())?;
+    let options = ParseOptions::new();
+    let saved = match probe.file_type() {
+        Some(FileType::Flac) => {
+            let mut file =
+                FlacFile::read_from(&mut probe.into_inner(), options).map_err(|e| e.to_string())?;
+            // Without Vorbis comments the scan reads an ID3v2 tag instead.
+            if file.vorbis_comments().is_none() && file.id3v2().is_some() {
+                return edit_generic_tag(path, edit);
+            }
+            let mut comments = file.remove_vorbis_comments().unwrap_or_default();
+            edit_comments(&mut comments, edit)?;
+            file.set_vorbis_comments(comments);
+            file.save_to_path(path, WriteOptions::default())
+        }
+        Some(FileType::Vorbis) => {
+            let mut file = VorbisFile::read_from(&mut probe.into_inner(), options)
+                .map_err(|e| e.to_string())?;
+            edit_comments(file.vorbis_comments_mut(), edit)?;
+            file.save_to_path(path, WriteOptions::default())
+        }
+        Some(FileType::Opus) => {
+            let mut file =
+                OpusFile::read_from(&mut probe.into_inner(), options).map_err(|e| e.to_string())?;
+            edit_comments(file.vorbis_comments_mut(), edit)?;
+            file.save_to_path(path, WriteOptions::default())
+        }
+        Some(FileType::Speex) => {
+            let mut file = SpeexFile::read_from(&mut probe.into_inner(), options)
+                .map_err(|e| e.to_string())?;
+            edit_comments(file.vorbis_comments_mut(), edit)?;
+            file.save_to_path(path, WriteOptions::default())
+        }
+        _ => return edit_generic_tag(path, edit),
+    };
+    saved.map_err(|e| e.to_string())
+}
+
+/// `edit_tag` for formats whose tags lofty's generic `Tag` covers.
+fn edit_generic_tag(
+    path: &Path,
+    edit: impl FnOnce(&mut Tag) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut tagged = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+    if tagged.primary_tag().is_none() && tagged.first_tag().is_none() {
+        tagged.insert_tag(Tag::new(tagged.primary_tag_type()));
+    }
+    let tag = if tagged.primary_tag().is_some() {
+        tagged.primary_tag_mut()
+    } else {
+        tagged.first_tag_mut()
+    }
+    .ok_or_else(|| "file cannot hold tags".to_string())?;
+    edit(tag)?;
+    tagged
+        .save_to_path(path, WriteOptions::default())
+        .map_err(|e| e.to_string())
+}
+
+/// Run `edit` on a generic copy of `comments`, then rewrite just the items
+/// whose values it changed. Everything else, custom comments included,
+/// stays as it was.
+fn edit_comments(
+    comments: &mut VorbisComments,
+    edit: impl FnOnce(&mut Tag) -> Result<(), String>,
+) -> Result<(), String> {
+    let before = Tag::from(comments.clone());
+    let mut after = before.clone();
+    edit(&mut after)?;
+
+    let mut keys: Vec<ItemKey> = Vec::new();
+    for item in before.items().chain(after.items()) {
+        if !keys.contains(&item.key()) {
+            keys.push(item.key());
+        }
+    }
+    for key in keys {
+        let values =
+            |tag: &Tag| -> Vec<String> { tag.get_strings(key).map(str::to_string).collect() };
+        let values_after = values(&after);
+        if values(&before) == values_after {
+            continue;
+        }
+        let Some(name) = key.map_key(TagType::VorbisComments) else {
+            continue;
+        };
+        comments.remove(name).for_each(drop);
+        if key == ItemKey::Popularimeter {
+            // Ratings can also carry an email: `RATING:user@example.com`.
+            let emailed: Vec<String> = comments
+                .items()
+                .map(|(k, _)| k)
+                .filter(|k| {
+                    k.get(..7)
+                        .is_some_and(|p| p.eq_ignore_ascii_case("RATING:"))
+                })
+                .map(str::to_string)
+                .collect();
+            for k in emailed {
+                comments.remove(&k).for_each(drop);
+            }
+        }
+        for value in values_after {
+            comments.push(name.to_string(), value);
+        }
+    }
+    Ok(())
+}
+
+fn apply(tag: &mut Tag, field: TagField, value: &str, separators: &[String]) {
+    let text = |tag: &mut Tag, key: ItemKey| {
+        if value.is_empty() {
+            tag.remove_key(key);
+        } else {
+            tag.insert_text(key, value.to_string());
+        }
+    };
+    // One item per separated value.
+    let list = |tag: &mut Tag, key: ItemKey| {
+        tag.remove_key(key);
+        for item in split_values(&[value.to_string()], separators) {
+            tag.push(TagItem::new(key, ItemValue::Text(item)));
+        }
+    };
+    // Validated by `write_tags`.
+    let n = number(value).ok().flatten();
+    match field {
+        TagField::Title => text(tag, ItemKey::TrackTitle),
+        TagField::Artist => {
+            // The multi-artist list would otherwise win over the new value.
+            tag.remove_key(ItemKey::TrackArtists);
+            list(tag, ItemKey::TrackArtist);
+        }
+        TagField::Album => text(tag, ItemKey::AlbumTitle),
+        TagField::AlbumArtist => text(tag, ItemKey::AlbumArtist),
+        TagField::TrackNumber => match n {
+            Some(n) => tag.set_track(n),
+            None => tag.remove_track(),
+        },
+        TagField::DiscNumber => match n {
+            Some(n) => tag.set_disk(n),
+            None => tag.remove_disk(),
+        },
+        // The editor only knows the year, so a full date is replaced by it.
+        TagField::Year => match n.and_then(|n| u16::try_from(n).ok()) {
+            Some(year) => tag.set_date(lofty::tag::items::Timestamp {
+                year,
+                ..Default::default()
+            }),
+            None => tag.remove_date(),
+        },
+        TagField::Genre => list(tag, ItemKey::Genre),
+    }
+}
+
+/// Read the track at `path` afresh, computing its display and sort strings.
+pub fn reread_track(path: &Path, settings: &LibrarySettings) -> Track {
+    build_track(path.to_path_buf(), read_tags(path), settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::{write_flac, write_wav};
+    use tempfile::tempdir;
+
+    #[test]
+    fn write_tags_round_trips_through_a_reread() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("untagged.wav");
+        write_wav(&path, 800);
+        let settings = LibrarySettings::default();
+        let track = build_track(path.clone(), read_tags(&path), &settings);
+        assert_eq!(track.title, "untagged");
+
+        let edits = [
+            (TagField::Title, "Song".to_string()),
+            (TagField::Artist, "Band".to_string()),
+            (TagField::TrackNumber, " 7 ".to_string()),
+            (TagField::Year, "2001".to_string()),
+            (TagField::Genre, "Jazz; Soul".to_string()),
+        ];
+        write_tags(&path, &edits, &settings).unwrap();
+        let track = reread_track(&path, &settings);
+        assert_eq!(track.title, "Song");
+        assert_eq!(track.artists, ["Band"]);
+        assert_eq!(track.track_number, Some(7));
+        assert_eq!(track.year, Some(2001));
+        assert_eq!(track.genres, vec!["Jazz", "Soul"]);
+        assert_eq!(TagField::Genre.value(&track, &settings), "Jazz; Soul");
+
+        // Both directions use the configured separators.
+        let slashes = LibrarySettings {
+            artist_separators: vec![" / ".to_string()],
+            ..LibrarySettings::default()
+        };
+        let edits = [(TagField::Artist, "Band / Guest; Friend".to_string())];
+        write_tags(&path, &edits, &slashes).unwrap();
+        let track = reread_track(&path, &slashes);
+        assert_eq!(track.artists, ["Band", "Guest; Friend"]);
+        assert_eq!(
+            TagField::Artist.value(&track, &slashes),
+            "Band / Guest; Friend"
+        );
+
+        write_tags(&path, &[(TagField::Artist, String::new())], &settings).unwrap();
+        assert!(reread_track(&path, &settings).artists.is_empty());
+
+        let invalid = [
+            (TagField::Title, "X".to_string()),
+            (TagField::DiscNumber, "two".to_string()),
+        ];
+        assert!(write_tags(&path, &invalid, &settings).is_err());
+        assert_eq!(reread_track(&path, &settings).title, "Song");
+    }
+
+    #[test]
+    fn write_tags_keeps_the_comments_it_does_not_edit() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("song.flac");
+        write_flac(
+            &path,
+            &[
+                "TITLE=Sogn",
+                "RATING=80",
+                "RATING:user@example.com=60",
+                "FMPS_RATING=0.6",
+                "CUSTOMFIELD=x",
+            ],
+        );
+        let edits = [(TagField::Title, "Song".to_string())];
+        write_tags(&path, &edits, &LibrarySettings::default()).unwrap();
+
+        let written = std::fs::read(&path).unwrap();
+        let holds = |comment: &str| {
+            written
+                .windows(comment.len())
+                .any(|w| w == comment.as_bytes())
+        };
+        assert!(holds("TITLE=Song"));
+        assert!(!holds("TITLE=Sogn"));
+        for kept in [
+            "RATING=80",
+            "RATING:user@example.com=60",
+            "FMPS_RATING=0.6",
+            "CUSTOMFIELD=x",
+        ] {
+            assert!(holds(kept), "{kept} was dropped");
+        }
+    }
+}
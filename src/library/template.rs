@@ -0,0 +1,319 @@
+//! Path templates and filename patterns over tag fields.
+//!
+//! `{albumartist}/{year} - {album}/{disc}-{track:02} {title}.{ext}` names a
+//! file from its tags; the same `{field}` syntax read backwards
+//! (`{track} - {artist} - {title}`) fills tags from a file name.
+
+use std::path::{Path, PathBuf};
+
+use super::model::Track;
+use super::tags::TagField;
+
+/// A `{field}` usable in templates and patterns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Tag(TagField),
+    Composer,
+    /// File extension, without the dot.
+    Ext,
+    /// Current file name without extension.
+    Filename,
+    /// `{_}`: matches anything in a pattern and is discarded.
+    Skip,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "title" => Self::Tag(TagField::Title),
+            "artist" => Self::Tag(TagField::Artist),
+            "album" => Self::Tag(TagField::Album),
+            "albumartist" => Self::Tag(TagField::AlbumArtist),
+            "track" => Self::Tag(TagField::TrackNumber),
+            "disc" => Self::Tag(TagField::DiscNumber),
+            "year" => Self::Tag(TagField::Year),
+            "genre" => Self::Tag(TagField::Genre),
+            "composer" => Self::Composer,
+            "ext" => Self::Ext,
+            "filename" => Self::Filename,
+            "_" => Self::Skip,
+            _ => return None,
+        })
+    }
+
+    fn is_number(self) -> bool {
+        matches!(
+            self,
+            Self::Tag(TagField::TrackNumber | TagField::DiscNumber | TagField::Year)
+        )
+    }
+
+    /// Value used when naming files; names fall back like the browser's labels.
+    fn render(self, track: &Track) -> String {
+        let or = |value: String, fallback: &str| {
+            if value.trim().is_empty() {
+                fallback.to_string()
+            } else {
+                value
+            }
+        };
+        match self {
+            Self::Tag(TagField::AlbumArtist) => or(
+                track
+                    .album_artist
+                    .clone()
+                    .or_else(|| track.artist().map(str::to_string))
+                    .unwrap_or_default(),
+                "Unknown artist",
+            ),
+            // Like genres, several artists name a folder after the first one.
+            Self::Tag(TagField::Artist) => or(
+                track.artist().map(str::to_string).unwrap_or_default(),
+                "Unknown artist",
+            ),
+            Self::Tag(TagField::Album) => {
+                or(track.album.clone().unwrap_or_default(), "Unknown album")
+            }
+            // Several genres name a folder after the first one.
+            Self::Tag(TagField::Genre) => track.genres.first().cloned().unwrap_or_default(),
+            Self::Tag(field) => field.single_value(track).unwrap_or_default(),
+            Self::Composer => track.composer.clone().unwrap_or_default(),
+            Self::Ext => extension(&track.path),
+            Self::Filename => track
+                .path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            Self::Skip => String::new(),
+        }
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// A field, zero-padded to `width` when it is a number (`{track:02}`).
+    Field {
+        field: Field,
+        width: usize,
+    },
+}
+
+/// Split `{field}` placeholders from literal text.
+fn parse_parts(text: &str) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(Part::Literal(rest[..open].to_string()));
+        }
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed {{ in \"{}\"", text))?;
+        let inner = &rest[open + 1..open + close];
+        let (name, width) = match inner.split_once(':') {
+            Some((name, width)) => (
+                name,
+                width
+                    .parse()
+                    .map_err(|_| format!("Bad width in {{{}}}", inner))?,
+            ),
+            None => (inner, 0),
+        };
+        let field = Field::parse(&name.trim().to_lowercase())
+            .ok_or_else(|| format!("Unknown field {{{}}}", name))?;
+        parts.push(Part::Field { field, width });
+        rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+/// Make a tag value safe inside one path component.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c == '/' || c == '\\' || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// A template naming files from their tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+    /// Whether the template places `{ext}` itself; otherwise it is appended.
+    has_ext: bool,
+}
+
+impl PathTemplate {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parts = parse_parts(text.trim())?;
+        if parts.is_empty() {
+            return Err("Empty template".to_string());
+        }
+        if text.trim().starts_with('/') {
+            return Err("Templates are relative to the library root".to_string());
+        }
+        let has_ext = parts.iter().any(|p| {
+            matches!(
+                p,
+                Part::Field {
+                    field: Field::Ext,
+                    ..
+                }
+            )
+        });
+        Ok(Self { parts, has_ext })
+    }
+
+    /// Path for `track` relative to its library root. Empty components and
+    /// leading/trailing spaces are dropped; `..` never escapes the root.
+    pub fn render(&self, track: &Track) -> PathBuf {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => text.push_str(s),
+                Part::Field { field, width } => {
+                    let value = sanitize(&field.render(track));
+                    if field.is_number() && !value.is_empty() {
+                        text.push_str(&format!("{:0>width$}", value, width = *width));
+                    } else {
+                        text.push_str(&value);
+                    }
+                }
+            }
+        }
+        if !self.has_ext {
+            let ext = extension(&track.path);
+            if !ext.is_empty() {
+                text.push('.');
+                text.push_str(&ext);
+            }
+        }
+        text.split('/')
+            .map(str::trim)
+            .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+            .collect()
+    }
+}
+
+/// A pattern reading tag fields out of file names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamePattern {
+    parts: Vec<Part>,
+    /// Path components matched: 1 for the file name, more when the pattern has `/`.
+    depth: usize,
+}
+
+impl NamePattern {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim().trim_end_matches(".{ext}");
+        let parts = parse_parts(text)?;
+        let mut any = false;
+        for part in &parts {
+            if let Part::Field { field, .. } = part {
+                match field {
+                    Field::Tag(_) => any = true,
+                    Field::Skip => {}
+                    _ => return Err("Patterns can only read tag fields (or {_})".to_string()),
+                }
+            }
+        }
+        if !any {
+            return Err("Pattern has no tag fields".to_string());
+        }
+        Ok(Self {
+            parts,
+            depth: text.matches('/').count() + 1,
+        })
+    }
+
+    /// Tag values read from `path` (extension excluded), or `None` when it does
+    /// not match. Empty captures are left out.
+    pub fn captures(&self, path: &Path) -> Option<Vec<(TagField, String)>> {
+        let mut components: Vec<String> = path
+            .with_extension("")
+            .components()
+            .rev()
+            .take(self.depth)
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        if components.len() < self.depth {
+            return None;
+        }
+        components.reverse();
+        let mut out = Vec::new();
+        match_parts(&self.parts, &components.join("/"), &mut out).then_some(out)
+    }
+}
+
+/// Match `parts` against all of `text`, filling `out`; fields take the
+/// shortest text that lets the rest match.
+fn match_parts(parts: &[Part], text: &str, out: &mut Vec<(TagField, String)>) -> bool {
+    let Some((first, rest)) = parts.split_first() else {
+        return text.is_empty();
+    };
+    match first {
+        Part::Literal(lit) => text
+            .strip_prefix(lit.as_str())
+            .is_some_and(|after| match_parts(rest, after, out)),
+        Part::Field { field, .. } => {
+            let ends: Vec<usize> = match rest.first() {
+                None => vec![text.len()],
+                Some(Part::Literal(lit)) => {
+                    text.match_indices(lit.as_str()).map(|(i, _)| i).collect()
+                }
+                // Two fields in a row: try every split.
+                Some(Part::Field { .. }) => text
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain([text.len()])
+                    .collect(),
+            };
+            for end in ends {
+                let value = text[..end].trim();
+                if value.contains('/')
+                    || (field.is_number() && !value.chars().all(|c| c.is_ascii_digit()))
+                {
+                    continue;
+                }
+                let mark = out.len();
+                if let Field::Tag(tag) = field
+                    && !value.is_empty()
+                {
+                    // `07` and `7` are the same track number.
+                    let value = if field.is_number() {
+                        value.trim_start_matches('0').to_string()
+                    } else {
+                        value.to_string()
+                    };
+                    let value = if value.is_empty() {
+                        "0".to_string()
+                    } else {
+                        value
+                    };
+                    out.push((*tag, value));
+                }
+                if match_parts(rest, &text[end..], out) {
+                    return true;
+                }
+                out.truncate(mark);
+            }
+            false
+        }
+    }
+}
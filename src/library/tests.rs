@@ -1,41 +1,540 @@
+use super::batch::FileRetag;
 use super::display::display_from_fields;
-use crate::config::TrackDisplayField;
-use std::path::Path;
+use super::{
+    AudioProperties, BatchOp, DisplayOrder, DupeGroup, FileMove, LibraryChange, LibraryIndex,
+    NamePattern, PathTemplate, SortSpec, TagField, Track, apply_changes, confirm_by_hash,
+    find_duplicates, load_cover, plan_renames, plan_retags, scan_streaming, trash_target,
+};
+use crate::config::{LibrarySettings, SortField, SortKey, SortPreset, TrackDisplayField};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn track(display: &str) -> Track {
+    Track {
+        path: PathBuf::from(format!("/music/{display}.mp3")),
+        title: display.to_string(),
+        display: display.to_string(),
+        ..Track::default()
+    }
+}
+
+/// The library under `dir` in display order, built the way the UI builds it:
+/// `scan_streaming` output merged into a `DisplayOrder` a few tracks at a time.
+pub(crate) fn scan_with_index(
+    dir: &Path,
+    settings: &LibrarySettings,
+    index: &mut LibraryIndex,
+) -> Vec<Track> {
+    let mut tracks = Vec::new();
+    let mut order = DisplayOrder::default();
+    scan_streaming(dir, settings, index, |track| {
+        tracks.push(track);
+        if tracks.len() % 3 == 0 {
+            order.extend(&tracks);
+        }
+    });
+    order.extend(&tracks);
+    let mut slots: Vec<Option<Track>> = tracks.into_iter().map(Some).collect();
+    order
+        .as_slice()
+        .iter()
+        .filter_map(|&i| slots[i].take())
+        .collect()
+}
+
+/// `scan_with_index` without a persistent index.
+pub(crate) fn scan(dir: &Path, settings: &LibrarySettings) -> Vec<Track> {
+    scan_with_index(dir, settings, &mut LibraryIndex::in_memory())
+}
+
+/// Write a silent mono 8 kHz 16-bit WAV of `samples` frames.
+pub(crate) fn write_wav(path: &Path, samples: u32) {
+    let data_len = samples * 2;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&8000u32.to_le_bytes());
+    bytes.extend_from_slice(&16000u32.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    bytes.resize(bytes.len() + data_len as usize, 0);
+    std::fs::write(path, bytes).unwrap();
+}
+
+/// A FLAC file with no audio frames and `comments` as Vorbis comments.
+pub(super) fn write_flac(path: &Path, comments: &[&str]) {
+    let mut bytes = b"fLaC".to_vec();
+    bytes.extend_from_slice(&[0, 0, 0, 34]);
+    bytes.extend_from_slice(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+    // 44.1 kHz, mono, 16 bits, no samples; then an empty MD5.
+    let packed: u64 = (44_100 << 44) | (15 << 36);
+    bytes.extend_from_slice(&packed.to_be_bytes());
+    bytes.extend_from_slice(&[0; 16]);
+
+    let mut block = Vec::new();
+    block.extend_from_slice(&4u32.to_le_bytes());
+    block.extend_from_slice(b"test");
+    block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        block.extend_from_slice(comment.as_bytes());
+    }
+    bytes.push(0x80 | 4);
+    bytes.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+    bytes.extend_from_slice(&block);
+    std::fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn display_order_merges_appended_tracks_case_insensitively() {
+    let mut tracks = vec![track("b"), track("D")];
+    let mut order = DisplayOrder::from_sorted(&tracks);
+    assert_eq!(order.as_slice(), &[0, 1]);
+
+    tracks.extend([track("c"), track("A"), track("b"), track("e")]);
+    order.extend(&tracks);
+    // Equal labels order by path; the two "b" tracks share one, so index decides.
+    assert_eq!(order.as_slice(), &[3, 0, 4, 2, 1, 5]);
+
+    order.extend(&tracks);
+    assert_eq!(order.as_slice().len(), 6);
+}
+
+#[test]
+fn display_order_sorts_by_several_keys_with_missing_values_last() {
+    let song = |display: &str, album: Option<&str>, year: Option<u32>, number: Option<u32>| Track {
+        album: album.map(str::to_string),
+        year,
+        track_number: number,
+        ..track(display)
+    };
+    let mut tracks = vec![
+        song("intro", Some("B-Sides"), Some(2001), Some(1)),
+        song("tenth", Some("Album"), Some(1999), Some(10)),
+        song("second", Some("album"), Some(1999), Some(2)),
+        song("loose", None, None, None),
+        song("untracked", Some("Album"), Some(1999), None),
+    ];
+    let by = |keys: Vec<SortKey>| SortSpec {
+        keys,
+        articles: Vec::new(),
+    };
+    let mut order = DisplayOrder::default();
+    order.set_sort(&tracks, &by(SortPreset::Album.keys(&[])));
+    // Case-insensitive album, numeric track, missing values last.
+    assert_eq!(order.as_slice(), &[2, 1, 4, 0, 3]);
+
+    // Newest first; a track without a year still sorts last.
+    order.set_sort(
+        &tracks,
+        &by(vec![
+            SortKey::descending(SortField::Year),
+            SortKey::ascending(SortField::Track),
+        ]),
+    );
+    assert_eq!(order.as_slice(), &[0, 2, 1, 4, 3]);
+
+    // Appended tracks merge under the same keys.
+    tracks.push(song("new", Some("Later"), Some(2005), Some(1)));
+    order.extend(&tracks);
+    assert_eq!(order.as_slice(), &[5, 0, 2, 1, 4, 3]);
+}
+
+#[test]
+fn display_order_is_natural_and_ignores_articles_and_uses_sort_tags() {
+    let artist = |display: &str, artist: &str| Track {
+        artists: vec![artist.to_string()],
+        ..track(display)
+    };
+    let mut tracks = vec![
+        track("Track 10"),
+        track("track 2"),
+        track("Track 02b"),
+        artist("x", "The Beatles"),
+        artist("y", "Abba"),
+        artist("z", "Theory"),
+    ];
+    tracks[5].sort_tags.artist = Some("Zz Top".to_string());
+
+    let mut order = DisplayOrder::default();
+    order.set_sort(&tracks, &SortSpec::default());
+    // Digit runs compare by value; display labels without tags sort as-is.
+    assert_eq!(&order.as_slice()[..3], &[1, 2, 0]);
+
+    let by_artist = SortSpec {
+        keys: vec![SortKey::ascending(SortField::Artist)],
+        articles: vec!["The".to_string()],
+    };
+    order.set_sort(&tracks, &by_artist);
+    // "The Beatles" sorts as "beatles"; the ARTISTSORT tag beats "Theory".
+    assert_eq!(&order.as_slice()[..3], &[4, 3, 5]);
+}
+
+#[test]
+fn apply_changes_follows_paths_and_reports_remap() {
+    let at = |path: &str, display: &str| Track {
+        path: PathBuf::from(path),
+        ..track(display)
+    };
+    let mut tracks = vec![
+        at("/m/a/1.mp3", "one"),
+        at("/m/b/2.mp3", "two"),
+        at("/m/b/3.mp3", "three"),
+        at("/m/4.mp3", "four"),
+        at("/m/bb.mp3", "bb"),
+    ];
+
+    let remap = apply_changes(
+        &mut tracks,
+        vec![
+            LibraryChange::Rename {
+                from: PathBuf::from("/m/4.mp3"),
+                to: at("/m/four.mp3", "four renamed"),
+            },
+            LibraryChange::Remove(PathBuf::from("/m/b")),
+            LibraryChange::Upsert(at("/m/a/1.mp3", "one retagged")),
+            LibraryChange::Upsert(at("/m/c/5.mp3", "five")),
+        ],
+    );
+
+    // Removing a directory takes only paths below it (`/m/bb.mp3` stays).
+    assert_eq!(remap, vec![Some(0), None, None, Some(1), Some(2)]);
+    let shown: Vec<(&str, &str)> = tracks
+        .iter()
+        .map(|t| (t.path.to_str().unwrap(), t.display.as_str()))
+        .collect();
+    assert_eq!(
+        shown,
+        vec![
+            ("/m/a/1.mp3", "one retagged"),
+            ("/m/four.mp3", "four renamed"),
+            ("/m/bb.mp3", "bb"),
+            ("/m/c/5.mp3", "five"),
+        ]
+    );
+}
 
 #[test]
 fn display_from_fields_can_format_artist_title() {
-    let p = Path::new("/tmp/Song.mp3");
-    assert_eq!(
-        display_from_fields(
-            p,
-            "Song",
-            Some("Artist"),
-            None,
-            &[TrackDisplayField::Artist, TrackDisplayField::Title],
-            " - ",
-        ),
+    let song = |artist: Option<&str>| Track {
+        path: PathBuf::from("/tmp/Song.mp3"),
+        title: "Song".to_string(),
+        artists: artist.map(str::to_string).into_iter().collect(),
+        ..Track::default()
+    };
+    let fields = [TrackDisplayField::Artist, TrackDisplayField::Title];
+    assert_eq!(
+        display_from_fields(&song(Some("Artist")), &fields, " - "),
         "Artist - Song"
     );
     assert_eq!(
-        display_from_fields(
-            p,
-            "Song",
-            Some("  Artist  "),
-            None,
-            &[TrackDisplayField::Artist, TrackDisplayField::Title],
-            " - ",
-        ),
+        display_from_fields(&song(Some("  Artist  ")), &fields, " - "),
         "Artist - Song"
     );
+    assert_eq!(display_from_fields(&song(None), &fields, " - "), "Song");
+}
+
+#[test]
+fn display_from_fields_formats_numbers_genres_and_album_artist() {
+    let track = Track {
+        path: PathBuf::from("/tmp/Song.mp3"),
+        title: "Song".to_string(),
+        album_artist: Some("Various Artists".to_string()),
+        genres: vec!["Jazz".to_string(), "Soul".to_string()],
+        track_number: Some(3),
+        disc_number: Some(2),
+        year: Some(1997),
+        ..Track::default()
+    };
+    let fields = [
+        TrackDisplayField::AlbumArtist,
+        TrackDisplayField::Year,
+        TrackDisplayField::DiscNumber,
+        TrackDisplayField::TrackNumber,
+        TrackDisplayField::Title,
+        TrackDisplayField::Genre,
+        // Empty fields are skipped.
+        TrackDisplayField::Composer,
+    ];
+    assert_eq!(
+        display_from_fields(&track, &fields, " | "),
+        "Various Artists | 1997 | 2 | 03 | Song | Jazz, Soul"
+    );
+}
+
+#[test]
+fn path_template_pads_numbers_and_keeps_values_inside_one_component() {
+    let song = Track {
+        path: PathBuf::from("/music/in/x.flac"),
+        title: "A/B".to_string(),
+        artists: vec!["Band".to_string()],
+        album: Some("..".to_string()),
+        track_number: Some(3),
+        disc_number: Some(1),
+        ..Track::default()
+    };
+    let template = PathTemplate::parse("{albumartist}/{album}/{disc}-{track:02} {title}").unwrap();
+    assert_eq!(template.render(&song), PathBuf::from("Band/1-03 A_B.flac"));
+    assert!(PathTemplate::parse("{bogus}").is_err());
+    assert!(PathTemplate::parse("/abs/{title}").is_err());
+}
+
+#[test]
+fn name_pattern_reads_fields_from_the_file_and_parent_names() {
+    let pattern = NamePattern::parse("{album}/{track} - {artist} - {title}.{ext}").unwrap();
+    assert_eq!(
+        pattern.captures(&PathBuf::from("/music/Record/07 - Band - Some - Song.mp3")),
+        Some(vec![
+            (TagField::Album, "Record".to_string()),
+            (TagField::TrackNumber, "7".to_string()),
+            (TagField::Artist, "Band".to_string()),
+            (TagField::Title, "Some - Song".to_string()),
+        ])
+    );
+    assert_eq!(
+        pattern.captures(&PathBuf::from("/music/Record/intro.mp3")),
+        None
+    );
+    assert!(NamePattern::parse("{filename}").is_err());
+}
+
+#[test]
+fn batch_plans_flag_conflicts_and_skip_unchanged_files() {
+    let root = PathBuf::from("/music");
+    let song = |name: &str, title: &str| Track {
+        path: root.join(name),
+        title: title.to_string(),
+        ..Track::default()
+    };
+    let tracks = vec![
+        song("a.mp3", "Same"),
+        song("b.mp3", "Same"),
+        song("Kept.mp3", "Kept"),
+        song("c.mp3", "New"),
+    ];
+    let template = PathTemplate::parse("{title}").unwrap();
+    let plan = plan_renames(
+        &tracks,
+        &[0, 1, 2, 3],
+        &template,
+        std::slice::from_ref(&root),
+    );
+    assert_eq!(plan.skipped, 2);
+    assert_eq!(plan.op.len(), 1);
+    assert!(plan.preview[0].starts_with("! a.mp3 -> Same.mp3"));
+    assert_eq!(plan.preview[2], "c.mp3 -> New.mp3");
+    assert_eq!(plan.preview[3], "(1 file(s) already named this way)");
+
+    let pattern = NamePattern::parse("{title}").unwrap();
+    let plan = plan_retags(
+        &tracks,
+        &[2, 3],
+        &pattern,
+        &[root],
+        &LibrarySettings::default(),
+    );
+    let BatchOp::Retag { files } = &plan.op else {
+        panic!("expected a retag");
+    };
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].edits, vec![(TagField::Title, "c".to_string())]);
+    assert_eq!(plan.preview[0], "c.mp3: Title: New -> c");
+    assert_eq!(plan.op.inverse().inverse(), plan.op);
+}
+
+#[test]
+fn moves_remove_emptied_folders_but_never_a_library_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("music");
+    let trash = dir.path().join("trash");
+    let from = root.join("Band/Record/01.mp3");
+    std::fs::create_dir_all(from.parent().unwrap()).unwrap();
+    std::fs::write(&from, b"").unwrap();
+    let to = trash_target(&trash, &from);
+    let op = BatchOp::Rename {
+        moves: vec![FileMove {
+            from: from.clone(),
+            to: to.clone(),
+        }],
+    };
+
+    let (done, failures) = op.apply(std::slice::from_ref(&root), &LibrarySettings::default());
+    assert!(failures.is_empty());
+    assert!(to.exists());
+    // The emptied album and artist folders go; the now empty root stays.
+    assert!(!root.join("Band").exists());
+    assert!(root.is_dir());
+
+    let (_, failures) = done
+        .inverse()
+        .apply(&[root.clone(), trash.clone()], &LibrarySettings::default());
+    assert!(failures.is_empty());
+    assert!(from.exists());
+    assert!(trash.is_dir());
+    assert_eq!(std::fs::read_dir(&trash).unwrap().count(), 0);
+}
+
+#[test]
+fn retag_undo_restores_the_exact_prior_tag_items() {
+    use super::tags::read_raw_fields;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("song.flac");
+    write_flac(
+        &path,
+        &[
+            "TITLE=Song",
+            "ARTIST=Miles Davis feat. John Coltrane",
+            "ARTIST=Bill Evans",
+            "DATE=1959-08-17",
+            "TRACKNUMBER=03",
+            "CUSTOMFIELD=kept",
+        ],
+    );
+    let custom_kept = || {
+        let written = std::fs::read(&path).unwrap();
+        written.windows(16).any(|w| w == b"CUSTOMFIELD=kept")
+    };
+    let fields = [TagField::Artist, TagField::Year, TagField::TrackNumber];
+    let before = read_raw_fields(&path, &fields).unwrap();
+    let op = BatchOp::Retag {
+        files: vec![FileRetag {
+            path: path.clone(),
+            edits: vec![
+                (TagField::Artist, "Someone".to_string()),
+                (TagField::Year, "2001".to_string()),
+                (TagField::TrackNumber, "4".to_string()),
+            ],
+            previous: Vec::new(),
+            restore: false,
+        }],
+    };
+
+    let (done, failures) = op.apply(&[], &LibrarySettings::default());
+    assert!(failures.is_empty());
+    let after = read_raw_fields(&path, &fields).unwrap();
+    assert_eq!(after[0].items[0], ["Someone"]);
+    assert_ne!(after, before);
+    assert!(custom_kept());
+
+    let (_, failures) = done.inverse().apply(&[], &LibrarySettings::default());
+    assert!(failures.is_empty());
+    assert_eq!(read_raw_fields(&path, &fields).unwrap(), before);
+    assert!(custom_kept());
+}
+
+#[test]
+fn unreverted_keeps_the_moves_an_undo_could_not_make() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let moves: Vec<FileMove> = ["a", "b"]
+        .iter()
+        .map(|name| FileMove {
+            from: root.join(format!("{name}.mp3")),
+            to: root.join(format!("{name}-moved.mp3")),
+        })
+        .collect();
+    for m in &moves {
+        std::fs::write(&m.from, b"").unwrap();
+    }
+    let op = BatchOp::Rename {
+        moves: moves.clone(),
+    };
+    let (done, failures) = op.apply(std::slice::from_ref(&root), &LibrarySettings::default());
+    assert!(failures.is_empty());
+
+    // Something new took `b`'s old name, so only `a` can move back.
+    std::fs::write(&moves[1].from, b"newer").unwrap();
+    let (reverted, failures) = done
+        .inverse()
+        .apply(std::slice::from_ref(&root), &LibrarySettings::default());
+    assert_eq!(failures.len(), 1);
+    assert_eq!(
+        done.unreverted(&reverted),
+        BatchOp::Rename {
+            moves: vec![moves[1].clone()]
+        }
+    );
+    assert!(done.unreverted(&done.inverse()).is_empty());
+}
+
+#[test]
+fn load_cover_falls_back_to_the_best_named_folder_image() {
+    let dir = tempfile::tempdir().unwrap();
+    let song = dir.path().join("song.mp3");
+    std::fs::write(&song, b"not a real mp3").unwrap();
+    std::fs::write(dir.path().join("Folder.PNG"), b"folder").unwrap();
+    std::fs::write(dir.path().join("back.jpg"), b"back").unwrap();
+    assert_eq!(load_cover(&song).unwrap().bytes, b"folder");
+
+    std::fs::write(dir.path().join("cover.jpg"), b"cover").unwrap();
+    let cover = load_cover(&song).unwrap();
+    assert_eq!(cover.source, dir.path().join("cover.jpg"));
+    assert_eq!(cover.bytes, b"cover");
+}
+
+#[test]
+fn find_duplicates_groups_by_song_and_duration_best_copy_first() {
+    let copy = |name: &str, artist: &str, title: &str, secs: u64, codec: &str, kbps: u32| Track {
+        artists: vec![artist.to_string()],
+        title: title.to_string(),
+        duration: Some(Duration::from_secs(secs)),
+        audio: AudioProperties {
+            codec: Some(codec.to_string()),
+            bitrate: Some(kbps),
+            ..AudioProperties::default()
+        },
+        ..track(name)
+    };
+    let tracks = vec![
+        copy("a", "AC/DC", "Thunderstruck", 292, "MPEG-1 Layer 3", 320),
+        copy("b", "ac dc", "thunderstruck!", 293, "FLAC", 900),
+        copy("c", "AC/DC", "Thunderstruck", 291, "MPEG-1 Layer 3", 128),
+        // A live take: same song, too long to be a copy.
+        copy("d", "AC/DC", "Thunderstruck", 340, "MPEG-1 Layer 3", 320),
+        copy("e", "Other", "Thunderstruck", 292, "FLAC", 900),
+        // Two-second steps: neighbours match, the ends do not.
+        copy("f", "Steps", "Walk", 100, "FLAC", 900),
+        copy("g", "Steps", "Walk", 102, "FLAC", 900),
+        copy("h", "Steps", "Walk", 104, "FLAC", 900),
+        copy("i", "Steps", "Walk", 106, "FLAC", 900),
+    ];
+    let mut groups = find_duplicates(&tracks);
+    let names = |group: &DupeGroup| -> Vec<String> {
+        group.copies.iter().map(|t| t.display.clone()).collect()
+    };
+    let steps: Vec<Vec<String>> = groups.drain(1..).map(|g| names(&g)).collect();
+    assert_eq!(steps, [["f", "g"], ["h", "i"]]);
+    assert_eq!(names(&groups[0]), ["b", "a", "c"]);
+    assert_eq!(groups[0].label(), "ac dc - thunderstruck!");
+
+    // Hashes split the group into identical recordings; singles drop out.
+    let hash = |path: &Path| match path.file_stem()?.to_str()? {
+        "a" | "c" => Some(1),
+        _ => Some(2),
+    };
+    let confirmed = confirm_by_hash(groups, hash);
+    assert_eq!(confirmed.len(), 1);
+    assert_eq!(names(&confirmed[0]), ["a", "c"]);
+}
+
+#[test]
+fn trash_target_keeps_the_full_path_and_avoids_taken_names() {
+    let trash = tempfile::tempdir().unwrap();
+    let song = Path::new("/music/Artist/song.mp3");
+    let target = trash_target(trash.path(), song);
+    assert_eq!(target, trash.path().join("music/Artist/song.mp3"));
+
+    std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+    std::fs::write(&target, b"older copy").unwrap();
     assert_eq!(
-        display_from_fields(
-            p,
-            "Song",
-            None,
-            None,
-            &[TrackDisplayField::Artist, TrackDisplayField::Title],
-            " - ",
-        ),
-        "Song"
+        trash_target(trash.path(), song),
+        trash.path().join("music/Artist/song~2.mp3")
     );
 }
@@ -0,0 +1,636 @@
+//! Live library updates from filesystem events (Linux inotify).
+//!
+//! `LibraryWatcher` watches every directory a scan would visit. Raw events are
+//! collected until the burst goes quiet, coalesced per path and then resolved
+//! against the disk, so an album being copied in becomes one batch of
+//! `LibraryChange`s rather than hundreds of partial updates. Moves, of files
+//! and of whole directories, are paired by their inotify cookie and reported
+//! as renames. Each root's `LibraryIndex` is kept up to date and saved after
+//! every batch, so the next start does not re-read what the watcher saw.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::LibrarySettings;
+
+use super::cache::{FileStamp, LibraryIndex};
+use super::changes::LibraryChange;
+use super::ignore::Exclusions;
+use super::model::Track;
+use super::scan::{audio_files, build_track, is_audio_file, is_hidden, library_dirs, read_tags};
+
+/// Events we care about on each watched directory.
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ONLYDIR;
+
+/// A busy burst (a long copy) is still flushed this often.
+const MAX_BATCH_WAIT: Duration = Duration::from_secs(2);
+
+/// One decoded `inotify_event`.
+struct RawEvent {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    name: PathBuf,
+}
+
+/// Owned inotify file descriptor.
+struct Inotify {
+    fd: i32,
+}
+
+impl Inotify {
+    fn new() -> io::Result<Self> {
+        // SAFETY: plain syscall with constant flags; the result is checked.
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    fn add_watch(&self, dir: &Path) -> io::Result<i32> {
+        let path = CString::new(dir.as_os_str().as_bytes()).map_err(io::Error::other)?;
+        // SAFETY: `path` is a valid NUL-terminated string that outlives the call.
+        let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    /// Wait up to `timeout` (`None` = forever) and return the pending events,
+    /// or `None` once `stop` has been signalled.
+    fn read(
+        &self,
+        timeout: Option<Duration>,
+        stop: &WatchStop,
+    ) -> io::Result<Option<Vec<RawEvent>>> {
+        let mut pfds = [self.fd, stop.signal.fd].map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        let timeout_ms = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        // SAFETY: `pfds` holds two valid pollfds and we pass a count of two.
+        let ready = unsafe { libc::poll(pfds.as_mut_ptr(), 2, timeout_ms) };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            return if err.kind() == io::ErrorKind::Interrupted {
+                Ok(Some(Vec::new()))
+            } else {
+                Err(err)
+            };
+        }
+        if pfds[1].revents != 0 || stop.is_stopped() {
+            return Ok(None);
+        }
+        if ready == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        let mut buf = vec![0u8; 64 * 1024];
+        // SAFETY: `buf` is writable for `buf.len()` bytes.
+        let len = unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(parse_events(&buf[..len as usize])))
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        // SAFETY: we own `fd` and close it exactly once.
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Ends a `LibraryWatcher` from another thread, waking it if it is blocked
+/// in `next_changes`. Clones share the same signal.
+#[derive(Clone)]
+pub struct WatchStop {
+    signal: Arc<StopSignal>,
+}
+
+/// Owned eventfd that becomes readable once stopped, plus a cheap flag.
+struct StopSignal {
+    fd: i32,
+    stopped: AtomicBool,
+}
+
+impl WatchStop {
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: plain syscall with constant flags; the result is checked.
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            signal: Arc::new(StopSignal {
+                fd,
+                stopped: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    /// Signal the watcher; it returns from `next_changes` and does not watch again.
+    pub fn stop(&self) {
+        if self.signal.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let one: u64 = 1;
+        // SAFETY: writes eight bytes from a live `u64` to an fd we own. The
+        // counter is never read, so the fd stays readable from now on.
+        unsafe {
+            libc::write(self.signal.fd, (&raw const one).cast(), 8);
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.signal.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Handles to this signal still alive, the watcher's included.
+    #[cfg(test)]
+    pub fn holders(&self) -> usize {
+        Arc::strong_count(&self.signal)
+    }
+}
+
+impl Drop for StopSignal {
+    fn drop(&mut self) {
+        // SAFETY: we own `fd` and close it exactly once.
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Decode a buffer of packed `inotify_event` records.
+fn parse_events(buf: &[u8]) -> Vec<RawEvent> {
+    let header = std::mem::size_of::<libc::inotify_event>();
+    let mut events = Vec::new();
+    let mut offset = 0;
+    while offset + header <= buf.len() {
+        // SAFETY: at least `header` bytes remain; the read tolerates misalignment.
+        let event: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+        let name_start = offset + header;
+        let name_end = (name_start + event.len as usize).min(buf.len());
+        // The name is NUL-padded to the record length.
+        let name = &buf[name_start..name_end];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+        events.push(RawEvent {
+            wd: event.wd,
+            mask: event.mask,
+            cookie: event.cookie,
+            name: PathBuf::from(OsStr::from_bytes(name)),
+        });
+        offset = name_end;
+    }
+    events
+}
+
+/// One watched library root, its exclusion rules and its tag index.
+struct WatchedRoot {
+    path: PathBuf,
+    exclusions: Exclusions,
+    index: LibraryIndex,
+}
+
+/// Watches library roots and turns filesystem events into `LibraryChange`s.
+pub struct LibraryWatcher {
+    inotify: Inotify,
+    roots: Vec<WatchedRoot>,
+    settings: LibrarySettings,
+    dirs: HashMap<i32, PathBuf>,
+    stop: WatchStop,
+}
+
+impl LibraryWatcher {
+    /// Watch each of `roots` and every directory below them that a scan would visit.
+    ///
+    /// Roots that cannot be watched (e.g. an unmounted share) are skipped; it is
+    /// an error only when none can be. `stop` ends the watch from another thread.
+    pub fn new(roots: &[PathBuf], settings: &LibrarySettings, stop: WatchStop) -> io::Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::new()?,
+            roots: Vec::new(),
+            settings: settings.clone(),
+            dirs: HashMap::new(),
+            stop,
+        };
+        let mut first_error = None;
+        for root in roots {
+            match watcher.inotify.add_watch(root) {
+                Ok(wd) => {
+                    watcher.dirs.insert(wd, root.clone());
+                    watcher.roots.push(WatchedRoot {
+                        path: root.clone(),
+                        exclusions: Exclusions::new(root, settings),
+                        index: LibraryIndex::in_memory(),
+                    });
+                    watcher.watch_tree(root);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) if watcher.roots.is_empty() => Err(e),
+            _ => Ok(watcher),
+        }
+    }
+
+    /// Keep `index` (the one the scan of `root` used) up to date from now on.
+    pub fn attach_index(&mut self, root: &Path, index: LibraryIndex) {
+        if let Some(watched) = self.roots.iter_mut().find(|r| r.path == root) {
+            watched.index = index;
+        }
+    }
+
+    /// Index of the (innermost) root containing `path`.
+    fn root_of(&self, path: &Path) -> Option<usize> {
+        (0..self.roots.len())
+            .filter(|&i| path.starts_with(&self.roots[i].path))
+            .max_by_key(|&i| self.roots[i].path.components().count())
+    }
+
+    /// Watch `dir` and its subdirectories, skipping any that vanish or fail.
+    fn watch_tree(&mut self, dir: &Path) {
+        let Some((root, settings)) = self.settings_below(dir) else {
+            return;
+        };
+        for sub in library_dirs(dir, &settings, Exclusions::new(&root, &self.settings)) {
+            if let Ok(wd) = self.inotify.add_watch(&sub) {
+                self.dirs.insert(wd, sub);
+            }
+        }
+    }
+
+    /// The root holding `dir` plus settings for walking `dir` with depth limits
+    /// still relative to that root, or `None` when nothing below `dir` can
+    /// belong to the library.
+    fn settings_below(&self, dir: &Path) -> Option<(PathBuf, LibrarySettings)> {
+        let root = self.roots[self.root_of(dir)?].path.clone();
+        let depth = dir
+            .strip_prefix(&root)
+            .map_or(0, |rel| rel.components().count());
+        let cap = if self.settings.recursive {
+            self.settings.max_depth
+        } else {
+            Some(1)
+        };
+        let mut settings = self.settings.clone();
+        if let Some(cap) = cap {
+            let remaining = cap.checked_sub(depth).filter(|&d| d > 0)?;
+            settings.recursive = true;
+            settings.max_depth = Some(remaining);
+        }
+        Some((root, settings))
+    }
+
+    /// Block until the library changes, then return the coalesced batch;
+    /// `None` once the watch has been stopped.
+    ///
+    /// Events are gathered until none arrive for `quiet` (or for at most
+    /// `MAX_BATCH_WAIT`), so files are read once they have finished writing.
+    pub fn next_changes(&mut self, quiet: Duration) -> io::Result<Option<Vec<LibraryChange>>> {
+        loop {
+            let Some(mut events) = self.inotify.read(None, &self.stop)? else {
+                return Ok(None);
+            };
+            let started = Instant::now();
+            while started.elapsed() < MAX_BATCH_WAIT {
+                let Some(more) = self.inotify.read(Some(quiet), &self.stop)? else {
+                    return Ok(None);
+                };
+                if more.is_empty() {
+                    break;
+                }
+                events.extend(more);
+            }
+
+            let changes = self.resolve(events);
+            if !changes.is_empty() {
+                for root in &self.roots {
+                    // Only an accelerator: a failed save means the next
+                    // start re-reads these files.
+                    let _ = root.index.save();
+                }
+                return Ok(Some(changes));
+            }
+        }
+    }
+
+    /// Coalesce raw events per path and check the disk for each one.
+    fn resolve(&mut self, events: Vec<RawEvent>) -> Vec<LibraryChange> {
+        let mut touched: Vec<PathBuf> = Vec::new();
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        let mut moved_from: HashMap<u32, PathBuf> = HashMap::new();
+        let mut renames: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let mut dir_moved_from: HashMap<u32, PathBuf> = HashMap::new();
+        let mut dir_renames: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let mut new_dirs: Vec<PathBuf> = Vec::new();
+        let mut gone_dirs: Vec<PathBuf> = Vec::new();
+        let mut touch = |path: PathBuf| {
+            if seen.insert(path.clone()) {
+                touched.push(path);
+            }
+        };
+
+        for event in events {
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                // Events were lost; re-read everything under the roots.
+                new_dirs.extend(self.roots.iter().map(|r| r.path.clone()));
+                continue;
+            }
+            if event.mask & libc::IN_IGNORED != 0 {
+                self.dirs.remove(&event.wd);
+                continue;
+            }
+            let Some(dir) = self.dirs.get(&event.wd) else {
+                continue;
+            };
+            let path = dir.join(&event.name);
+            if !self.settings.include_hidden && is_hidden(&path) {
+                continue;
+            }
+            let is_dir = event.mask & libc::IN_ISDIR != 0;
+            if let Some(i) = self.root_of(&path)
+                && self.roots[i].exclusions.is_excluded(&path, is_dir)
+            {
+                continue;
+            }
+
+            if is_dir {
+                if event.mask & libc::IN_MOVED_FROM != 0 {
+                    dir_moved_from.insert(event.cookie, path);
+                } else if event.mask & libc::IN_MOVED_TO != 0 {
+                    match dir_moved_from.remove(&event.cookie) {
+                        Some(from) => {
+                            // The watches moved with the directory; later
+                            // events in this batch already use the new name.
+                            self.rename_watched_dirs(&from, &path);
+                            dir_renames.push((from, path));
+                        }
+                        None => new_dirs.push(path),
+                    }
+                } else if event.mask & libc::IN_CREATE != 0 {
+                    new_dirs.push(path);
+                } else if event.mask & libc::IN_DELETE != 0 {
+                    gone_dirs.push(path);
+                }
+            } else if event.mask & libc::IN_MOVED_FROM != 0 {
+                moved_from.insert(event.cookie, path);
+            } else if event.mask & libc::IN_MOVED_TO != 0 {
+                match moved_from.remove(&event.cookie) {
+                    Some(from) => renames.push((from, path)),
+                    None => touch(path),
+                }
+            } else {
+                touch(path);
+            }
+        }
+        // A move without its partner left (or entered) the watched tree.
+        for (_, from) in moved_from {
+            touch(from);
+        }
+        gone_dirs.extend(dir_moved_from.into_values());
+
+        for dir in &gone_dirs {
+            self.forget(dir);
+        }
+        let mut changes: Vec<LibraryChange> =
+            gone_dirs.into_iter().map(LibraryChange::Remove).collect();
+        for (from_dir, to_dir) in dir_renames {
+            self.watch_tree(&to_dir);
+            self.rename_index_entries(&from_dir, &to_dir);
+            for path in self.audio_files_below(&to_dir) {
+                let rest = path.strip_prefix(&to_dir).unwrap_or(&path);
+                let from = from_dir.join(rest);
+                changes.push(LibraryChange::Rename {
+                    from,
+                    to: self.track(path),
+                });
+            }
+            // Whatever the moved directory held that is no longer a track
+            // (now excluded, or beyond `max_depth`).
+            self.forget(&from_dir);
+            changes.push(LibraryChange::Remove(from_dir));
+        }
+        for (from, to) in renames {
+            self.rename_index_entries(&from, &to);
+            if let Some(track) = self.library_track(&to) {
+                changes.push(LibraryChange::Rename { from, to: track });
+            } else if is_audio_file(&from, &self.settings) {
+                self.forget(&to);
+                changes.push(LibraryChange::Remove(from));
+            }
+        }
+        for path in touched {
+            if let Some(track) = self.library_track(&path) {
+                changes.push(LibraryChange::Upsert(track));
+            } else if is_audio_file(&path, &self.settings) {
+                // Cover art, lyrics files and partial downloads are not tracks.
+                self.forget(&path);
+                changes.push(LibraryChange::Remove(path));
+            }
+        }
+        for dir in new_dirs {
+            self.watch_tree(&dir);
+            for path in self.audio_files_below(&dir) {
+                changes.push(LibraryChange::Upsert(self.track(path)));
+            }
+        }
+        changes
+    }
+
+    /// Point the watches on `from` and its subdirectories at their new path.
+    fn rename_watched_dirs(&mut self, from: &Path, to: &Path) {
+        for dir in self.dirs.values_mut() {
+            if let Ok(rest) = dir.strip_prefix(from) {
+                *dir = to.join(rest);
+            }
+        }
+    }
+
+    /// Library files below `dir`, walked like a scan would.
+    fn audio_files_below(&self, dir: &Path) -> Vec<PathBuf> {
+        match self.settings_below(dir) {
+            Some((root, settings)) => {
+                let exclusions = Exclusions::new(&root, &self.settings);
+                audio_files(dir, &settings, exclusions, |_| {}).collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// `path` relative to each root holding it, with that root's index.
+    fn indexes_for<'a>(
+        &'a mut self,
+        path: &'a Path,
+    ) -> impl Iterator<Item = (&'a Path, &'a mut LibraryIndex)> {
+        self.roots
+            .iter_mut()
+            .filter_map(move |root| Some((path.strip_prefix(&root.path).ok()?, &mut root.index)))
+    }
+
+    fn forget(&mut self, path: &Path) {
+        for (key, index) in self.indexes_for(path) {
+            index.remove(key);
+        }
+    }
+
+    /// Carry index entries (and so each file's "date added") along a move.
+    fn rename_index_entries(&mut self, from: &Path, to: &Path) {
+        for root in &mut self.roots {
+            let Ok(old) = from.strip_prefix(&root.path) else {
+                continue;
+            };
+            match to.strip_prefix(&root.path) {
+                Ok(new) => root.index.rename(old, new),
+                // Moved to another root, whose index reads it afresh.
+                Err(_) => root.index.remove(old),
+            }
+        }
+    }
+
+    /// Read `path` as a library track if it is an audio file that still exists.
+    fn library_track(&mut self, path: &Path) -> Option<Track> {
+        (path.is_file() && is_audio_file(path, &self.settings)).then(|| self.track(path.into()))
+    }
+
+    /// Build the track at `path`, from the index when the file is unchanged,
+    /// and record its tags in the index.
+    fn track(&mut self, path: PathBuf) -> Track {
+        let stamp = FileStamp::of(&path);
+        let cached = self
+            .indexes_for(&path)
+            .find_map(|(key, index)| index.lookup(key, stamp));
+        let mut tags = cached.unwrap_or_else(|| read_tags(&path));
+        for (key, index) in self.indexes_for(&path) {
+            tags = index.insert(key, stamp, tags);
+        }
+        build_track(path, tags, &self.settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn summary(changes: &[LibraryChange]) -> Vec<String> {
+        let name = |p: &Path| p.file_name().unwrap().to_string_lossy().into_owned();
+        let mut out: Vec<String> = changes
+            .iter()
+            .map(|c| match c {
+                LibraryChange::Upsert(t) => format!("upsert {}", name(&t.path)),
+                LibraryChange::Remove(p) => format!("remove {}", name(p)),
+                LibraryChange::Rename { from, to } => {
+                    format!("rename {} {}", name(from), name(&to.path))
+                }
+            })
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn watcher_reports_creates_renames_deletes_and_new_directories() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("old.mp3"), b"not real").unwrap();
+        let settings = LibrarySettings::default();
+        let mut watcher = LibraryWatcher::new(
+            &[dir.path().to_path_buf()],
+            &settings,
+            WatchStop::new().unwrap(),
+        )
+        .unwrap();
+        let quiet = Duration::from_millis(50);
+
+        fs::write(dir.path().join("new.mp3"), b"not real").unwrap();
+        fs::write(dir.path().join("cover.jpg"), b"not audio").unwrap();
+        fs::rename(dir.path().join("old.mp3"), dir.path().join("moved.mp3")).unwrap();
+        assert_eq!(
+            summary(&watcher.next_changes(quiet).unwrap().unwrap()),
+            vec!["rename old.mp3 moved.mp3", "upsert new.mp3"]
+        );
+
+        let album = dir.path().join("album");
+        fs::create_dir(&album).unwrap();
+        fs::write(album.join("01.flac"), b"not real").unwrap();
+        fs::remove_file(dir.path().join("new.mp3")).unwrap();
+        let changes = summary(&watcher.next_changes(quiet).unwrap().unwrap());
+        assert!(changes.contains(&"upsert 01.flac".to_string()));
+        assert!(changes.contains(&"remove new.mp3".to_string()));
+
+        // The new directory is watched too.
+        fs::write(album.join("02.flac"), b"not real").unwrap();
+        assert_eq!(
+            summary(&watcher.next_changes(quiet).unwrap().unwrap()),
+            vec!["upsert 02.flac"]
+        );
+    }
+
+    #[test]
+    fn watcher_pairs_directory_moves_and_keeps_the_index_current() {
+        let dir = tempdir().unwrap();
+        let cache = tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        fs::create_dir(root.join("Album")).unwrap();
+        fs::write(root.join("Album/01.mp3"), b"not real").unwrap();
+        let settings = LibrarySettings::default();
+        let index_file = cache.path().join("index.json");
+        let mut index = LibraryIndex::load(Some(index_file.clone()), &root);
+        crate::library::scan_streaming(&root, &settings, &mut index, |_| {});
+        let mut watcher = LibraryWatcher::new(
+            std::slice::from_ref(&root),
+            &settings,
+            WatchStop::new().unwrap(),
+        )
+        .unwrap();
+        watcher.attach_index(&root, index);
+        let quiet = Duration::from_millis(50);
+
+        fs::rename(root.join("Album"), root.join("Renamed")).unwrap();
+        let changes = watcher.next_changes(quiet).unwrap().unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec!["remove Album", "rename 01.mp3 01.mp3"]
+        );
+        assert!(changes.iter().any(|c| matches!(c,
+            LibraryChange::Rename { from, to }
+                if *from == root.join("Album/01.mp3") && to.path == root.join("Renamed/01.mp3"))));
+
+        // The moved directory's watch reports under its new name.
+        fs::write(root.join("Renamed/02.mp3"), b"not real").unwrap();
+        let changes = watcher.next_changes(quiet).unwrap().unwrap();
+        assert!(matches!(&changes[..],
+            [LibraryChange::Upsert(t)] if t.path == root.join("Renamed/02.mp3")));
+
+        let mut saved = LibraryIndex::load(Some(index_file), &root);
+        for name in ["Renamed/01.mp3", "Renamed/02.mp3"] {
+            let stamp = FileStamp::of(&root.join(name));
+            assert!(saved.lookup(Path::new(name), stamp).is_some(), "{}", name);
+        }
+        let stamp = FileStamp::of(&root.join("Renamed/01.mp3"));
+        assert!(saved.lookup(Path::new("Album/01.mp3"), stamp).is_none());
+    }
+}
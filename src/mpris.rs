@@ -58,7 +58,7 @@ impl MprisHandle {
         if let Ok(mut s) = self.state.lock() {
             if let Some(t) = track {
                 s.title = Some(t.title.clone());
-                s.artist = t.artist.clone().into_iter().collect();
+                s.artist = t.artists.clone();
                 s.album = t.album.clone();
                 s.url = Some(t.path.to_string_lossy().to_string());
                 s.length_micros = t
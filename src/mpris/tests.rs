@@ -7,10 +7,11 @@ fn make_track() -> Track {
     Track {
         path: PathBuf::from("/tmp/music/test.mp3"),
         title: "Test Title".to_string(),
-        artist: Some("Test Artist".to_string()),
+        artists: vec!["Test Artist".to_string()],
         album: Some("Test Album".to_string()),
         duration: Some(Duration::from_micros(1_234_567)),
         display: "Test Artist - Test Title".to_string(),
+        ..Track::default()
     }
 }
 
@@ -0,0 +1,330 @@
+//! `:rename`, `:retag` and `:undo`: batch file operations on the visible list.
+//!
+//! Both batch commands open a dry-run preview first; confirming applies the
+//! batch, refreshes the affected tracks and records it in an undo log next to
+//! the state file, so `:undo` works across restarts. `:dupes` records its trash moves here too.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::app::{App, BatchPreview};
+use crate::audio::{AudioCmd, AudioPlayer};
+use crate::config;
+use crate::config::load::default_config_path;
+use crate::library::{
+    BatchOp, LibraryChange, NamePattern, PathTemplate, plan_renames, plan_retags, reread_track,
+};
+use crate::runtime::event_loop::EventLoopState;
+
+use super::{dupes, ratings};
+
+/// Batches kept for `:undo`.
+const UNDO_DEPTH: usize = 20;
+
+/// Undo history file (`undo.json` beside the config), newest batch last.
+pub struct UndoLog {
+    path: Option<PathBuf>,
+}
+
+impl UndoLog {
+    pub fn new_default() -> Self {
+        Self {
+            path: default_config_path().and_then(|p| p.parent().map(|d| d.join("undo.json"))),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_path(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    fn load(&self) -> io::Result<Vec<BatchOp>> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(Vec::new());
+        };
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, ops: &[BatchOp]) -> io::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(ops).map_err(io::Error::other)?)
+    }
+
+    /// Record an applied batch, dropping the oldest beyond `UNDO_DEPTH`.
+    pub fn push(&self, op: BatchOp) -> io::Result<()> {
+        let mut ops = self.load()?;
+        ops.push(op);
+        let excess = ops.len().saturating_sub(UNDO_DEPTH);
+        ops.drain(..excess);
+        self.save(&ops)
+    }
+
+    /// Take the most recent batch off the log.
+    pub fn pop(&self) -> io::Result<Option<BatchOp>> {
+        let mut ops = self.load()?;
+        let op = ops.pop();
+        if op.is_some() {
+            self.save(&ops)?;
+        }
+        Ok(op)
+    }
+}
+
+pub(super) fn roots(state: &EventLoopState) -> Vec<PathBuf> {
+    state.roots.iter().map(PathBuf::from).collect()
+}
+
+/// Preview renaming the visible tracks to `template`.
+pub(super) fn preview_rename(
+    template: &str,
+    app: &mut App,
+    state: &EventLoopState,
+) -> Result<(), String> {
+    let template = PathTemplate::parse(template)?;
+    let plan = plan_renames(
+        &app.tracks,
+        &app.display_indices(),
+        &template,
+        &roots(state),
+    );
+    open_preview("rename", plan, app)
+}
+
+/// Preview filling the visible tracks' tags from their names with `pattern`.
+pub(super) fn preview_retag(
+    pattern: &str,
+    settings: &config::Settings,
+    app: &mut App,
+    state: &EventLoopState,
+) -> Result<(), String> {
+    let pattern = NamePattern::parse(pattern)?;
+    let plan = plan_retags(
+        &app.tracks,
+        &app.display_indices(),
+        &pattern,
+        &roots(state),
+        &settings.library,
+    );
+    open_preview("retag", plan, app)
+}
+
+fn open_preview(title: &str, plan: crate::library::BatchPlan, app: &mut App) -> Result<(), String> {
+    if plan.op.is_empty() && plan.skipped == 0 {
+        return Err(format!("Nothing to {}", title));
+    }
+    app.batch_preview = Some(BatchPreview {
+        title: title.to_string(),
+        plan,
+        scroll: 0,
+    });
+    Ok(())
+}
+
+/// Handle a key while a batch preview is open.
+pub(super) fn handle_key(
+    key: KeyEvent,
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    state: &EventLoopState,
+) {
+    let Some(preview) = app.batch_preview.as_mut() else {
+        return;
+    };
+    match key.code {
+        KeyCode::Char('j') | KeyCode::Down => {
+            preview.scroll = (preview.scroll + 1).min(preview.plan.preview.len().saturating_sub(1));
+        }
+        KeyCode::Char('k') | KeyCode::Up => preview.scroll = preview.scroll.saturating_sub(1),
+        KeyCode::Char('y') | KeyCode::Enter => {
+            let Some(preview) = app.batch_preview.take() else {
+                return;
+            };
+            if preview.plan.op.is_empty() {
+                app.set_notice(format!("Nothing to {}", preview.title));
+                return;
+            }
+            let (done, failures) = preview.plan.op.apply(&roots(state), &settings.library);
+            let message = finish(settings, app, audio_player, &roots(state), &done, &failures);
+            if let Err(e) = UndoLog::new_default().push(done) {
+                app.set_notice(format!("{}; undo log save failed: {}", message, e));
+            } else {
+                app.set_notice(message);
+            }
+        }
+        KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => app.batch_preview = None,
+        _ => {}
+    }
+}
+
+/// Revert the most recent batch.
+pub(super) fn undo(
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    state: &EventLoopState,
+) -> Result<(), String> {
+    let log = UndoLog::new_default();
+    let op = log
+        .pop()
+        .map_err(|e| format!("Undo log load failed: {}", e))?
+        .ok_or_else(|| "Nothing to undo".to_string())?;
+    // Moving files back out of the trash may empty folders there too.
+    let mut cleanup = roots(state);
+    cleanup.extend(dupes::trash_dir(&settings.library));
+    let (done, failures) = op.inverse().apply(&cleanup, &settings.library);
+    let message = finish(settings, app, audio_player, &roots(state), &done, &failures);
+    // Files that could not be reverted stay on the log for another try.
+    let left = op.unreverted(&done);
+    if !left.is_empty()
+        && let Err(e) = log.push(left)
+    {
+        app.set_notice(format!("Undo: {}; undo log save failed: {}", message, e));
+    } else {
+        app.set_notice(format!("Undo: {}", message));
+    }
+    Ok(())
+}
+
+/// Refresh the library after `done` ran. Files moved out of the library
+/// roots (to the trash) are removed; files moved back in are added.
+pub(super) fn refresh(
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    roots: &[PathBuf],
+    done: &BatchOp,
+) {
+    if let BatchOp::Rename { moves } = done {
+        let moved: Vec<(PathBuf, PathBuf)> = moves
+            .iter()
+            .map(|m| (m.from.clone(), m.to.clone()))
+            .collect();
+        ratings::follow_moves(app, &moved);
+    }
+    let known = |path: &PathBuf| app.tracks.iter().any(|t| &t.path == path);
+    let inside = |path: &PathBuf| roots.iter().any(|r| path.starts_with(r));
+    let changes: Vec<LibraryChange> = match done {
+        BatchOp::Rename { moves } => moves
+            .iter()
+            .filter_map(|m| match (known(&m.from), inside(&m.to)) {
+                (true, true) => Some(LibraryChange::Rename {
+                    from: m.from.clone(),
+                    to: reread_track(&m.to, &settings.library),
+                }),
+                (true, false) => Some(LibraryChange::Remove(m.from.clone())),
+                (false, true) => Some(LibraryChange::Upsert(reread_track(
+                    &m.to,
+                    &settings.library,
+                ))),
+                // An undo may reach into another library.
+                (false, false) => None,
+            })
+            .collect(),
+        BatchOp::Retag { files } => files
+            .iter()
+            .filter(|f| known(&f.path))
+            .map(|f| LibraryChange::Upsert(reread_track(&f.path, &settings.library)))
+            .collect(),
+    };
+    if !changes.is_empty() {
+        // Same order as watcher events: the UI first, then the audio thread.
+        app.apply_library_changes(changes.clone());
+        let _ = audio_player.send(AudioCmd::ApplyLibraryChanges(changes));
+    }
+}
+
+/// Notice text for a batch: `<verb> N file(s)` plus the first failure.
+pub(super) fn outcome(verb: &str, done: &BatchOp, failures: &[String]) -> String {
+    match failures.first() {
+        None => format!("{} {} file(s)", verb, done.len()),
+        Some(first) => format!(
+            "{} {} file(s), {} failed: {}",
+            verb,
+            done.len(),
+            failures.len(),
+            first
+        ),
+    }
+}
+
+/// Refresh after `done` and describe it.
+fn finish(
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    roots: &[PathBuf],
+    done: &BatchOp,
+    failures: &[String],
+) -> String {
+    refresh(settings, app, audio_player, roots, done);
+    let verb = match done {
+        BatchOp::Rename { .. } => "Renamed",
+        BatchOp::Retag { .. } => "Retagged",
+    };
+    outcome(verb, done, failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::Track;
+
+    #[test]
+    fn rename_applies_and_undo_log_reverts_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("music");
+        fs::create_dir_all(root.join("incoming")).unwrap();
+        let from = root.join("incoming").join("01 whatever.mp3");
+        fs::write(&from, b"").unwrap();
+        let track = Track {
+            path: from.clone(),
+            title: "Song".into(),
+            artists: vec!["Band".into()],
+            album: Some("Record".into()),
+            track_number: Some(1),
+            year: Some(1999),
+            ..Track::default()
+        };
+        let template =
+            PathTemplate::parse("{albumartist}/{year} - {album}/{track:02} {title}").unwrap();
+        let plan = plan_renames(&[track], &[0], &template, std::slice::from_ref(&root));
+        assert_eq!(
+            plan.preview,
+            vec!["incoming/01 whatever.mp3 -> Band/1999 - Record/01 Song.mp3"]
+        );
+
+        let log = UndoLog::with_path(Some(dir.path().join("undo.json")));
+        let (done, failures) = plan.op.apply(
+            std::slice::from_ref(&root),
+            &config::LibrarySettings::default(),
+        );
+        assert!(failures.is_empty());
+        let to = root.join("Band/1999 - Record/01 Song.mp3");
+        assert!(to.exists());
+        // The emptied source directory is cleaned up.
+        assert!(!root.join("incoming").exists());
+        log.push(done).unwrap();
+
+        let (_, failures) = log.pop().unwrap().unwrap().inverse().apply(
+            std::slice::from_ref(&root),
+            &config::LibrarySettings::default(),
+        );
+        assert!(failures.is_empty());
+        assert!(from.exists() && !to.exists());
+        assert_eq!(log.pop().unwrap(), None);
+    }
+}
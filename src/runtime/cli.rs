@@ -0,0 +1,137 @@
+//! Command-line argument parsing.
+//!
+//! Presto takes an optional subcommand, an optional music directory and a few
+//! flags; the parser is hand-written to keep the dependency list short.
+
+pub(crate) const USAGE: &str = "usage: presto [--rescan] [DIR]
+       presto dupes [--hash] [DIR]
+       presto scan [--report] [--rescan] [DIR]
+
+  DIR        music directory to open (default: [library] roots, else the current directory)
+  --rescan   ignore the library index cache and re-read every file's tags
+  -h, --help show this help
+
+commands:
+  dupes      list duplicate tracks (same artist and title, durations within 2s),
+             best copy first; --hash also compares the decoded audio
+  scan       scan the library and update the index cache; --report prints every
+             broken, untagged or unreadable file as tab-separated lines";
+
+/// What to do instead of opening the player.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Command {
+    #[default]
+    Play,
+    /// Print a duplicate report.
+    Dupes,
+    /// Scan the library, optionally printing its problems.
+    Scan,
+}
+
+/// Parsed command-line arguments.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct CliArgs {
+    pub(crate) command: Command,
+    /// Music directory to open.
+    pub(crate) dir: Option<String>,
+    /// Force a full library rebuild instead of reusing the index cache.
+    pub(crate) rescan: bool,
+    /// Confirm duplicates by hashing decoded audio (`dupes` only).
+    pub(crate) hash: bool,
+    /// Print every scan problem (`scan` only).
+    pub(crate) report: bool,
+    /// Print usage and exit.
+    pub(crate) help: bool,
+}
+
+impl CliArgs {
+    /// Parse `args` (without the program name).
+    pub(crate) fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut only_positional = false;
+
+        for (i, arg) in args.into_iter().enumerate() {
+            // A subcommand only counts as the first word; `./dupes` opens a directory.
+            if i == 0 {
+                let command = match arg.as_str() {
+                    "dupes" => Some(Command::Dupes),
+                    "scan" => Some(Command::Scan),
+                    _ => None,
+                };
+                if let Some(command) = command {
+                    parsed.command = command;
+                    continue;
+                }
+            }
+            if only_positional || !arg.starts_with('-') || arg == "-" {
+                if parsed.dir.is_some() {
+                    return Err(format!("unexpected extra argument '{}'", arg));
+                }
+                parsed.dir = Some(arg);
+                continue;
+            }
+
+            match arg.as_str() {
+                "--" => only_positional = true,
+                "--rescan" => parsed.rescan = true,
+                "--hash" if parsed.command == Command::Dupes => parsed.hash = true,
+                "--report" if parsed.command == Command::Scan => parsed.report = true,
+                "-h" | "--help" => parsed.help = true,
+                _ => return Err(format!("unknown option '{}'", arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CliArgs, Command};
+
+    fn parse(args: &[&str]) -> Result<CliArgs, String> {
+        CliArgs::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parses_dir_and_flags_in_any_order() {
+        let args = parse(&["--rescan", "Music"]).unwrap();
+        assert_eq!(args.dir.as_deref(), Some("Music"));
+        assert!(args.rescan);
+
+        let args = parse(&["Music", "--rescan"]).unwrap();
+        assert_eq!(args.dir.as_deref(), Some("Music"));
+        assert!(args.rescan);
+
+        assert_eq!(parse(&[]).unwrap(), CliArgs::default());
+        assert_eq!(parse(&["--", "-odd"]).unwrap().dir.as_deref(), Some("-odd"));
+    }
+
+    #[test]
+    fn parses_commands_only_as_the_first_word() {
+        let args = parse(&["dupes", "--hash", "Music"]).unwrap();
+        assert_eq!(args.command, Command::Dupes);
+        assert!(args.hash);
+        assert_eq!(args.dir.as_deref(), Some("Music"));
+
+        let args = parse(&["Music", "dupes"]);
+        assert!(args.unwrap_err().contains("'dupes'"));
+        assert_eq!(parse(&["--", "dupes"]).unwrap().command, Command::Play);
+        assert!(parse(&["--hash"]).unwrap_err().contains("--hash"));
+
+        let args = parse(&["scan", "--report", "--rescan"]).unwrap();
+        assert_eq!(args.command, Command::Scan);
+        assert!(args.report && args.rescan);
+        assert!(
+            parse(&["dupes", "--report"])
+                .unwrap_err()
+                .contains("--report")
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_flags_and_extra_dirs() {
+        assert!(parse(&["--bogus"]).unwrap_err().contains("--bogus"));
+        assert!(parse(&["a", "b"]).unwrap_err().contains("'b'"));
+    }
+}
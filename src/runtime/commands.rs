@@ -0,0 +1,195 @@
+//! `:` command line.
+//!
+//! `:cd <dir>` switches the library to another directory without restarting:
+//! the current directory's state is saved, the new one is scanned in the
+//! background and its saved state applied, while the song that is playing
+//! carries on until it ends or something from the new library is played.
+//! `:rename`, `:retag` and `:undo` live in `runtime::batch`, `:dupes` in
+//! `runtime::dupes` and `:report` in `runtime::report`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::app::App;
+use crate::audio::{AudioCmd, AudioPlayer};
+use crate::config;
+use crate::mpris::MprisHandle;
+use crate::runtime::event_loop::EventLoopState;
+use crate::runtime::mpris_sync::update_mpris;
+
+use super::{
+    absolutize_dir_for_display, batch, dupes, expand_home, library_scan, report, startup, state,
+};
+
+/// Run one command line (without the leading `:`); problems become notices.
+pub(super) fn run_command(
+    line: &str,
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris: &MprisHandle,
+    state: &mut EventLoopState,
+) {
+    let line = line.trim();
+    let (name, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let result = match name {
+        "" => Ok(()),
+        "cd" => change_directory(arg.trim(), settings, app, audio_player, state),
+        "rename" => batch::preview_rename(arg, app, state),
+        "retag" => batch::preview_retag(arg, settings, app, state),
+        "undo" => batch::undo(settings, app, audio_player, state),
+        "dupes" => dupes::open(arg, app, state),
+        "report" => report::open(app),
+        _ => Err(format!("Unknown command: {}", name)),
+    };
+    if let Err(message) = result {
+        app.set_notice(message);
+    }
+    update_mpris(mpris, app);
+}
+
+/// Resolve a `:cd` argument: `~` expands to the home directory and relative
+/// paths start from the current library's first root.
+fn resolve_dir(arg: &str, roots: &[String]) -> PathBuf {
+    let path = PathBuf::from(expand_home(arg));
+    match roots.first() {
+        Some(root) if path.is_relative() => {
+            PathBuf::from(absolutize_dir_for_display(root)).join(path)
+        }
+        _ => path,
+    }
+}
+
+fn change_directory(
+    arg: &str,
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    state: &mut EventLoopState,
+) -> Result<(), String> {
+    if arg.is_empty() {
+        return Err("Usage: :cd <directory>".to_string());
+    }
+    let dir = resolve_dir(arg, &state.roots);
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("Cannot open {}: {}", dir.display(), e))?;
+    if !dir.is_dir() {
+        return Err(format!("Not a directory: {}", dir.display()));
+    }
+    let root = dir.to_string_lossy().to_string();
+
+    let store = state::StateStore::new_default();
+    let mut failures: Vec<String> = Vec::new();
+    if settings.state.enabled
+        && let Err(e) = store.persist_directory_state(&state.session_key(), app)
+    {
+        failures.push(format!("State save failed: {}", e));
+    }
+
+    let scan = library_scan::spawn(vec![dir], settings.library.clone(), false);
+    let shuffle = app.shuffle;
+    app.replace_library(Vec::new());
+    let _ = audio_player.send(AudioCmd::ReplaceLibrary(Vec::new()));
+    state.roots = vec![root.clone()];
+
+    let persisted = if settings.state.enabled {
+        store
+            .load_directory_state(&state.session_key())
+            .unwrap_or_else(|e| {
+                failures.push(format!("State load failed: {}", e));
+                None
+            })
+    } else {
+        None
+    };
+    let browse = startup::apply_directory_state(app, settings, persisted.as_ref(), audio_player);
+    if app.shuffle != shuffle {
+        let _ = audio_player.send(AudioCmd::ToggleShuffle);
+    }
+    let _ = audio_player.send(AudioCmd::SetLoopMode(app.loop_mode));
+
+    app.set_current_dir(root);
+    app.scanning = true;
+    state.pending_shuffle_reselect_from = None;
+    state.watch_library_scan(scan, state::restore_path(persisted.as_ref()));
+    if let Some((artist, album)) = browse {
+        state.restore_browse_path(artist, album);
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("; "))
+    }
+}
+
+/// Complete the directory argument of a `:cd` line to the longest prefix
+/// shared by the matching subdirectories; a single match gets a trailing `/`.
+pub(super) fn complete(line: &str, roots: &[String]) -> Option<String> {
+    let arg = line.strip_prefix("cd ")?.trim_start();
+    let (parent, partial) = match arg.rfind('/') {
+        Some(slash) => (&arg[..=slash], &arg[slash + 1..]),
+        None => ("", arg),
+    };
+    let search = if parent.is_empty() {
+        resolve_dir(".", roots)
+    } else {
+        resolve_dir(parent, roots)
+    };
+
+    let mut names: Vec<String> = fs::read_dir(&search)
+        .ok()?
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| {
+            name.starts_with(partial) && (partial.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    names.sort();
+    let first = names.first()?;
+    let mut prefix_len = first.len();
+    for name in &names[1..] {
+        prefix_len = common_prefix_len(&first[..prefix_len], name);
+    }
+    let mut completed = format!("cd {}{}", parent, &first[..prefix_len]);
+    if names.len() == 1 {
+        completed.push('/');
+    }
+    Some(completed)
+}
+
+/// Byte length of the common prefix of `a` and `b`, on a char boundary.
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_extends_to_the_shared_prefix_of_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["Jazz", "Jazz Live", "Rock", ".hidden"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+        }
+        fs::write(dir.path().join("Jazzy.mp3"), b"").unwrap();
+        let roots = vec![dir.path().to_string_lossy().to_string()];
+
+        assert_eq!(complete("cd J", &roots).as_deref(), Some("cd Jazz"));
+        assert_eq!(complete("cd R", &roots).as_deref(), Some("cd Rock/"));
+        assert_eq!(complete("cd X", &roots), None);
+        assert_eq!(complete("cd .h", &roots).as_deref(), Some("cd .hidden/"));
+
+        let absolute = format!("cd {}/Ro", dir.path().display());
+        assert_eq!(
+            complete(&absolute, &roots),
+            Some(format!("cd {}/Rock/", dir.path().display()))
+        );
+    }
+}
@@ -0,0 +1,449 @@
+//! Cover art loading and terminal graphics output.
+//!
+//! A worker thread finds the selected track's cover, decodes it, scales it to
+//! the pane and encodes it for the terminal (kitty graphics, sixel or
+//! half-block cells), caching decoded images per source so an album's tracks
+//! share one decode. Kitty and sixel output cannot go through ratatui's
+//! buffer, so `CoverLoader::present` writes it after each frame that changes
+//! what is on screen.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use crossterm::{cursor::MoveTo, queue};
+use image::{RgbImage, imageops::FilterType};
+use ratatui::{Terminal, backend::CrosstermBackend, layout::Rect};
+
+use crate::app::{App, CoverArt, CoverImage};
+use crate::config::CoverArtSetting;
+use crate::library::load_cover;
+use crate::ui::CoverSlot;
+
+/// Decoded images kept; the cache is simply emptied when it fills up.
+const DECODED_CACHE: usize = 32;
+/// Encoded covers kept, per source and cell size.
+const ENCODED_CACHE: usize = 64;
+/// Cell size assumed when the terminal does not report pixels.
+const FALLBACK_CELL: (u16, u16) = (8, 16);
+/// Kitty graphics payloads are sent in chunks of at most this many bytes.
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Kitty,
+    Sixel,
+    HalfBlocks,
+}
+
+/// Pick the protocol for `setting`, looking at the terminal for `auto`.
+fn detect_protocol(
+    setting: CoverArtSetting,
+    var: impl Fn(&str) -> Option<String>,
+) -> Option<Protocol> {
+    match setting {
+        CoverArtSetting::Off => None,
+        CoverArtSetting::Kitty => Some(Protocol::Kitty),
+        CoverArtSetting::Sixel => Some(Protocol::Sixel),
+        CoverArtSetting::HalfBlocks => Some(Protocol::HalfBlocks),
+        CoverArtSetting::Auto => {
+            // Multiplexers drop graphics escapes unless set up to pass them on.
+            if var("TMUX").is_some() || var("STY").is_some() {
+                return Some(Protocol::HalfBlocks);
+            }
+            let term = var("TERM").unwrap_or_default();
+            let program = var("TERM_PROGRAM").unwrap_or_default();
+            Some(
+                if matches!(term.as_str(), "xterm-kitty" | "xterm-ghostty")
+                    || var("KITTY_WINDOW_ID").is_some()
+                    || program == "ghostty"
+                {
+                    Protocol::Kitty
+                } else if term.starts_with("foot")
+                    || term.starts_with("mlterm")
+                    || term == "contour"
+                    || program == "WezTerm"
+                {
+                    Protocol::Sixel
+                } else {
+                    Protocol::HalfBlocks
+                },
+            )
+        }
+    }
+}
+
+/// One cover to prepare.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    track: PathBuf,
+    cols: u16,
+    rows: u16,
+    /// Cell size in pixels, for kitty and sixel.
+    cell: (u16, u16),
+}
+
+/// Cell size in pixels as reported by the terminal.
+fn cell_size() -> (u16, u16) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            (size.width / size.columns, size.height / size.rows)
+        }
+        _ => FALLBACK_CELL,
+    }
+}
+
+/// Background cover preparation plus the graphics currently on screen.
+pub struct CoverLoader {
+    protocol: Protocol,
+    requests: mpsc::Sender<Request>,
+    results: mpsc::Receiver<CoverArt>,
+    requested: Option<Request>,
+    /// Graphics on screen: the track, where, and the screen size they were
+    /// drawn at (a resize clears the screen).
+    shown: Option<(PathBuf, Rect, (u16, u16))>,
+}
+
+impl CoverLoader {
+    /// Start the worker, or `None` when cover art is off.
+    pub fn spawn(setting: CoverArtSetting) -> Option<Self> {
+        let protocol = detect_protocol(setting, |name| std::env::var(name).ok())?;
+        let (request_tx, request_rx) = mpsc::channel::<Request>();
+        let (result_tx, result_rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("cover-art".to_string())
+            .spawn(move || worker(protocol, request_rx, result_tx))
+            .ok()?;
+        Some(Self {
+            protocol,
+            requests: request_tx,
+            results: result_rx,
+            requested: None,
+            shown: None,
+        })
+    }
+
+    /// Store finished covers and ask for the one `slot` shows, if missing.
+    pub fn update(&mut self, app: &mut App, slot: Option<&CoverSlot>) {
+        while let Ok(art) = self.results.try_recv() {
+            app.cover = Some(art);
+        }
+        let Some(slot) = slot else {
+            return;
+        };
+        let (cols, rows) = (slot.area.width, slot.area.height);
+        if app
+            .cover
+            .as_ref()
+            .is_some_and(|c| c.fits(&slot.track, cols, rows))
+        {
+            return;
+        }
+        let request = Request {
+            track: slot.track.clone(),
+            cols,
+            rows,
+            cell: cell_size(),
+        };
+        if self.requested.as_ref() != Some(&request) {
+            let _ = self.requests.send(request.clone());
+            self.requested = Some(request);
+        }
+    }
+
+    /// Bring kitty/sixel graphics in line with the frame just drawn.
+    pub fn present(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+        app: &App,
+        slot: Option<&CoverSlot>,
+    ) -> io::Result<()> {
+        if self.protocol == Protocol::HalfBlocks {
+            return Ok(());
+        }
+        let screen = crossterm::terminal::size()?;
+        let wanted = slot.filter(|s| s.graphics).and_then(|s| {
+            let art = app.cover.as_ref()?;
+            match &art.image {
+                CoverImage::Graphics(seq) if art.fits(&s.track, s.area.width, s.area.height) => {
+                    Some((s, seq))
+                }
+                _ => None,
+            }
+        });
+        let key = wanted.map(|(s, _)| (s.track.clone(), s.area, screen));
+        if key == self.shown {
+            return Ok(());
+        }
+
+        if let Some((_, area, _)) = &self.shown {
+            match self.protocol {
+                Protocol::Kitty => write!(terminal.backend_mut(), "\x1b_Ga=d,d=A,q=2\x1b\\")?,
+                // Sixel pixels stay until text is drawn over them; a new image
+                // of the same size covers them, anything else needs a repaint.
+                _ if wanted.is_some_and(|(s, _)| s.area == *area) => {}
+                _ => terminal.clear()?,
+            }
+        }
+        if let Some((slot, seq)) = wanted {
+            let out = terminal.backend_mut();
+            queue!(out, MoveTo(slot.area.x, slot.area.y))?;
+            out.write_all(seq.as_bytes())?;
+        }
+        terminal.backend_mut().flush()?;
+        self.shown = key;
+        Ok(())
+    }
+}
+
+/// Prepare covers until the loader goes away, skipping requests that were
+/// superseded while busy.
+fn worker(protocol: Protocol, requests: mpsc::Receiver<Request>, results: mpsc::Sender<CoverArt>) {
+    let mut decoded: HashMap<PathBuf, Option<RgbImage>> = HashMap::new();
+    let mut encoded: HashMap<(PathBuf, u16, u16), CoverImage> = HashMap::new();
+    while let Ok(mut request) = requests.recv() {
+        while let Ok(newer) = requests.try_recv() {
+            request = newer;
+        }
+        let image = match load_cover(&request.track) {
+            None => CoverImage::Missing,
+            Some(cover) => {
+                let key = (cover.source.clone(), request.cols, request.rows);
+                if let Some(image) = encoded.get(&key) {
+                    image.clone()
+                } else {
+                    if decoded.len() >= DECODED_CACHE {
+                        decoded.clear();
+                    }
+                    let source = decoded.entry(cover.source).or_insert_with(|| {
+                        image::load_from_memory(&cover.bytes)
+                            .ok()
+                            .map(|img| img.to_rgb8())
+                    });
+                    let image = match source {
+                        Some(source) => encode(protocol, source, &request),
+                        None => CoverImage::Missing,
+                    };
+                    if encoded.len() >= ENCODED_CACHE {
+                        encoded.clear();
+                    }
+                    encoded.insert(key, image.clone());
+                    image
+                }
+            }
+        };
+        let art = CoverArt {
+            track: request.track,
+            cols: request.cols,
+            rows: request.rows,
+            image,
+        };
+        if results.send(art).is_err() {
+            break;
+        }
+    }
+}
+
+/// Scale `source` to fill the requested cells (cropping the longer side) and
+/// encode it.
+fn encode(protocol: Protocol, source: &RgbImage, request: &Request) -> CoverImage {
+    let fill = |w: u32, h: u32| {
+        image::DynamicImage::ImageRgb8(source.clone())
+            .resize_to_fill(w.max(1), h.max(1), FilterType::Triangle)
+            .to_rgb8()
+    };
+    let (cols, rows) = (u32::from(request.cols), u32::from(request.rows));
+    let (cell_w, cell_h) = (u32::from(request.cell.0), u32::from(request.cell.1));
+    match protocol {
+        Protocol::HalfBlocks => CoverImage::Cells(half_blocks(&fill(cols, rows * 2))),
+        Protocol::Sixel => CoverImage::Graphics(sixel(&fill(cols * cell_w, rows * cell_h))),
+        Protocol::Kitty => CoverImage::Graphics(kitty(
+            &fill(cols * cell_w, rows * cell_h),
+            request.cols,
+            request.rows,
+        )),
+    }
+}
+
+/// Pair up pixel rows into `▀` cells.
+fn half_blocks(img: &RgbImage) -> Vec<Vec<([u8; 3], [u8; 3])>> {
+    (0..img.height() / 2)
+        .map(|row| {
+            (0..img.width())
+                .map(|x| (img.get_pixel(x, row * 2).0, img.get_pixel(x, row * 2 + 1).0))
+                .collect()
+        })
+        .collect()
+}
+
+/// Kitty graphics: raw RGB, placed over `cols` x `rows` cells without moving
+/// the cursor, with replies suppressed.
+fn kitty(img: &RgbImage, cols: u16, rows: u16) -> String {
+    let data = base64(img.as_raw());
+    let chunks: Vec<&str> = data
+        .as_bytes()
+        .chunks(KITTY_CHUNK)
+        .map(|c| std::str::from_utf8(c).unwrap_or_default())
+        .collect();
+    let mut out = String::with_capacity(data.len() + chunks.len() * 16 + 64);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=24,s={},v={},c={},r={},C=1,q=2,m={};",
+                img.width(),
+                img.height(),
+                cols,
+                rows,
+                more
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(chunk);
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Sixel with a fixed 6x6x6 colour cube palette.
+fn sixel(img: &RgbImage) -> String {
+    let level = |v: u8| (u16::from(v) * 5 + 127) / 255;
+    let (width, height) = (img.width(), img.height());
+    let index: Vec<u16> = img
+        .pixels()
+        .map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        .collect();
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    let mut used = [false; 216];
+    for &i in &index {
+        used[usize::from(i)] = true;
+    }
+    for (i, _) in used.iter().enumerate().filter(|(_, u)| **u) {
+        let pct = |l: usize| l * 20;
+        out.push_str(&format!(
+            "#{};2;{};{};{}",
+            i,
+            pct(i / 36),
+            pct(i / 6 % 6),
+            pct(i % 6)
+        ));
+    }
+
+    for band in (0..height).step_by(6) {
+        let band_rows = (height - band).min(6);
+        let mut colors: Vec<u16> = (0..band_rows)
+            .flat_map(|dy| {
+                let start = ((band + dy) * width) as usize;
+                index[start..start + width as usize].iter().copied()
+            })
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+        for (n, &color) in colors.iter().enumerate() {
+            if n > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{}", color));
+            let column = |x: u32| {
+                let bits = (0..band_rows)
+                    .filter(|dy| index[((band + dy) * width + x) as usize] == color)
+                    .fold(0u8, |bits, dy| bits | (1 << dy));
+                (63 + bits) as char
+            };
+            let mut x = 0;
+            while x < width {
+                let c = column(x);
+                let mut run = 1;
+                while x + run < width && column(x + run) == c {
+                    run += 1;
+                }
+                if run > 3 {
+                    out.push_str(&format!("!{}{}", run, c));
+                } else {
+                    (0..run).for_each(|_| out.push(c));
+                }
+                x += run;
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_detection_prefers_graphics_the_terminal_is_known_for() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let auto = CoverArtSetting::Auto;
+        assert_eq!(
+            detect_protocol(auto, env(&[("TERM", "xterm-kitty")])),
+            Some(Protocol::Kitty)
+        );
+        assert_eq!(
+            detect_protocol(auto, env(&[("TERM", "foot")])),
+            Some(Protocol::Sixel)
+        );
+        assert_eq!(
+            detect_protocol(auto, env(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/t")])),
+            Some(Protocol::HalfBlocks)
+        );
+        assert_eq!(
+            detect_protocol(auto, env(&[("TERM", "xterm-256color")])),
+            Some(Protocol::HalfBlocks)
+        );
+        assert_eq!(detect_protocol(CoverArtSetting::Off, env(&[])), None);
+    }
+
+    #[test]
+    fn encoders_produce_cells_base64_and_run_length_sixels() {
+        let mut img = RgbImage::from_pixel(8, 2, image::Rgb([255, 0, 0]));
+        img.put_pixel(0, 1, image::Rgb([0, 0, 255]));
+        let cells = half_blocks(&img);
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0][0], ([255, 0, 0], [0, 0, 255]));
+
+        assert_eq!(base64(b"cover"), "Y292ZXI=");
+        assert_eq!(base64(b"co"), "Y28=");
+
+        // Blue is only the first column's lower pixel; red fills the rest.
+        let seq = sixel(&img);
+        assert!(seq.starts_with("\x1bPq\"1;1;8;2#5;2;0;0;100#180;2;100;0;0"));
+        assert!(seq.contains("#5A!7?$#180@!7B-"));
+        assert!(seq.ends_with("\x1b\\"));
+    }
+}
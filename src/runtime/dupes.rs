@@ -0,0 +1,229 @@
+//! Duplicate finder: the `presto dupes` report and the `:dupes` view.
+//!
+//! Unwanted copies are moved (never deleted) to the trash folder as one
+//! rename batch in the undo log, so `:undo` puts them back.
+
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::app::{App, DupesView};
+use crate::audio::AudioPlayer;
+use crate::config;
+use crate::library::{
+    BatchOp, DupeGroup, FileMove, confirm_by_hash, content_hash, copy_summary, find_duplicates,
+    trash_target,
+};
+use crate::runtime::event_loop::EventLoopState;
+
+use super::batch::{self, UndoLog};
+use super::{expand_home, library_scan};
+
+/// The trash folder: `[library] trash_dir`, else `presto/trash` in the XDG
+/// data directory.
+pub(super) fn trash_dir(settings: &config::LibrarySettings) -> Option<PathBuf> {
+    if !settings.trash_dir.trim().is_empty() {
+        return Some(PathBuf::from(expand_home(settings.trash_dir.trim())));
+    }
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(xdg) => PathBuf::from(xdg),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_home.join("presto").join("trash"))
+}
+
+/// `presto dupes`: scan `roots` and print every duplicate group.
+pub(super) fn report(
+    roots: &[String],
+    settings: &config::Settings,
+    hash: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scan = library_scan::scan_all(
+        roots.iter().map(PathBuf::from).collect(),
+        settings.library.clone(),
+        false,
+    );
+    if let Some(err) = scan.index_error {
+        eprintln!("presto: index_save_failed error=\"{}\"", err);
+    }
+    let mut groups = find_duplicates(&scan.tracks);
+    if hash {
+        let files: usize = groups.iter().map(|g| g.copies.len()).sum();
+        eprintln!("presto: hashing the audio of {} file(s)", files);
+        groups = confirm_by_hash(groups, content_hash);
+    }
+
+    let mut out = io::stdout().lock();
+    let mut extra_bytes = 0;
+    for group in &groups {
+        writeln!(out, "{} ({} copies)", group.label(), group.copies.len())?;
+        for (i, copy) in group.copies.iter().enumerate() {
+            let mark = if i == 0 { "keep" } else { "    " };
+            if i > 0 {
+                extra_bytes += copy.audio.file_size.unwrap_or(0);
+            }
+            writeln!(
+                out,
+                "  {} {}  {}",
+                mark,
+                copy_summary(copy),
+                copy.path.display()
+            )?;
+        }
+        writeln!(out)?;
+    }
+    let extra: usize = groups.iter().map(|g| g.copies.len() - 1).sum();
+    writeln!(
+        out,
+        "{} duplicate group(s), {} extra copies, {:.1} MB",
+        groups.len(),
+        extra,
+        extra_bytes as f64 / 1e6
+    )?;
+    Ok(())
+}
+
+/// `:dupes` opens the finder over the current library; `:dupes hash` first
+/// confirms the groups by hashing decoded audio on a background thread.
+pub(super) fn open(arg: &str, app: &mut App, state: &mut EventLoopState) -> Result<(), String> {
+    let hash = match arg.trim() {
+        "" => false,
+        "hash" => true,
+        _ => return Err("Usage: :dupes [hash]".to_string()),
+    };
+    let groups = find_duplicates(&app.tracks);
+    if groups.is_empty() {
+        return Err("No duplicates found".to_string());
+    }
+    if !hash {
+        app.dupes = Some(DupesView::new(groups, false));
+        return Ok(());
+    }
+    let files: usize = groups.iter().map(|g| g.copies.len()).sum();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(confirm_by_hash(groups, content_hash));
+    });
+    state.dupes_search = Some(rx);
+    app.set_notice(format!("Hashing the audio of {} file(s)...", files));
+    Ok(())
+}
+
+/// Open the finder once background hashing has finished.
+pub(super) fn poll(app: &mut App, state: &mut EventLoopState) {
+    let Some(rx) = state.dupes_search.as_ref() else {
+        return;
+    };
+    let groups: Vec<DupeGroup> = match rx.try_recv() {
+        Ok(groups) => groups,
+        Err(mpsc::TryRecvError::Empty) => return,
+        Err(mpsc::TryRecvError::Disconnected) => Vec::new(),
+    };
+    state.dupes_search = None;
+    if groups.is_empty() {
+        app.set_notice("No duplicates with identical audio".to_string());
+    } else {
+        app.set_notice(format!("{} group(s) with identical audio", groups.len()));
+        app.dupes = Some(DupesView::new(groups, true));
+    }
+}
+
+/// Handle a key while the duplicate finder is open.
+pub(super) fn handle_key(
+    key: KeyEvent,
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    state: &EventLoopState,
+) {
+    let Some(view) = app.dupes.as_mut() else {
+        return;
+    };
+    if view.confirm_all {
+        view.confirm_all = false;
+        if matches!(key.code, KeyCode::Char('y') | KeyCode::Enter) {
+            let all: Vec<usize> = (0..view.groups.len()).collect();
+            trash(&all, settings, app, audio_player, state);
+        }
+        return;
+    }
+    match key.code {
+        KeyCode::Char('j') | KeyCode::Down => view.step(1),
+        KeyCode::Char('k') | KeyCode::Up => view.step(-1),
+        KeyCode::Char(' ') | KeyCode::Enter => view.keep_selected(),
+        KeyCode::Char('x') => {
+            let (g, _) = view.position();
+            trash(&[g], settings, app, audio_player, state);
+        }
+        KeyCode::Char('X') => view.confirm_all = true,
+        KeyCode::Char('q') | KeyCode::Esc => app.dupes = None,
+        _ => {}
+    }
+}
+
+/// Move every copy of `groups` except the kept one to the trash folder.
+fn trash(
+    groups: &[usize],
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    state: &EventLoopState,
+) {
+    let Some(view) = app.dupes.as_ref() else {
+        return;
+    };
+    let Some(trash) = trash_dir(&settings.library) else {
+        app.set_notice("No trash folder: set [library] trash_dir".to_string());
+        return;
+    };
+    let moves: Vec<FileMove> = groups
+        .iter()
+        .flat_map(|&g| view.extras(g))
+        .map(|track| {
+            let absolute = std::path::absolute(&track.path).unwrap_or(track.path.clone());
+            FileMove {
+                from: track.path.clone(),
+                to: trash_target(&trash, &absolute),
+            }
+        })
+        .collect();
+
+    let roots = batch::roots(state);
+    let (done, failures) = BatchOp::Rename { moves }.apply(&roots, &settings.library);
+    batch::refresh(settings, app, audio_player, &roots, &done);
+    let mut message = batch::outcome("Trashed", &done, &failures);
+    if !done.is_empty()
+        && let Err(e) = UndoLog::new_default().push(done.clone())
+    {
+        message = format!("{}; undo log save failed: {}", message, e);
+    }
+    app.set_notice(message);
+
+    // Groups whose extras all moved are settled; failed ones stay listed.
+    let BatchOp::Rename { moves } = &done else {
+        return;
+    };
+    let Some(view) = app.dupes.as_mut() else {
+        return;
+    };
+    let mut settled: Vec<usize> = groups
+        .iter()
+        .copied()
+        .filter(|&g| {
+            view.extras(g)
+                .iter()
+                .all(|t| moves.iter().any(|m| m.from == t.path))
+        })
+        .collect();
+    settled.sort_unstable();
+    for &g in settled.iter().rev() {
+        view.remove_group(g);
+    }
+    if view.groups.is_empty() {
+        app.dupes = None;
+    }
+}
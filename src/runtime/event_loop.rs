@@ -4,12 +4,21 @@ use std::time::Duration;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::{Terminal, backend::CrosstermBackend};
 
-use crate::app::{App, PlaybackState};
+use crate::app::{App, LibraryView, PlaybackState};
 use crate::audio::{AudioCmd, AudioPlayer};
 use crate::config;
+use crate::library::{DupeGroup, diagnose};
 use crate::mpris::ControlCmd;
 use crate::mpris::MprisHandle;
+use crate::runtime::batch;
+use crate::runtime::commands;
+use crate::runtime::cover::CoverLoader;
+use crate::runtime::dupes;
+use crate::runtime::library_scan::{LibraryScan, ScanEvent};
 use crate::runtime::mpris_sync::update_mpris;
+use crate::runtime::ratings;
+use crate::runtime::report;
+use crate::runtime::tag_edit;
 use crate::ui;
 
 /// Track a pending single-key sequence (like `gg` or `zz`).
@@ -42,7 +51,7 @@ pub struct EventLoopState {
     /// Optional snapshot of prior order when shuffle was toggled; used to
     /// detect a changed randomized order and reselect the top item.
     pub pending_shuffle_reselect_from: Option<Vec<usize>>,
-    /// Internal two-key prefix state used for `gg`/`zz` handling.
+    /// Internal two-key prefix state used for `gg`/`zz`/`R<n>` handling.
     pending_key: PendingKey,
     /// Pending numeric prefix (Vim-like count), e.g. `10j`.
     pending_count: Option<u32>,
@@ -50,20 +59,61 @@ pub struct EventLoopState {
     pub last_mpris_index: Option<usize>,
     /// Last-known playback state as emitted to MPRIS.
     pub last_mpris_playback: PlaybackState,
+    /// Background library scan (and watcher), while it is still reporting.
+    library_events: Option<LibraryScan>,
+    /// Persisted track path to select once the scan delivers it.
+    restore_path: Option<String>,
+    /// Persisted browser artist/album, re-selected when the scan finishes.
+    restore_browse: Option<(String, Option<String>)>,
+    /// Keep the cursor on the restore target (or the top of the list) as
+    /// tracks stream in, until it is found or the user presses a key.
+    hold_selection: bool,
+    /// Library roots being played; `:cd` replaces them.
+    pub roots: Vec<String>,
+    /// `:dupes hash` groups, while the audio is still being hashed.
+    pub dupes_search: Option<mpsc::Receiver<Vec<DupeGroup>>>,
+    /// Tag editor writes, while the files are still being written.
+    pub tag_write: Option<mpsc::Receiver<tag_edit::TagWrite>>,
 }
 
 impl EventLoopState {
-    /// Construct a new `EventLoopState` seeded from `app`.
-    pub fn new(app: &App) -> Self {
+    /// Construct a new `EventLoopState` seeded from `app` for the library at `roots`.
+    pub fn new(app: &App, roots: Vec<String>) -> Self {
         Self {
             pending_shuffle_reselect_from: None,
             pending_key: PendingKey::default(),
             pending_count: None,
             last_mpris_index: None,
             last_mpris_playback: app.playback,
+            library_events: None,
+            restore_path: None,
+            restore_browse: None,
+            hold_selection: false,
+            roots,
+            dupes_search: None,
+            tag_write: None,
         }
     }
 
+    /// Key the current library's persisted state is stored under.
+    pub fn session_key(&self) -> String {
+        super::state::session_key(&self.roots)
+    }
+
+    /// Feed tracks from a background scan into the loop, restoring the
+    /// selection to `restore_path` once that track arrives.
+    pub fn watch_library_scan(&mut self, scan: LibraryScan, restore_path: Option<String>) {
+        self.library_events = Some(scan);
+        self.restore_path = restore_path;
+        self.hold_selection = true;
+    }
+
+    /// Return the browser to `artist`/`album` once the scan has finished,
+    /// unless the user moves the cursor first.
+    pub fn restore_browse_path(&mut self, artist: String, album: Option<String>) {
+        self.restore_browse = Some((artist, album));
+    }
+
     fn clear_count(&mut self) {
         self.pending_count = None;
     }
@@ -149,6 +199,89 @@ fn shuffle_reselect_target(
     }
 }
 
+/// Apply tracks and library changes reported since the last iteration.
+fn drain_library_scan(state: &mut EventLoopState, app: &mut App, audio_player: &AudioPlayer) {
+    let Some(scan) = state.library_events.as_ref() else {
+        return;
+    };
+    let first_new = app.tracks.len();
+    let mut finished = false;
+    let mut disconnected = false;
+    loop {
+        match scan.events.try_recv() {
+            Ok(ScanEvent::Found(tracks)) => {
+                // The audio thread must know the tracks before the next SetQueue.
+                let _ = audio_player.send(AudioCmd::AppendTracks(tracks.clone()));
+                app.append_tracks(tracks);
+            }
+            Ok(ScanEvent::Finished {
+                walk_problems,
+                index_error,
+            }) => {
+                app.walk_problems = walk_problems;
+                let unreadable = diagnose(&app.tracks, &app.walk_problems)
+                    .iter()
+                    .filter(|d| d.issue.is_error())
+                    .count();
+                if let Some(err) = index_error {
+                    app.set_notice(format!("Library index save failed: {}", err));
+                } else if unreadable > 0 {
+                    app.set_notice(format!(
+                        "{} file(s) could not be read; :report lists them",
+                        unreadable
+                    ));
+                }
+                finished = true;
+                break;
+            }
+            Ok(ScanEvent::Changed(changes)) => {
+                // Remap the UI first: it also fixes up the shared handles the
+                // audio thread will overwrite once it applies the same batch.
+                app.apply_library_changes(changes.clone());
+                let _ = audio_player.send(AudioCmd::ApplyLibraryChanges(changes));
+            }
+            Ok(ScanEvent::WatchFailed(err)) => {
+                app.set_notice(format!("Library watch failed: {}", err));
+            }
+            Err(mpsc::TryRecvError::Empty) => break,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                finished = true;
+                disconnected = true;
+                break;
+            }
+        }
+    }
+
+    if state.hold_selection && app.tracks.len() > first_new {
+        let restored = state.restore_path.as_deref().and_then(|path| {
+            app.tracks[first_new..]
+                .iter()
+                .position(|t| t.path.to_string_lossy() == path)
+                .map(|offset| first_new + offset)
+        });
+        if let Some(idx) = restored {
+            app.set_selected(idx);
+            state.hold_selection = false;
+        } else if let Some(&first) = app.display_indices().first() {
+            app.set_selected(first);
+        }
+    }
+
+    if disconnected {
+        state.library_events = None;
+    }
+    if finished {
+        if let Some((artist, album)) = state.restore_browse.take()
+            && app.view == LibraryView::Browser
+        {
+            app.select_browse_path(&artist, album.as_deref());
+        }
+        state.restore_path = None;
+        state.hold_selection = false;
+        app.scanning = false;
+    }
+}
+
 /// Main terminal event loop: handles input, UI drawing, sync with the audio
 /// thread and MPRIS. Returns `Ok(())` when shutdown is requested.
 pub fn run(
@@ -161,7 +294,12 @@ pub fn run(
     control_rx: &mpsc::Receiver<ControlCmd>,
     state: &mut EventLoopState,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cover = CoverLoader::spawn(settings.ui.cover_art);
     loop {
+        drain_library_scan(state, app, audio_player);
+        dupes::poll(app, state);
+        tag_edit::poll(app, audio_player, state);
+
         // If shuffle just turned on, reselect the first track in the new randomized order.
         let new_order =
             if state.pending_shuffle_reselect_from.is_some() && app.shuffle && !app.filter_mode {
@@ -191,12 +329,17 @@ pub fn run(
         // Sync playback state from audio thread; optionally follow now-playing.
         // Clone the Arc handle to avoid borrowing `app` immutably across mutations.
         let mut playback_index_snapshot: Option<usize> = None;
+        let idx_opt = app.playing_index();
         if let Some(handle) = app.playback_handle.as_ref().cloned() {
-            if let Ok(info) = handle.lock() {
-                let idx_opt = info.index;
+            if let Ok(mut info) = handle.lock() {
                 let is_playing = info.playing;
+                let error = info.error.take();
                 drop(info);
 
+                if let Some(error) = error {
+                    app.set_notice(error);
+                }
+
                 playback_index_snapshot = idx_opt;
                 let follow_update = follow_playback_update(
                     app.follow_playback,
@@ -245,6 +388,9 @@ pub fn run(
                 if key.kind != KeyEventKind::Press {
                     continue;
                 }
+                // Once the user takes over, streamed-in tracks no longer move the cursor.
+                state.hold_selection = false;
+                state.restore_browse = None;
                 if handle_key_event(key, settings, app, audio_player, mpris, control_tx, state)? {
                     break;
                 }
@@ -253,7 +399,14 @@ pub fn run(
 
         sync_pending_count(state, app);
         let display = app.display_indices();
-        terminal.draw(|f| ui::draw(f, app, &display, &settings.ui, &settings.controls))?;
+        let mut cover_slot = None;
+        terminal.draw(|f| {
+            cover_slot = ui::draw(f, app, &display, &settings.ui, &settings.controls);
+        })?;
+        if let Some(cover) = cover.as_mut() {
+            cover.present(terminal, app, cover_slot.as_ref())?;
+            cover.update(app, cover_slot.as_ref());
+        }
     }
 
     Ok(())
@@ -430,6 +583,29 @@ fn handle_key_event(
     control_tx: &mpsc::Sender<ControlCmd>,
     state: &mut EventLoopState,
 ) -> Result<bool, Box<dyn std::error::Error>> {
+    if app.tag_editor.is_some() {
+        tag_edit::handle_key(key, settings, app, state);
+        update_mpris(mpris, app);
+        return Ok(false);
+    }
+    if app.batch_preview.is_some() {
+        batch::handle_key(key, settings, app, audio_player, state);
+        update_mpris(mpris, app);
+        return Ok(false);
+    }
+    if app.dupes.is_some() {
+        dupes::handle_key(key, settings, app, audio_player, state);
+        update_mpris(mpris, app);
+        return Ok(false);
+    }
+    if app.scan_report.is_some() {
+        report::handle_key(key, app);
+        return Ok(false);
+    }
+    if app.command_mode {
+        handle_command_key_event(key, settings, app, audio_player, mpris, state);
+        return Ok(false);
+    }
     if app.filter_mode {
         state.pending_key.clear();
         if let KeyCode::Char(c) = key.code {
@@ -511,6 +687,41 @@ fn handle_filter_key_event(
     Ok(false)
 }
 
+/// Handle key events while the `:` command line is open.
+fn handle_command_key_event(
+    key: KeyEvent,
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris: &MprisHandle,
+    state: &mut EventLoopState,
+) {
+    match key.code {
+        KeyCode::Esc => {
+            app.take_command_line();
+        }
+        KeyCode::Backspace => {
+            // Deleting past the start closes the line, as in Vim.
+            if app.command_line.is_empty() {
+                app.take_command_line();
+            } else {
+                app.command_line.pop();
+            }
+        }
+        KeyCode::Tab => {
+            if let Some(line) = commands::complete(&app.command_line, &state.roots) {
+                app.command_line = line;
+            }
+        }
+        KeyCode::Enter => {
+            let line = app.take_command_line();
+            commands::run_command(&line, settings, app, audio_player, mpris, state);
+        }
+        KeyCode::Char(c) if !c.is_control() => app.command_line.push(c),
+        _ => {}
+    }
+}
+
 /// Handle key events in normal (non-filter) mode.
 fn handle_normal_key_event(
     key: KeyEvent,
@@ -529,6 +740,9 @@ fn handle_normal_key_event(
             if app.lyrics_popup {
                 app.toggle_lyrics_popup();
             }
+            if app.lyrics_fullscreen {
+                app.toggle_lyrics_fullscreen();
+            }
             state.pending_key.clear();
             clear_pending_count(state, app);
         }
@@ -550,6 +764,12 @@ fn handle_normal_key_event(
             if c.is_ascii_digit() && !key.modifiers.contains(KeyModifiers::CONTROL) =>
         {
             let digit = c.to_digit(10).unwrap_or(0);
+            if state.pending_key.take_if('R') {
+                clear_pending_count(state, app);
+                ratings::rate(digit as u8, settings, app, audio_player, state);
+                update_mpris(mpris, app);
+                return Ok(false);
+            }
             if digit != 0 || state.pending_count.is_some() {
                 state.pending_key.clear();
                 state.push_count_digit(digit);
@@ -562,6 +782,28 @@ fn handle_normal_key_event(
             app.enter_filter_mode();
             update_mpris(mpris, app);
         }
+        KeyCode::Char('e') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            if !app.open_tag_editor(&settings.library) {
+                app.set_notice("No track to edit".to_string());
+            }
+        }
+        KeyCode::Char('R') => {
+            clear_pending_count(state, app);
+            state.pending_key.set('R');
+        }
+        KeyCode::Char('F') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            ratings::toggle_favourites(settings, app);
+            update_mpris(mpris, app);
+        }
+        KeyCode::Char(':') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            app.enter_command_mode();
+        }
         KeyCode::Char('s') => {
             state.pending_key.clear();
             clear_pending_count(state, app);
@@ -584,6 +826,26 @@ fn handle_normal_key_event(
             }
             update_mpris(mpris, app);
         }
+        KeyCode::Char('o') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            if app.cycle_sort_preset() {
+                let _ = audio_player.send(AudioCmd::SetSortOrder(app.sort_spec()));
+                app.set_notice(format!("Sort: {}", app.sort_preset.label()));
+            } else {
+                app.set_notice("This view keeps its own order; o sorts the track list".to_string());
+            }
+        }
+        KeyCode::Char(c @ ('b' | 'f')) => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            app.toggle_view(if c == 'b' {
+                LibraryView::Browser
+            } else {
+                LibraryView::Folders
+            });
+            let _ = audio_player.send(AudioCmd::SetSortOrder(app.sort_spec()));
+        }
         KeyCode::Char('r') => {
             state.pending_key.clear();
             clear_pending_count(state, app);
@@ -591,13 +853,41 @@ fn handle_normal_key_event(
             let _ = audio_player.send(AudioCmd::SetLoopMode(app.loop_mode));
             update_mpris(mpris, app);
         }
+        KeyCode::Char('x') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            let _ = audio_player.send(AudioCmd::ToggleStopAfterCurrent);
+        }
+        KeyCode::Char('X') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            let _ = audio_player.send(AudioCmd::ToggleStopAfterAlbum);
+        }
+        KeyCode::Char('n') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            let _ = audio_player.send(AudioCmd::ToggleNightMode);
+        }
+        KeyCode::Char('v') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            let _ = audio_player.send(AudioCmd::ToggleKaraoke);
+        }
+        KeyCode::Char('[') | KeyCode::Char(']') => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            let step = i32::from(settings.controls.karaoke_step_percent);
+            let delta = if key.code == KeyCode::Char('[') {
+                -step
+            } else {
+                step
+            };
+            let _ = audio_player.send(AudioCmd::StepKaraokeAmount(delta));
+        }
         KeyCode::Char('z') => {
             clear_pending_count(state, app);
             if state.pending_key.take_if('z') {
-                let track_id = app
-                    .playback_handle
-                    .as_ref()
-                    .and_then(|handle_val| handle_val.lock().ok().and_then(|info| info.index));
+                let track_id = app.playing_index();
                 if let Some(id) = track_id {
                     app.set_selected(id);
                     update_mpris(mpris, app);
@@ -642,7 +932,11 @@ fn handle_normal_key_event(
             app.pending_count = None;
             app.follow_playback_off();
             for _ in 0..count {
-                app.next();
+                match app.view {
+                    LibraryView::List => app.next(),
+                    LibraryView::Browser => app.browse_step(true),
+                    LibraryView::Folders => app.folder_step(true),
+                }
             }
             update_mpris(mpris, app);
         }
@@ -652,21 +946,37 @@ fn handle_normal_key_event(
             app.pending_count = None;
             app.follow_playback_off();
             for _ in 0..count {
-                app.prev();
+                match app.view {
+                    LibraryView::List => app.prev(),
+                    LibraryView::Browser => app.browse_step(false),
+                    LibraryView::Folders => app.folder_step(false),
+                }
             }
             update_mpris(mpris, app);
         }
+        KeyCode::Enter if app.view != LibraryView::List => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            // Artists, albums and folders queue the whole group and start at its first track.
+            let play = if app.view == LibraryView::Browser {
+                app.browse_activate()
+            } else {
+                app.folder_activate()
+            };
+            if let Some(idx) = play {
+                app.follow_playback_on();
+                app.set_pending_follow_index(idx);
+                let _ = audio_player.send(AudioCmd::Play(idx));
+                app.playback = PlaybackState::Playing;
+                update_mpris(mpris, app);
+            }
+        }
         KeyCode::Enter => {
             state.pending_key.clear();
             clear_pending_count(state, app);
             if app.has_tracks() {
                 let is_playing_selected = app.playback == PlaybackState::Playing
-                    && app
-                        .playback_handle
-                        .as_ref()
-                        .and_then(|h| h.lock().ok().and_then(|info| info.index))
-                        .map(|idx| idx == app.selected)
-                        .unwrap_or(false);
+                    && app.playing_index() == Some(app.selected);
                 if !is_playing_selected {
                     app.follow_playback_on();
                     app.set_pending_follow_index(app.selected);
@@ -692,6 +1002,14 @@ fn handle_normal_key_event(
                         "Lyrics are disabled in config ([ui].lyrics_enabled = true)".to_string(),
                     );
                 }
+            } else if app.view != LibraryView::List {
+                state.pending_key.clear();
+                clear_pending_count(state, app);
+                if app.view == LibraryView::Browser {
+                    app.browse_deeper();
+                } else {
+                    app.folder_expand();
+                }
             } else {
                 state.pending_key.clear();
                 let count = state.take_count_or_default();
@@ -701,6 +1019,15 @@ fn handle_normal_key_event(
                 }
             }
         }
+        KeyCode::Char('h') if app.view != LibraryView::List => {
+            state.pending_key.clear();
+            clear_pending_count(state, app);
+            if app.view == LibraryView::Browser {
+                app.browse_shallower();
+            } else {
+                app.folder_collapse();
+            }
+        }
         KeyCode::Char('h') => {
             state.pending_key.clear();
             let count = state.take_count_or_default();
@@ -725,6 +1052,18 @@ fn handle_normal_key_event(
             reset_volume(app, audio_player);
         }
         KeyCode::Char('L') => {
+            if state.pending_key.take_if('g') {
+                clear_pending_count(state, app);
+                if settings.ui.lyrics_enabled {
+                    app.toggle_lyrics_fullscreen();
+                    app.clear_notice();
+                } else {
+                    app.set_notice(
+                        "Lyrics are disabled in config ([ui].lyrics_enabled = true)".to_string(),
+                    );
+                }
+                return Ok(false);
+            }
             state.pending_key.clear();
             clear_pending_count(state, app);
             let secs = settings.controls.scrub_seconds.min(i32::MAX as u64) as i32;
@@ -0,0 +1,233 @@
+//! Background library scan and watcher feeding the event loop.
+//!
+//! The scan runs on its own thread so the UI can draw immediately. Tracks are
+//! sent to the event loop in batches as they are found; the loop appends them
+//! to the `App` and forwards them to the audio thread. With `[library] watch`
+//! the same thread then reports filesystem changes until the `LibraryScan`
+//! is dropped (on exit, or when `:cd` replaces the library).
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::LibrarySettings;
+use crate::library::{
+    Diagnostic, LibraryChange, LibraryIndex, LibraryWatcher, Track, WatchStop, scan_streaming,
+};
+
+/// Send a batch once it holds this many tracks...
+const BATCH_TRACKS: usize = 256;
+/// ...or once this much time has passed since the last one.
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+/// Filesystem events are applied once the directory has been quiet this long.
+const WATCH_QUIET: Duration = Duration::from_millis(500);
+
+/// Progress reported by the scan thread.
+pub enum ScanEvent {
+    /// Newly found tracks, in no particular order.
+    Found(Vec<Track>),
+    /// The scan is complete; carries the entries the walk could not list
+    /// and the index save error, if any.
+    Finished {
+        walk_problems: Vec<Diagnostic>,
+        index_error: Option<String>,
+    },
+    /// Files were added, changed, removed or renamed after the scan.
+    Changed(Vec<LibraryChange>),
+    /// Watching could not be set up or stopped working.
+    WatchFailed(String),
+}
+
+/// A running scan and watch. Dropping it stops the thread's watcher, which
+/// releases its inotify descriptor; a scan still walking finishes on its own
+/// once its sends start failing.
+pub struct LibraryScan {
+    pub events: mpsc::Receiver<ScanEvent>,
+    stop: Option<WatchStop>,
+}
+
+impl Drop for LibraryScan {
+    fn drop(&mut self) {
+        if let Some(stop) = &self.stop {
+            stop.stop();
+        }
+    }
+}
+
+/// Everything a scan run to the end found.
+#[derive(Default)]
+pub struct FullScan {
+    /// Tracks in no particular order.
+    pub tracks: Vec<Track>,
+    pub walk_problems: Vec<Diagnostic>,
+    pub index_error: Option<String>,
+}
+
+/// Scan `roots` to the end without watching, for command-line reports.
+pub fn scan_all(roots: Vec<PathBuf>, mut settings: LibrarySettings, rescan: bool) -> FullScan {
+    settings.watch = false;
+    let mut scan = FullScan::default();
+    let run = spawn(roots, settings, rescan);
+    for event in run.events.iter() {
+        match event {
+            ScanEvent::Found(batch) => scan.tracks.extend(batch),
+            ScanEvent::Finished {
+                walk_problems,
+                index_error,
+            } => {
+                scan.walk_problems = walk_problems;
+                scan.index_error = index_error;
+                break;
+            }
+            ScanEvent::Changed(_) | ScanEvent::WatchFailed(_) => {}
+        }
+    }
+    scan
+}
+
+/// Scan `roots` on a background thread, reusing each root's on-disk library
+/// index unless disabled or `rescan` is set, then watch them if enabled.
+pub fn spawn(roots: Vec<PathBuf>, settings: LibrarySettings, rescan: bool) -> LibraryScan {
+    let (tx, rx) = mpsc::channel();
+    let stop = if settings.watch {
+        match WatchStop::new() {
+            Ok(stop) => Some(stop),
+            Err(e) => {
+                let _ = tx.send(ScanEvent::WatchFailed(e.to_string()));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let watch_stop = stop.clone();
+    thread::spawn(move || {
+        // Watch before walking so nothing added mid-scan is missed; events for
+        // files the scan already saw just refresh them.
+        let mut watcher = watch_stop.and_then(|stop| {
+            LibraryWatcher::new(&roots, &settings, stop)
+                .map_err(|e| {
+                    let _ = tx.send(ScanEvent::WatchFailed(e.to_string()));
+                })
+                .ok()
+        });
+
+        let mut batch: Vec<Track> = Vec::new();
+        let mut last_sent = Instant::now();
+        // Nested or repeated roots must not list a file twice.
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        let mut index_error = None;
+        let mut walk_problems = Vec::new();
+        for dir in &roots {
+            let mut index = if !settings.index_cache {
+                LibraryIndex::in_memory()
+            } else {
+                let root = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+                if rescan {
+                    LibraryIndex::fresh_default(&root)
+                } else {
+                    LibraryIndex::load_default(&root)
+                }
+            };
+
+            let unlisted = scan_streaming(dir, &settings, &mut index, |track| {
+                if !seen.insert(track.path.clone()) {
+                    return;
+                }
+                batch.push(track);
+                if batch.len() >= BATCH_TRACKS || last_sent.elapsed() >= BATCH_INTERVAL {
+                    // A closed channel means the UI has quit or moved on
+                    // to another library (`:cd`); finish quietly.
+                    let _ = tx.send(ScanEvent::Found(std::mem::take(&mut batch)));
+                    last_sent = Instant::now();
+                }
+            });
+            walk_problems.extend(unlisted);
+            if let Err(e) = index.save() {
+                index_error.get_or_insert(e.to_string());
+            }
+            if let Some(watcher) = watcher.as_mut() {
+                watcher.attach_index(dir, index);
+            }
+        }
+        if !batch.is_empty() {
+            let _ = tx.send(ScanEvent::Found(batch));
+        }
+        let _ = tx.send(ScanEvent::Finished {
+            walk_problems,
+            index_error,
+        });
+
+        let Some(watcher) = watcher.as_mut() else {
+            return;
+        };
+        loop {
+            match watcher.next_changes(WATCH_QUIET) {
+                Ok(None) => return,
+                Ok(Some(changes)) => {
+                    if tx.send(ScanEvent::Changed(changes)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(ScanEvent::WatchFailed(e.to_string()));
+                    return;
+                }
+            }
+        }
+    });
+    LibraryScan { events: rx, stop }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Start a watching scan of `dir` and wait for it to finish walking.
+    fn watched_scan(dir: &std::path::Path) -> LibraryScan {
+        let settings = LibrarySettings {
+            index_cache: false,
+            ..LibrarySettings::default()
+        };
+        let scan = spawn(vec![dir.to_path_buf()], settings, false);
+        loop {
+            match scan.events.recv_timeout(Duration::from_secs(5)).unwrap() {
+                ScanEvent::Finished { .. } => return scan,
+                ScanEvent::WatchFailed(e) => panic!("watch failed: {}", e),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn replaced_scans_stop_watching_and_release_their_watcher() {
+        let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        for dir in &dirs {
+            fs::write(dir.path().join("a.mp3"), b"not real").unwrap();
+        }
+
+        // What `:cd` does twice: each new scan replaces (drops) the previous one.
+        let mut current = watched_scan(dirs[0].path());
+        let mut replaced = Vec::new();
+        for dir in &dirs[1..] {
+            replaced.push(current.stop.clone().unwrap());
+            current = watched_scan(dir.path());
+        }
+
+        // Each old thread wakes without any filesystem event, drops its
+        // watcher (and with it the inotify fd) and exits.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        for stop in &replaced {
+            assert!(stop.is_stopped());
+            while stop.holders() > 1 {
+                assert!(Instant::now() < deadline, "old watcher still running");
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        // The current library is still watched.
+        assert_eq!(current.stop.as_ref().unwrap().holders(), 2);
+    }
+}
@@ -1,5 +1,4 @@
 use std::env;
-use std::path::Path;
 use std::path::PathBuf;
 use std::sync::mpsc;
 
@@ -11,14 +10,22 @@ use ratatui::{Terminal, backend::CrosstermBackend};
 
 use crate::app::App;
 use crate::audio::{AudioCmd, AudioPlayer};
-use crate::library::scan;
 use crate::mpris::ControlCmd;
 
+mod batch;
+mod cli;
+mod commands;
+mod cover;
+mod dupes;
 mod event_loop;
+mod library_scan;
 mod mpris_sync;
+mod ratings;
+mod report;
 mod settings;
 mod startup;
 mod state;
+mod tag_edit;
 
 /// Expand a directory to an absolute path for UI display.
 fn absolutize_dir_for_display(dir: &str) -> String {
@@ -39,30 +46,93 @@ fn absolutize_dir_for_display(dir: &str) -> String {
         .to_string()
 }
 
-/// Initialize settings, library, audio thread, and enter the main event loop.
-pub fn run() -> Result<(), Box<dyn std::error::Error>> {
-    let settings = settings::load_settings();
+/// Expand a leading `~/` (or a bare `~`) in a directory to the home directory.
+fn expand_home(dir: &str) -> String {
+    let rest = dir.strip_prefix("~/").or((dir == "~").then_some(""));
+    match (rest, env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest).to_string_lossy().to_string(),
+        _ => dir.to_string(),
+    }
+}
 
-    let dir = env::args().nth(1).unwrap_or_else(|| {
+/// Resolve the library roots: the command-line directory, else `[library] roots`,
+/// else the current directory.
+fn library_roots(
+    cli_dir: Option<String>,
+    settings: &crate::config::LibrarySettings,
+) -> Vec<String> {
+    if let Some(dir) = cli_dir {
+        return vec![dir];
+    }
+    if !settings.roots.is_empty() {
+        let mut roots: Vec<String> = Vec::new();
+        for root in settings.roots.iter().map(|r| expand_home(r.trim())) {
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        return roots;
+    }
+    vec![
         std::env::current_dir()
             .ok()
             .and_then(|p| p.to_str().map(|s| s.to_string()))
-            .unwrap_or_else(|| "Music".to_string())
-    });
+            .unwrap_or_else(|| "Music".to_string()),
+    ]
+}
 
-    let tracks = scan(Path::new(&dir), &settings.library);
-    let audio_player = AudioPlayer::new(tracks.clone(), settings.audio.clone());
-    let mut app = App::new(tracks);
+/// Initialize settings, library, audio thread, and enter the main event loop.
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let args = match cli::CliArgs::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("presto: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let settings = settings::load_settings();
+
+    let roots = library_roots(args.dir, &settings.library);
+    // Persisted state is keyed by the whole set of roots.
+    let session = state::session_key(&roots);
+
+    match args.command {
+        cli::Command::Dupes => return dupes::report(&roots, &settings, args.hash),
+        cli::Command::Scan => return report::print(&roots, &settings, args.rescan, args.report),
+        cli::Command::Play => {}
+    }
+
+    // Tracks arrive from the background scan once the event loop is running.
+    let library_scan = library_scan::spawn(
+        roots.iter().map(PathBuf::from).collect(),
+        settings.library.clone(),
+        args.rescan,
+    );
+    let audio_player = AudioPlayer::new(Vec::new(), settings.audio.clone());
+    let mut app = App::new(Vec::new());
+    app.scanning = true;
 
     app.follow_playback = settings.ui.follow_playback;
-    app.set_current_dir(absolutize_dir_for_display(&dir));
+    app.set_current_dir(
+        roots
+            .iter()
+            .map(|r| absolutize_dir_for_display(r))
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
     app.set_playback_handle(audio_player.playback_handle());
     app.set_order_handle(audio_player.order_handle());
     app.set_initial_volume_percent(settings.audio.initial_volume_percent);
+    ratings::load(&settings, &mut app);
 
     let store = state::StateStore::new_default();
     let persisted_state = if settings.state.enabled {
-        match store.load_directory_state(&dir) {
+        match store.load_directory_state(&session) {
             Ok(state) => state,
             Err(err) => {
                 app.set_notice(format!("State load failed: {}", err));
@@ -77,29 +147,14 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         None
     };
-    // Apply persisted playback defaults before applying selection fallbacks.
-    app.shuffle = persisted_state
-        .as_ref()
-        .and_then(|s| s.shuffle)
-        .unwrap_or(settings.playback.shuffle);
-
-    app.loop_mode = persisted_state
-        .as_ref()
-        .and_then(|s| s.loop_mode)
-        .unwrap_or(match settings.playback.loop_mode {
-            crate::config::LoopModeSetting::NoLoop => crate::audio::LoopMode::NoLoop,
-            crate::config::LoopModeSetting::LoopAll => crate::audio::LoopMode::LoopAll,
-            crate::config::LoopModeSetting::LoopOne => crate::audio::LoopMode::LoopOne,
-        });
-
-    state::apply_filter_and_selection(&mut app, persisted_state.as_ref());
-
-    if let Some(fp) = persisted_state.as_ref().and_then(|s| s.follow_playback) {
-        if fp {
-            app.follow_playback_on();
-        } else {
-            app.follow_playback_off();
-        }
+    let browse = startup::apply_directory_state(
+        &mut app,
+        &settings,
+        persisted_state.as_ref(),
+        &audio_player,
+    );
+    if let Some(pct) = persisted_state.as_ref().and_then(|s| s.volume_percent) {
+        app.set_initial_volume_percent(pct);
     }
 
     let _ = audio_player.send(AudioCmd::SetVolume(app.volume()));
@@ -117,10 +172,14 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let run_result: Result<(), Box<dyn std::error::Error>> = (|| {
-        let mut state = event_loop::EventLoopState::new(&app);
-        state.pending_shuffle_reselect_from = pending_shuffle_reselect_from;
+    let mut state = event_loop::EventLoopState::new(&app, roots);
+    state.pending_shuffle_reselect_from = pending_shuffle_reselect_from;
+    state.watch_library_scan(library_scan, state::restore_path(persisted_state.as_ref()));
+    if let Some((artist, album)) = browse {
+        state.restore_browse_path(artist, album);
+    }
 
+    let run_result: Result<(), Box<dyn std::error::Error>> = (|| {
         event_loop::run(
             &mut terminal,
             &settings,
@@ -132,13 +191,15 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
             &mut state,
         )
     })();
+    // `:cd` may have moved the session to another directory.
+    let session = state.session_key();
 
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
     if settings.state.enabled {
-        if let Err(e) = store.persist_directory_state(&dir, &app) {
+        if let Err(e) = store.persist_directory_state(&session, &app) {
             eprintln!(
                 "presto: state_persist_failed path=\"{}\" error=\"{}\"",
                 e.path().display(),
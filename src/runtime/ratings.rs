@@ -0,0 +1,167 @@
+//! Star ratings: `R` then `0`-`5` rates the selected track.
+//!
+//! Ratings go into the file's tags, through the tag editor's background
+//! writer, or with `[library] write_ratings = false` into a presto-side file
+//! keyed by path. Stored ratings follow the moves presto makes itself
+//! (`:rename`, `:undo`, `:dupes`), not ones made outside.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::app::App;
+use crate::audio::{AudioCmd, AudioPlayer};
+use crate::config;
+use crate::config::load::default_config_path;
+use crate::library::{LibraryChange, MAX_STARS, stars_text, write_rating};
+use crate::runtime::event_loop::EventLoopState;
+
+use super::tag_edit;
+
+/// Stored ratings file (`ratings.json` beside the config).
+pub struct RatingStore {
+    path: Option<PathBuf>,
+}
+
+impl RatingStore {
+    pub fn new_default() -> Self {
+        Self {
+            path: default_config_path().and_then(|p| p.parent().map(|d| d.join("ratings.json"))),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_path(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> io::Result<HashMap<PathBuf, u8>> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(HashMap::new());
+        };
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, ratings: &HashMap<PathBuf, u8>) -> io::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(ratings).map_err(io::Error::other)?)
+    }
+}
+
+/// Load the stored ratings into `app` when ratings are not written to tags.
+pub(super) fn load(settings: &config::Settings, app: &mut App) {
+    if settings.library.write_ratings {
+        return;
+    }
+    match RatingStore::new_default().load() {
+        Ok(ratings) => app.set_stored_ratings(ratings),
+        Err(e) => app.set_notice(format!("Ratings load failed: {}", e)),
+    }
+}
+
+/// Give the selected track `stars` (0 clears its rating).
+pub(super) fn rate(
+    stars: u8,
+    settings: &config::Settings,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    state: &mut EventLoopState,
+) {
+    if stars > MAX_STARS {
+        app.set_notice(format!("Ratings go from 0 to {} stars", MAX_STARS));
+        return;
+    }
+    let Some(track) = app
+        .browse_indices()
+        .contains(&app.selected)
+        .then(|| app.tracks[app.selected].clone())
+    else {
+        app.set_notice("No track to rate".to_string());
+        return;
+    };
+
+    let done = if stars == 0 {
+        "Rating cleared".to_string()
+    } else {
+        format!("Rated {}", stars_text(stars))
+    };
+    if settings.library.write_ratings {
+        if state.tag_write.is_some() {
+            app.set_notice("Tags are still being written".to_string());
+            return;
+        }
+        tag_edit::write_in_background(
+            vec![track.path],
+            &settings.library,
+            move |path| write_rating(path, Some(stars)),
+            done,
+            state,
+        );
+        return;
+    }
+
+    app.store_rating(&track.path, stars);
+    if let Err(e) = RatingStore::new_default().save(app.stored_ratings()) {
+        app.set_notice(format!("Ratings save failed: {}", e));
+        return;
+    }
+    let changes = vec![LibraryChange::Upsert(track)];
+    // Same order as watcher events: the UI first, then the audio thread.
+    app.apply_library_changes(changes.clone());
+    let _ = audio_player.send(AudioCmd::ApplyLibraryChanges(changes));
+    app.set_notice(done);
+}
+
+/// `F`: toggle the favourites view.
+pub(super) fn toggle_favourites(settings: &config::Settings, app: &mut App) {
+    app.toggle_favourites(settings.ui.favourite_stars);
+    let message = match app.favourites {
+        Some(_) if app.display_indices().is_empty() => {
+            format!(
+                "No favourites yet (rated {}+ stars)",
+                settings.ui.favourite_stars
+            )
+        }
+        Some(min) => format!("Favourites: rated {}+ stars", min),
+        None => "All tracks".to_string(),
+    };
+    app.set_notice(message);
+}
+
+/// Carry stored ratings along with files presto moved, before the moved
+/// tracks are applied. A failed save is retried by the next rating.
+pub(super) fn follow_moves(app: &mut App, moves: &[(PathBuf, PathBuf)]) {
+    if app.move_stored_ratings(moves) {
+        let _ = RatingStore::new_default().save(app.stored_ratings());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rating_store_round_trips_and_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RatingStore::with_path(Some(dir.path().join("sub/ratings.json")));
+        assert!(store.load().unwrap().is_empty());
+
+        let ratings = HashMap::from([
+            (PathBuf::from("/m/a.flac"), 4),
+            (PathBuf::from("/m/b.mp3"), 0),
+        ]);
+        store.save(&ratings).unwrap();
+        assert_eq!(store.load().unwrap(), ratings);
+    }
+}
@@ -0,0 +1,96 @@
+//! Scan diagnostics: `presto scan [--report]` and the `:report` view.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::app::{App, ScanReport};
+use crate::config;
+use crate::library::{diagnose, issue_counts};
+
+use super::library_scan;
+
+/// `presto scan`: scan `roots`, updating the index, and summarise problems
+/// on stderr; with `report`, print each one to stdout as
+/// `kind<TAB>path<TAB>detail`.
+pub(super) fn print(
+    roots: &[String],
+    settings: &config::Settings,
+    rescan: bool,
+    report: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scan = library_scan::scan_all(
+        roots.iter().map(PathBuf::from).collect(),
+        settings.library.clone(),
+        rescan,
+    );
+    if let Some(err) = &scan.index_error {
+        eprintln!("presto: index_save_failed error=\"{}\"", err);
+    }
+    let diagnostics = diagnose(&scan.tracks, &scan.walk_problems);
+
+    if report {
+        let mut out = io::stdout().lock();
+        for d in &diagnostics {
+            writeln!(out, "{}\t{}\t{}", d.issue.kind(), d.path.display(), d.issue)?;
+        }
+    }
+    let counts: Vec<String> = issue_counts(&diagnostics)
+        .into_iter()
+        .map(|(kind, n)| format!("{} {}", n, kind))
+        .collect();
+    eprintln!(
+        "presto: scanned {} track(s), {} problem(s){}{}",
+        scan.tracks.len(),
+        diagnostics.len(),
+        if counts.is_empty() { "" } else { ": " },
+        counts.join(", ")
+    );
+    Ok(())
+}
+
+/// `:report` lists the problems of the current library.
+pub(super) fn open(app: &mut App) -> Result<(), String> {
+    let diagnostics = diagnose(&app.tracks, &app.walk_problems);
+    if diagnostics.is_empty() {
+        return Err(if app.scanning {
+            "No scan problems yet (still scanning)".to_string()
+        } else {
+            "No scan problems".to_string()
+        });
+    }
+    app.scan_report = Some(ScanReport {
+        diagnostics,
+        cursor: 0,
+    });
+    Ok(())
+}
+
+/// Handle a key while the scan report is open.
+pub(super) fn handle_key(key: KeyEvent, app: &mut App) {
+    let Some(report) = app.scan_report.as_mut() else {
+        return;
+    };
+    let last = report.diagnostics.len().saturating_sub(1);
+    match key.code {
+        KeyCode::Char('j') | KeyCode::Down => report.cursor = (report.cursor + 1).min(last),
+        KeyCode::Char('k') | KeyCode::Up => report.cursor = report.cursor.saturating_sub(1),
+        KeyCode::Char('g') | KeyCode::Home => report.cursor = 0,
+        KeyCode::Char('G') | KeyCode::End => report.cursor = last,
+        KeyCode::Enter => {
+            let path = report.diagnostics[report.cursor].path.clone();
+            let Some(idx) = app.tracks.iter().position(|t| t.path == path) else {
+                app.set_notice("Not in the library (the scan could not list it)".to_string());
+                return;
+            };
+            app.scan_report = None;
+            app.set_selected(idx);
+            if app.selected != idx {
+                app.set_notice("Hidden by the current filter or view".to_string());
+            }
+        }
+        KeyCode::Char('q') | KeyCode::Esc => app.scan_report = None,
+        _ => {}
+    }
+}
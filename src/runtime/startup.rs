@@ -3,8 +3,68 @@
 //! Functions here translate `config` values into runtime settings and
 //! initialize audio thread state accordingly.
 
-use crate::app::App;
-use crate::audio::{AudioCmd, AudioPlayer};
+use crate::app::{App, LibraryView};
+use crate::audio::{AudioCmd, AudioPlayer, LoopMode};
+use crate::config::{LoopModeSetting, Settings};
+
+use super::state::{self, DirectoryState};
+
+/// Apply a library directory's persisted state (falling back to `settings`)
+/// to `app` and re-sort the audio thread to match. Returns the browser
+/// artist/album to restore once the scan has finished.
+///
+/// Volume is left to the caller so that `:cd` does not jump the volume of
+/// the song that keeps playing.
+pub fn apply_directory_state(
+    app: &mut App,
+    settings: &Settings,
+    persisted: Option<&DirectoryState>,
+    audio_player: &AudioPlayer,
+) -> Option<(String, Option<String>)> {
+    // Apply persisted playback defaults before applying selection fallbacks.
+    app.shuffle = persisted
+        .and_then(|s| s.shuffle)
+        .unwrap_or(settings.playback.shuffle);
+
+    app.loop_mode =
+        persisted
+            .and_then(|s| s.loop_mode)
+            .unwrap_or(match settings.playback.loop_mode {
+                LoopModeSetting::NoLoop => LoopMode::NoLoop,
+                LoopModeSetting::LoopAll => LoopMode::LoopAll,
+                LoopModeSetting::LoopOne => LoopMode::LoopOne,
+            });
+
+    app.set_sort(
+        persisted
+            .and_then(|s| s.sort_preset)
+            .unwrap_or(settings.library.sort_preset),
+        settings.library.sort.clone(),
+        settings.library.sort_articles.clone(),
+    );
+    // Only the browser is persisted; the folder view stays open across `:cd`.
+    let browse = persisted.and_then(|s| s.browse.clone());
+    if let Some(ref browse) = browse {
+        app.browse_level = browse.level;
+        app.set_view(LibraryView::Browser);
+    } else if app.view == LibraryView::Browser {
+        app.set_view(LibraryView::List);
+    }
+    let _ = audio_player.send(AudioCmd::SetSortOrder(app.sort_spec()));
+
+    state::apply_filter_and_selection(app, persisted);
+
+    if let Some(fp) = persisted.and_then(|s| s.follow_playback) {
+        if fp {
+            app.follow_playback_on();
+        } else {
+            app.follow_playback_off();
+        }
+    }
+
+    let browse = browse?;
+    Some((browse.artist?, browse.album))
+}
 
 /// Apply playback-related defaults from `settings` to the `app` and
 /// initialize the `audio_player` accordingly.
@@ -5,8 +5,9 @@ use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::app::App;
+use crate::app::{App, BrowseLevel, LibraryView};
 use crate::audio::LoopMode;
+use crate::config::SortPreset;
 use crate::config::load::default_config_path;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -18,6 +19,17 @@ pub struct DirectoryState {
     pub shuffle: Option<bool>,
     pub loop_mode: Option<LoopMode>,
     pub follow_playback: Option<bool>,
+    pub sort_preset: Option<SortPreset>,
+    /// Present when the artist/album browser was open.
+    pub browse: Option<BrowseState>,
+}
+
+/// Browser column and the artist/album path it was showing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BrowseState {
+    pub level: BrowseLevel,
+    pub artist: Option<String>,
+    pub album: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -94,9 +106,7 @@ impl StateStore {
             .map(|t| t.path.to_string_lossy().to_string());
 
         let last_played_path = app
-            .playback_handle
-            .as_ref()
-            .and_then(|h| h.lock().ok().and_then(|info| info.index))
+            .playing_index()
             .and_then(|idx| app.tracks.get(idx))
             .map(|t| t.path.to_string_lossy().to_string())
             .or_else(|| selected_path.clone());
@@ -115,6 +125,15 @@ impl StateStore {
                 shuffle: Some(app.shuffle),
                 loop_mode: Some(app.loop_mode),
                 follow_playback: Some(app.follow_playback),
+                sort_preset: Some(app.sort_preset),
+                browse: (app.view == LibraryView::Browser).then(|| {
+                    let (artist, album) = app.browse_path().unzip();
+                    BrowseState {
+                        level: app.browse_level,
+                        artist,
+                        album,
+                    }
+                }),
             },
         );
 
@@ -152,6 +171,31 @@ fn state_file_path() -> Option<PathBuf> {
     default_config_path().and_then(|p| p.parent().map(|d| d.join("state.toml")))
 }
 
+/// Key persisted state by the session's library roots.
+///
+/// A single root keeps its plain directory key, so existing state carries over;
+/// several roots are sorted and joined, so the same set restores the same state
+/// whatever order the config lists them in.
+pub fn session_key(roots: &[String]) -> String {
+    match roots {
+        [root] => root.clone(),
+        _ => {
+            let mut sorted = roots.to_vec();
+            sorted.sort();
+            sorted.join(" + ")
+        }
+    }
+}
+
+/// Return the persisted track path to select: last played, else last selected.
+pub fn restore_path(state: Option<&DirectoryState>) -> Option<String> {
+    state.and_then(|st| {
+        st.last_played_path
+            .clone()
+            .or_else(|| st.selected_path.clone())
+    })
+}
+
 /// Apply persisted filter and selection to the app, if present.
 pub fn apply_filter_and_selection(app: &mut App, state: Option<&DirectoryState>) {
     if let Some(st) = state {
@@ -160,14 +204,8 @@ pub fn apply_filter_and_selection(app: &mut App, state: Option<&DirectoryState>)
             app.mark_queue_dirty();
         }
 
-        if let Some(pct) = st.volume_percent {
-            app.set_initial_volume_percent(pct);
-        }
-
-        let candidate_path = st.last_played_path.as_ref().or(st.selected_path.as_ref());
-
         let mut selected_set = false;
-        if let Some(path) = candidate_path {
+        if let Some(path) = restore_path(Some(st)) {
             if let Some((idx, _)) = app
                 .tracks
                 .iter()
@@ -201,10 +239,8 @@ mod tests {
         Track {
             path: std::path::PathBuf::from(path),
             title: title.to_string(),
-            artist: None,
-            album: None,
-            duration: None,
             display: title.to_string(),
+            ..Track::default()
         }
     }
 
@@ -218,11 +254,14 @@ mod tests {
             track("/music/b.mp3", "Beta"),
         ];
         let mut app = App::new(tracks);
+        app.toggle_view(LibraryView::Browser);
+        app.browse_level = BrowseLevel::Album;
         app.set_selected(1);
         app.filter_query = "abc".to_string();
         app.shuffle = true;
         app.loop_mode = LoopMode::LoopOne;
         app.follow_playback = false;
+        app.set_sort_preset(SortPreset::Artist);
 
         store.persist_directory_state("/music", &app).unwrap();
 
@@ -234,6 +273,24 @@ mod tests {
         assert_eq!(loaded.shuffle, Some(true));
         assert_eq!(loaded.loop_mode, Some(LoopMode::LoopOne));
         assert_eq!(loaded.follow_playback, Some(false));
+        assert_eq!(loaded.sort_preset, Some(SortPreset::Artist));
+        assert_eq!(
+            loaded.browse,
+            Some(BrowseState {
+                level: BrowseLevel::Album,
+                artist: Some("Unknown artist".to_string()),
+                album: Some("Unknown album".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn session_key_is_plain_for_one_root_and_order_independent_for_many() {
+        assert_eq!(session_key(&["/music".to_string()]), "/music");
+        let a = session_key(&["/nas".to_string(), "/ssd".to_string()]);
+        let b = session_key(&["/ssd".to_string(), "/nas".to_string()]);
+        assert_eq!(a, b);
+        assert_ne!(a, "/nas");
     }
 
     #[test]
@@ -268,6 +325,8 @@ mod tests {
             shuffle: None,
             loop_mode: None,
             follow_playback: None,
+            sort_preset: None,
+            browse: None,
         };
 
         apply_filter_and_selection(&mut app, Some(&state));
@@ -0,0 +1,167 @@
+//! Tag editor input, and the background writer it shares with ratings.
+//!
+//! Files are written on a background thread, like `:dupes hash`, so a large
+//! selection or a slow disk does not freeze the UI. Saved files are re-read
+//! and fed through the same `LibraryChange::Upsert` path as watcher events,
+//! so the UI and the audio thread refresh the tracks in place (display
+//! string, filter index and sort order included).
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::app::App;
+use crate::audio::{AudioCmd, AudioPlayer};
+use crate::config::{self, LibrarySettings};
+use crate::library::{LibraryChange, reread_track, write_tags};
+use crate::runtime::event_loop::EventLoopState;
+
+/// Outcome of a background tag write.
+pub(super) struct TagWrite {
+    /// Re-read tracks for every file written.
+    changes: Vec<LibraryChange>,
+    /// `path: reason` for every file that could not be written.
+    failures: Vec<String>,
+    /// Notice once every file was written.
+    done: String,
+}
+
+/// Handle a key while the tag editor is open.
+pub(super) fn handle_key(
+    key: KeyEvent,
+    settings: &config::Settings,
+    app: &mut App,
+    state: &mut EventLoopState,
+) {
+    let Some(editor) = app.tag_editor.as_mut() else {
+        return;
+    };
+    if editor.confirming {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter if state.tag_write.is_some() => {
+                app.set_notice("Tags are still being written".to_string());
+            }
+            KeyCode::Char('y') | KeyCode::Enter => save(settings, app, state),
+            KeyCode::Char('n') | KeyCode::Esc => editor.confirming = false,
+            _ => {}
+        }
+        return;
+    }
+    match key.code {
+        KeyCode::Esc => app.tag_editor = None,
+        KeyCode::Tab | KeyCode::Down => editor.step(true),
+        KeyCode::BackTab | KeyCode::Up => editor.step(false),
+        KeyCode::Backspace => editor.pop(),
+        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => editor.clear(),
+        KeyCode::Enter => {
+            let changes = editor.changes();
+            if changes.is_empty() {
+                app.set_notice("No tag changes to write".to_string());
+            } else if let Some(err) = changes
+                .iter()
+                .find_map(|(field, value)| field.validate(value).err())
+            {
+                app.set_notice(err);
+            } else {
+                editor.confirming = true;
+            }
+        }
+        KeyCode::Char(c) if !c.is_control() => editor.push(c),
+        _ => {}
+    }
+}
+
+/// Write the confirmed edits to every target file on a background thread;
+/// `poll` refreshes the tracks once it is done.
+fn save(settings: &config::Settings, app: &mut App, state: &mut EventLoopState) {
+    let Some(editor) = app.tag_editor.take() else {
+        return;
+    };
+    let edits = editor.changes();
+    let paths: Vec<PathBuf> = editor
+        .targets
+        .iter()
+        .filter_map(|&i| app.tracks.get(i))
+        .map(|track| track.path.clone())
+        .collect();
+    let notice = format!("Writing tags to {} file(s)...", paths.len());
+    let done = format!("Tags written to {} file(s)", paths.len());
+    let library = settings.library.clone();
+    write_in_background(
+        paths,
+        &settings.library,
+        move |path| write_tags(path, &edits, &library),
+        done,
+        state,
+    );
+    app.set_notice(notice);
+}
+
+/// Run `write` on each of `paths` on a background thread and re-read the
+/// files it wrote; `poll` applies them and shows `done` or the failures.
+/// Callers check `state.tag_write` first, so writes never overlap.
+pub(super) fn write_in_background(
+    paths: Vec<PathBuf>,
+    settings: &LibrarySettings,
+    write: impl Fn(&Path) -> Result<(), String> + Send + 'static,
+    done: String,
+    state: &mut EventLoopState,
+) {
+    let settings = settings.clone();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut changes = Vec::new();
+        let mut failures = Vec::new();
+        for path in paths {
+            match write(&path) {
+                Ok(()) => changes.push(LibraryChange::Upsert(reread_track(&path, &settings))),
+                Err(e) => failures.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        let _ = tx.send(TagWrite {
+            changes,
+            failures,
+            done,
+        });
+    });
+    state.tag_write = Some(rx);
+}
+
+/// Refresh the written tracks once a background tag write has finished.
+pub(super) fn poll(app: &mut App, audio_player: &AudioPlayer, state: &mut EventLoopState) {
+    let Some(rx) = state.tag_write.as_ref() else {
+        return;
+    };
+    let TagWrite {
+        changes,
+        failures,
+        done,
+    } = match rx.try_recv() {
+        Ok(result) => result,
+        Err(mpsc::TryRecvError::Empty) => return,
+        Err(mpsc::TryRecvError::Disconnected) => TagWrite {
+            changes: Vec::new(),
+            failures: vec!["tag writer stopped".to_string()],
+            done: String::new(),
+        },
+    };
+    state.tag_write = None;
+
+    let written = changes.len();
+    if !changes.is_empty() {
+        // Same order as watcher events: the UI first, then the audio thread.
+        app.apply_library_changes(changes.clone());
+        let _ = audio_player.send(AudioCmd::ApplyLibraryChanges(changes));
+    }
+    app.set_notice(match failures.first() {
+        None => done,
+        Some(first) => format!(
+            "Tag write failed for {} of {} file(s): {}",
+            failures.len(),
+            written + failures.len(),
+            first
+        ),
+    });
+}
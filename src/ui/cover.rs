@@ -0,0 +1,99 @@
+//! Cover art at the top of the metadata pane.
+//!
+//! Half-block art is drawn into the buffer like any text. Kitty and sixel
+//! images cannot be: their cells are marked as skipped so ratatui leaves them
+//! alone, and the runtime writes the escape sequence into the returned
+//! `CoverSlot` after the frame.
+
+use std::path::{Path, PathBuf};
+
+use ratatui::{
+    Frame,
+    layout::{Alignment, Rect},
+    style::{Color, Modifier, Style},
+    widgets::Paragraph,
+};
+
+use crate::app::{App, CoverImage};
+
+/// Largest cover, in rows.
+const MAX_ROWS: u16 = 16;
+/// Smallest cover worth drawing, in rows.
+const MIN_ROWS: u16 = 4;
+
+/// Where the selected track's cover went this frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverSlot {
+    pub track: PathBuf,
+    pub area: Rect,
+    /// False while a popup may overlap the pane: graphics must not be drawn.
+    pub graphics: bool,
+}
+
+/// Rows the cover takes in a pane content area of `width` x `height`: a
+/// square at the usual 1:2 cell aspect, using at most half the height.
+pub(crate) fn cover_rows(width: u16, height: u16) -> u16 {
+    let rows = (width / 2).min(height / 2).min(MAX_ROWS);
+    if rows < MIN_ROWS { 0 } else { rows }
+}
+
+/// Draw `track`'s cover in the top `rows` of `area`, centred.
+pub(crate) fn render_cover(
+    frame: &mut Frame,
+    area: Rect,
+    rows: u16,
+    app: &App,
+    track: &Path,
+) -> CoverSlot {
+    let cols = rows * 2;
+    let area = Rect {
+        x: area.x + area.width.saturating_sub(cols) / 2,
+        y: area.y,
+        width: cols.min(area.width),
+        height: rows,
+    };
+    let graphics = !app.popup_open();
+    let image = app
+        .cover
+        .as_ref()
+        .filter(|c| c.fits(track, area.width, area.height))
+        .map(|c| &c.image);
+    let buf = frame.buffer_mut();
+    match image {
+        Some(CoverImage::Cells(lines)) => {
+            for (y, line) in (area.y..area.bottom()).zip(lines) {
+                for (x, (top, bottom)) in (area.x..area.right()).zip(line) {
+                    buf[(x, y)]
+                        .set_symbol("▀")
+                        .set_fg(Color::Rgb(top[0], top[1], top[2]))
+                        .set_bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+                }
+            }
+        }
+        Some(CoverImage::Graphics(_)) if graphics => {
+            for y in area.y..area.bottom() {
+                for x in area.x..area.right() {
+                    buf[(x, y)].set_skip(true);
+                }
+            }
+        }
+        Some(CoverImage::Missing) => {
+            let middle = Rect {
+                y: area.y + area.height / 2,
+                height: 1,
+                ..area
+            };
+            let text = Paragraph::new("no cover art")
+                .alignment(Alignment::Center)
+                .style(Style::default().add_modifier(Modifier::DIM));
+            frame.render_widget(text, middle);
+        }
+        // Still loading, or graphics hidden under a popup.
+        _ => {}
+    }
+    CoverSlot {
+        track: track.to_path_buf(),
+        area,
+        graphics,
+    }
+}
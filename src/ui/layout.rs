@@ -1,5 +1,6 @@
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 
+use super::cover::cover_rows;
 use super::text::wrapped_line_count;
 
 pub(crate) struct RootLayout {
@@ -76,6 +77,7 @@ pub(crate) fn main_layout(
     show_metadata: bool,
     show_lyrics: bool,
     metadata_text: Option<&str>,
+    show_cover: bool,
 ) -> MainLayout {
     let mut list_area = main_area;
     let mut meta_area: Option<Rect> = None;
@@ -98,8 +100,16 @@ pub(crate) fn main_layout(
                 let sidebar = panes[2];
                 let meta = metadata_text.unwrap_or_default();
                 let meta_content_width = sidebar.width.saturating_sub(3).max(1);
+                let cover_height = if show_cover {
+                    match cover_rows(meta_content_width, u16::MAX) {
+                        0 => 0,
+                        rows => rows + 1,
+                    }
+                } else {
+                    0
+                };
                 let ideal_meta_height = wrapped_line_count(meta, meta_content_width)
-                    .saturating_add(2)
+                    .saturating_add(2 + cover_height)
                     .max(7);
                 let max_meta_height = sidebar.height.saturating_sub(8).max(7);
                 let meta_height = ideal_meta_height.min(max_meta_height);
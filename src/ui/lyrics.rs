@@ -111,9 +111,93 @@ pub(crate) fn lyrics_text(
     Text::from(lines)
 }
 
+/// Spread `text` out with spaces so it reads larger, if that still fits in `width`.
+pub(crate) fn enlarge_line(text: &str, width: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let spread_len = (chars.len() * 2).saturating_sub(1);
+    if chars.is_empty() || spread_len > width {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(spread_len * 4);
+    for (i, c) in chars.into_iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Build the full-screen (karaoke) view of timed lyrics.
+///
+/// The active line sits in the vertical middle, enlarged and bolded with a blank line on
+/// either side; sung lines are dimmed above it and upcoming lines follow below.
+pub(crate) fn fullscreen_timed_lyrics_lines(
+    lines: &[TimedLyricLine],
+    elapsed: Duration,
+    height: usize,
+    width: usize,
+) -> Vec<Line<'static>> {
+    if lines.is_empty() {
+        return vec![Line::from("No timed lyrics found.")];
+    }
+
+    let active = active_timed_lyric_index(lines, elapsed);
+    let middle = height / 2;
+    let above = middle.saturating_sub(1);
+    let (past, upcoming_from) = match active {
+        Some(idx) => (&lines[idx.saturating_sub(above)..idx], idx + 1),
+        None => (&lines[..0], 0),
+    };
+
+    let mut rendered: Vec<Line<'static>> = Vec::new();
+    let padding = above.saturating_sub(past.len());
+    rendered.extend((0..padding).map(|_| Line::from(String::new())));
+    for line in past {
+        rendered.push(Line::from(Span::styled(
+            line.text.clone(),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    rendered.push(Line::from(String::new()));
+    let active_text = active.map_or(String::new(), |idx| enlarge_line(&lines[idx].text, width));
+    rendered.push(Line::from(Span::styled(
+        active_text,
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+    rendered.push(Line::from(String::new()));
+
+    let remaining = height.saturating_sub(rendered.len());
+    rendered.extend(
+        lines[upcoming_from.min(lines.len())..]
+            .iter()
+            .take(remaining)
+            .map(|line| Line::from(line.text.clone())),
+    );
+    rendered
+}
+
+/// Build the full-screen lyrics view for the currently playing track.
+pub(crate) fn fullscreen_lyrics_text(app: &App, height: usize, width: usize) -> Text<'static> {
+    let lines = match (app.current_track_lyrics_index(), app.current_track_lyrics()) {
+        (None, _) => vec![Line::from("No track playing.")],
+        (Some(_), None) => vec![Line::from("No embedded lyrics found.")],
+        (Some(_), Some(Lyrics::Plain(lyrics))) => lyrics
+            .lines()
+            .map(|line| Line::from(line.to_string()))
+            .collect(),
+        (Some(_), Some(Lyrics::Timed(timed))) => {
+            let elapsed = current_playback_elapsed(app).unwrap_or(Duration::ZERO);
+            fullscreen_timed_lyrics_lines(timed, elapsed, height, width)
+        }
+    };
+    Text::from(lines)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::timed_lyrics_lines;
+    use super::{enlarge_line, fullscreen_timed_lyrics_lines, timed_lyrics_lines};
     use crate::library::TimedLyricLine;
     use ratatui::style::Color;
     use std::time::Duration;
@@ -149,4 +233,32 @@ mod tests {
         );
         assert_eq!(third_style.fg, None);
     }
+
+    #[test]
+    fn enlarge_line_spreads_only_when_it_fits() {
+        assert_eq!(enlarge_line("la la", 9), "l a   l a");
+        assert_eq!(enlarge_line("la la", 8), "la la");
+        assert_eq!(enlarge_line("", 10), "");
+    }
+
+    #[test]
+    fn fullscreen_lyrics_centres_the_active_line() {
+        let lines: Vec<TimedLyricLine> = (0..6)
+            .map(|i| TimedLyricLine {
+                timestamp: Duration::from_secs(i * 5),
+                text: format!("l{i}"),
+            })
+            .collect();
+
+        let rendered = fullscreen_timed_lyrics_lines(&lines, Duration::from_secs(11), 9, 40);
+        let texts: Vec<String> = rendered.iter().map(|l| l.to_string()).collect();
+        assert_eq!(texts, vec!["", "l0", "l1", "", "l 2", "", "l3", "l4", "l5"]);
+        assert_eq!(rendered[2].spans[0].style.fg, Some(Color::DarkGray));
+        assert!(
+            rendered[4].spans[0]
+                .style
+                .add_modifier
+                .contains(ratatui::style::Modifier::BOLD)
+        );
+    }
 }
@@ -3,6 +3,7 @@
 //! This module orchestrates rendering and delegates text/layout/pane logic
 //! to focused submodules.
 
+mod cover;
 mod layout;
 mod lyrics;
 mod panes;
@@ -10,27 +11,32 @@ mod text;
 
 use ratatui::Frame;
 
-use crate::app::App;
-use crate::config::{ControlsSettings, UiSettings};
+use crate::app::{App, LibraryView};
+use crate::config::{ControlsSettings, CoverArtSetting, UiSettings};
 
+pub use self::cover::CoverSlot;
 use self::layout::{main_layout, root_layout};
 use self::panes::{
-    render_bottom_input, render_controls_popup, render_header, render_lyrics_pane,
-    render_metadata_pane, render_status, render_track_list,
+    render_batch_preview, render_bottom_input, render_browser, render_controls_popup, render_dupes,
+    render_folders, render_header, render_lyrics_fullscreen, render_lyrics_pane,
+    render_metadata_pane, render_scan_report, render_status, render_tag_editor, render_track_list,
 };
 use self::text::{bottom_info_text, metadata_text, status_text};
 
 /// Render the entire UI into the provided `frame` using `app` state and settings.
+///
+/// Returns where the cover art went, if the metadata pane showed one.
 pub fn draw(
     frame: &mut Frame,
     app: &App,
     display: &[usize],
     ui_settings: &UiSettings,
     controls_settings: &ControlsSettings,
-) {
+) -> Option<CoverSlot> {
     let bottom_text = bottom_info_text(app, ui_settings);
     let status_text_val = status_text(app, ui_settings);
     let metadata = metadata_text(app);
+    let show_cover = ui_settings.cover_art != CoverArtSetting::Off;
 
     let root = root_layout(frame.area(), &status_text_val, bottom_text.as_deref());
     let show_lyrics_pane = app.lyrics_popup && ui_settings.lyrics_enabled;
@@ -39,23 +45,39 @@ pub fn draw(
         app.metadata_window,
         show_lyrics_pane,
         Some(&metadata),
+        show_cover,
     );
 
+    let mut cover = None;
     render_header(frame, root.header, ui_settings);
     render_status(frame, root.status, status_text_val);
-    render_track_list(frame, main.list, app, display, ui_settings);
 
-    if let Some(meta_area) = main.metadata {
-        render_metadata_pane(frame, meta_area, metadata);
-    }
+    if app.lyrics_fullscreen && ui_settings.lyrics_enabled {
+        render_lyrics_fullscreen(frame, root.main, app, ui_settings);
+    } else {
+        match app.view {
+            LibraryView::List => render_track_list(frame, main.list, app, display, ui_settings),
+            LibraryView::Browser => render_browser(frame, main.list, app),
+            LibraryView::Folders => render_folders(frame, main.list, app),
+        }
+
+        if let Some(meta_area) = main.metadata {
+            cover = render_metadata_pane(frame, meta_area, app, metadata, show_cover);
+        }
 
-    if let Some(lyrics_area) = main.lyrics {
-        render_lyrics_pane(frame, lyrics_area, app, ui_settings);
+        if let Some(lyrics_area) = main.lyrics {
+            render_lyrics_pane(frame, lyrics_area, app, ui_settings);
+        }
     }
 
     render_controls_popup(frame, app, controls_settings);
+    render_tag_editor(frame, app);
+    render_batch_preview(frame, app);
+    render_dupes(frame, app);
+    render_scan_report(frame, app);
 
     if let (Some(bottom_area), Some(text)) = (root.bottom, bottom_text) {
         render_bottom_input(frame, bottom_area, text);
     }
+    cover
 }
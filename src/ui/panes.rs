@@ -1,17 +1,22 @@
-use ratatui::text::Text;
+use ratatui::text::{Line, Text};
 use ratatui::{
     Frame,
-    layout::{Alignment, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Modifier, Style, Stylize},
     widgets::{Block, Borders, Clear, List, ListItem, Padding, Paragraph, Wrap},
 };
 
-use crate::app::App;
+use crate::app::{App, BrowseGroup, BrowseLevel};
 use crate::config::{ControlsSettings, UiSettings};
 
+use super::cover::{CoverSlot, cover_rows, render_cover};
 use super::layout::centered_rect_sized;
-use super::lyrics::lyrics_text;
-use super::text::{controls_text, list_item_wrapped, wrapped_line_count};
+use super::lyrics::{fullscreen_lyrics_text, lyrics_text};
+use super::text::{
+    batch_preview_text, browse_track_text, controls_text, dupes_text, folder_row_text,
+    list_item_wrapped, now_playing_track_text, rating_column, scan_report_text, tag_editor_text,
+    wrapped_line_count,
+};
 
 /// Render the top header banner.
 pub(crate) fn render_header(frame: &mut Frame, area: Rect, ui_settings: &UiSettings) {
@@ -54,7 +59,9 @@ pub(crate) fn render_track_list(
     display: &[usize],
     ui_settings: &UiSettings,
 ) {
-    let q = app.filter_query.trim();
+    // Highlight only the fuzzy part of the query, not property predicates.
+    let filter_text = app.filter_text();
+    let q = filter_text.as_str();
     let query_lower = if q.is_empty() {
         None
     } else if app.uses_lower_titles() {
@@ -63,33 +70,25 @@ pub(crate) fn render_track_list(
         None
     };
 
-    // Center the selected item when possible by creating a visible window.
     // Important: only build ListItems for the visible window (avoid allocating the entire list).
     let total = display.len();
-    let list_height = area.height as usize;
     let sel_pos = display.iter().position(|&i| i == app.selected).unwrap_or(0);
-    let (start, end, selected_pos_in_visible) = if total <= list_height || list_height == 0 {
-        (0, total, sel_pos)
-    } else {
-        let half = list_height / 2;
-        let mut start = if sel_pos > half { sel_pos - half } else { 0 };
-        if start + list_height > total {
-            start = total - list_height;
-        }
-        (start, start + list_height, sel_pos - start)
-    };
+    let (start, end, selected_pos_in_visible) = visible_window(total, area.height, sel_pos);
 
     let item_width = area.width.saturating_sub(4).max(1);
     let show_relative = ui_settings.show_relative_numbers;
     let show_current = ui_settings.show_current_line_number;
     let number_width = total.to_string().len().max(1);
+    // The stars column only appears once something in the list is rated.
+    let show_ratings =
+        ui_settings.show_ratings && display.iter().any(|&i| app.tracks[i].rating.is_some());
 
     let visible_items: Vec<ListItem> = display[start..end]
         .iter()
         .enumerate()
         .map(|(offset, &i)| {
             let absolute_pos = start + offset;
-            let prefix = if show_relative || show_current {
+            let mut prefix = if show_relative || show_current {
                 if show_relative && show_current {
                     let val = if absolute_pos == sel_pos {
                         absolute_pos + 1
@@ -117,6 +116,9 @@ pub(crate) fn render_track_list(
             } else {
                 String::new()
             };
+            if show_ratings {
+                prefix.push_str(&rating_column(app.tracks[i].rating));
+            }
             let title = &app.tracks[i].display;
             if q.is_empty() {
                 list_item_wrapped(&prefix, title.as_str(), item_width)
@@ -160,22 +162,154 @@ pub(crate) fn render_track_list(
     frame.render_stateful_widget(list, area, &mut state);
 }
 
+/// Window `(start, end, selected offset)` of a `total`-row list that fits
+/// `height` rows, centered on the selection when possible.
+fn visible_window(total: usize, height: u16, sel_pos: usize) -> (usize, usize, usize) {
+    let list_height = height as usize;
+    if total <= list_height || list_height == 0 {
+        return (0, total, sel_pos);
+    }
+    let half = list_height / 2;
+    let mut start = sel_pos.saturating_sub(half);
+    if start + list_height > total {
+        start = total - list_height;
+    }
+    (start, start + list_height, sel_pos - start)
+}
+
+/// Render the artist → album → track browser as three columns.
+///
+/// The focused column gets the usual reversed highlight; the others only
+/// mark their entry in bold so the path stays visible.
+pub(crate) fn render_browser(frame: &mut Frame, area: Rect, app: &App) {
+    let view = app.browse_view();
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+        ])
+        .split(area);
+
+    let labels = |groups: &[BrowseGroup]| groups.iter().map(|g| g.label.clone()).collect();
+    let tracks = view
+        .tracks()
+        .iter()
+        .map(|&i| browse_track_text(&app.tracks[i]))
+        .collect();
+    let column_data: [(&str, Vec<String>, usize, BrowseLevel); 3] = [
+        (
+            " artists ",
+            labels(&view.artists),
+            view.artist,
+            BrowseLevel::Artist,
+        ),
+        (
+            " albums ",
+            labels(&view.albums),
+            view.album,
+            BrowseLevel::Album,
+        ),
+        (" tracks ", tracks, view.track, BrowseLevel::Track),
+    ];
+
+    for ((title, entries, selected, level), &column) in column_data.into_iter().zip(columns.iter())
+    {
+        let total = entries.len();
+        let (start, end, sel) = visible_window(total, column.height.saturating_sub(2), selected);
+        let width = column.width.saturating_sub(4).max(1);
+        let items: Vec<ListItem> = entries[start..end]
+            .iter()
+            .map(|text| list_item_wrapped("", text.as_str(), width))
+            .collect();
+        let focused = level == app.browse_level;
+        let highlight = if focused {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        };
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(highlight)
+            .highlight_symbol(if focused { "> " } else { "  " });
+        let mut state = ratatui::widgets::ListState::default();
+        if total > 0 {
+            state.select(Some(sel));
+        }
+        frame.render_stateful_widget(list, column, &mut state);
+    }
+}
+
+/// Render the folder tree, indenting each level and marking open folders.
+pub(crate) fn render_folders(frame: &mut Frame, area: Rect, app: &App) {
+    let rows = app.folder_rows();
+    let total = rows.len();
+    let cursor = app.folder_cursor_row(&rows);
+    let (start, end, sel) = visible_window(total, area.height.saturating_sub(2), cursor);
+    let width = area.width.saturating_sub(4).max(1);
+    let items: Vec<ListItem> = rows[start..end]
+        .iter()
+        .map(|row| {
+            let indent = "  ".repeat(row.depth);
+            list_item_wrapped(&indent, folder_row_text(app, row), width)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(" folders "))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    let mut state = ratatui::widgets::ListState::default();
+    if total > 0 {
+        state.select(Some(sel));
+    }
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
 /// Render the metadata side pane for the currently selected track.
-pub(crate) fn render_metadata_pane(frame: &mut Frame, area: Rect, metadata_text: String) {
+pub(crate) fn render_metadata_pane(
+    frame: &mut Frame,
+    area: Rect,
+    app: &App,
+    metadata_text: String,
+    show_cover: bool,
+) -> Option<CoverSlot> {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" metadata (K closes) ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let mut text_area = inner;
+    let mut slot = None;
+    let rows = cover_rows(inner.width.saturating_sub(1), inner.height);
+    if let Some(track) = app.tracks.get(app.selected)
+        && show_cover
+        && rows > 0
+    {
+        let cover_area = Rect {
+            x: inner.x + 1,
+            width: inner.width - 1,
+            ..inner
+        };
+        slot = Some(render_cover(frame, cover_area, rows, app, &track.path));
+        // One blank row between the cover and the text.
+        let used = (rows + 1).min(inner.height);
+        text_area.y += used;
+        text_area.height -= used;
+    }
+
     let meta_paragraph = Paragraph::new(metadata_text)
-        .block(
-            Block::default()
-                .padding(Padding {
-                    left: 1,
-                    right: 0,
-                    top: 0,
-                    bottom: 0,
-                })
-                .borders(Borders::ALL)
-                .title(" metadata (K closes) "),
-        )
+        .block(Block::default().padding(Padding {
+            left: 1,
+            right: 0,
+            top: 0,
+            bottom: 0,
+        }))
         .wrap(Wrap { trim: true });
-    frame.render_widget(meta_paragraph, area);
+    frame.render_widget(meta_paragraph, text_area);
+    slot
 }
 
 /// Render the lyrics side pane for the current playback context.
@@ -203,6 +337,29 @@ pub(crate) fn render_lyrics_pane(
     frame.render_widget(lyrics_paragraph, area);
 }
 
+/// Render the full-screen (karaoke) lyrics view over the main content area.
+pub(crate) fn render_lyrics_fullscreen(
+    frame: &mut Frame,
+    area: Rect,
+    app: &App,
+    ui_settings: &UiSettings,
+) {
+    let title = match app.current_track_lyrics_index() {
+        Some(idx) => format!(
+            " {} (gL closes) ",
+            now_playing_track_text(app, idx, ui_settings)
+        ),
+        None => " lyrics (gL closes) ".to_string(),
+    };
+    let height = area.height.saturating_sub(2) as usize;
+    let width = area.width.saturating_sub(2) as usize;
+    let paragraph = Paragraph::new(fullscreen_lyrics_text(app, height, width))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(paragraph, area);
+}
+
 /// Render the controls popup overlay when it is enabled.
 pub(crate) fn render_controls_popup(
     frame: &mut Frame,
@@ -240,6 +397,95 @@ pub(crate) fn render_controls_popup(
     frame.render_widget(popup, popup_area);
 }
 
+/// Render the tag editor popup when it is open.
+pub(crate) fn render_tag_editor(frame: &mut Frame, app: &App) {
+    let Some(editor) = app.tag_editor.as_ref() else {
+        return;
+    };
+    let (lines, focused) = tag_editor_text(app, editor);
+    let max_width = frame.area().width.saturating_sub(4).max(30);
+    let popup_width = lines
+        .iter()
+        .map(|l| l.chars().count() as u16 + 6)
+        .max()
+        .unwrap_or(0)
+        .clamp(40, 90)
+        .min(max_width);
+    let popup_height = (lines.len() as u16).saturating_add(4);
+    let popup_area = centered_rect_sized(popup_width, popup_height, frame.area());
+    frame.render_widget(Clear, popup_area);
+
+    let text: Vec<Line> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if Some(i) == focused {
+                Line::from(line).style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                Line::from(line)
+            }
+        })
+        .collect();
+    let title = if editor.confirming {
+        " confirm tag changes "
+    } else {
+        " edit tags "
+    };
+    let popup =
+        Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title).padding(
+            Padding {
+                left: 1,
+                right: 1,
+                top: 1,
+                bottom: 1,
+            },
+        ));
+    frame.render_widget(popup, popup_area);
+}
+
+/// Render the `:rename` / `:retag` preview popup when it is open.
+pub(crate) fn render_batch_preview(frame: &mut Frame, app: &App) {
+    let Some(preview) = app.batch_preview.as_ref() else {
+        return;
+    };
+    // Borders, padding, header, footer and the position line.
+    let rows = frame.area().height.saturating_sub(12) as usize;
+    let lines = batch_preview_text(preview, rows);
+    let max_width = frame.area().width.saturating_sub(4).max(30);
+    let popup_width = lines
+        .iter()
+        .map(|l| l.chars().count() as u16 + 6)
+        .max()
+        .unwrap_or(0)
+        .clamp(40, 120)
+        .min(max_width);
+    let popup_height = (lines.len() as u16).saturating_add(4);
+    let popup_area = centered_rect_sized(popup_width, popup_height, frame.area());
+    frame.render_widget(Clear, popup_area);
+
+    let text: Vec<Line> = lines
+        .into_iter()
+        .map(|line| {
+            if line.starts_with("! ") {
+                Line::from(line).style(Style::default().add_modifier(Modifier::DIM))
+            } else {
+                Line::from(line)
+            }
+        })
+        .collect();
+    let title = format!(" {} preview ", preview.title);
+    let popup =
+        Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title).padding(
+            Padding {
+                left: 1,
+                right: 1,
+                top: 1,
+                bottom: 1,
+            },
+        ));
+    frame.render_widget(popup, popup_area);
+}
+
 /// Render the bottom input panel (filter and auxiliary info).
 pub(crate) fn render_bottom_input(frame: &mut Frame, area: Rect, text: String) {
     let bottom_panel = Paragraph::new(text)
@@ -258,3 +504,93 @@ pub(crate) fn render_bottom_input(frame: &mut Frame, area: Rect, text: String) {
 
     frame.render_widget(bottom_panel, area);
 }
+
+/// Render the `:dupes` duplicate finder popup when it is open.
+pub(crate) fn render_dupes(frame: &mut Frame, app: &App) {
+    let Some(view) = app.dupes.as_ref() else {
+        return;
+    };
+    // Borders, padding, header and footer.
+    let rows = frame.area().height.saturating_sub(12) as usize;
+    let (lines, focused) = dupes_text(view, rows);
+    let max_width = frame.area().width.saturating_sub(4).max(30);
+    let popup_width = lines
+        .iter()
+        .map(|l| l.chars().count() as u16 + 6)
+        .max()
+        .unwrap_or(0)
+        .clamp(40, 120)
+        .min(max_width);
+    let popup_height = (lines.len() as u16).saturating_add(4);
+    let popup_area = centered_rect_sized(popup_width, popup_height, frame.area());
+    frame.render_widget(Clear, popup_area);
+
+    let text: Vec<Line> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if Some(i) == focused {
+                Line::from(line).style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                Line::from(line)
+            }
+        })
+        .collect();
+    let popup = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" duplicates ")
+            .padding(Padding {
+                left: 1,
+                right: 1,
+                top: 1,
+                bottom: 1,
+            }),
+    );
+    frame.render_widget(popup, popup_area);
+}
+
+/// Render the `:report` scan problem list when it is open.
+pub(crate) fn render_scan_report(frame: &mut Frame, app: &App) {
+    let Some(report) = app.scan_report.as_ref() else {
+        return;
+    };
+    // Borders, padding, header, footer and the position line.
+    let rows = frame.area().height.saturating_sub(12) as usize;
+    let (lines, focused) = scan_report_text(report, rows);
+    let max_width = frame.area().width.saturating_sub(4).max(30);
+    let popup_width = lines
+        .iter()
+        .map(|l| l.chars().count() as u16 + 6)
+        .max()
+        .unwrap_or(0)
+        .clamp(40, 120)
+        .min(max_width);
+    let popup_height = (lines.len() as u16).saturating_add(4);
+    let popup_area = centered_rect_sized(popup_width, popup_height, frame.area());
+    frame.render_widget(Clear, popup_area);
+
+    let text: Vec<Line> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if Some(i) == focused {
+                Line::from(line).style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                Line::from(line)
+            }
+        })
+        .collect();
+    let popup = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" scan report ")
+            .padding(Padding {
+                left: 1,
+                right: 1,
+                top: 1,
+                bottom: 1,
+            }),
+    );
+    frame.render_widget(popup, popup_area);
+}
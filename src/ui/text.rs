@@ -2,8 +2,11 @@ use ratatui::text::Line;
 use ratatui::widgets::ListItem;
 use std::{collections::BTreeMap, sync::LazyLock, time::Duration};
 
-use crate::app::App;
-use crate::config::{TimeField, TrackDisplayField, UiSettings};
+use crate::app::{App, BatchPreview, DupesView, FolderRow, FolderRowKind, ScanReport, TagEditor};
+use crate::config::{TimeField, UiSettings};
+use crate::library::{
+    AudioProperties, MAX_STARS, Track, copy_summary, field_text, issue_counts, stars_text,
+};
 
 static CONTROLS_MAP: LazyLock<BTreeMap<String, String>> = LazyLock::new(|| {
     let mut map: BTreeMap<String, String> = BTreeMap::new();
@@ -22,6 +25,18 @@ static CONTROLS_MAP: LazyLock<BTreeMap<String, String>> = LazyLock::new(|| {
     map.insert("/".to_string(), "filter".to_string());
     map.insert("s".to_string(), "shuffle".to_string());
     map.insert("r".to_string(), "loop mode".to_string());
+    map.insert("o".to_string(), "sort order".to_string());
+    map.insert("b".to_string(), "artist/album browser".to_string());
+    map.insert("f".to_string(), "folder tree".to_string());
+    map.insert(":cd".to_string(), "switch directory".to_string());
+    map.insert("e".to_string(), "edit tags".to_string());
+    map.insert("R0-5".to_string(), "rate".to_string());
+    map.insert("F".to_string(), "favourites".to_string());
+    map.insert("x/X".to_string(), "stop after song/album".to_string());
+    map.insert("n".to_string(), "night mode".to_string());
+    map.insert("v".to_string(), "karaoke".to_string());
+    map.insert("[/]".to_string(), "karaoke amount".to_string());
+    map.insert("gL".to_string(), "full-screen lyrics".to_string());
     map.insert("K".to_string(), "metadata".to_string());
     map.insert("q".to_string(), "quit".to_string());
     map
@@ -34,7 +49,7 @@ pub(crate) fn controls_text(scrub_seconds: u64) -> String {
     // Keep the rendered order stable and human-friendly.
     let order = [
         "j/k", "h/l", "H/L", "-", "+", "=", "enter", "ctrl+e", "space/p", "gg/G", "K", "/", "s",
-        "r", "gl", "g?", "q",
+        "r", "o", "b", "f", "x/X", "n", "v", "[/]", "gl", "gL", "e", "R0-5", "F", ":cd", "g?", "q",
     ];
     order
         .iter()
@@ -242,10 +257,14 @@ pub(crate) fn list_item_wrapped<T: Into<String>>(
     ListItem::new(line_items)
 }
 
-/// Build the input panel content (filter + count), or none when empty.
+/// Build the input panel content (command line, filter + count), or none when empty.
 pub(crate) fn bottom_info_text(app: &App, ui_settings: &UiSettings) -> Option<String> {
     let mut lines: Vec<String> = Vec::new();
 
+    if app.command_mode {
+        lines.push(format!(":{}", app.command_line));
+    }
+
     let q = app.filter_query.trim();
     if app.filter_mode || !q.is_empty() {
         if q.is_empty() {
@@ -268,55 +287,233 @@ pub(crate) fn bottom_info_text(app: &App, ui_settings: &UiSettings) -> Option<St
     }
 }
 
-/// Build the "now playing" track text according to `ui` settings.
-pub(crate) fn now_playing_track_text(app: &App, track_index: usize, ui: &UiSettings) -> String {
-    let track = &app.tracks[track_index];
-    let mut parts: Vec<String> = Vec::new();
+/// Tag editor popup lines: the form, or the diff preview while confirming.
+/// The second value is the line to highlight.
+pub(crate) fn tag_editor_text(app: &App, editor: &TagEditor) -> (Vec<String>, Option<usize>) {
+    let files = match editor.targets.as_slice() {
+        [one] => app
+            .tracks
+            .get(*one)
+            .and_then(|t| t.path.file_name())
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        many => format!("{} tracks", many.len()),
+    };
+    let mut lines = Vec::new();
+    if editor.confirming {
+        lines.push(format!("Write to {}?", files));
+        lines.push(String::new());
+        lines.extend(editor.diff());
+        lines.push(String::new());
+        lines.push("y / Enter: write • n / Esc: back".to_string());
+        return (lines, None);
+    }
+    lines.push(format!("Editing {}", files));
+    lines.push(String::new());
+    for f in &editor.fields {
+        let value = if f.original.is_none() && !f.touched {
+            "(mixed)".to_string()
+        } else {
+            f.value.clone()
+        };
+        let mark = if f.changed() { "*" } else { " " };
+        lines.push(format!("{}{:>13}: {}", mark, f.field.label(), value));
+    }
+    lines.push(String::new());
+    lines.push("Tab/↑↓: field • Ctrl+u: clear • Enter: review • Esc: cancel".to_string());
+    (lines, Some(2 + editor.cursor))
+}
 
-    for f in &ui.now_playing_track_fields {
-        match f {
-            TrackDisplayField::Display => {
-                if !track.display.trim().is_empty() {
-                    parts.push(track.display.clone());
-                }
-            }
-            TrackDisplayField::Title => {
-                if !track.title.trim().is_empty() {
-                    parts.push(track.title.clone());
-                }
-            }
-            TrackDisplayField::Artist => {
-                if let Some(a) = track
-                    .artist
-                    .as_deref()
-                    .map(str::trim)
-                    .filter(|s| !s.is_empty())
-                {
-                    parts.push(a.to_string());
-                }
-            }
-            TrackDisplayField::Album => {
-                if let Some(a) = track
-                    .album
-                    .as_deref()
-                    .map(str::trim)
-                    .filter(|s| !s.is_empty())
-                {
-                    parts.push(a.to_string());
-                }
-            }
-            TrackDisplayField::Filename => {
-                if let Some(stem) = track.path.file_stem().and_then(|s| s.to_str()) {
-                    if !stem.trim().is_empty() {
-                        parts.push(stem.to_string());
-                    }
-                }
-            }
-            TrackDisplayField::Path => {
-                parts.push(track.path.display().to_string());
+/// Batch preview popup lines, showing at most `rows` plan lines from the
+/// scroll position.
+pub(crate) fn batch_preview_text(preview: &BatchPreview, rows: usize) -> Vec<String> {
+    let plan = &preview.plan;
+    let mut lines = vec![format!(
+        "{} {} file(s){}",
+        if preview.title == "rename" {
+            "Rename"
+        } else {
+            "Retag"
+        },
+        plan.op.len(),
+        if plan.skipped > 0 {
+            format!(", skipping {} (marked !)", plan.skipped)
+        } else {
+            String::new()
+        }
+    )];
+    lines.push(String::new());
+    let rows = rows.max(1);
+    let start = preview.scroll.min(plan.preview.len().saturating_sub(rows));
+    lines.extend(plan.preview.iter().skip(start).take(rows).cloned());
+    if plan.preview.len() > rows {
+        lines.push(format!(
+            "({}-{} of {})",
+            start + 1,
+            (start + rows).min(plan.preview.len()),
+            plan.preview.len()
+        ));
+    }
+    lines.push(String::new());
+    lines.push("j/k: scroll • y / Enter: apply • n / Esc: cancel".to_string());
+    lines
+}
+
+/// Duplicate finder popup lines, showing at most `rows` group lines around
+/// the cursor, plus the index of the line to highlight.
+pub(crate) fn dupes_text(view: &DupesView, rows: usize) -> (Vec<String>, Option<usize>) {
+    let extra: usize = view.groups.iter().map(|g| g.copies.len() - 1).sum();
+    let mut lines = vec![format!(
+        "{} group(s), {} extra copies{}",
+        view.groups.len(),
+        extra,
+        if view.hashed {
+            " (identical audio)"
+        } else {
+            ""
+        }
+    )];
+    lines.push(String::new());
+
+    let (group, copy) = view.position();
+    let mut body = Vec::new();
+    let mut cursor_line = 0;
+    for (g, dupes) in view.groups.iter().enumerate() {
+        body.push(dupes.label());
+        for (c, track) in dupes.copies.iter().enumerate() {
+            if (g, c) == (group, copy) {
+                cursor_line = body.len();
             }
+            let mark = if view.keep[g] == c { "keep" } else { "    " };
+            body.push(format!(
+                "  {} {}  {}",
+                mark,
+                copy_summary(track),
+                track.path.display()
+            ));
+        }
+    }
+    let rows = rows.max(1);
+    let start = cursor_line
+        .saturating_sub(rows / 2)
+        .min(body.len().saturating_sub(rows));
+    lines.extend(body.iter().skip(start).take(rows).cloned());
+    let focused = 2 + cursor_line - start;
+    lines.push(String::new());
+    lines.push(if view.confirm_all {
+        format!(
+            "Trash {} extra copies? y / Enter: yes • any other key: no",
+            extra
+        )
+    } else {
+        "j/k: move • Space: keep • x: trash extras • X: trash all • Esc: close".to_string()
+    });
+    (lines, Some(focused))
+}
+
+/// Scan report popup lines, showing at most `rows` problems around the
+/// cursor, plus the index of the line to highlight.
+pub(crate) fn scan_report_text(report: &ScanReport, rows: usize) -> (Vec<String>, Option<usize>) {
+    let counts: Vec<String> = issue_counts(&report.diagnostics)
+        .into_iter()
+        .map(|(kind, n)| format!("{} {}", n, kind))
+        .collect();
+    let mut lines = vec![counts.join(", "), String::new()];
+    let rows = rows.max(1);
+    let total = report.diagnostics.len();
+    let start = report
+        .cursor
+        .saturating_sub(rows / 2)
+        .min(total.saturating_sub(rows));
+    lines.extend(
+        report
+            .diagnostics
+            .iter()
+            .skip(start)
+            .take(rows)
+            .map(|d| format!("{}  {}", d.path.display(), d.issue)),
+    );
+    let focused = 2 + report.cursor - start;
+    if total > rows {
+        lines.push(format!(
+            "({}-{} of {})",
+            start + 1,
+            (start + rows).min(total),
+            total
+        ));
+    }
+    lines.push(String::new());
+    lines.push("j/k: move • Enter: go to track • Esc: close".to_string());
+    (lines, Some(focused))
+}
+
+/// Browser track column entry: `disc-track title`, e.g. `2-03 Song`.
+pub(crate) fn browse_track_text(track: &Track) -> String {
+    let number = match (track.disc_number, track.track_number) {
+        (Some(disc), Some(n)) if disc > 1 || track.disc_total.is_some_and(|t| t > 1) => {
+            format!("{disc}-{n:02} ")
+        }
+        (_, Some(n)) => format!("{n:02} "),
+        _ => String::new(),
+    };
+    format!("{}{}", number, track.title)
+}
+
+/// Folder view entry: `▸ name/  12 tracks · 48:10` for folders, the file name for tracks.
+pub(crate) fn folder_row_text(app: &App, row: &FolderRow) -> String {
+    match &row.kind {
+        FolderRowKind::Folder {
+            name,
+            tracks,
+            duration,
+            expanded,
+            ..
+        } => {
+            let marker = if *expanded { '▾' } else { '▸' };
+            let noun = if *tracks == 1 { "track" } else { "tracks" };
+            format!(
+                "{} {}/  {} {} · {}",
+                marker,
+                name,
+                tracks,
+                noun,
+                format_long_duration(*duration)
+            )
         }
+        FolderRowKind::Track(i) => app.tracks[*i]
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| app.tracks[*i].title.clone()),
     }
+}
+
+/// `mm:ss`, or `h:mm:ss` from an hour up.
+fn format_long_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format_mmss(d)
+    }
+}
+
+/// Build the "now playing" track text according to `ui` settings.
+pub(crate) fn now_playing_track_text(app: &App, track_index: usize, ui: &UiSettings) -> String {
+    // The audio thread may briefly report an index from before a library update.
+    let Some(track) = app.tracks.get(track_index) else {
+        return String::new();
+    };
+    playing_track_text(track, ui)
+}
+
+/// Now-playing label for `track` per `now_playing_track_fields`.
+fn playing_track_text(track: &Track, ui: &UiSettings) -> String {
+    let parts: Vec<String> = ui
+        .now_playing_track_fields
+        .iter()
+        .filter_map(|f| field_text(track, *f))
+        .collect();
 
     if parts.is_empty() {
         track.display.clone()
@@ -364,7 +561,9 @@ pub(crate) fn now_playing_time_text(
 pub(crate) fn status_text(app: &App, ui_settings: &UiSettings) -> String {
     let mut parts: Vec<String> = Vec::new();
 
-    let state = if app.filter_mode {
+    let state = if app.command_mode {
+        "COMMAND"
+    } else if app.filter_mode {
         "FILTER"
     } else if app.follow_playback {
         "FOLLOWING_PLAYING"
@@ -386,25 +585,47 @@ pub(crate) fn status_text(app: &App, ui_settings: &UiSettings) -> String {
     };
     parts.push(loop_text.to_string());
 
-    if let Some(ref h) = app.playback_handle {
-        if let Ok(info) = h.lock() {
-            let state = if info.playing { "Playing" } else { "Paused" };
-            if let Some(idx) = info.index {
-                let track = &app.tracks[idx];
-                let song = now_playing_track_text(app, idx, ui_settings);
-                let time = now_playing_time_text(info.elapsed, track.duration, ui_settings);
-                if let Some(time) = time {
-                    parts.push(format!("Song: {} [{}]", song, time));
-                } else {
-                    parts.push(format!("Song: {}", song));
-                }
-                parts.push(state.to_string());
+    // Read before locking: `playing_index` takes the same lock.
+    let playing_index = app.playing_index();
+    if let Some(ref h) = app.playback_handle
+        && let Ok(info) = h.lock()
+    {
+        match (info.stop_after_current, info.stop_after_album) {
+            (true, true) => parts.push("STOP AFTER: Song + Album".to_string()),
+            (true, false) => parts.push("STOP AFTER: Song".to_string()),
+            (false, true) => parts.push("STOP AFTER: Album".to_string()),
+            (false, false) => {}
+        }
+        if info.night_mode {
+            parts.push("NIGHT".to_string());
+        }
+        if info.karaoke {
+            parts.push(format!("KARAOKE {}%", info.karaoke_amount_percent));
+        }
+
+        let state = if info.playing { "Playing" } else { "Paused" };
+        // A track left over from a replaced library (`:cd`) has no index.
+        let playing = playing_index
+            .and_then(|idx| app.tracks.get(idx))
+            .or(info.detached.as_ref());
+        if let Some(track) = playing {
+            let song = playing_track_text(track, ui_settings);
+            let time = now_playing_time_text(info.elapsed, track.duration, ui_settings);
+            if let Some(time) = time {
+                parts.push(format!("Song: {} [{}]", song, time));
             } else {
-                parts.push("Stopped".to_string());
+                parts.push(format!("Song: {}", song));
             }
+            parts.push(state.to_string());
+        } else {
+            parts.push("Stopped".to_string());
         }
     }
 
+    if let Some(min_stars) = app.favourites {
+        parts.push(format!("FAVOURITES {}+", min_stars));
+    }
+
     if app.shuffle {
         parts.push("Shuffle: ON".to_string());
     } else {
@@ -417,6 +638,10 @@ pub(crate) fn status_text(app: &App, ui_settings: &UiSettings) -> String {
         parts.push(format!("Dir: {}", dir));
     }
 
+    if app.scanning {
+        parts.push(format!("Scanning… {} files", app.tracks.len()));
+    }
+
     if let Some(notice) = &app.notice {
         parts.push(format!("Notice: {}", notice));
     }
@@ -442,24 +667,86 @@ fn format_duration_mmss_ceil(d: Option<Duration>) -> String {
     format!("{}:{:02} ({}s)", minutes, seconds, total_secs)
 }
 
+/// Track list column for `rating`: its stars, or blanks when unrated.
+pub(crate) fn rating_column(rating: Option<u8>) -> String {
+    match rating {
+        Some(stars) => format!("{} ", stars_text(stars)),
+        None => " ".repeat(usize::from(MAX_STARS) + 1),
+    }
+}
+
 /// Build the metadata pane content for the currently selected track.
 pub(crate) fn metadata_text(app: &App) -> String {
     let selected = app.tracks.get(app.selected);
     if let Some(track) = selected {
         let dur = format_duration_mmss_ceil(track.duration);
+        let or_dash = |s: Option<&str>| s.unwrap_or("-").to_string();
+        let list = |values: &[String]| {
+            if values.is_empty() {
+                "-".to_string()
+            } else {
+                values.join(", ")
+            }
+        };
+        let date = track
+            .date
+            .clone()
+            .or_else(|| track.year.map(|y| y.to_string()))
+            .unwrap_or_else(|| "-".to_string());
         format!(
-            "Selected Track\n\nTitle: {}\nArtist: {}\nAlbum: {}\nDuration: {}\nPath: {}",
+            "Selected Track\n\nTitle: {}\nArtist: {}\nAlbum: {}\nAlbum Artist: {}\nTrack: {}\nDisc: {}\nYear: {}\nGenre: {}\nComposer: {}\nRating: {}\nDuration: {}\nComment: {}\nPath: {}\n\n{}",
             track.title,
-            track.artist.as_deref().unwrap_or("-"),
-            track.album.as_deref().unwrap_or("-"),
+            list(&track.artists),
+            or_dash(track.album.as_deref()),
+            or_dash(track.album_artist.as_deref()),
+            number_of(track.track_number, track.track_total),
+            number_of(track.disc_number, track.disc_total),
+            date,
+            list(&track.genres),
+            or_dash(track.composer.as_deref()),
+            track.rating.map_or_else(|| "-".to_string(), stars_text),
             dur,
-            track.path.display()
+            or_dash(track.comment.as_deref()),
+            track.path.display(),
+            audio_properties_text(&track.audio)
         )
     } else {
         "Selected Track\n\nNo track selected".to_string()
     }
 }
 
+/// Build the "Audio" section of the metadata pane.
+fn audio_properties_text(audio: &AudioProperties) -> String {
+    let or_dash = |s: Option<String>| s.unwrap_or_else(|| "-".to_string());
+    let channels = audio.channels.map(|n| match n {
+        1 => "1 (mono)".to_string(),
+        2 => "2 (stereo)".to_string(),
+        n => n.to_string(),
+    });
+    format!(
+        "Audio\nCodec: {}\nBitrate: {}\nSample Rate: {}\nBit Depth: {}\nChannels: {}\nFile Size: {}",
+        or_dash(audio.codec.clone()),
+        or_dash(audio.bitrate.map(|b| format!("{} kbps", b))),
+        or_dash(
+            audio
+                .sample_rate
+                .map(|r| format!("{} kHz", f64::from(r) / 1000.0))
+        ),
+        or_dash(audio.bit_depth.map(|b| format!("{}-bit", b))),
+        or_dash(channels),
+        or_dash(audio.file_size.map(|s| format!("{:.1} MB", s as f64 / 1e6))),
+    )
+}
+
+/// Format a position such as a track number as `3/12`, `3`, or `-`.
+fn number_of(number: Option<u32>, total: Option<u32>) -> String {
+    match (number, total) {
+        (Some(n), Some(t)) => format!("{}/{}", n, t),
+        (Some(n), None) => n.to_string(),
+        (None, _) => "-".to_string(),
+    }
+}
+
 /// Format a `Duration` as `MM:SS`.
 fn format_mmss(d: Duration) -> String {
     let secs = d.as_secs();